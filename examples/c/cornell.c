@@ -0,0 +1,130 @@
+/*
+ * Renders the Cornell box through the C interface and saves it as a PPM.
+ * Build the library, then this, from the repository's root with
+ *
+ *     cargo build --release
+ *     cc -Iinclude examples/c/cornell.c -Ltarget/release -lpath_tracer_gpu \
+ *         -Wl,-rpath,target/release -o cornell
+ *     ./cornell examples/c/cornell.json cornell.ppm
+ *
+ * Ctrl+C stops the render early and still saves it.
+ */
+
+#include "path_tracer.h"
+
+#include <signal.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#define WIDTH 256
+#define HEIGHT 256
+#define SAMPLES 64
+#define DEPTH 5
+
+static PtCancel *cancel;
+
+static void on_interrupt(int sig)
+{
+    (void)sig;
+    pt_cancel(cancel);
+}
+
+static void on_progress(uint32_t samples, uint32_t total, void *user)
+{
+    fprintf(stderr, "\r%s: %u/%u samples", (const char *)user, samples, total);
+}
+
+static char *read_file(const char *path)
+{
+    FILE *f = fopen(path, "rb");
+    char *text;
+    long size;
+
+    if (!f)
+        return NULL;
+
+    fseek(f, 0, SEEK_END);
+    size = ftell(f);
+    fseek(f, 0, SEEK_SET);
+
+    text = malloc(size + 1);
+    if (text && fread(text, 1, size, f) == (size_t)size)
+        text[size] = '\0';
+    else
+    {
+        free(text);
+        text = NULL;
+    }
+
+    fclose(f);
+    return text;
+}
+
+/* clamps a colour to a byte, like the images path-tracer-gpu saves */
+static unsigned char to_byte(float c)
+{
+    c = c < 0.0f ? 0.0f : c > 1.0f ? 1.0f : c;
+    return (unsigned char)(c * 255.0f + 0.5f);
+}
+
+int main(int argc, char **argv)
+{
+    const char *scene_path = argc > 1 ? argv[1] : "examples/c/cornell.json";
+    const char *output = argc > 2 ? argv[2] : "cornell.ppm";
+    char *json, *err = NULL;
+    PtScene *scene;
+    float *pixels;
+    FILE *f;
+    int32_t code;
+    size_t i;
+
+    json = read_file(scene_path);
+    if (!json)
+    {
+        fprintf(stderr, "Could not read %s\n", scene_path);
+        return 1;
+    }
+
+    scene = pt_scene_parse(json, &err);
+    free(json);
+    if (!scene)
+    {
+        fprintf(stderr, "Could not parse %s: %s\n", scene_path, err);
+        pt_string_free(err);
+        return 1;
+    }
+
+    cancel = pt_cancel_new();
+    signal(SIGINT, on_interrupt);
+
+    code = pt_render_progressive(
+        scene, WIDTH, HEIGHT, SAMPLES, DEPTH, on_progress, (void *)scene_path, cancel, &pixels);
+    fprintf(stderr, "\n");
+
+    pt_cancel_free(cancel);
+    pt_scene_free(scene);
+
+    if (code != PT_OK)
+    {
+        fprintf(stderr, "Could not render %s: %s\n", scene_path, pt_last_error());
+        return 1;
+    }
+
+    f = fopen(output, "wb");
+    if (!f)
+    {
+        fprintf(stderr, "Could not write %s\n", output);
+        pt_buffer_free(pixels);
+        return 1;
+    }
+
+    fprintf(f, "P6\n%d %d\n255\n", WIDTH, HEIGHT);
+    for (i = 0; i < (size_t)WIDTH * HEIGHT * 3; i++)
+        fputc(to_byte(pixels[i]), f);
+
+    fclose(f);
+    pt_buffer_free(pixels);
+
+    fprintf(stderr, "Saved to %s\n", output);
+    return 0;
+}
@@ -0,0 +1,206 @@
+/* The C interface to the path tracer, generated by cbindgen from src/ffi.rs. */
+
+#ifndef PATH_TRACER_H
+#define PATH_TRACER_H
+
+/* Don't edit this file, run cbindgen again instead. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * It worked.
+ */
+#define PT_OK 0
+
+/**
+ * A pointer was null or a number was 0, see `pt_last_error`.
+ */
+#define PT_INVALID_ARGUMENT 1
+
+/**
+ * The render failed, see `pt_last_error`.
+ */
+#define PT_RENDER_FAILED 2
+
+/**
+ * The renderer panicked, which is a bug, see `pt_last_error`.
+ */
+#define PT_PANICKED 3
+
+/**
+ * Errors, for `pt_set_log`.
+ */
+#define PT_LOG_ERROR 0
+
+/**
+ * Warnings, for `pt_set_log`.
+ */
+#define PT_LOG_WARN 1
+
+/**
+ * Progress and summaries, for `pt_set_log`.
+ */
+#define PT_LOG_INFO 2
+
+/**
+ * Adapter details, buffer sizes and timings, for `pt_set_log`.
+ */
+#define PT_LOG_DEBUG 3
+
+/**
+ * Per-sample timings, for `pt_set_log`.
+ */
+#define PT_LOG_TRACE 4
+
+/**
+ * Stops a render from `pt_render_progressive` from another thread, see
+ * `pt_cancel`.
+ */
+typedef struct PtCancel PtCancel;
+
+/**
+ * A scene from `pt_scene_parse`.
+ */
+typedef struct PtScene PtScene;
+
+/**
+ * Called by `pt_render_progressive` with the samples finished so far, the
+ * samples asked for and the caller's `user` pointer, on the thread that
+ * called it.
+ */
+typedef void (*PtProgress)(uint32_t samples, uint32_t total, void *user);
+
+/**
+ * Called by the renderer with each message's level, one of the
+ * `PT_LOG_*`, the message and the `user` pointer given to `pt_set_log`,
+ * on whichever thread logged it.
+ */
+typedef void (*PtLog)(int32_t level, const char *message, void *user);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Parses a JSON scene. Returns null on failure, with the error in `*err`
+ * when `err` isn't null, which the caller frees with `pt_string_free`.
+ * Files the scene refers to are relative to the working directory.
+ *
+ * # Safety
+ *
+ * `json` must be a nul-terminated string and `err` null or writable.
+ */
+struct PtScene *pt_scene_parse(const char *json, char **err);
+
+/**
+ * Frees a scene from `pt_scene_parse`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `scene` must be from `pt_scene_parse` and not freed already.
+ */
+void pt_scene_free(struct PtScene *scene);
+
+/**
+ * Renders `scene` at `width` by `height` with `samples` samples per pixel
+ * and `depth` bounces, on the GPU or on the CPU if there isn't one. On
+ * success `*pixels_out` is `width * height` RGB colours as floats, from
+ * the top left, which the caller frees with `pt_buffer_free`.
+ *
+ * # Safety
+ *
+ * `scene` must be from `pt_scene_parse` and `pixels_out` writable.
+ */
+int32_t pt_render(const struct PtScene *scene,
+                  uint32_t width,
+                  uint32_t height,
+                  uint32_t samples,
+                  uint32_t depth,
+                  float **pixels_out);
+
+/**
+ * Like `pt_render`, calling `progress`, if it isn't null, after every
+ * sample and stopping early if `cancel`, if it isn't null, is cancelled.
+ * A cancelled render still succeeds with the samples it finished.
+ *
+ * # Safety
+ *
+ * As `pt_render`, and `cancel` must be null or from `pt_cancel_new`.
+ */
+int32_t pt_render_progressive(const struct PtScene *scene,
+                              uint32_t width,
+                              uint32_t height,
+                              uint32_t samples,
+                              uint32_t depth,
+                              PtProgress progress,
+                              void *user,
+                              const struct PtCancel *cancel,
+                              float **pixels_out);
+
+/**
+ * Frees pixels from `pt_render`. Null and pointers that weren't from it are
+ * ignored.
+ */
+void pt_buffer_free(float *pixels);
+
+/**
+ * A token for stopping renders, freed with `pt_cancel_free`.
+ */
+struct PtCancel *pt_cancel_new(void);
+
+/**
+ * Stops the renders using `cancel` after the sample they're on, and any
+ * started with it later. Safe to call from any thread.
+ *
+ * # Safety
+ *
+ * `cancel` must be from `pt_cancel_new` and not freed already.
+ */
+void pt_cancel(const struct PtCancel *cancel);
+
+/**
+ * Frees a token from `pt_cancel_new`, once no render is using it. Null is
+ * ignored.
+ *
+ * # Safety
+ *
+ * `cancel` must be from `pt_cancel_new` and not freed already.
+ */
+void pt_cancel_free(struct PtCancel *cancel);
+
+/**
+ * Sends the renderer's messages up to `level`, one of the `PT_LOG_*`, to
+ * `log`, or to stdout and stderr if it's null. Until this is called they
+ * go to stdout and stderr, up to `PT_LOG_INFO`.
+ *
+ * # Safety
+ *
+ * `log` must be safe to call with `user` from any thread until this is
+ * called again.
+ */
+int32_t pt_set_log(int32_t level, PtLog log, void *user);
+
+/**
+ * The message for the last error on this thread, valid until the next
+ * call that fails on it, or an empty string.
+ */
+const char *pt_last_error(void);
+
+/**
+ * Frees a string from `pt_scene_parse`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `s` must be from this library and not freed already.
+ */
+void pt_string_free(char *s);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* PATH_TRACER_H */
@@ -0,0 +1,1103 @@
+use crate::gpu::Camera;
+use crate::transform::Placement;
+use crate::vec3::{add, rotate, scale, sub};
+
+use json::JsonValue;
+
+#[derive(Clone, Debug)]
+pub struct Animation
+{
+    pub frames: u32,
+    pub fps: f32,
+    base: Camera,
+    pos: Option<[Track; 3]>,
+    look_at: Option<[Track; 3]>,
+    fov: Option<Track>,
+    keyframes: Vec<Keyframe>,
+    /// named nodes from the scene's "nodes" and how they move
+    nodes: Vec<(String, NodeTracks)>,
+    /// what positions are multiplied by, the scene's units along with its
+    /// own "scale"
+    scale: f32,
+    /// the object it was parsed from, for writing the scene back out
+    source: JsonValue,
+}
+
+/// A camera circling a point for a turntable, see `Scene::orbit`. It goes
+/// round once over `frames`, always looking at `center`.
+#[derive(Copy, Clone, Debug)]
+pub struct Orbit
+{
+    pub center: [f32; 3],
+    /// the distance from `center` across `up`
+    pub radius: f32,
+    /// how far above `center` along `up` the circle is
+    pub height: f32,
+    /// the axis the camera goes round, which is also its up
+    pub up: [f32; 3],
+    /// the direction from `center` to the first frame's camera, across `up`
+    pub start: [f32; 3],
+    /// in radians, like `Camera::fov`
+    pub fov: f32,
+    pub frames: u32,
+}
+
+impl Orbit
+{
+    /// The camera for a frame, starting at 1 like
+    /// `Animation::camera_at_frame`. The frames are evenly spaced in angle,
+    /// so the one after the last would be the first again.
+    pub fn camera_at_frame(&self, frame: u32) -> Camera
+    {
+        let turn = (frame as f64 - 1.0) / self.frames as f64;
+        let angle = (turn * std::f64::consts::TAU) as f32;
+
+        let dir = rotate(self.start, self.up, angle);
+        let pos = add(self.center, add(scale(dir, self.radius), scale(self.up, self.height)));
+
+        Camera
+        {
+            pos: pos,
+            front: normalize(sub(self.center, pos)),
+            up: self.up,
+            fov: self.fov,
+        }
+    }
+}
+
+/// A camera at a point in time (in seconds). Positions between keyframes are
+/// interpolated with a Catmull-Rom spline and directions spherically.
+#[derive(Copy, Clone, Debug)]
+struct Keyframe
+{
+    time: f32,
+    pos: [f32; 3],
+    front: [f32; 3],
+    up: [f32; 3],
+    fov: f32,
+}
+
+/// How a node's placement changes, with anything left out staying as the
+/// scene file has it. The angle (in degrees) is about `axis` if it's given,
+/// or else the node's own axis. Positions are in the parent node, so they're
+/// scaled along with it rather than by the animation's "scale".
+#[derive(Clone, Debug)]
+struct NodeTracks
+{
+    translate: Option<[Track; 3]>,
+    axis: Option<[f32; 3]>,
+    angle: Option<Track>,
+    scale: Option<Track>,
+}
+
+/// A single animated value, either keyframes (frame, value) interpolated
+/// linearly or an expression of `t` (0 to 1 over the animation) and `f`
+/// (the frame number, starting at 1).
+#[derive(Clone, Debug)]
+enum Track
+{
+    Keys(Vec<(f32, f32)>),
+    Expr(Expr),
+}
+
+impl Animation
+{
+    /// Parses an `"animation"` object, where `base` is the scene's camera,
+    /// used for anything the animation doesn't change, and `scale` is what
+    /// the scene's positions were multiplied by, see `SceneDef::scale`.
+    pub fn parse(val: &JsonValue, base: Camera, scale: f32) -> Result<Animation, String>
+    {
+        if !val.is_object()
+        {
+            return Err("\"animation\" entry in Scene wasn't an object".to_owned());
+        }
+
+        let frames = if val.has_key("frames")
+        {
+            match val["frames"].as_u32()
+            {
+                Some(frames) if frames > 0 => frames,
+                _ => return Err(
+                    "\"frames\" in \"animation\" wasn't a positive u32".to_owned()),
+            }
+        }
+        else
+        {
+            return Err("\"animation\" didn't contain \"frames\" u32".to_owned());
+        };
+
+        let fps = if val.has_key("fps")
+        {
+            match val["fps"].as_f32()
+            {
+                Some(fps) if fps > 0.0 => fps,
+                _ => return Err(
+                    "\"fps\" in \"animation\" wasn't a positive f32".to_owned()),
+            }
+        }
+        else
+        {
+            24.0
+        };
+
+        let scale = if val.has_key("scale")
+        {
+            match val["scale"].as_f32()
+            {
+                Some(s) if s > 0.0 => s * scale,
+                _ => return Err(
+                    "\"scale\" in \"animation\" wasn't a positive f32".to_owned()),
+            }
+        }
+        else
+        {
+            scale
+        };
+
+        let mut anim = Animation
+        {
+            frames: frames,
+            fps: fps,
+            base: base,
+            pos: None,
+            look_at: None,
+            fov: None,
+            keyframes: Vec::new(),
+            nodes: Vec::new(),
+            scale: scale,
+            source: val.clone(),
+        };
+
+        if val.has_key("camera")
+        {
+            let camera = &val["camera"];
+
+            if !camera.is_object()
+            {
+                return Err("\"camera\" in \"animation\" wasn't an object".to_owned());
+            }
+
+            for (name, track) in camera.entries()
+            {
+                match name
+                {
+                    "pos" => anim.pos = Some(parse_track3(track, name)?),
+                    "look_at" => anim.look_at = Some(parse_track3(track, name)?),
+                    "fov" => anim.fov = Some(parse_track(track, name)?),
+                    _ => return Err(format!(
+                        "Unknown entry \"{}\" in animation \"camera\"", name)),
+                }
+            }
+        }
+
+        if val.has_key("keyframes")
+        {
+            if val.has_key("camera")
+            {
+                return Err(
+                    "\"animation\" can't contain both \"camera\" and \"keyframes\""
+                    .to_owned());
+            }
+
+            anim.keyframes = parse_keyframes(&val["keyframes"], base, scale)?;
+        }
+
+        if val.has_key("nodes")
+        {
+            let nodes = &val["nodes"];
+
+            if !nodes.is_object()
+            {
+                return Err("\"nodes\" in \"animation\" wasn't an object".to_owned());
+            }
+
+            for (name, node) in nodes.entries()
+            {
+                anim.nodes.push((name.to_owned(), parse_node(node, name)?));
+            }
+        }
+
+        Ok(anim)
+    }
+
+    /// The animation as it was written, with the scale its positions were
+    /// multiplied by, for `Scene::to_json`.
+    pub fn to_json(&self) -> JsonValue
+    {
+        let mut source = self.source.clone();
+
+        // the rest of the scene is written in meters
+        if self.scale != 1.0
+        {
+            source["scale"] = self.scale.into();
+        }
+
+        source
+    }
+
+    /// Changes the camera used for anything the animation doesn't change.
+    pub fn set_base(&mut self, base: Camera)
+    {
+        self.base = base;
+    }
+
+    /// The camera for a frame, starting at 1.
+    pub fn camera_at_frame(&self, frame: u32) -> Camera
+    {
+        self.camera_at((frame - 1) as f32 / self.fps)
+    }
+
+    /// The nodes the animation moves, by name.
+    pub fn node_names(&self) -> impl Iterator<Item = &str>
+    {
+        self.nodes.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Where a node is in a frame, starting at 1, with anything the
+    /// animation doesn't change taken from `base`.
+    pub fn place_node(&self, name: &str, frame: u32, base: Placement) -> Placement
+    {
+        let tracks = match self.nodes.iter().find(|(n, _)| n == name)
+        {
+            Some((_, tracks)) => tracks,
+            None => return base,
+        };
+
+        let f = frame as f32;
+        let t = self.progress(f);
+
+        Placement
+        {
+            scale: tracks.scale.as_ref().map_or(base.scale, |s| s.eval(f, t)),
+            axis: tracks.axis.unwrap_or(base.axis),
+            angle: tracks.angle.as_ref().map_or(base.angle, |a| a.eval(f, t).to_radians()),
+            offset: match &tracks.translate
+            {
+                Some(translate) => [0, 1, 2].map(|i| translate[i].eval(f, t)),
+                None => base.offset,
+            },
+        }
+    }
+
+    /// How far frame `f` is through the animation, from 0 to 1.
+    fn progress(&self, f: f32) -> f32
+    {
+        if self.frames > 1
+        {
+            (f - 1.0) / (self.frames - 1) as f32
+        }
+        else
+        {
+            0.0
+        }
+    }
+
+    /// The camera at a time in seconds from the first frame, with anything
+    /// that isn't animated taken from the scene's camera.
+    pub fn camera_at(&self, time: f32) -> Camera
+    {
+        if !self.keyframes.is_empty()
+        {
+            return self.keyframe_camera(time);
+        }
+
+        let f = time * self.fps + 1.0;
+        let t = self.progress(f);
+
+        let mut camera = self.base;
+
+        if let Some(pos) = &self.pos
+        {
+            camera.pos = [pos[0].eval(f, t), pos[1].eval(f, t), pos[2].eval(f, t)];
+            camera.pos = camera.pos.map(|c| c * self.scale);
+        }
+
+        if let Some(look_at) = &self.look_at
+        {
+            let target = [
+                look_at[0].eval(f, t),
+                look_at[1].eval(f, t),
+                look_at[2].eval(f, t)].map(|c| c * self.scale);
+
+            camera.front = [
+                target[0] - camera.pos[0],
+                target[1] - camera.pos[1],
+                target[2] - camera.pos[2]];
+        }
+
+        if let Some(fov) = &self.fov
+        {
+            camera.fov = fov.eval(f, t).to_radians();
+        }
+
+        camera
+    }
+
+    fn keyframe_camera(&self, time: f32) -> Camera
+    {
+        let keys = &self.keyframes;
+
+        let last = keys.len() - 1;
+        if time <= keys[0].time
+        {
+            return keys[0].camera();
+        }
+        if time >= keys[last].time
+        {
+            return keys[last].camera();
+        }
+
+        // keys[i] <= time < keys[i + 1]
+        let i = keys.iter().rposition(|k| k.time <= time).unwrap();
+        let (k1, k2) = (&keys[i], &keys[i + 1]);
+        let k0 = &keys[i.saturating_sub(1)];
+        let k3 = &keys[(i + 2).min(last)];
+
+        let u = (time - k1.time) / (k2.time - k1.time);
+
+        let pos = |c: usize| catmull_rom(k0.pos[c], k1.pos[c], k2.pos[c], k3.pos[c], u);
+
+        Camera
+        {
+            pos: [pos(0), pos(1), pos(2)],
+            front: slerp(k1.front, k2.front, u),
+            up: slerp(k1.up, k2.up, u),
+            fov: k1.fov + (k2.fov - k1.fov) * u,
+        }
+    }
+}
+
+impl Keyframe
+{
+    fn camera(&self) -> Camera
+    {
+        Camera
+        {
+            pos: self.pos,
+            front: self.front,
+            up: self.up,
+            fov: self.fov,
+        }
+    }
+}
+
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, u: f32) -> f32
+{
+    let u2 = u * u;
+    let u3 = u2 * u;
+
+    0.5 * (2.0 * p1
+        + (p2 - p0) * u
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u2
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u3)
+}
+
+/// Spherical interpolation between two directions, which don't need to be
+/// normalized. The result is normalized.
+fn slerp(a: [f32; 3], b: [f32; 3], u: f32) -> [f32; 3]
+{
+    let a = normalize(a);
+    let b = normalize(b);
+
+    let cos = (a[0] * b[0] + a[1] * b[1] + a[2] * b[2]).clamp(-1.0, 1.0);
+    let angle = cos.acos();
+
+    // nearly parallel, where the slerp weights are unstable
+    if angle.sin().abs() < 1e-4
+    {
+        return normalize([
+            a[0] + (b[0] - a[0]) * u,
+            a[1] + (b[1] - a[1]) * u,
+            a[2] + (b[2] - a[2]) * u]);
+    }
+
+    let wa = ((1.0 - u) * angle).sin() / angle.sin();
+    let wb = (u * angle).sin() / angle.sin();
+
+    normalize([
+        a[0] * wa + b[0] * wb,
+        a[1] * wa + b[1] * wb,
+        a[2] * wa + b[2] * wb])
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3]
+{
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+fn parse_keyframes(val: &JsonValue, base: Camera, scale: f32)
+    -> Result<Vec<Keyframe>, String>
+{
+    if !val.is_array()
+    {
+        return Err("\"keyframes\" in \"animation\" wasn't an array".to_owned());
+    }
+
+    if val.len() < 2
+    {
+        return Err("\"keyframes\" needs at least two keyframes".to_owned());
+    }
+
+    let mut keys: Vec<Keyframe> = Vec::new();
+
+    for (i, key) in val.members().enumerate()
+    {
+        if !key.is_object()
+        {
+            return Err(format!("keyframe {} wasn't an object", i));
+        }
+
+        let time = key["time"].as_f32()
+            .ok_or(format!("keyframe {} didn't contain a \"time\" f32", i))?;
+
+        if let Some(last) = keys.last()
+        {
+            if time <= last.time
+            {
+                return Err(format!(
+                    "keyframe {} at time {} isn't after the previous keyframe at {}",
+                    i, time, last.time));
+            }
+        }
+
+        let vec3 = |name: &str| -> Result<Option<[f32; 3]>, String>
+        {
+            if !key.has_key(name)
+            {
+                return Ok(None);
+            }
+
+            let v = &key[name];
+            if !v.is_array() || v.len() != 3
+            {
+                return Err(format!(
+                    "\"{}\" in keyframe {} wasn't an array of 3 f32s", name, i));
+            }
+
+            match (v[0].as_f32(), v[1].as_f32(), v[2].as_f32())
+            {
+                (Some(x), Some(y), Some(z)) => Ok(Some([x, y, z])),
+                _ => Err(format!(
+                    "\"{}\" in keyframe {} wasn't an array of 3 f32s", name, i)),
+            }
+        };
+
+        let scaled = |name: &str| -> Result<Option<[f32; 3]>, String>
+        {
+            Ok(vec3(name)?.map(|v| v.map(|c| c * scale)))
+        };
+
+        let pos = scaled("pos")?.unwrap_or(base.pos);
+
+        let front = match (scaled("look_at")?, vec3("front")?)
+        {
+            (Some(_), Some(_)) => return Err(format!(
+                "keyframe {} can't have both \"look_at\" and \"front\"", i)),
+            (Some(at), None) => [at[0] - pos[0], at[1] - pos[1], at[2] - pos[2]],
+            (None, Some(front)) => front,
+            (None, None) => base.front,
+        };
+
+        if front == [0.0; 3]
+        {
+            return Err(format!("keyframe {} looks at its own position", i));
+        }
+
+        let fov = if key.has_key("fov")
+        {
+            key["fov"].as_f32()
+                .ok_or(format!("\"fov\" in keyframe {} wasn't an f32", i))?
+                .to_radians()
+        }
+        else
+        {
+            base.fov
+        };
+
+        keys.push(Keyframe
+        {
+            time: time,
+            pos: pos,
+            front: front,
+            up: vec3("up")?.unwrap_or(base.up),
+            fov: fov,
+        });
+    }
+
+    Ok(keys)
+}
+
+impl Track
+{
+    fn eval(&self, f: f32, t: f32) -> f32
+    {
+        match self
+        {
+            Track::Keys(keys) =>
+            {
+                let next = keys.iter().position(|&(frame, _)| frame > f);
+
+                match next
+                {
+                    Some(0) => keys[0].1,
+                    Some(i) =>
+                    {
+                        let (f0, v0) = keys[i - 1];
+                        let (f1, v1) = keys[i];
+
+                        v0 + (v1 - v0) * (f - f0) / (f1 - f0)
+                    },
+                    None => keys[keys.len() - 1].1,
+                }
+            },
+            Track::Expr(expr) => expr.eval(f, t),
+        }
+    }
+}
+
+/// Either `[[frame, value], ...]` or an expression string (or number).
+fn parse_track(val: &JsonValue, name: &str) -> Result<Track, String>
+{
+    if let Some(n) = val.as_f32()
+    {
+        return Ok(Track::Expr(Expr::Num(n)));
+    }
+
+    if let Some(s) = val.as_str()
+    {
+        return Expr::parse(s)
+            .map(Track::Expr)
+            .map_err(|e| format!("Bad expression for \"{}\": {}", name, e));
+    }
+
+    parse_keys(val, name, 1).map(|mut keys| Track::Keys(
+        keys.drain(..).map(|(frame, v)| (frame, v[0])).collect()))
+}
+
+/// Either `[[frame, [x, y, z]], ...]` or an array of 3 expressions.
+fn parse_track3(val: &JsonValue, name: &str) -> Result<[Track; 3], String>
+{
+    let is_keys = val.is_array()
+        && val.members().next().map(|k| k.is_array()).unwrap_or(false);
+
+    if is_keys
+    {
+        let keys = parse_keys(val, name, 3)?;
+        let track = |i: usize| Track::Keys(
+            keys.iter().map(|&(frame, ref v)| (frame, v[i])).collect());
+
+        return Ok([track(0), track(1), track(2)]);
+    }
+
+    if !val.is_array() || val.len() != 3
+    {
+        return Err(format!(
+            "\"{}\" in animation wasn't keyframes or an array of 3 expressions",
+            name));
+    }
+
+    Ok([
+        parse_track(&val[0], name)?,
+        parse_track(&val[1], name)?,
+        parse_track(&val[2], name)?])
+}
+
+/// An entry in the animation's "nodes", see `NodeTracks`.
+fn parse_node(val: &JsonValue, node: &str) -> Result<NodeTracks, String>
+{
+    if !val.is_object()
+    {
+        return Err(format!("\"{}\" in animation \"nodes\" wasn't an object", node));
+    }
+
+    let mut tracks = NodeTracks
+    {
+        translate: None,
+        axis: None,
+        angle: None,
+        scale: None,
+    };
+
+    for (name, track) in val.entries()
+    {
+        match name
+        {
+            "translate" => tracks.translate = Some(parse_track3(track, name)?),
+            "angle" => tracks.angle = Some(parse_track(track, name)?),
+            "scale" => tracks.scale = Some(parse_track(track, name)?),
+            "axis" =>
+            {
+                let axis = track.members().map(|c| c.as_f32()).collect::<Option<Vec<_>>>();
+                let axis = match axis.as_deref()
+                {
+                    Some(&[x, y, z]) => [x, y, z],
+                    _ => return Err(format!(
+                        "\"axis\" of node \"{}\" in animation wasn't an array of 3 f32s",
+                        node)),
+                };
+
+                if axis == [0.0; 3]
+                {
+                    return Err(format!(
+                        "\"axis\" of node \"{}\" in animation is zero", node));
+                }
+
+                tracks.axis = Some(normalize(axis));
+            },
+            _ => return Err(format!(
+                "Unknown entry \"{}\" in animation node \"{}\"", name, node)),
+        }
+    }
+
+    Ok(tracks)
+}
+
+fn parse_keys(val: &JsonValue, name: &str, len: usize)
+    -> Result<Vec<(f32, Vec<f32>)>, String>
+{
+    if !val.is_array() || val.is_empty()
+    {
+        return Err(format!("\"{}\" in animation wasn't an array of keyframes", name));
+    }
+
+    let mut keys: Vec<(f32, Vec<f32>)> = Vec::new();
+
+    for key in val.members()
+    {
+        if !key.is_array() || key.len() != 2
+        {
+            return Err(format!(
+                "keyframe in \"{}\" wasn't a [frame, value] pair", name));
+        }
+
+        let frame = key[0].as_f32().ok_or(format!(
+            "keyframe frame in \"{}\" wasn't an f32", name))?;
+
+        let value = if len == 1
+        {
+            vec![key[1].as_f32().ok_or(format!(
+                "keyframe value in \"{}\" wasn't an f32", name))?]
+        }
+        else
+        {
+            if !key[1].is_array() || key[1].len() != len
+            {
+                return Err(format!(
+                    "keyframe value in \"{}\" didn't have a length of {}", name, len));
+            }
+
+            key[1].members()
+                .map(|v| v.as_f32())
+                .collect::<Option<Vec<f32>>>()
+                .ok_or(format!("keyframe value in \"{}\" wasn't all f32s", name))?
+        };
+
+        if let Some(&(last, _)) = keys.last()
+        {
+            if frame <= last
+            {
+                return Err(format!(
+                    "keyframes in \"{}\" aren't in increasing frame order", name));
+            }
+        }
+
+        keys.push((frame, value));
+    }
+
+    Ok(keys)
+}
+
+/// A small arithmetic expression: numbers, `t`, `f`, `pi`, + - * / ^,
+/// parentheses and the functions sin, cos, tan, sqrt and abs.
+#[derive(Clone, Debug)]
+enum Expr
+{
+    Num(f32),
+    T,
+    F,
+    Neg(Box<Expr>),
+    Op(char, Box<Expr>, Box<Expr>),
+    Call(fn(f32) -> f32, Box<Expr>),
+}
+
+impl Expr
+{
+    fn parse(s: &str) -> Result<Expr, String>
+    {
+        let tokens = tokenize(s)?;
+        let mut pos = 0;
+
+        let expr = parse_sum(&tokens, &mut pos)?;
+
+        if pos != tokens.len()
+        {
+            return Err(format!("unexpected \"{}\"", tokens[pos]));
+        }
+
+        Ok(expr)
+    }
+
+    fn eval(&self, f: f32, t: f32) -> f32
+    {
+        match self
+        {
+            Expr::Num(n) => *n,
+            Expr::T => t,
+            Expr::F => f,
+            Expr::Neg(e) => -e.eval(f, t),
+            Expr::Op(op, a, b) =>
+            {
+                let (a, b) = (a.eval(f, t), b.eval(f, t));
+
+                match op
+                {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    '^' => a.powf(b),
+                    _ => unreachable!(),
+                }
+            },
+            Expr::Call(func, e) => func(e.eval(f, t)),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<String>, String>
+{
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek()
+    {
+        if c.is_whitespace()
+        {
+            chars.next();
+        }
+        else if c.is_ascii_digit() || c == '.'
+        {
+            let mut num = String::new();
+            while let Some(&c) = chars.peek()
+            {
+                if !(c.is_ascii_digit() || c == '.')
+                {
+                    break;
+                }
+
+                num.push(c);
+                chars.next();
+            }
+            tokens.push(num);
+        }
+        else if c.is_ascii_alphabetic()
+        {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek()
+            {
+                if !c.is_ascii_alphanumeric()
+                {
+                    break;
+                }
+
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(name);
+        }
+        else if "+-*/^()".contains(c)
+        {
+            tokens.push(c.to_string());
+            chars.next();
+        }
+        else
+        {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_sum(tokens: &[String], pos: &mut usize) -> Result<Expr, String>
+{
+    let mut expr = parse_product(tokens, pos)?;
+
+    while *pos < tokens.len() && (tokens[*pos] == "+" || tokens[*pos] == "-")
+    {
+        let op = tokens[*pos].chars().next().unwrap();
+        *pos += 1;
+
+        expr = Expr::Op(op, Box::new(expr), Box::new(parse_product(tokens, pos)?));
+    }
+
+    Ok(expr)
+}
+
+fn parse_product(tokens: &[String], pos: &mut usize) -> Result<Expr, String>
+{
+    let mut expr = parse_unary(tokens, pos)?;
+
+    while *pos < tokens.len() && (tokens[*pos] == "*" || tokens[*pos] == "/")
+    {
+        let op = tokens[*pos].chars().next().unwrap();
+        *pos += 1;
+
+        expr = Expr::Op(op, Box::new(expr), Box::new(parse_unary(tokens, pos)?));
+    }
+
+    Ok(expr)
+}
+
+fn parse_unary(tokens: &[String], pos: &mut usize) -> Result<Expr, String>
+{
+    if *pos < tokens.len() && tokens[*pos] == "-"
+    {
+        *pos += 1;
+
+        return Ok(Expr::Neg(Box::new(parse_unary(tokens, pos)?)));
+    }
+
+    let base = parse_atom(tokens, pos)?;
+
+    // right associative, and binds tighter than unary minus
+    if *pos < tokens.len() && tokens[*pos] == "^"
+    {
+        *pos += 1;
+
+        return Ok(Expr::Op('^', Box::new(base), Box::new(parse_unary(tokens, pos)?)));
+    }
+
+    Ok(base)
+}
+
+fn parse_atom(tokens: &[String], pos: &mut usize) -> Result<Expr, String>
+{
+    let token = tokens.get(*pos).ok_or("unexpected end of expression")?;
+    *pos += 1;
+
+    if token == "("
+    {
+        let expr = parse_sum(tokens, pos)?;
+
+        if tokens.get(*pos).map(|t| t.as_str()) != Some(")")
+        {
+            return Err("missing \")\"".to_owned());
+        }
+        *pos += 1;
+
+        return Ok(expr);
+    }
+
+    if let Ok(n) = token.parse::<f32>()
+    {
+        return Ok(Expr::Num(n));
+    }
+
+    let func: fn(f32) -> f32 = match token.as_str()
+    {
+        "t" => return Ok(Expr::T),
+        "f" => return Ok(Expr::F),
+        "pi" => return Ok(Expr::Num(std::f32::consts::PI)),
+        "sin" => f32::sin,
+        "cos" => f32::cos,
+        "tan" => f32::tan,
+        "sqrt" => f32::sqrt,
+        "abs" => f32::abs,
+        _ => return Err(format!("unknown name \"{}\"", token)),
+    };
+
+    if tokens.get(*pos).map(|t| t.as_str()) != Some("(")
+    {
+        return Err(format!("expected \"(\" after \"{}\"", token));
+    }
+
+    Ok(Expr::Call(func, Box::new(parse_atom(tokens, pos)?)))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::vec3::{cross, dot, length};
+
+    fn close(a: [f32; 3], b: [f32; 3]) -> bool
+    {
+        length(sub(a, b)) < 1e-4
+    }
+
+    fn orbit(frames: u32) -> Orbit
+    {
+        Orbit
+        {
+            center: [1.0, 2.0, 3.0],
+            radius: 4.0,
+            height: 1.5,
+            up: [0.0, 1.0, 0.0],
+            start: [0.0, 0.0, -1.0],
+            fov: 0.8,
+            frames: frames,
+        }
+    }
+
+    /// Parses an animation made of `keys`, over a camera at the origin
+    /// looking down -z.
+    fn keyframed(keys: &str) -> Result<Animation, String>
+    {
+        let base = Camera
+        {
+            pos: [0.0; 3],
+            front: [0.0, 0.0, -1.0],
+            up: [0.0, 1.0, 0.0],
+            fov: 1.0,
+        };
+
+        let val = json::parse(&format!(r#"{{ "frames": 72, "fps": 24, "keyframes": [{}] }}"#, keys))
+            .unwrap();
+        Animation::parse(&val, base, 1.0)
+    }
+
+    #[test]
+    fn keyframes_are_interpolated_smoothly()
+    {
+        let anim = keyframed(r#"
+            { "time": 0, "pos": [0, 0, 0], "front": [0, 0, -1], "fov": 40 },
+            { "time": 1, "pos": [1, 2, 0], "front": [1, 0, 0], "fov": 60 },
+            { "time": 2, "pos": [3, 1, 0], "front": [0, 0, 1] },
+            { "time": 3, "pos": [4, 4, 0], "front": [0, 0, 1] }"#).unwrap();
+
+        // every keyframe is passed through exactly
+        let first = anim.camera_at(0.0);
+        assert!(close(first.pos, [0.0, 0.0, 0.0]));
+        assert!(close(anim.camera_at(1.0).pos, [1.0, 2.0, 0.0]));
+        assert!(close(anim.camera_at(2.0).pos, [3.0, 1.0, 0.0]));
+        assert!(close(anim.camera_at(3.0).pos, [4.0, 4.0, 0.0]));
+        assert!((first.fov - 40f32.to_radians()).abs() < 1e-6);
+        assert_eq!(anim.camera_at_frame(25).pos, anim.camera_at(1.0).pos);
+
+        // and it stays on the ends outside them
+        assert_eq!(anim.camera_at(-1.0).pos, first.pos);
+        assert!(close(anim.camera_at(5.0).pos, [4.0, 4.0, 0.0]));
+
+        // no corner at a keyframe, where linear steps would turn sharply
+        let h = 1e-3;
+        let before = scale(sub(anim.camera_at(1.0).pos, anim.camera_at(1.0 - h).pos), 1.0 / h);
+        let after = scale(sub(anim.camera_at(1.0 + h).pos, anim.camera_at(1.0).pos), 1.0 / h);
+        assert!(length(sub(before, after)) < 0.05, "{:?} then {:?}", before, after);
+
+        // directions turn at an even rate and stay unit length, fov is linear
+        let half = anim.camera_at(0.5);
+        assert!(close(half.front, normalize([1.0, 0.0, -1.0])));
+        assert!((anim.camera_at(0.25).front[0] - (std::f32::consts::PI / 8.0).sin()).abs() < 1e-4);
+        assert!((half.fov - 50f32.to_radians()).abs() < 1e-5);
+        for i in 0..=30
+        {
+            assert!((length(anim.camera_at(i as f32 * 0.1).front) - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn evenly_spaced_keyframes_move_steadily()
+    {
+        let anim = keyframed(r#"
+            { "time": 0, "pos": [0, 0, 0] },
+            { "time": 1, "pos": [1, 0, 0] },
+            { "time": 2, "pos": [2, 0, 0] },
+            { "time": 3, "pos": [3, 0, 0] }"#).unwrap();
+
+        for i in 0..=10
+        {
+            let t = 1.0 + i as f32 * 0.1;
+            assert!(close(anim.camera_at(t).pos, [t, 0.0, 0.0]), "at {}", t);
+        }
+    }
+
+    #[test]
+    fn keyframes_are_checked()
+    {
+        let err = keyframed(r#"{ "time": 0, "pos": [0, 0, 0] }"#).unwrap_err();
+        assert!(err.contains("at least two"), "{}", err);
+
+        let err = keyframed(r#"
+            { "time": 1, "pos": [0, 0, 0] },
+            { "time": 0, "pos": [1, 0, 0] }"#).unwrap_err();
+        assert!(err.contains("isn't after"), "{}", err);
+
+        let err = keyframed(r#"
+            { "time": 0, "pos": [0, 0, 0] },
+            { "time": 0, "pos": [1, 0, 0] }"#).unwrap_err();
+        assert!(err.contains("isn't after"), "{}", err);
+
+        assert!(keyframed(r#"
+            { "time": 0, "pos": [0, 0, 0], "look_at": [0, 0, 0] },
+            { "time": 1, "pos": [1, 0, 0] }"#).is_err());
+    }
+
+    #[test]
+    fn orbit_comes_back_round()
+    {
+        for frames in [1, 2, 7, 36, 360]
+        {
+            let orbit = orbit(frames);
+            let first = orbit.camera_at_frame(1);
+
+            assert!(close(orbit.camera_at_frame(frames + 1).pos, first.pos), "{} frames", frames);
+            // frame 0 is a whole turn before frame N
+            assert!(close(orbit.camera_at_frame(0).pos, orbit.camera_at_frame(frames).pos));
+            assert!(close(first.pos, [1.0, 3.5, -1.0]));
+        }
+    }
+
+    #[test]
+    fn orbit_frames_are_evenly_spaced()
+    {
+        let orbit = orbit(12);
+        let step = std::f32::consts::TAU / 12.0;
+
+        // the direction from the center across up, and the signed angle
+        // between two of them
+        let across = |frame: u32|
+        {
+            let c = orbit.camera_at_frame(frame);
+            normalize(sub(sub(c.pos, orbit.center), scale(orbit.up, orbit.height)))
+        };
+        let angle = |a: [f32; 3], b: [f32; 3]| dot(cross(a, b), orbit.up).atan2(dot(a, b));
+
+        for frame in 1..=12
+        {
+            let c = orbit.camera_at_frame(frame);
+            let offset = sub(c.pos, orbit.center);
+
+            assert!((dot(offset, orbit.up) - orbit.height).abs() < 1e-4);
+            assert!((length(sub(offset, scale(orbit.up, orbit.height))) - orbit.radius).abs() < 1e-4);
+            assert!(close(c.front, normalize(sub(orbit.center, c.pos))));
+            assert_eq!(c.up, orbit.up);
+            assert_eq!(c.fov, orbit.fov);
+
+            let turned = angle(across(frame), across(frame + 1));
+            assert!((turned - step).abs() < 1e-4, "frame {} turns {}", frame, turned);
+        }
+    }
+
+    #[test]
+    fn scene_orbit_starts_at_the_camera()
+    {
+        let scene = crate::builtin_scene("cornell").unwrap();
+        let orbit = scene.orbit(24, None, None, None, 1.0).unwrap();
+        let (min, max) = scene.bounds().unwrap();
+
+        assert!(close(orbit.center, scale(add(min, max), 0.5)));
+        assert_eq!(orbit.frames, 24);
+
+        // the first frame is the same way round from the center as the
+        // scene's camera
+        let camera = scene.camera;
+        let flat = |v: [f32; 3]| normalize(sub(v, scale(orbit.up, dot(v, orbit.up))));
+        let first = orbit.camera_at_frame(1);
+        assert!(close(flat(sub(first.pos, orbit.center)), flat(sub(camera.pos, orbit.center))));
+
+        assert!(scene.orbit(0, None, None, None, 1.0).is_err());
+    }
+}
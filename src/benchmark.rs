@@ -0,0 +1,258 @@
+use crate::gpu::{GpuProfile, Timings};
+
+use std::time::Duration;
+
+/// The results of `Scene::benchmark`. Samples from the warm-up are left out
+/// of the rates.
+#[derive(Clone, Debug)]
+pub struct Benchmark
+{
+    pub device: String,
+    pub res: [u32; 2],
+    /// the most rays a path can cast, the depth when path tracing
+    pub rays_per_path: u32,
+    /// creating the buffers, before the first sample
+    pub setup: Duration,
+    pub warmup_samples: u32,
+    /// samples after the warm-up
+    pub samples: u32,
+    /// time spent on `samples`
+    pub measured: Duration,
+    pub samples_per_sec: f64,
+    /// counting every path as `rays_per_path` rays, though most end sooner,
+    /// so it's an upper bound rather than the rays actually cast
+    pub max_rays_per_sec: f64,
+    /// `measured` over `samples`
+    pub ms_per_sample: f64,
+    /// of the samples' own times, which the benchmark waits for the GPU to
+    /// finish one at a time to measure
+    pub ms_std_dev: f64,
+    /// with `RenderSettings::profile_gpu`, over every sample including the
+    /// warm-up
+    pub profile: Option<GpuProfile>,
+}
+
+impl Benchmark
+{
+    /// Splits the samples in `timings` after `warmup` and works out the rates
+    /// from the rest. `pixels` is how many pixels each sample renders.
+    pub(crate) fn new(
+        device: &str,
+        res: [u32; 2],
+        rays_per_path: u32,
+        pixels: u64,
+        timings: &Timings,
+        warmup: Duration)
+        -> Result<Benchmark, String>
+    {
+        let mut warmup_samples = 0;
+        let mut elapsed = Duration::from_secs(0);
+
+        for t in &timings.samples
+        {
+            elapsed += *t;
+
+            if elapsed > warmup
+            {
+                break;
+            }
+
+            warmup_samples += 1;
+        }
+
+        let measured = &timings.samples[warmup_samples..];
+
+        if measured.is_empty()
+        {
+            return Err(format!(
+                "No samples finished after the {:.1}s warm-up",
+                warmup.as_secs_f64()));
+        }
+
+        let n = measured.len() as f64;
+        let total = measured.iter().sum::<Duration>();
+        let mean = total.as_secs_f64() * 1000.0 / n;
+        let ms = measured.iter()
+            .map(|t| t.as_secs_f64() * 1000.0)
+            .collect::<Vec<_>>();
+        let variance = if ms.len() > 1
+        {
+            ms.iter().map(|t| (t - mean) * (t - mean)).sum::<f64>() / (n - 1.0)
+        }
+        else
+        {
+            0.0
+        };
+
+        let samples_per_sec = n / total.as_secs_f64();
+
+        Ok(Benchmark
+        {
+            device: device.to_owned(),
+            res: res,
+            rays_per_path: rays_per_path,
+            setup: timings.setup,
+            warmup_samples: warmup_samples as u32,
+            samples: measured.len() as u32,
+            measured: total,
+            samples_per_sec: samples_per_sec,
+            max_rays_per_sec: samples_per_sec * pixels as f64 * rays_per_path as f64,
+            ms_per_sample: mean,
+            ms_std_dev: variance.sqrt(),
+            profile: timings.profile.clone(),
+        })
+    }
+
+    /// The results as one line of JSON, for comparing runs in scripts.
+    pub fn to_json(&self) -> String
+    {
+        json::object!
+        {
+            "device": self.device.as_str(),
+            "width": self.res[0],
+            "height": self.res[1],
+            "rays_per_path": self.rays_per_path,
+            "setup_ms": self.setup.as_secs_f64() * 1000.0,
+            "warmup_samples": self.warmup_samples,
+            "samples": self.samples,
+            "measured_s": self.measured.as_secs_f64(),
+            "samples_per_sec": self.samples_per_sec,
+            "max_rays_per_sec": self.max_rays_per_sec,
+            "ms_per_sample": self.ms_per_sample,
+            "ms_std_dev": self.ms_std_dev,
+            "gpu_profile": self.profile.as_ref().map(GpuProfile::to_json),
+        }.dump()
+    }
+}
+
+/// The results of `Scene::estimate`, from a short probe render at a lower
+/// resolution. The time for a sample is taken to grow with the pixels.
+#[derive(Clone, Debug)]
+pub struct Estimate
+{
+    /// the resolution being estimated
+    pub res: [u32; 2],
+    pub probe_res: [u32; 2],
+    /// samples timed in the probe, after the first
+    pub probe_samples: u32,
+    pub probe_time: Duration,
+    /// the probe's rate
+    pub probe_samples_per_sec: f64,
+    /// the rate expected at `res`
+    pub samples_per_sec: f64,
+    /// creating the buffers, which isn't scaled
+    pub setup: Duration,
+}
+
+impl Estimate
+{
+    /// The first sample is left out, as it can include compiling the
+    /// pipeline. `pixels` and `probe_pixels` are how many pixels each sample
+    /// renders at each resolution.
+    pub(crate) fn new(
+        res: [u32; 2],
+        probe_res: [u32; 2],
+        pixels: u64,
+        probe_pixels: u64,
+        timings: &Timings)
+        -> Result<Estimate, String>
+    {
+        let measured = timings.samples.get(1..).unwrap_or(&[]);
+
+        if measured.is_empty()
+        {
+            return Err("The probe render didn't finish enough samples to time".to_owned());
+        }
+
+        let total = measured.iter().sum::<Duration>();
+        let rate = measured.len() as f64 / total.as_secs_f64();
+
+        Ok(Estimate
+        {
+            res: res,
+            probe_res: probe_res,
+            probe_samples: measured.len() as u32,
+            probe_time: total,
+            probe_samples_per_sec: rate,
+            samples_per_sec: rate * probe_pixels as f64 / pixels as f64,
+            setup: timings.setup,
+        })
+    }
+
+    /// How long `samples` samples should take at the full resolution.
+    pub fn time_for(&self, samples: u32) -> Duration
+    {
+        self.setup + Duration::from_secs_f64(samples as f64 / self.samples_per_sec)
+    }
+
+    /// How many samples should fit in `time` at the full resolution.
+    pub fn samples_in(&self, time: Duration) -> u32
+    {
+        let time = time.saturating_sub(self.setup);
+
+        (time.as_secs_f64() * self.samples_per_sec).min(u32::MAX as f64) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn ms(ms: u64) -> Duration
+    {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn rates_leave_out_the_warmup()
+    {
+        let timings = Timings
+        {
+            samples: vec![ms(40), ms(40), ms(10), ms(30), ms(20)],
+            .. Timings::default()
+        };
+
+        let bench = Benchmark::new("test", [10, 10], 5, 100, &timings, ms(80)).unwrap();
+
+        assert_eq!(bench.warmup_samples, 2);
+        assert_eq!(bench.samples, 3);
+        assert_eq!(bench.measured, ms(60));
+        assert!((bench.ms_per_sample - 20.0).abs() < 1e-9);
+        assert!((bench.ms_std_dev - 10.0).abs() < 1e-9);
+        assert!((bench.samples_per_sec - 50.0).abs() < 1e-9);
+        assert!((bench.max_rays_per_sec - 50.0 * 100.0 * 5.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn estimates_from_the_probe()
+    {
+        let timings = Timings
+        {
+            setup: ms(500),
+            samples: vec![ms(1000), ms(10), ms(10)],
+            .. Timings::default()
+        };
+
+        // the probe has a quarter of the pixels
+        let estimate = Estimate::new([200, 200], [100, 100], 40000, 10000, &timings).unwrap();
+
+        assert_eq!(estimate.probe_samples, 2);
+        assert!((estimate.probe_samples_per_sec - 100.0).abs() < 1e-9);
+        assert!((estimate.samples_per_sec - 25.0).abs() < 1e-9);
+        assert_eq!(estimate.time_for(50), ms(2500));
+        assert_eq!(estimate.samples_in(ms(4500)), 100);
+    }
+
+    #[test]
+    fn fails_without_samples_after_the_warmup()
+    {
+        let timings = Timings
+        {
+            samples: vec![ms(40), ms(40)],
+            .. Timings::default()
+        };
+
+        assert!(Benchmark::new("test", [10, 10], 5, 100, &timings, ms(100)).is_err());
+    }
+}
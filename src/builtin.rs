@@ -0,0 +1,169 @@
+//! Scenes built into the renderer, for trying it out and for comparing
+//! renders in bug reports. They're made with `Scene`'s methods, so they're
+//! also examples of building scenes in code.
+
+use crate::gpu::Material;
+use crate::scene::Scene;
+
+/// The built-in scenes' names, with what each one is.
+pub const BUILTIN_SCENES: [(&str, &str); 4] = [
+    ("cornell", "The Cornell box, with the original's dimensions, materials and light"),
+    ("spheres", "Matte, glossy and mirror spheres on a floor under a square light"),
+    ("furnace", "A white sphere inside a glowing box, which should disappear into it"),
+    ("mirror-box", "A light and a matte sphere in a box of mirrors, for long paths"),
+];
+
+/// The built-in scene called `name`, see `BUILTIN_SCENES`.
+pub fn builtin_scene(name: &str) -> Option<Scene>
+{
+    Some(match name
+    {
+        "cornell" => cornell(),
+        "spheres" => spheres(),
+        "furnace" => furnace(),
+        "mirror-box" => mirror_box(),
+        _ => return None,
+    })
+}
+
+/// A material that scatters `colour` of the light in every direction.
+fn matte(colour: [f32; 3]) -> Material
+{
+    Material::new(colour, [0.0, 0.0, 0.0], 0.0, [1.0, 1.0, 1.0])
+}
+
+/// A material that reflects `gloss` of the light like a mirror, tinted by
+/// `reflect_c`, and scatters the rest like `matte`.
+fn glossy(colour: [f32; 3], gloss: f32, reflect_c: [f32; 3]) -> Material
+{
+    Material::new(colour, [0.0, 0.0, 0.0], gloss, reflect_c)
+}
+
+/// A black material glowing `glow`, from its front only if `one_sided`.
+fn light(glow: [f32; 3], one_sided: bool) -> Material
+{
+    let mut mat = Material::new([0.0, 0.0, 0.0], glow, 0.0, [1.0, 1.0, 1.0]);
+    if one_sided
+    {
+        mat.flags = Material::ONE_SIDED;
+    }
+
+    mat
+}
+
+/// The six sides of the box from `min` to `max`, the bottom and top first.
+fn add_box(scene: &mut Scene, min: [f32; 3], max: [f32; 3], mats: [u32; 6])
+{
+    let [x0, y0, z0] = min;
+    let [x1, y1, z1] = max;
+
+    scene
+        .add_quad([x0, y0, z0], [x1, y0, z0], [x1, y0, z1], [x0, y0, z1], mats[0])
+        .add_quad([x0, y1, z0], [x0, y1, z1], [x1, y1, z1], [x1, y1, z0], mats[1])
+        .add_quad([x0, y0, z0], [x0, y1, z0], [x1, y1, z0], [x1, y0, z0], mats[2])
+        .add_quad([x0, y0, z1], [x1, y0, z1], [x1, y1, z1], [x0, y1, z1], mats[3])
+        .add_quad([x0, y0, z0], [x0, y0, z1], [x0, y1, z1], [x0, y1, z0], mats[4])
+        .add_quad([x1, y0, z0], [x1, y1, z0], [x1, y1, z1], [x1, y0, z1], mats[5]);
+}
+
+/// The measurements of the box at Cornell, in millimetres, with the usual
+/// RGB versions of its reflectances and light.
+fn cornell() -> Scene
+{
+    // a 35mm lens on a 25mm square of film
+    let mut scene = Scene::new(
+        [278.0, 273.0, -800.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 39.3f32.to_radians());
+
+    let white = scene.add_material(matte([0.725, 0.71, 0.68]));
+    let red = scene.add_material(matte([0.63, 0.065, 0.05]));
+    let green = scene.add_material(matte([0.14, 0.45, 0.091]));
+    let lamp = scene.add_material(light([17.0, 12.0, 4.0], true));
+
+    scene
+        // floor, ceiling and back wall
+        .add_quad([552.8, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 559.2], [549.6, 0.0, 559.2], white)
+        .add_quad([556.0, 548.8, 0.0], [556.0, 548.8, 559.2], [0.0, 548.8, 559.2], [0.0, 548.8, 0.0], white)
+        .add_quad([549.6, 0.0, 559.2], [0.0, 0.0, 559.2], [0.0, 548.8, 559.2], [556.0, 548.8, 559.2], white)
+        // the right wall, then the left, which isn't quite flat
+        .add_quad([0.0, 0.0, 559.2], [0.0, 0.0, 0.0], [0.0, 548.8, 0.0], [0.0, 548.8, 559.2], green)
+        .add_triangle([552.8, 0.0, 0.0], [549.6, 0.0, 559.2], [556.0, 548.8, 559.2], red)
+        .add_triangle([552.8, 0.0, 0.0], [556.0, 548.8, 559.2], [556.0, 548.8, 0.0], red)
+        // facing down, just under the ceiling
+        .add_quad([343.0, 548.7, 227.0], [343.0, 548.7, 332.0], [213.0, 548.7, 332.0], [213.0, 548.7, 227.0], lamp);
+
+    // the short block, then the tall one, tops first
+    let blocks: [[[f32; 3]; 4]; 2] = [
+        [[130.0, 165.0, 65.0], [82.0, 165.0, 225.0], [240.0, 165.0, 272.0], [290.0, 165.0, 114.0]],
+        [[423.0, 330.0, 247.0], [265.0, 330.0, 296.0], [314.0, 330.0, 456.0], [472.0, 330.0, 406.0]],
+    ];
+
+    for top in blocks
+    {
+        scene.add_quad(top[0], top[1], top[2], top[3], white);
+
+        for i in 0..4
+        {
+            let (a, b) = (top[i], top[(i + 1) % 4]);
+            scene.add_quad([a[0], 0.0, a[2]], a, b, [b[0], 0.0, b[2]], white);
+        }
+    }
+
+    scene
+}
+
+fn spheres() -> Scene
+{
+    let mut scene = Scene::new(
+        [0.0, 2.0, -7.0], [0.0, -0.15, 1.0], [0.0, 1.0, 0.0], 50.0f32.to_radians());
+
+    let floor = scene.add_material(matte([0.8, 0.8, 0.8]));
+    let red = scene.add_material(matte([0.8, 0.2, 0.2]));
+    let green = scene.add_material(glossy([0.2, 0.6, 0.2], 0.3, [1.0, 1.0, 1.0]));
+    let mirror = scene.add_material(glossy([0.0, 0.0, 0.0], 1.0, [0.95, 0.95, 0.95]));
+    let lamp = scene.add_material(light([8.0, 7.5, 7.0], false));
+
+    scene
+        .add_quad([-10.0, 0.0, -10.0], [-10.0, 0.0, 10.0], [10.0, 0.0, 10.0], [10.0, 0.0, -10.0], floor)
+        .add_quad([-1.5, 5.0, -1.5], [1.5, 5.0, -1.5], [1.5, 5.0, 1.5], [-1.5, 5.0, 1.5], lamp)
+        .add_sphere_mesh([-2.2, 1.0, 0.0], 1.0, 3, red)
+        .add_sphere_mesh([0.0, 1.0, 0.0], 1.0, 3, green)
+        .add_sphere_mesh([2.2, 1.0, 0.0], 1.0, 3, mirror);
+
+    scene
+}
+
+/// Every ray ends on the box, which glows the same everywhere, and the
+/// sphere reflects all of the light, so the image should be one flat
+/// colour. Anything else is a bias in the renderer.
+fn furnace() -> Scene
+{
+    let mut scene = Scene::new(
+        [0.0, 0.0, -3.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 60.0f32.to_radians());
+
+    let glow = scene.add_material(light([0.5, 0.5, 0.5], false));
+    let white = scene.add_material(matte([1.0, 1.0, 1.0]));
+
+    add_box(&mut scene, [-5.0, -5.0, -5.0], [5.0, 5.0, 5.0], [glow; 6]);
+    scene.add_sphere_mesh([0.0, 0.0, 0.0], 1.0, 3, white);
+
+    scene
+}
+
+fn mirror_box() -> Scene
+{
+    let mut scene = Scene::new(
+        [0.0, 1.0, -1.8], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 70.0f32.to_radians());
+
+    let mirror = scene.add_material(glossy([0.0, 0.0, 0.0], 1.0, [0.9, 0.9, 0.9]));
+    let floor = scene.add_material(matte([0.7, 0.7, 0.7]));
+    let white = scene.add_material(matte([0.8, 0.8, 0.8]));
+    let lamp = scene.add_material(light([10.0, 10.0, 10.0], true));
+
+    add_box(&mut scene, [-2.0, 0.0, -2.0], [2.0, 2.0, 2.0], [floor, mirror, mirror, mirror, mirror, mirror]);
+
+    scene
+        .add_quad([-0.3, 1.99, -0.3], [0.3, 1.99, -0.3], [0.3, 1.99, 0.3], [-0.3, 1.99, 0.3], lamp)
+        .add_sphere_mesh([0.0, 0.5, 0.5], 0.5, 3, white);
+
+    scene
+}
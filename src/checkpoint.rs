@@ -0,0 +1,351 @@
+use crate::gpu::Colour;
+
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 8] = b"PTGPUCKP";
+/// Version 2 added the seeds. Version 1 files still load, without them.
+const VERSION: u32 = 2;
+
+#[derive(Clone, Debug)]
+pub struct Checkpoint
+{
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub scene_hash: u64,
+    /// the seeds of every render that went into it, or empty for version 1
+    /// files, which didn't keep them
+    pub seeds: Vec<u64>,
+    pub pixels: Vec<Colour>,
+}
+
+impl Checkpoint
+{
+    pub fn load(path: &str) -> Result<Checkpoint, String>
+    {
+        let mut file = std::fs::File::open(path)
+            .map_err(|e| format!("Could not open checkpoint \"{}\": {}", path, e))?;
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|e| format!("Could not read checkpoint \"{}\": {}", path, e))?;
+
+        Checkpoint::from_bytes(&bytes)
+            .map_err(|e| format!("Invalid checkpoint \"{}\": {}", path, e))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Checkpoint, String>
+    {
+        let mut reader = Reader { bytes: bytes, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC
+        {
+            return Err("not a checkpoint file".to_owned());
+        }
+
+        let version = reader.u32()?;
+        if version != 1 && version != VERSION
+        {
+            return Err(format!("unsupported checkpoint version {}", version));
+        }
+
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let samples = reader.u32()?;
+        let scene_hash = reader.u64()?;
+
+        let mut seeds = Vec::new();
+        if version >= 2
+        {
+            for _ in 0..reader.u32()?
+            {
+                seeds.push(reader.u64()?);
+            }
+        }
+
+        let len = reader.u64()? as usize;
+
+        if len != width as usize * height as usize
+        {
+            return Err(format!(
+                "pixel count {} doesn't match resolution {}x{}",
+                len, width, height));
+        }
+
+        let mut pixels = Vec::with_capacity(len);
+        for _ in 0..len
+        {
+            pixels.push(Colour
+            {
+                r: reader.f32()?,
+                g: reader.f32()?,
+                b: reader.f32()?,
+            });
+        }
+
+        if reader.pos != bytes.len()
+        {
+            return Err("trailing data after pixels".to_owned());
+        }
+
+        Ok(Checkpoint
+        {
+            width: width,
+            height: height,
+            samples: samples,
+            scene_hash: scene_hash,
+            seeds: seeds,
+            pixels: pixels,
+        })
+    }
+
+    /// Checks that this checkpoint can be resumed for the given render.
+    pub fn check(&self, res: [u32; 2], scene_hash: u64) -> Result<(), String>
+    {
+        if [self.width, self.height] != res
+        {
+            return Err(format!(
+                "Checkpoint resolution {}x{} doesn't match render resolution {}x{}",
+                self.width, self.height, res[0], res[1]));
+        }
+
+        if self.scene_hash != scene_hash
+        {
+            return Err(format!(
+                "Checkpoint scene hash {:016x} doesn't match scene hash {:016x}",
+                self.scene_hash, scene_hash));
+        }
+
+        Ok(())
+    }
+
+    /// Writes the checkpoint, see `save`.
+    pub fn save(&self, path: &str) -> Result<(), String>
+    {
+        save(
+            path, self.width, self.height, self.samples, self.scene_hash, &self.seeds,
+            &self.pixels)
+    }
+
+    /// Adds up checkpoints of the same render made with different seeds, as
+    /// if all their samples were from one render. Each is given with the
+    /// file it came from, which errors name.
+    pub fn merge(checkpoints: &[(&str, Checkpoint)]) -> Result<Checkpoint, String>
+    {
+        let (first_path, first) = match checkpoints
+        {
+            [first, ..] => first,
+            [] => return Err("No checkpoints to merge".to_owned()),
+        };
+
+        let mut merged = first.clone();
+
+        for (i, (path, c)) in checkpoints.iter().enumerate().skip(1)
+        {
+            if [c.width, c.height] != [merged.width, merged.height]
+            {
+                return Err(format!(
+                    "\"{}\": resolution {}x{} doesn't match {}x{} in \"{}\"",
+                    path, c.width, c.height, merged.width, merged.height, first_path));
+            }
+
+            if c.scene_hash != merged.scene_hash
+            {
+                return Err(format!(
+                    "\"{}\": scene hash {:016x} doesn't match {:016x} in \"{}\"",
+                    path, c.scene_hash, merged.scene_hash, first_path));
+            }
+
+            // the same seed makes the same samples, which would count twice
+            if let Some(seed) = c.seeds.iter().find(|s| merged.seeds.contains(s))
+            {
+                let other = checkpoints[..i].iter()
+                    .find(|(_, o)| o.seeds.contains(seed))
+                    .map_or(*first_path, |(p, _)| p);
+
+                return Err(format!(
+                    "\"{}\": seed {} was also used for \"{}\", \
+                     render it again with another --seed",
+                    path, seed, other));
+            }
+
+            merged.samples = match merged.samples.checked_add(c.samples)
+            {
+                Some(samples) => samples,
+                None => return Err(format!("\"{}\": samples add up to too many", path)),
+            };
+
+            merged.seeds.extend(&c.seeds);
+
+            for (sum, px) in merged.pixels.iter_mut().zip(&c.pixels)
+            {
+                sum.r += px.r;
+                sum.g += px.g;
+                sum.b += px.b;
+            }
+        }
+
+        Ok(merged)
+    }
+}
+
+/// Writes a checkpoint without taking ownership of the pixel data.
+///
+/// The file is written next to `path` and then renamed over it, so an
+/// interrupted write never clobbers the previous checkpoint.
+pub fn save(
+    path: &str,
+    width: u32,
+    height: u32,
+    samples: u32,
+    scene_hash: u64,
+    seeds: &[u64],
+    pixels: &[Colour])
+    -> Result<(), String>
+{
+    let mut bytes = Vec::with_capacity(52 + seeds.len() * 8 + pixels.len() * 12);
+
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&width.to_le_bytes());
+    bytes.extend_from_slice(&height.to_le_bytes());
+    bytes.extend_from_slice(&samples.to_le_bytes());
+    bytes.extend_from_slice(&scene_hash.to_le_bytes());
+    bytes.extend_from_slice(&(seeds.len() as u32).to_le_bytes());
+
+    for seed in seeds
+    {
+        bytes.extend_from_slice(&seed.to_le_bytes());
+    }
+
+    bytes.extend_from_slice(&(pixels.len() as u64).to_le_bytes());
+
+    for px in pixels
+    {
+        bytes.extend_from_slice(&px.r.to_le_bytes());
+        bytes.extend_from_slice(&px.g.to_le_bytes());
+        bytes.extend_from_slice(&px.b.to_le_bytes());
+    }
+
+    let temp = format!("{}.tmp", path);
+
+    {
+        let mut file = std::fs::File::create(&temp)
+            .map_err(|e| format!("Could not create checkpoint \"{}\": {}", temp, e))?;
+        file.write_all(&bytes)
+            .map_err(|e| format!("Could not write checkpoint \"{}\": {}", temp, e))?;
+    }
+
+    std::fs::rename(&temp, path)
+        .map_err(|e| format!("Could not move checkpoint to \"{}\": {}", path, e))
+}
+
+struct Reader<'a>
+{
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a>
+{
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String>
+    {
+        if self.pos + n > self.bytes.len()
+        {
+            return Err("unexpected end of file".to_owned());
+        }
+
+        let slice = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, String>
+    {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, String>
+    {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn f32(&mut self) -> Result<f32, String>
+    {
+        Ok(f32::from_bits(self.u32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn checkpoint(res: [u32; 2], scene_hash: u64, seeds: &[u64], value: f32) -> Checkpoint
+    {
+        let px = Colour { r: value, g: value, b: value };
+
+        Checkpoint
+        {
+            width: res[0],
+            height: res[1],
+            samples: 10,
+            scene_hash: scene_hash,
+            seeds: seeds.to_vec(),
+            pixels: vec![px; (res[0] * res[1]) as usize],
+        }
+    }
+
+    #[test]
+    fn merges()
+    {
+        let merged = Checkpoint::merge(&[
+            ("a", checkpoint([2, 2], 7, &[1], 1.0)),
+            ("b", checkpoint([2, 2], 7, &[2], 2.0)),
+            ("c", checkpoint([2, 2], 7, &[3, 4], 3.0)),
+        ]).unwrap();
+
+        assert_eq!(merged.samples, 30);
+        assert_eq!(merged.seeds, [1, 2, 3, 4]);
+        assert!(merged.pixels.iter().all(|px| [px.r, px.g, px.b] == [6.0; 3]));
+    }
+
+    #[test]
+    fn refuses_mismatches()
+    {
+        let bad = [
+            (checkpoint([2, 3], 7, &[2], 1.0),
+                "\"b\": resolution 2x3 doesn't match 2x2 in \"a\""),
+            (checkpoint([2, 2], 8, &[2], 1.0),
+                "\"b\": scene hash 0000000000000008 doesn't match 0000000000000007 in \"a\""),
+            (checkpoint([2, 2], 7, &[1], 1.0),
+                "\"b\": seed 1 was also used for \"a\", render it again with another --seed"),
+            (Checkpoint { samples: u32::MAX, .. checkpoint([2, 2], 7, &[2], 1.0) },
+                "\"b\": samples add up to too many"),
+        ];
+
+        for (b, why) in bad
+        {
+            let e = Checkpoint::merge(&[("a", checkpoint([2, 2], 7, &[1], 1.0)), ("b", b)])
+                .unwrap_err();
+            assert_eq!(e, why);
+        }
+
+        // the seed is blamed on whichever file had it first
+        let e = Checkpoint::merge(&[
+            ("a", checkpoint([2, 2], 7, &[1], 1.0)),
+            ("b", checkpoint([2, 2], 7, &[2], 1.0)),
+            ("c", checkpoint([2, 2], 7, &[2], 1.0)),
+        ]).unwrap_err();
+        assert!(e.starts_with("\"c\": seed 2 was also used for \"b\""), "{}", e);
+
+        assert_eq!(Checkpoint::merge(&[]).unwrap_err(), "No checkpoints to merge");
+    }
+}
@@ -0,0 +1,42 @@
+//! `Instant`, which is `std::time::Instant` except on the web, where the
+//! standard one panics, so there it's from the page's clock.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web::Instant;
+
+#[cfg(target_arch = "wasm32")]
+mod web
+{
+    use std::time::Duration;
+
+    /// Milliseconds since 1970.
+    #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+    pub struct Instant(f64);
+
+    impl Instant
+    {
+        pub fn now() -> Instant
+        {
+            Instant(js_sys::Date::now())
+        }
+
+        pub fn elapsed(&self) -> Duration
+        {
+            Instant::now() - *self
+        }
+    }
+
+    impl std::ops::Sub for Instant
+    {
+        type Output = Duration;
+
+        /// Zero if `earlier` is later, as the clock can be set back.
+        fn sub(self, earlier: Instant) -> Duration
+        {
+            Duration::from_secs_f64((self.0 - earlier.0).max(0.0) / 1000.0)
+        }
+    }
+}
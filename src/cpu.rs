@@ -0,0 +1,1023 @@
+//! The shader's path tracing on the CPU, for machines without a usable GPU.
+//! Every pixel draws the same random numbers as it would on the GPU, so both
+//! converge to the same image.
+
+use crate::clock::Instant;
+use crate::gpu::{frame_seed, furthest, squares_of, Aovs, Camera, Colour, GpuError, Hooks,
+    Material, Noise, RenderMode, SceneBuffers, ShaderSettings, Sum, Timings, Triangle, MISS};
+use crate::stop::relative_noise;
+use crate::texture::{Texture, DEFAULT_UVS};
+use crate::vec3::{add, cross, dot, length, normalize, scale, sub};
+
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+
+/// Like `run_shader`, with the whole region rendered at once so there's no
+/// tiling, and the pixels of each sample spread over rayon's threads.
+pub(crate) fn run_cpu(
+    image: &mut Vec<Colour>,
+    cameras: &[Camera],
+    scene: &SceneBuffers,
+    settings: &ShaderSettings,
+    timings: &mut Timings,
+    hooks: Hooks)
+    -> Result<u32, GpuError>
+{
+    let SceneBuffers { triangles, materials, velocities, shutter, colours, uvs, textures,
+        normals, noises } = *scene;
+    let ShaderSettings { width, height, mode, noise, depth_map, depth, ray_epsilon, seed,
+        start_samples, region, .. } = *settings;
+    let Hooks { condition, want_image, on_image, on_frame } = hooks;
+
+    // the noise is measured from the squares
+    let noise = noise || condition.noise_every().is_some();
+
+    let setup_start = Instant::now();
+
+    if timings.profile.take().is_some()
+    {
+        pt_warn!("There's no GPU to profile when rendering on the CPU");
+    }
+
+    let region = region.unwrap_or([0, 0, width, height]);
+    let pixels = (width * height) as usize;
+
+    // where each triangle is at the end of the frame, if any of them move
+    let moving = shutter > 0.0 && velocities.len() == triangles.len()
+        && velocities.iter().any(|v| *v != [0.0, 0.0, 0.0]);
+    let ends = if moving
+    {
+        triangles.iter()
+            .zip(velocities)
+            .map(|(t, &v)| Triangle
+            {
+                a: add(t.a, v),
+                b: add(t.b, v),
+                c: add(t.c, v),
+                mat: t.mat,
+            })
+            .collect()
+    }
+    else
+    {
+        Vec::new()
+    };
+
+    let coloured = colours.len() == triangles.len()
+        && colours.iter().any(|c| *c != [[1.0; 3]; 3]);
+    let colours = if coloured { colours } else { &[] };
+
+    let textured = !textures.is_empty()
+        && materials.iter().any(Material::textured);
+    let default_uvs;
+    let uvs = match textured
+    {
+        true if uvs.len() == triangles.len() => uvs,
+        true =>
+        {
+            default_uvs = vec![DEFAULT_UVS; triangles.len()];
+            &default_uvs
+        },
+        false => &[],
+    };
+
+    let smooth = normals.len() == triangles.len()
+        && normals.iter().any(|n| *n != [[0.0; 3]; 3]);
+    let normals = if smooth { normals } else { &[] };
+
+    let noisy = noises.len() == materials.len()
+        && noises.iter().any(|n| n.octaves != 0);
+    let noises = if noisy { noises } else { &[] };
+
+    let matte = mode == RenderMode::PathTrace && triangles.iter().any(|t| matches!(
+        materials.get(t.mat as usize),
+        Some(m) if m.flags & Material::SHADOW_CATCHER != 0));
+
+    // resuming: continue accumulating on top of the previous samples
+    let mut full = if image.len() == pixels && region == [0, 0, width, height]
+    {
+        image.iter().map(|&c| Sum::from(c)).collect()
+    }
+    else
+    {
+        vec![Sum::default(); pixels]
+    };
+    let mut full_matte = vec![Sum::<4>::default(); if matte { pixels } else { 0 }];
+    let mut full_squares = vec![Sum::<1>::default(); if noise { pixels } else { 0 }];
+    let mut full_depths = vec![0.0f32; if depth_map { pixels } else { 0 }];
+
+    timings.setup = setup_start.elapsed();
+
+    // the first frame decides the sample count, like the first tile on the
+    // GPU, and every other frame renders the same count
+    let mut total = None;
+
+    for (frame, camera) in cameras.iter().enumerate()
+    {
+        let scene = CpuScene
+        {
+            camera: camera,
+            triangles: triangles,
+            materials: materials,
+            ends: &ends,
+            colours: colours,
+            uvs: uvs,
+            textures: textures,
+            normals: normals,
+            noises: noises,
+            width: width,
+            height: height,
+            depth: depth,
+            ray_epsilon: ray_epsilon,
+            shutter: shutter,
+            mode: mode,
+            far: furthest(camera, triangles),
+        };
+        let frame_seed = frame_seed(seed, frame);
+
+        let mut samples = match total
+        {
+            None => start_samples,
+            Some(_) =>
+            {
+                full.fill(Sum::default());
+                full_matte.fill(Sum::default());
+                full_squares.fill(Sum::default());
+                full_depths.fill(0.0);
+
+                0
+            },
+        };
+
+        while match total
+        {
+            Some(total) => samples < total,
+            None => condition.go_on(samples),
+        }
+        {
+            samples += 1;
+
+            let sample_start = Instant::now();
+
+            let pixels = 0..region[2] * region[3];
+            // there are no threads on the web
+            #[cfg(not(target_arch = "wasm32"))]
+            let pixels = pixels.into_par_iter();
+
+            let results = pixels
+                .map(|i|
+                {
+                    let x = region[0] + i % region[2];
+                    let y = region[1] + i / region[2];
+                    let px = (y * width + x) as usize;
+                    let want_depth = depth_map && full_depths[px] == 0.0;
+
+                    (px, scene.pixel(x, y, samples, frame_seed, want_depth))
+                })
+                .collect::<Vec<_>>();
+
+            for (px, result) in results
+            {
+                let c = result.colour;
+
+                full[px].add(c);
+
+                if matte
+                {
+                    full_matte[px].add(result.matte);
+                }
+
+                if noise
+                {
+                    let l = dot(c, [0.2126, 0.7152, 0.0722]);
+                    full_squares[px].add([l * l]);
+                }
+
+                if let Some(d) = result.depth
+                {
+                    full_depths[px] = d;
+                }
+            }
+
+            let sample_time = sample_start.elapsed();
+            timings.samples.push(sample_time);
+
+            pt_trace!("Sample {} took {:.2}ms",
+                samples, sample_time.as_secs_f64() * 1000.0);
+
+            if total.is_none() && matches!(condition.noise_every(), Some(every) if samples.is_multiple_of(every))
+            {
+                if let Some(noise) = relative_noise(&Sum::totals(&full), &squares_of(&full_squares), samples)
+                {
+                    pt_debug!("Noise {:.4} after {} samples", noise, samples);
+                    condition.noise(samples, noise);
+                }
+            }
+
+            if want_image(samples)
+            {
+                on_image(samples, &Sum::totals(&full));
+            }
+        }
+        total = Some(samples);
+
+        let (matte_sums, square_sums) = (Sum::values(&full_matte), squares_of(&full_squares));
+
+        on_frame(frame, samples, &Sum::totals(&full), Aovs
+        {
+            matte: if matte { Some(&matte_sums) } else { None },
+            squares: if noise { Some(&square_sums) } else { None },
+            depth: if depth_map { Some(&full_depths) } else { None },
+        });
+    }
+
+    *image = Sum::totals(&full);
+
+    Ok(total.unwrap_or(0))
+}
+
+/// Everything the shader reads from its buffers, for one frame.
+struct CpuScene<'a>
+{
+    camera: &'a Camera,
+    triangles: &'a [Triangle],
+    materials: &'a [Material],
+    /// empty unless the triangles move
+    ends: &'a [Triangle],
+    /// empty unless the triangles have colours
+    colours: &'a [[[f32; 3]; 3]],
+    /// empty unless a material has a texture
+    uvs: &'a [[[f32; 2]; 3]],
+    textures: &'a [Texture],
+    /// empty unless the triangles have smooth normals
+    normals: &'a [[[f32; 3]; 3]],
+    /// empty unless a material has noise
+    noises: &'a [Noise],
+    width: u32,
+    height: u32,
+    depth: u32,
+    /// see `RenderSettings::ray_epsilon`
+    ray_epsilon: f32,
+    shutter: f32,
+    mode: RenderMode,
+    far: f32,
+}
+
+/// One sample of one pixel.
+struct PixelSample
+{
+    colour: [f32; 3],
+    matte: [f32; 4],
+    /// distance to the first surface, or -1 for a miss, if it was asked for
+    depth: Option<f32>,
+}
+
+/// The shader's random numbers: a hash of a key and a counter.
+struct Random
+{
+    key: u32,
+    counter: u32,
+}
+
+impl Random
+{
+    fn next(&mut self) -> f32
+    {
+        // the top 24 bits, so it's below 1
+        let r = (pcg(self.key ^ pcg(self.counter)) >> 8) as f32 / 16777216.0;
+        self.counter = self.counter.wrapping_add(1);
+
+        r
+    }
+}
+
+/// PCG-RXS-M-XS, as a hash
+fn pcg(v: u32) -> u32
+{
+    let state = v.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+
+    (word >> 22) ^ word
+}
+
+/// A random number below 1 for each point of each octave's grid.
+fn lattice(p: [i32; 3], octave: u32) -> f32
+{
+    let h = pcg(p[0] as u32 ^ pcg(p[1] as u32 ^ pcg(p[2] as u32 ^ pcg(octave))));
+
+    (h >> 8) as f32 / 16777216.0
+}
+
+/// The lattice's numbers blended smoothly between the grid points around `p`.
+fn value_noise(p: [f32; 3], octave: u32) -> f32
+{
+    let i = p.map(|x| x.floor() as i32);
+    let w = p.map(|x|
+    {
+        let f = x - x.floor();
+        f * f * (3.0 - 2.0 * f)
+    });
+
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let at = |x: i32, y: i32, z: i32| lattice([i[0] + x, i[1] + y, i[2] + z], octave);
+
+    let x00 = mix(at(0, 0, 0), at(1, 0, 0), w[0]);
+    let x10 = mix(at(0, 1, 0), at(1, 1, 0), w[0]);
+    let x01 = mix(at(0, 0, 1), at(1, 0, 1), w[0]);
+    let x11 = mix(at(0, 1, 1), at(1, 1, 1), w[0]);
+
+    mix(mix(x00, x10, w[1]), mix(x01, x11, w[1]), w[2])
+}
+
+/// Octaves of value noise, each finer and weaker than the last, averaged so
+/// it stays between 0 and 1.
+fn fbm(p: [f32; 3], noise: &Noise) -> f32
+{
+    let mut sum = 0.0;
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = noise.scale;
+
+    for o in 0..noise.octaves
+    {
+        sum += amplitude * value_noise(scale(p, frequency), o);
+        total += amplitude;
+        amplitude *= noise.gain;
+        frequency *= noise.lacunarity;
+    }
+
+    sum / total
+}
+
+#[derive(Copy, Clone)]
+struct Ray
+{
+    start: [f32; 3],
+    vec: [f32; 3],
+}
+
+struct Hit
+{
+    dist: f32,
+    point: [f32; 3],
+    norm: [f32; 3],
+    front: bool,
+    mat: Material,
+    /// the triangle that was hit, moved to the ray's time
+    tri: Triangle,
+    index: usize,
+}
+
+impl Hit
+{
+    /// The brightness of the glow the ray hit, or 0 if it missed.
+    fn glow_seen(&self) -> f32
+    {
+        if self.dist >= MISS || (!self.front && self.mat.flags & Material::ONE_SIDED != 0)
+        {
+            return 0.0;
+        }
+
+        dot(self.mat.glow, [0.2126, 0.7152, 0.0722])
+    }
+}
+
+impl<'a> CpuScene<'a>
+{
+    /// The shader's `main` for one pixel.
+    fn pixel(&self, x: u32, y: u32, sample: u32, seed: u32, want_depth: bool) -> PixelSample
+    {
+        let mut rand = Random
+        {
+            key: pcg(x ^ pcg(y ^ pcg(sample ^ pcg(seed)))),
+            counter: 0,
+        };
+
+        let x_step = 1.0 / self.width as f32;
+        let y_step = 1.0 / self.height as f32;
+        let ratio = self.width as f32 / self.height as f32;
+        let dist = 0.5 / (self.camera.fov / 2.0).tan();
+
+        let up = normalize(self.camera.up);
+        let front = normalize(self.camera.front);
+        let right = normalize(cross(front, up));
+
+        let x_offset = -0.5 + x_step * (x as f32 + 0.5);
+        let y_offset = (-0.5 + y_step * (y as f32 + 0.5)) / ratio;
+
+        let rx = rand.next() - 0.5;
+        let ry = rand.next() - 0.5;
+
+        // when in the frame this path is, for motion blur
+        let time = rand.next() * self.shutter;
+
+        let pos = self.camera.pos;
+        let pix = add(add(add(
+            pos,
+            scale(front, dist)),
+            scale(right, x_offset + rx * x_step)),
+            scale(up, y_offset + ry * y_step));
+
+        let ray = Ray
+        {
+            start: pos,
+            vec: normalize(sub(pix, pos)),
+        };
+
+        let (colour, matte) = match self.mode
+        {
+            RenderMode::PathTrace => self.cast_ray(ray, rand, time),
+            RenderMode::AmbientOcclusion { rays, distance } =>
+            {
+                let distance = distance.unwrap_or(MISS).min(MISS);
+
+                (self.ambient_occlusion(ray, rand, time, rays, distance), [0.0; 4])
+            },
+            _ => (self.debug_view(ray, time), [0.0; 4]),
+        };
+
+        let depth = if want_depth
+        {
+            let hit = self.trace(ray, time, false);
+
+            Some(if hit.dist >= MISS { -1.0 } else { hit.dist })
+        }
+        else
+        {
+            None
+        };
+
+        PixelSample
+        {
+            colour: colour,
+            matte: matte,
+            depth: depth,
+        }
+    }
+
+    /// The nearest triangle along the ray, at time `time` in the frame, only
+    /// looking at glowing triangles when `lights_only` is set.
+    fn trace(&self, ray: Ray, time: f32, lights_only: bool) -> Hit
+    {
+        let mut hit = Hit
+        {
+            dist: MISS,
+            point: [0.0; 3],
+            norm: [0.0; 3],
+            front: true,
+            mat: bytemuck::Zeroable::zeroed(),
+            tri: bytemuck::Zeroable::zeroed(),
+            index: 0,
+        };
+
+        for (i, &tri) in self.triangles.iter().enumerate()
+        {
+            let mat = self.materials[tri.mat as usize];
+
+            if lights_only && mat.glow[0] + mat.glow[1] + mat.glow[2] <= 0.0
+            {
+                continue;
+            }
+
+            let tri = match self.ends.get(i)
+            {
+                Some(end) => at_time(&tri, end, time),
+                None => tri,
+            };
+
+            let p = match ray_vs_triangle(ray, &tri)
+            {
+                Some(p) => p,
+                None => continue,
+            };
+
+            let dist = length(sub(p, ray.start));
+
+            // cut out parts are passed over, so the ray goes on to whatever
+            // is behind them
+            if dist < hit.dist && !self.cut_out(i, &tri, &mat, p)
+            {
+                let normal = normalize(cross(sub(tri.b, tri.a), sub(tri.c, tri.a)));
+
+                hit.dist = dist;
+                hit.point = p;
+                hit.norm = if dot(ray.vec, normal) >= 0.0 { scale(normal, -1.0) } else { normal };
+                hit.front = dot(ray.vec, normal) < 0.0;
+                hit.mat = mat;
+                hit.tri = tri;
+                hit.index = i;
+            }
+        }
+
+        hit
+    }
+
+    /// Whether the material's alpha texture cuts a hole in triangle `index`
+    /// at `p`.
+    fn cut_out(&self, index: usize, tri: &Triangle, mat: &Material, p: [f32; 3]) -> bool
+    {
+        let (texture, corners) = match (mat.alpha_texture, self.uvs.get(index))
+        {
+            (0, _) | (_, None) => return false,
+            (t, Some(corners)) => (&self.textures[t as usize - 1], corners),
+        };
+
+        let [wa, wb, wc] = weights(tri, p);
+        let uv = [0, 1].map(|i| corners[0][i] * wa + corners[1][i] * wb + corners[2][i] * wc);
+
+        texture.sample(uv)[3] < mat.alpha_cutoff
+    }
+
+    /// Where a ray leaving `point` starts, off the surface by the epsilon
+    /// times the point's largest coordinate, since floats lose precision
+    /// in proportion to their size.
+    fn offset(&self, point: [f32; 3], norm: [f32; 3]) -> [f32; 3]
+    {
+        let size = point.iter().fold(1.0f32, |size, c| size.max(c.abs()));
+
+        add(point, scale(norm, self.ray_epsilon * size))
+    }
+
+    /// The material's colour, or its noise at the hit, times the colours at
+    /// the triangle's points blended by how close the hit is to each.
+    fn albedo(&self, hit: &Hit) -> [f32; 3]
+    {
+        let colour = match self.noises.get(hit.tri.mat as usize)
+        {
+            Some(noise) if noise.octaves != 0 => lerp(noise.a, noise.b, fbm(hit.point, noise)),
+            _ => hit.mat.colour,
+        };
+
+        let corners = match self.colours.get(hit.index)
+        {
+            Some(corners) => corners,
+            None => return colour,
+        };
+
+        let [wa, wb, wc] = barycentric(hit);
+
+        let blend = add(add(
+            scale(corners[0], wa),
+            scale(corners[1], wb)),
+            scale(corners[2], wc));
+
+        mul(colour, blend)
+    }
+
+    /// The normals at the triangle's points blended by how close the hit is
+    /// to each, on the same side as `hit.norm`, or `hit.norm` for triangles
+    /// without any, then tilted by the material's normal map.
+    fn shading_normal(&self, hit: &Hit) -> [f32; 3]
+    {
+        let corners = match self.normals.get(hit.index)
+        {
+            Some(corners) => corners,
+            None => return self.normal_mapped(hit, hit.norm),
+        };
+
+        let [wa, wb, wc] = barycentric(hit);
+
+        let n = add(add(
+            scale(corners[0], wa),
+            scale(corners[1], wb)),
+            scale(corners[2], wc));
+
+        if dot(n, n) == 0.0
+        {
+            return self.normal_mapped(hit, hit.norm);
+        }
+
+        let n = scale(normalize(n), if dot(n, hit.norm) >= 0.0 { 1.0 } else { -1.0 });
+
+        self.normal_mapped(hit, n)
+    }
+
+    /// `n` tilted by the material's normal map, in a tangent frame along the
+    /// triangle's u and v, or `n` if it has none or its uvs are degenerate.
+    fn normal_mapped(&self, hit: &Hit, n: [f32; 3]) -> [f32; 3]
+    {
+        let (texture, corners) = match (hit.mat.normal_texture, self.uvs.get(hit.index))
+        {
+            (0, _) | (_, None) => return n,
+            (t, Some(corners)) => (&self.textures[t as usize - 1], corners),
+        };
+
+        let (e1, e2) = (sub(hit.tri.b, hit.tri.a), sub(hit.tri.c, hit.tri.a));
+        let d1 = [corners[1][0] - corners[0][0], corners[1][1] - corners[0][1]];
+        let d2 = [corners[2][0] - corners[0][0], corners[2][1] - corners[0][1]];
+
+        let det = d1[0] * d2[1] - d2[0] * d1[1];
+
+        if det == 0.0
+        {
+            return n;
+        }
+
+        let t = scale(sub(scale(e1, d2[1]), scale(e2, d1[1])), 1.0 / det);
+        let b = scale(sub(scale(e2, d1[0]), scale(e1, d2[0])), 1.0 / det);
+
+        // the tangent square to n, and the bitangent the way v goes
+        let t = sub(t, scale(n, dot(n, t)));
+
+        if dot(t, t) == 0.0
+        {
+            return n;
+        }
+
+        let t = normalize(t);
+        let b = scale(cross(n, t), if dot(cross(n, t), b) >= 0.0 { 1.0 } else { -1.0 });
+
+        let [wa, wb, wc] = barycentric(hit);
+        let uv = [0, 1].map(|i| corners[0][i] * wa + corners[1][i] * wb + corners[2][i] * wc);
+
+        let [x, y, z, _] = texture.sample(uv);
+        let strength = hit.mat.normal_strength;
+        let m = [(x * 2.0 - 1.0) * strength, (y * 2.0 - 1.0) * strength, z * 2.0 - 1.0];
+
+        let tilted = add(add(scale(t, m[0]), scale(b, m[1])), scale(n, m[2]));
+
+        if dot(tilted, tilted) == 0.0
+        {
+            return n;
+        }
+
+        normalize(tilted)
+    }
+
+    /// The material's glow, times its glow texture if it has one.
+    fn emission(&self, hit: &Hit) -> [f32; 3]
+    {
+        let (texture, corners) = match (hit.mat.glow_texture, self.uvs.get(hit.index))
+        {
+            (0, _) | (_, None) => return hit.mat.glow,
+            (t, Some(corners)) => (&self.textures[t as usize - 1], corners),
+        };
+
+        let [wa, wb, wc] = barycentric(hit);
+        let uv = [0, 1].map(|i| corners[0][i] * wa + corners[1][i] * wb + corners[2][i] * wc);
+
+        let [r, g, b, _] = texture.sample(uv);
+
+        mul(hit.mat.glow, [r, g, b])
+    }
+
+    /// The colour along a path, and the matte, like the shader's `cast_ray`.
+    fn cast_ray(&self, mut ray: Ray, mut rand: Random, time: f32) -> ([f32; 3], [f32; 4])
+    {
+        let mut matte = [0.0; 4];
+        let mut colour = [0.0; 3];
+        let mut throughput = [1.0; 3];
+
+        for d in 0..self.depth
+        {
+            // the camera uses the first block
+            rand.counter = (d + 1) * 8;
+
+            let hit = self.trace(ray, time, false);
+
+            if hit.dist >= MISS
+            {
+                break;
+            }
+
+            let (point, norm, mat) = (hit.point, hit.norm, hit.mat);
+            let shade = self.shading_normal(&hit);
+
+            // shadow catchers are see-through to the camera, and only
+            // measure how much light things around them block
+            if d == 0 && mat.flags & Material::SHADOW_CATCHER != 0
+            {
+                let (u1, u2) = (rand.next(), rand.next());
+
+                let light = Ray
+                {
+                    start: self.offset(point, norm),
+                    vec: cosine_sample(shade, u1, u2),
+                };
+
+                let matte = [
+                    0.0,
+                    1.0,
+                    self.trace(light, time, false).glow_seen(),
+                    self.trace(light, time, true).glow_seen(),
+                ];
+
+                return ([0.0; 3], matte);
+            }
+
+            if d == 0
+            {
+                matte = [1.0, 0.0, 0.0, 0.0];
+            }
+
+            if rand.next() >= mat.gloss
+            {
+                if hit.front || mat.flags & Material::ONE_SIDED == 0
+                {
+                    colour = add(colour, mul(throughput, self.emission(&hit)));
+                }
+
+                // the cosine and the pdf cancel, see `cosine_sample`
+                throughput = mul(throughput, self.albedo(&hit));
+
+                let (u1, u2) = (rand.next(), rand.next());
+
+                ray.start = self.offset(point, norm);
+                ray.vec = cosine_sample(shade, u1, u2);
+            }
+            else
+            {
+                throughput = mul(throughput, mat.reflect_c);
+
+                ray.start = self.offset(point, norm);
+                ray.vec = reflect_vec(ray.vec, scale(shade, -1.0));
+            }
+
+            // bounces off smooth normals can point into the surface, so
+            // they're mirrored back out of it
+            let under = dot(ray.vec, norm);
+            if under < 0.0
+            {
+                ray.vec = sub(ray.vec, scale(norm, 2.0 * under));
+            }
+        }
+
+        (colour, matte)
+    }
+
+    /// How much of the sky the first surface sees within `distance`.
+    fn ambient_occlusion(&self, ray: Ray, mut rand: Random, time: f32, rays: u32, distance: f32)
+        -> [f32; 3]
+    {
+        let hit = self.trace(ray, time, false);
+
+        if hit.dist >= MISS
+        {
+            return [1.0; 3];
+        }
+
+        rand.counter = 8;
+
+        let mut open = 0.0;
+
+        for _ in 0..rays
+        {
+            let u1 = rand.next();
+            let u2 = rand.next();
+
+            let probe = Ray
+            {
+                start: self.offset(hit.point, hit.norm),
+                vec: cosine_sample(hit.norm, u1, u2),
+            };
+
+            if self.trace(probe, time, false).dist > distance
+            {
+                open += 1.0;
+            }
+        }
+
+        let grey = open / rays.max(1) as f32;
+
+        [grey; 3]
+    }
+
+    /// The first surface's normal, distance, material or edges.
+    fn debug_view(&self, ray: Ray, time: f32) -> [f32; 3]
+    {
+        let hit = self.trace(ray, time, false);
+
+        if hit.dist >= MISS
+        {
+            return [0.0; 3];
+        }
+
+        match self.mode
+        {
+            RenderMode::Normals =>
+            {
+                // as they face, not as they're flipped towards the ray
+                let shade = self.shading_normal(&hit);
+                let n = if hit.front { shade } else { scale(shade, -1.0) };
+
+                add(scale(n, 0.5), [0.5; 3])
+            },
+            RenderMode::Depth => [(1.0 - hit.dist / self.far).clamp(0.0, 1.0); 3],
+            RenderMode::MaterialId =>
+            {
+                let h = pcg(hit.tri.mat + 1);
+
+                [
+                    (h & 255) as f32 / 255.0,
+                    ((h >> 8) & 255) as f32 / 255.0,
+                    ((h >> 16) & 255) as f32 / 255.0,
+                ]
+            },
+            _ =>
+            {
+                // each vertex's barycentric weight times its height over the
+                // opposite edge is the distance to that edge
+                let (a, b, c, p) = (hit.tri.a, hit.tri.b, hit.tri.c, hit.point);
+
+                let area = length(cross(sub(b, a), sub(c, a)));
+                let wa = length(cross(sub(b, p), sub(c, p))) / area;
+                let wb = length(cross(sub(c, p), sub(a, p))) / area;
+                let wc = length(cross(sub(a, p), sub(b, p))) / area;
+
+                let edge = (wa * area / length(sub(c, b)))
+                    .min(wb * area / length(sub(a, c)))
+                    .min(wc * area / length(sub(b, a)));
+
+                // how wide a pixel is this far from the camera
+                let pixel = hit.dist
+                    / (self.width as f32 * 0.5 / (self.camera.fov / 2.0).tan());
+
+                [0.2 + 0.7 * (edge / pixel - 0.5).clamp(0.0, 1.0); 3]
+            },
+        }
+    }
+}
+
+fn mul(a: [f32; 3], b: [f32; 3]) -> [f32; 3]
+{
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2]]
+}
+
+fn lerp(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3]
+{
+    add(a, scale(sub(b, a), t))
+}
+
+/// The triangle part way through the frame, at `t` in frames.
+fn at_time(start: &Triangle, end: &Triangle, t: f32) -> Triangle
+{
+    Triangle
+    {
+        a: lerp(start.a, end.a, t),
+        b: lerp(start.b, end.b, t),
+        c: lerp(start.c, end.c, t),
+        mat: start.mat,
+    }
+}
+
+/// Where the ray hits the triangle, if it does, by the shader's watertight
+/// test: the triangle is sheared into a space where the ray runs along z
+/// from the origin, so two triangles sharing an edge agree on which side of
+/// it the ray is.
+fn ray_vs_triangle(ray: Ray, triangle: &Triangle) -> Option<[f32; 3]>
+{
+    // z is the ray's largest axis, and x and y are swapped if it's negative
+    // to keep the winding
+    let m = ray.vec.map(f32::abs);
+    let kz = if m[2] >= m[0].max(m[1]) { 2 } else if m[1] >= m[0] { 1 } else { 0 };
+    let (kx, ky) = match ray.vec[kz] < 0.0
+    {
+        true => ((kz + 2) % 3, (kz + 1) % 3),
+        false => ((kz + 1) % 3, (kz + 2) % 3),
+    };
+
+    let d = ray.vec;
+    let shear = [-d[kx] / d[kz], -d[ky] / d[kz], 1.0 / d[kz]];
+
+    let [a, b, c] = [triangle.a, triangle.b, triangle.c].map(|p|
+    {
+        let p = sub(p, ray.start);
+        [p[kx] + shear[0] * p[kz], p[ky] + shear[1] * p[kz], p[kz]]
+    });
+
+    // the edge functions, all the same sign inside
+    let u = c[0] * b[1] - c[1] * b[0];
+    let v = a[0] * c[1] - a[1] * c[0];
+    let w = b[0] * a[1] - b[1] * a[0];
+
+    let det = u + v + w;
+
+    if det == 0.0 || !inside_edge(u, b, c, det) || !inside_edge(v, c, a, det)
+        || !inside_edge(w, a, b, det)
+    {
+        return None;
+    }
+
+    // anything in front of the start counts, like the shader
+    let t = (u * a[2] + v * b[2] + w * c[2]) * shear[2] / det;
+
+    if t > 0.0
+    {
+        Some(add(ray.start, scale(ray.vec, t)))
+    }
+    else
+    {
+        None
+    }
+}
+
+/// Whether the ray is inside the edge from `p` to `q`, whose edge function
+/// is `f`, where `det` has the sign of the inside. A ray exactly on the edge
+/// goes to whichever triangle has its inside to the edge's top-left, so of
+/// two triangles sharing the edge exactly one is hit, like the shader's
+/// `inside_edge`.
+fn inside_edge(f: f32, p: [f32; 3], q: [f32; 3], det: f32) -> bool
+{
+    if f != 0.0
+    {
+        return (f > 0.0) == (det > 0.0);
+    }
+
+    let (ex, ey) = (q[0] - p[0], q[1] - p[1]);
+    let top_left = ey > 0.0 || (ey == 0.0 && ex > 0.0);
+
+    top_left == (det > 0.0)
+}
+
+fn reflect_vec(incoming: [f32; 3], normal: [f32; 3]) -> [f32; 3]
+{
+    let v = normalize(incoming);
+    let n = normalize(normal);
+
+    normalize(sub(v, scale(n, 2.0 * dot(v, n))))
+}
+
+/// How close the hit is to each of the triangle's points, adding up to 1.
+fn barycentric(hit: &Hit) -> [f32; 3]
+{
+    weights(&hit.tri, hit.point)
+}
+
+/// How close `p` is to each of the triangle's points, adding up to 1.
+fn weights(tri: &Triangle, p: [f32; 3]) -> [f32; 3]
+{
+    let (a, b, c) = (tri.a, tri.b, tri.c);
+
+    let area = length(cross(sub(b, a), sub(c, a)));
+
+    [
+        length(cross(sub(b, p), sub(c, p))) / area,
+        length(cross(sub(c, p), sub(a, p))) / area,
+        length(cross(sub(a, p), sub(b, p))) / area,
+    ]
+}
+
+/// A direction around `n`, more likely the closer it is to `n` (a pdf of
+/// cos / pi), from two random numbers.
+fn cosine_sample(n: [f32; 3], u1: f32, u2: f32) -> [f32; 3]
+{
+    // Duff et al.'s branchless basis
+    let sign = if n[2] >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (sign + n[2]);
+    let c = n[0] * n[1] * a;
+
+    let t = [1.0 + sign * n[0] * n[0] * a, sign * c, -sign * n[0]];
+    let b = [c, sign + n[1] * n[1] * a, -n[1]];
+
+    let r = u1.sqrt();
+    let phi = std::f32::consts::TAU * u2;
+
+    normalize(add(add(
+        scale(t, r * phi.cos()),
+        scale(b, r * phi.sin())),
+        scale(n, (1.0 - u1).max(0.0).sqrt())))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn tri(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> Triangle
+    {
+        Triangle { a: a, b: b, c: c, mat: 0 }
+    }
+
+    #[test]
+    fn shared_edges_are_hit_once()
+    {
+        let (a, b, c, d) = ([-1.0, -1.0, 1.0], [1.0, -1.0, 1.0], [1.0, 1.0, 1.0], [-1.0, 1.0, 1.0]);
+
+        // split along a to c like `Scene::add_quad`, and with the second
+        // half wound the other way
+        let quads = [
+            [tri(a, b, c), tri(a, c, d)],
+            [tri(a, b, c), tri(a, d, c)],
+        ];
+
+        for quad in &quads
+        {
+            for start in [[0.0, 0.0, 0.0], [0.0, 0.0, 2.0], [0.3, -0.7, -1.0], [-2.0, 5.0, 3.0]]
+            {
+                for i in 0..=8
+                {
+                    // points along the diagonal, which is the shared edge
+                    let s = -0.8 + 0.2 * i as f32;
+                    let ray = Ray
+                    {
+                        start: start,
+                        vec: normalize(sub([s, s, 1.0], start)),
+                    };
+
+                    let hits = quad.iter().filter(|t| ray_vs_triangle(ray, t).is_some()).count();
+                    assert_eq!(hits, 1, "from {:?} to ({}, {}, 1)", start, s, s);
+                }
+            }
+        }
+    }
+}
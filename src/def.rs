@@ -0,0 +1,2290 @@
+//! Typed definitions of a scene file. Reading them keeps track of where in
+//! the file each value came from, so problems can say where they are, like
+//! `surfaces[12].quad[3]: expected an array of 3 numbers at line 87`.
+
+use json::JsonValue;
+
+use crate::mesh::{MAX_HEIGHTMAP_RESOLUTION, MAX_SPHERE_SUBDIVISIONS};
+use crate::transform::{Placement, Transform};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Clone, Debug)]
+pub struct SceneDef
+{
+    /// the singular "camera", which is named "default"
+    pub camera: Option<CameraDef>,
+    /// "camera" was "auto", see `Scene::auto_camera`
+    pub auto_camera: bool,
+    pub cameras: Vec<(String, CameraDef)>,
+    pub materials: Vec<(String, MaterialDef)>,
+    /// for surfaces without a "mat", mid-grey if not given
+    pub default_material: Option<MaterialDef>,
+    /// those from "nodes" after the rest, already where their nodes put them
+    pub surfaces: Vec<SurfaceDef>,
+    /// every node in "nodes", each after its parent
+    pub nodes: Vec<NodeDef>,
+    /// animations have their own parser, see `Animation::parse`
+    pub animation: Option<(JsonValue, Location)>,
+    /// files whose materials and surfaces go before this file's own, and
+    /// what their positions are multiplied by, see
+    /// `SceneDef::resolve_includes`
+    pub include: Vec<(String, f32, Location)>,
+    /// every file `include` read, directly or not, once
+    /// `SceneDef::resolve_includes` has merged them
+    pub included: Vec<String>,
+    /// what positions were multiplied by to make them meters, from "units"
+    /// or "scale", already done to everything but the animation
+    pub scale: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct CameraDef
+{
+    pub pos: [f32; 3],
+    pub front: [f32; 3],
+    pub up: [f32; 3],
+    /// in degrees, kept as f64 so `Scene::to_json` can write any f32 of
+    /// radians
+    pub fov: f64,
+    /// brightness in stops, see `Scene::exposure`
+    pub exposure: f32,
+    /// see `Scene::shutter`
+    pub shutter: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct MaterialDef
+{
+    pub colour: [f32; 3],
+    pub glow: [f32; 3],
+    pub gloss: f32,
+    pub reflect_c: [f32; 3],
+    /// only glows from the front, the side the points go anticlockwise on
+    pub one_sided: bool,
+    /// only shows the shadows on it, see `Material::SHADOW_CATCHER`
+    pub shadow_catcher: bool,
+    /// multiplies the glow across the surface
+    pub glow_texture: Option<TextureDef>,
+    /// tilts the shading normal, see `Material::normal_texture`. It's linear
+    /// unless it says it's sRGB.
+    pub normal_map: Option<TextureDef>,
+    pub normal_strength: f32,
+    /// cuts holes where its alpha, or its brightness if it has none, is
+    /// below `alpha_cutoff`, see `Material::alpha_texture`
+    pub alpha_texture: Option<TextureDef>,
+    pub alpha_cutoff: f32,
+    /// replaces the colour, see `Noise`
+    pub noise: Option<NoiseDef>,
+}
+
+/// Fractal noise for a material's colour, see `Noise`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NoiseDef
+{
+    /// the colours at the lowest and highest noise
+    pub colours: [[f32; 3]; 2],
+    pub scale: f32,
+    pub octaves: u32,
+    pub lacunarity: f32,
+    pub gain: f32,
+}
+
+/// An image file, relative to the scene file it's in until the scene is
+/// loaded, see `SceneDef::load`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureDef
+{
+    pub file: String,
+    /// the colours are sRGB rather than linear, which most images are
+    pub srgb: bool,
+}
+
+/// A node in "nodes", which places its surfaces and its children's. A named
+/// node is also a group of everything in it.
+#[derive(Clone, Debug)]
+pub struct NodeDef
+{
+    pub name: Option<String>,
+    /// by its index in `SceneDef::nodes`
+    pub parent: Option<usize>,
+    /// where it puts things in its parent
+    pub placement: Placement,
+    /// what a top level node's positions are multiplied by, see
+    /// `SceneDef::scale`
+    pub units: f32,
+    pub at: Location,
+}
+
+#[derive(Clone, Debug)]
+pub struct SurfaceDef
+{
+    pub shape: ShapeDef,
+    pub mat: MatRef,
+    pub group: Option<String>,
+    /// the node it's in, by its index in `SceneDef::nodes`
+    pub node: Option<usize>,
+    /// reverses the winding, which turns the front to the back
+    pub flip: bool,
+    /// how far it moves in a frame, see `Scene::velocities`
+    pub velocity: [f32; 3],
+    /// colours at a triangle's points, see `Scene::colours`
+    pub colours: Option<[[f32; 3]; 3]>,
+    /// texture coordinates at a triangle's or quad's points, see
+    /// `Scene::uvs`
+    pub uvs: Option<Vec<[f32; 2]>>,
+    /// normals at a triangle's points, see `Scene::normals`
+    pub normals: Option<[[f32; 3]; 3]>,
+    pub at: Location,
+}
+
+#[derive(Clone, Debug)]
+pub enum ShapeDef
+{
+    Tri([[f32; 3]; 3]),
+    Quad([[f32; 3]; 4]),
+    /// at least 3 points in order around the edge, see `Scene::add_polygon`
+    Polygon(Vec<[f32; 3]>),
+    /// an icosphere, see `Scene::add_sphere_mesh`
+    SphereMesh
+    {
+        center: [f32; 3],
+        radius: f32,
+        subdivisions: u32,
+    },
+    /// a grid with the image's top left pixel at `corner`, its rows along
+    /// `across` and its columns along `down`, raised by `height` times
+    /// each pixel's brightness, see `mesh::height_grid`
+    Heightmap
+    {
+        /// relative to the scene file it's in until the scene is loaded
+        file: String,
+        corner: [f32; 3],
+        across: [f32; 3],
+        down: [f32; 3],
+        height: f32,
+        /// vertices each way, or one per pixel
+        resolution: Option<[u32; 2]>,
+    },
+}
+
+/// A material by its position in "materials", by name or written out in
+/// place, or the default material when a surface doesn't say.
+#[derive(Clone, Debug)]
+pub enum MatRef
+{
+    Index(u32),
+    Name(String),
+    /// written out on the surface itself, so it has no name
+    Inline(MaterialDef),
+    Default,
+}
+
+/// Where a value is in the scene file. The line is only looked for when
+/// it's asked for, since finding it scans the file.
+#[derive(Clone)]
+pub struct Location
+{
+    /// the included file it's from, `None` for the scene itself
+    pub file: Option<String>,
+    pub path: String,
+    /// the JSON source and the path through it
+    source: Option<Arc<str>>,
+    segs: Vec<Seg>,
+}
+
+impl std::fmt::Debug for Location
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        f.debug_struct("Location")
+            .field("file", &self.file)
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+impl Location
+{
+    /// The line the value starts on, if the scene was JSON.
+    pub fn line(&self) -> Option<usize>
+    {
+        self.source.as_deref().and_then(|s| locate(s, &self.segs))
+    }
+
+    /// `msg` prefixed with the file and path and followed by the line.
+    pub fn message(&self, msg: &str) -> String
+    {
+        let msg = match self.line()
+        {
+            Some(line) => format!("{}: {} at line {}", self.path, msg, line),
+            None => format!("{}: {}", self.path, msg),
+        };
+
+        match &self.file
+        {
+            Some(file) => format!("{}: {}", file, msg),
+            None => msg,
+        }
+    }
+}
+
+/// The formats a scene file can be written in. YAML and TOML need the
+/// "yaml" and "toml" features.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format
+{
+    /// JSON that can also have comments, trailing commas and unquoted keys
+    Json,
+    StrictJson,
+    Yaml,
+    Toml,
+}
+
+impl Format
+{
+    /// Chooses the format from a file's extension, defaulting to JSON.
+    pub fn from_path(path: &str) -> Format
+    {
+        let ext = std::path::Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+
+        match ext.as_deref()
+        {
+            Some("yaml") | Some("yml") => Format::Yaml,
+            Some("toml") => Format::Toml,
+            _ => Format::Json,
+        }
+    }
+}
+
+impl SceneDef
+{
+    /// Reads a scene, also returning warnings about keys that aren't used,
+    /// which are usually typos. Every format reads into the same tree, but
+    /// only JSON errors have line numbers past the initial parse.
+    /// Includes aren't read, see `SceneDef::load` and
+    /// `SceneDef::resolve_includes`.
+    pub fn parse(source: &str, format: Format) -> Result<(SceneDef, Vec<String>), String>
+    {
+        SceneDef::parse_as(source, format, false)
+    }
+
+    /// Reads a scene file in the format its extension says, along with
+    /// everything it includes.
+    pub fn load(path: &str) -> Result<(SceneDef, Vec<String>), String>
+    {
+        SceneDef::load_as(path, Format::from_path(path))
+    }
+
+    /// Like `SceneDef::load`, but reading the file itself as `format`.
+    /// Included files still go by their extensions.
+    pub fn load_as(path: &str, format: Format) -> Result<(SceneDef, Vec<String>), String>
+    {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read scene \"{}\": {}", path, e))?;
+
+        let (mut def, mut warnings) = SceneDef::parse(&source, format)?;
+
+        let path = Path::new(path);
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+        def.resolve_paths(dir);
+
+        if !def.include.is_empty()
+        {
+            let canon = path.canonicalize()
+                .map_err(|e| format!("Could not read scene \"{}\": {}", path.display(), e))?;
+
+            warnings.extend(def.merge_includes(dir, &mut vec![canon], None)?);
+        }
+
+        Ok((def, warnings))
+    }
+
+    /// Reads a scene that isn't in a file, like one piped in, with includes
+    /// and relative paths going from `dir`.
+    pub fn load_source(source: &str, format: Format, dir: &Path) -> Result<(SceneDef, Vec<String>), String>
+    {
+        let (mut def, mut warnings) = SceneDef::parse(source, format)?;
+
+        def.resolve_paths(dir);
+        warnings.extend(def.resolve_includes(dir)?);
+
+        Ok((def, warnings))
+    }
+
+    /// Like `SceneDef::load_source`, but for scenes that can't be trusted:
+    /// every file it includes or reads, followed through `..` and links,
+    /// has to be inside `root`.
+    pub fn load_confined(source: &str, format: Format, root: &Path)
+        -> Result<(SceneDef, Vec<String>), String>
+    {
+        let root = root.canonicalize()
+            .map_err(|e| format!("Could not find \"{}\": {}", root.display(), e))?;
+
+        let (mut def, mut warnings) = SceneDef::parse(source, format)?;
+
+        def.resolve_paths(&root);
+        warnings.extend(def.merge_includes(&root, &mut Vec::new(), Some(&root))?);
+
+        for file in def.files()
+        {
+            match Path::new(&file).canonicalize()
+            {
+                Ok(canon) if canon.starts_with(&root) => (),
+                _ => return Err(format!("\"{}\" isn't a file inside the scene root", file)),
+            }
+        }
+
+        Ok((def, warnings))
+    }
+
+    /// Moves the materials and surfaces of every included file, and
+    /// everything they include, ahead of this scene's own. Include paths are
+    /// relative to `dir`, and a file included more than once is only used
+    /// the first time. Material indices count from the start of their own
+    /// file, so they're offset to match. Returns warnings from the included
+    /// files.
+    pub fn resolve_includes(&mut self, dir: &Path) -> Result<Vec<String>, String>
+    {
+        self.merge_includes(dir, &mut Vec::new(), None)
+    }
+
+    /// `stack` is the chain of files that included this one, for finding
+    /// cycles. Every include has to be inside `root`, if there is one.
+    fn merge_includes(&mut self, dir: &Path, stack: &mut Vec<PathBuf>, root: Option<&Path>)
+        -> Result<Vec<String>, String>
+    {
+        if self.include.is_empty()
+        {
+            return Ok(Vec::new());
+        }
+
+        let mut warnings = Vec::new();
+        let mut files = Vec::new();
+        SceneDef::collect(&self.include, dir, root, stack, &mut Vec::new(), &mut files, &mut warnings)?;
+
+        let own = SceneDef
+        {
+            materials: std::mem::take(&mut self.materials),
+            surfaces: std::mem::take(&mut self.surfaces),
+            nodes: std::mem::take(&mut self.nodes),
+            include: std::mem::take(&mut self.include),
+            .. self.clone()
+        };
+        files.push((None, own));
+
+        self.included.extend(files.iter().filter_map(|(file, _)| file.clone()));
+
+        // which file each material name came from
+        let mut names: HashMap<String, Option<String>> = HashMap::new();
+        let describe = |file: &Option<String>| match file
+        {
+            Some(file) => format!("\"{}\"", file),
+            None => "the scene".to_owned(),
+        };
+
+        for (file, def) in files
+        {
+            let offset = self.materials.len() as u32;
+            let count = def.materials.len();
+            let node_offset = self.nodes.len();
+
+            for mut node in def.nodes
+            {
+                if let Some(name) = &node.name
+                {
+                    if self.nodes.iter().any(|n| n.name.as_ref() == Some(name))
+                    {
+                        return Err(format!("Node \"{}\" is in {} and an earlier file",
+                            name, describe(&file)));
+                    }
+                }
+
+                node.parent = node.parent.map(|p| p + node_offset);
+                node.at.file = file.clone();
+                self.nodes.push(node);
+            }
+
+            for (name, mat) in def.materials
+            {
+                if let Some(other) = names.insert(name.clone(), file.clone())
+                {
+                    return Err(format!("Material \"{}\" is in both {} and {}",
+                        name, describe(&other), describe(&file)));
+                }
+
+                self.materials.push((name, mat));
+            }
+
+            for mut surface in def.surfaces
+            {
+                surface.at.file = file.clone();
+                surface.node = surface.node.map(|n| n + node_offset);
+
+                if let MatRef::Index(i) = &mut surface.mat
+                {
+                    if *i as usize >= count
+                    {
+                        return Err(surface.at.message(&format!(
+                            "material {} doesn't exist, the file has {}", i, count)));
+                    }
+
+                    *i += offset;
+                }
+
+                self.surfaces.push(surface);
+            }
+        }
+
+        Ok(warnings)
+    }
+
+    /// Reads the files in `include`, putting each after the files it
+    /// includes itself.
+    fn collect(
+        include: &[(String, f32, Location)],
+        dir: &Path,
+        root: Option<&Path>,
+        stack: &mut Vec<PathBuf>,
+        seen: &mut Vec<PathBuf>,
+        files: &mut Vec<(Option<String>, SceneDef)>,
+        warnings: &mut Vec<String>)
+        -> Result<(), String>
+    {
+        for (file, scale, at) in include
+        {
+            let path = dir.join(file);
+            let name = path.display().to_string();
+            let canon = path.canonicalize()
+                .map_err(|e| at.message(&format!("could not include \"{}\": {}", name, e)))?;
+
+            if matches!(root, Some(root) if !canon.starts_with(root))
+            {
+                return Err(at.message(&format!("\"{}\" isn't inside the scene root", name)));
+            }
+
+            if let Some(start) = stack.iter().position(|p| *p == canon)
+            {
+                let cycle = stack[start..].iter()
+                    .chain(std::iter::once(&canon))
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>();
+
+                return Err(at.message(&format!("include cycle {}", cycle.join(" -> "))));
+            }
+
+            if seen.contains(&canon)
+            {
+                continue;
+            }
+            seen.push(canon.clone());
+
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| at.message(&format!("could not include \"{}\": {}", name, e)))?;
+            let (mut def, more) = SceneDef::parse_as(&source, Format::from_path(file), true)
+                .map_err(|e| format!("{}: {}", name, e))?;
+
+            def.resolve_paths(path.parent().unwrap_or_else(|| Path::new(".")));
+
+            for (_, _, at) in &mut def.include
+            {
+                at.file = Some(name.clone());
+            }
+
+            warnings.extend(more.into_iter().map(|w| format!("{}: {}", name, w)));
+
+            if def.camera.is_some() || !def.cameras.is_empty() || def.animation.is_some()
+            {
+                warnings.push(format!(
+                    "{}: cameras and animations in included files are ignored", name));
+            }
+
+            let first = files.len();
+
+            stack.push(canon);
+            SceneDef::collect(&def.include,
+                path.parent().unwrap_or_else(|| Path::new(".")),
+                root, stack, seen, files, warnings)?;
+            stack.pop();
+
+            files.push((Some(name), def));
+
+            // the include's scale is on top of the units the files declare
+            for (_, def) in &mut files[first..]
+            {
+                def.scale_by(*scale);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Included files, and files that include others, don't need
+    /// "materials" or "surfaces".
+    fn parse_as(source: &str, format: Format, included: bool)
+        -> Result<(SceneDef, Vec<String>), String>
+    {
+        match format
+        {
+            Format::Json =>
+            {
+                let source = relax(source);
+                let top = json::parse(&source)
+                    .map_err(|e| format!("Error parsing scene JSON: {}", e))?;
+
+                SceneDef::read(&top, Some(&source), included)
+            },
+            Format::StrictJson =>
+            {
+                let top = json::parse(source)
+                    .map_err(|e| format!("Error parsing scene JSON: {}", e))?;
+
+                SceneDef::read(&top, Some(source), included)
+            },
+            Format::Yaml => SceneDef::read(&parse_yaml(source)?, None, included),
+            Format::Toml => SceneDef::read(&parse_toml(source)?, None, included),
+        }
+    }
+
+    /// How many triangles the surfaces make, without making them.
+    pub fn triangle_count(&self) -> u64
+    {
+        self.surfaces.iter().map(|s| s.shape.triangle_count()).sum()
+    }
+
+    /// Every file the scene reads besides itself: what it includes, then
+    /// textures and heightmaps, each once.
+    pub fn files(&self) -> Vec<String>
+    {
+        let heightmaps = self.surfaces.iter().filter_map(|s| match &s.shape
+        {
+            ShapeDef::Heightmap { file, .. } => Some(file),
+            _ => None,
+        });
+
+        let inline = self.surfaces.iter().filter_map(|s| match &s.mat
+        {
+            MatRef::Inline(mat) => Some(mat),
+            _ => None,
+        });
+
+        let textures = self.materials.iter()
+            .map(|(_, mat)| mat)
+            .chain(self.default_material.iter())
+            .chain(inline)
+            .flat_map(|mat| mat.glow_texture.iter()
+                .chain(mat.normal_map.iter())
+                .chain(mat.alpha_texture.iter()))
+            .map(|t| &t.file);
+
+        let mut files: Vec<String> = Vec::new();
+
+        for file in self.included.iter().chain(textures).chain(heightmaps)
+        {
+            if !files.contains(file)
+            {
+                files.push(file.clone());
+            }
+        }
+
+        files
+    }
+
+    /// Makes the files materials and heightmaps use relative to `dir`
+    /// instead of this scene file.
+    fn resolve_paths(&mut self, dir: &Path)
+    {
+        let resolve = |file: &mut String| if Path::new(file.as_str()).is_relative()
+        {
+            *file = dir.join(file.as_str()).display().to_string();
+        };
+
+        for surface in &mut self.surfaces
+        {
+            if let ShapeDef::Heightmap { file, .. } = &mut surface.shape
+            {
+                resolve(file);
+            }
+        }
+
+        let inline = self.surfaces.iter_mut().filter_map(|s| match &mut s.mat
+        {
+            MatRef::Inline(mat) => Some(mat),
+            _ => None,
+        });
+
+        let materials = self.materials.iter_mut()
+            .map(|(_, mat)| mat)
+            .chain(self.default_material.iter_mut())
+            .chain(inline);
+
+        for mat in materials
+        {
+            let textures = mat.glow_texture.iter_mut()
+                .chain(mat.normal_map.iter_mut())
+                .chain(mat.alpha_texture.iter_mut());
+
+            for texture in textures
+            {
+                resolve(&mut texture.file);
+            }
+        }
+    }
+
+    /// Multiplies every position and size by `scale`, leaving directions
+    /// alone. Includes and the animation are scaled when they're used.
+    fn scale_by(&mut self, scale: f32)
+    {
+        if scale == 1.0
+        {
+            return;
+        }
+
+        let mul = |v: &mut [f32; 3]| v.iter_mut().for_each(|c| *c *= scale);
+
+        for camera in self.camera.iter_mut().chain(self.cameras.iter_mut().map(|(_, c)| c))
+        {
+            mul(&mut camera.pos);
+        }
+
+        for surface in &mut self.surfaces
+        {
+            match &mut surface.shape
+            {
+                ShapeDef::Tri(points) => points.iter_mut().for_each(mul),
+                ShapeDef::Quad(points) => points.iter_mut().for_each(mul),
+                ShapeDef::Polygon(points) => points.iter_mut().for_each(mul),
+                ShapeDef::SphereMesh { center, radius, .. } =>
+                {
+                    mul(center);
+                    *radius *= scale;
+                },
+                ShapeDef::Heightmap { corner, across, down, height, .. } =>
+                {
+                    mul(corner);
+                    mul(across);
+                    mul(down);
+                    *height *= scale;
+                },
+            }
+
+            mul(&mut surface.velocity);
+        }
+
+        for node in self.nodes.iter_mut().filter(|n| n.parent.is_none())
+        {
+            node.units *= scale;
+        }
+
+        self.scale *= scale;
+    }
+
+    fn read(top: &JsonValue, source: Option<&str>, included: bool)
+        -> Result<(SceneDef, Vec<String>), String>
+    {
+        let ctx = Context
+        {
+            source: source.map(Arc::from),
+            warnings: RefCell::new(Vec::new()),
+        };
+        let root = Node
+        {
+            val: top,
+            path: Vec::new(),
+            ctx: &ctx,
+        };
+
+        root.object(&[
+            "include", "camera", "cameras", "materials", "default_material", "surfaces", "nodes",
+            "animation", "units", "scale",
+        ])?;
+
+        let mut include = Vec::new();
+        if let Some(files) = root.key("include")
+        {
+            for file in files.members()?
+            {
+                if let Some(path) = file.val.as_str()
+                {
+                    include.push((path.to_owned(), 1.0, file.location()));
+                    continue;
+                }
+
+                if !file.val.is_object()
+                {
+                    return file.error("expected a file name or an object with a \"file\"");
+                }
+
+                file.object(&["file", "units", "scale"])?;
+
+                let path = file.required("file")?;
+                match path.val.as_str()
+                {
+                    Some(name) => include.push((name.to_owned(), read_scale(&file)?,
+                        path.location())),
+                    None => return path.error("expected a file name"),
+                }
+            }
+        }
+
+        let scale = read_scale(&root)?;
+
+        // a missing key gets the usual error
+        let partial = included || !include.is_empty();
+        let optional = |key: &str| match root.key(key)
+        {
+            Some(node) => Ok(Some(node)),
+            None if partial => Ok(None),
+            None => root.required(key).map(Some),
+        };
+
+        let auto_camera = matches!(root.key("camera"), Some(c) if c.val.as_str() == Some("auto"));
+        let camera = match root.key("camera")
+        {
+            Some(_) if auto_camera => None,
+            Some(camera) => Some(CameraDef::read(&camera)?),
+            None => None,
+        };
+
+        let mut cameras = Vec::new();
+        if let Some(named) = root.key("cameras")
+        {
+            named.object(&[])?;
+
+            for (name, camera) in named.entries()
+            {
+                cameras.push((name.to_owned(), CameraDef::read(&camera)?));
+            }
+        }
+
+        let mut materials = Vec::new();
+        if let Some(mats) = optional("materials")?
+        {
+            mats.object(&[])?;
+
+            for (name, mat) in mats.entries()
+            {
+                materials.push((name.to_owned(), MaterialDef::read(&mat)?));
+            }
+        }
+
+        let default_material = match root.key("default_material")
+        {
+            Some(mat) => Some(MaterialDef::read(&mat)?),
+            None => None,
+        };
+
+        // a scene can be all nodes
+        let mut surfaces = Vec::new();
+        let surfs = match root.key("nodes")
+        {
+            Some(_) => root.key("surfaces"),
+            None => optional("surfaces")?,
+        };
+        if let Some(surfs) = surfs
+        {
+            for surface in surfs.members()?
+            {
+                SurfaceDef::read_all(&surface, &mut surfaces)?;
+            }
+        }
+
+        let mut nodes = Vec::new();
+        if let Some(tree) = root.key("nodes")
+        {
+            for node in tree.members()?
+            {
+                NodeDef::read_all(&node, None, &Transform::IDENTITY, &mut nodes, &mut surfaces)?;
+            }
+        }
+
+        let animation = root.key("animation").map(|a| (a.val.clone(), a.location()));
+
+        let mut def = SceneDef
+        {
+            camera: camera,
+            auto_camera: auto_camera,
+            cameras: cameras,
+            materials: materials,
+            default_material: default_material,
+            surfaces: surfaces,
+            nodes: nodes,
+            animation: animation,
+            include: include,
+            included: Vec::new(),
+            scale: 1.0,
+        };
+
+        def.scale_by(scale);
+
+        Ok((def, ctx.warnings.into_inner()))
+    }
+}
+
+impl CameraDef
+{
+    fn read(node: &Node) -> Result<CameraDef, String>
+    {
+        node.object(&["pos", "front", "up", "fov", "exposure", "shutter"])?;
+
+        Ok(CameraDef
+        {
+            pos: node.required("pos")?.vec3()?,
+            front: node.required("front")?.vec3()?,
+            up: node.required("up")?.vec3()?,
+            fov: node.required("fov")?.f64()?,
+            exposure: match node.key("exposure")
+            {
+                Some(e) => e.f32()?,
+                None => 0.0,
+            },
+            shutter: match node.key("shutter")
+            {
+                Some(shutter) => match shutter.f32()?
+                {
+                    s if (0.0..=1.0).contains(&s) => s,
+                    _ => return shutter.error("expected a shutter from 0 to 1 frames"),
+                },
+                None => 0.0,
+            },
+        })
+    }
+}
+
+impl MaterialDef
+{
+    /// The names "preset" takes, in the order they're listed in errors.
+    pub const PRESETS: [&'static str; 4] = ["matte", "mirror", "glass", "light"];
+
+    /// What a material is without a preset, black and not shiny.
+    pub const BLANK: MaterialDef = MaterialDef
+    {
+        colour: [0.0, 0.0, 0.0],
+        glow: [0.0, 0.0, 0.0],
+        gloss: 0.0,
+        reflect_c: [1.0, 1.0, 1.0],
+        one_sided: false,
+        shadow_catcher: false,
+        glow_texture: None,
+        normal_map: None,
+        normal_strength: 1.0,
+        alpha_texture: None,
+        alpha_cutoff: 0.5,
+        noise: None,
+    };
+
+    /// A material to start from instead of writing out every field.
+    pub fn preset(name: &str) -> Option<MaterialDef>
+    {
+        let base = MaterialDef::BLANK;
+
+        Some(match name
+        {
+            // white paint reflects about 80% of light
+            "matte" => MaterialDef { colour: [0.8, 0.8, 0.8], ..base },
+            // polished silver or aluminium
+            "mirror" => MaterialDef { gloss: 1.0, reflect_c: [0.95, 0.95, 0.95], ..base },
+            // the few percent that glass reflects at its surface over a pale
+            // body, until light can pass through surfaces
+            "glass" => MaterialDef { colour: [0.9, 0.9, 0.9], gloss: 0.08, ..base },
+            // a bright, slightly warm panel that glows from its front
+            "light" => MaterialDef { glow: [8.0, 7.5, 7.0], one_sided: true, ..base },
+            _ => return None,
+        })
+    }
+
+    fn read(node: &Node) -> Result<MaterialDef, String>
+    {
+        node.object(&[
+            "preset", "colour", "glow", "gloss", "reflect_c", "one_sided", "shadow_catcher",
+            "glow_texture", "normal_map", "normal_strength", "alpha_texture", "alpha_cutoff",
+            "noise"])?;
+
+        let base = match node.key("preset")
+        {
+            Some(preset) => match preset.val.as_str()
+            {
+                Some(name) => match MaterialDef::preset(name)
+                {
+                    Some(base) => base,
+                    None => return preset.error(&format!(
+                        "unknown preset \"{}\", expected one of \"{}\"",
+                        name, MaterialDef::PRESETS.join("\", \""))),
+                },
+                None => return preset.error("expected a preset name"),
+            },
+            None => MaterialDef::BLANK,
+        };
+
+        let vec3 = |key: &str, default: [f32; 3]| match node.key(key)
+        {
+            Some(v) => v.vec3(),
+            None => Ok(default),
+        };
+
+        Ok(MaterialDef
+        {
+            colour: vec3("colour", base.colour)?,
+            glow: vec3("glow", base.glow)?,
+            gloss: match node.key("gloss")
+            {
+                Some(gloss) => gloss.f32()?,
+                None => base.gloss,
+            },
+            reflect_c: vec3("reflect_c", base.reflect_c)?,
+            one_sided: match node.key("one_sided")
+            {
+                Some(one_sided) => one_sided.bool()?,
+                None => base.one_sided,
+            },
+            shadow_catcher: match node.key("shadow_catcher")
+            {
+                Some(catcher) => catcher.bool()?,
+                None => base.shadow_catcher,
+            },
+            glow_texture: match node.key("glow_texture")
+            {
+                Some(texture) => Some(TextureDef::read(&texture, true)?),
+                None => base.glow_texture,
+            },
+            normal_map: match node.key("normal_map")
+            {
+                Some(texture) => Some(TextureDef::read(&texture, false)?),
+                None => base.normal_map,
+            },
+            normal_strength: match node.key("normal_strength")
+            {
+                Some(strength) => match strength.f32()?
+                {
+                    s if s >= 0.0 => s,
+                    _ => return strength.error("expected a strength of at least 0"),
+                },
+                None => base.normal_strength,
+            },
+            alpha_texture: match node.key("alpha_texture")
+            {
+                Some(texture) => Some(TextureDef::read(&texture, false)?),
+                None => base.alpha_texture,
+            },
+            alpha_cutoff: match node.key("alpha_cutoff")
+            {
+                Some(cutoff) => match cutoff.f32()?
+                {
+                    c if (0.0..=1.0).contains(&c) => c,
+                    _ => return cutoff.error("expected a cutoff from 0 to 1"),
+                },
+                None => base.alpha_cutoff,
+            },
+            noise: match node.key("noise")
+            {
+                Some(noise) => Some(NoiseDef::read(&noise)?),
+                None => base.noise,
+            },
+        })
+    }
+}
+
+impl NoiseDef
+{
+    /// More octaves are finer than a pixel in any sensible scene.
+    pub const MAX_OCTAVES: u32 = 16;
+
+    /// An object with the two "colours" and optionally the "scale",
+    /// "octaves", "lacunarity" and "gain".
+    fn read(node: &Node) -> Result<NoiseDef, String>
+    {
+        node.object(&["colours", "scale", "octaves", "lacunarity", "gain"])?;
+
+        let colours = node.required("colours")?;
+
+        if !colours.val.is_array() || colours.val.len() != 2
+        {
+            return colours.error("expected an array of 2 colours");
+        }
+
+        let members = colours.members()?;
+
+        let positive = |key: &str, default: f32| match node.key(key)
+        {
+            Some(n) => match n.f32()?
+            {
+                v if v > 0.0 => Ok(v),
+                _ => n.error(&format!("expected a positive {}", key)),
+            },
+            None => Ok(default),
+        };
+
+        Ok(NoiseDef
+        {
+            colours: [members[0].vec3()?, members[1].vec3()?],
+            scale: positive("scale", 1.0)?,
+            octaves: match node.key("octaves")
+            {
+                Some(octaves) => match octaves.val.as_u32()
+                {
+                    Some(n) if (1..=NoiseDef::MAX_OCTAVES).contains(&n) => n,
+                    _ => return octaves.error(&format!(
+                        "expected a whole number from 1 to {}", NoiseDef::MAX_OCTAVES)),
+                },
+                None => 4,
+            },
+            lacunarity: positive("lacunarity", 2.0)?,
+            gain: match node.key("gain")
+            {
+                Some(gain) => match gain.f32()?
+                {
+                    g if g >= 0.0 => g,
+                    _ => return gain.error("expected a gain of at least 0"),
+                },
+                None => 0.5,
+            },
+        })
+    }
+}
+
+impl TextureDef
+{
+    /// A file name, or an object with a "file" and whether it's "srgb",
+    /// which is `srgb` when it's left out.
+    fn read(node: &Node, srgb: bool) -> Result<TextureDef, String>
+    {
+        if let Some(file) = node.val.as_str()
+        {
+            return Ok(TextureDef { file: file.to_owned(), srgb: srgb });
+        }
+
+        if !node.val.is_object()
+        {
+            return node.error("expected a file name or an object with a \"file\"");
+        }
+
+        node.object(&["file", "srgb"])?;
+
+        let file = node.required("file")?;
+
+        Ok(TextureDef
+        {
+            file: match file.val.as_str()
+            {
+                Some(name) => name.to_owned(),
+                None => return file.error("expected a file name"),
+            },
+            srgb: match node.key("srgb")
+            {
+                Some(srgb) => srgb.bool()?,
+                None => srgb,
+            },
+        })
+    }
+}
+
+impl ShapeDef
+{
+    /// How many triangles it's split into. Heightmaps without a resolution
+    /// read the image's size, and count none if it can't be read, which
+    /// loading the scene reports.
+    pub fn triangle_count(&self) -> u64
+    {
+        match self
+        {
+            ShapeDef::Tri(_) => 1,
+            ShapeDef::Quad(_) => 2,
+            ShapeDef::Polygon(points) => points.len() as u64 - 2,
+            ShapeDef::SphereMesh { subdivisions, .. } => 20 << (2 * subdivisions),
+            ShapeDef::Heightmap { file, resolution, .. } =>
+            {
+                let [cols, rows] = match resolution
+                {
+                    Some(resolution) => *resolution,
+                    None => image::image_dimensions(file).map_or([0, 0], |(w, h)| [w, h]),
+                };
+
+                2 * (cols.max(1) as u64 - 1) * (rows.max(1) as u64 - 1)
+            },
+        }
+    }
+}
+
+impl SurfaceDef
+{
+    /// Reads a surface into `out`, or every copy of the surfaces in a
+    /// "repeat", see `read_repeat`.
+    fn read_all(node: &Node, out: &mut Vec<SurfaceDef>) -> Result<(), String>
+    {
+        let repeat = match node.key("repeat")
+        {
+            Some(repeat) => repeat,
+            None =>
+            {
+                out.push(SurfaceDef::read(node)?);
+                return Ok(());
+            },
+        };
+
+        node.object(&["repeat", "surfaces"])?;
+
+        let copies = read_repeat(&repeat)?;
+
+        let mut inner = Vec::new();
+        for surface in node.required("surfaces")?.members()?
+        {
+            SurfaceDef::read_all(&surface, &mut inner)?;
+        }
+
+        let total = copies.len() as u64 * inner.len() as u64;
+        if total > MAX_REPEATED
+        {
+            return repeat.error(&format!(
+                "would make {} surfaces, the most is {}", total, MAX_REPEATED));
+        }
+
+        for copy in &copies
+        {
+            let copy = copy.transform();
+            out.extend(inner.iter().map(|s| s.placed(&copy)));
+        }
+
+        Ok(())
+    }
+
+    /// A copy of the surface where `placement` puts it.
+    fn placed(&self, placement: &Transform) -> SurfaceDef
+    {
+        let points = |points: &[[f32; 3]]| points.iter()
+            .map(|&p| placement.point(p))
+            .collect::<Vec<_>>();
+
+        let shape = match &self.shape
+        {
+            ShapeDef::Tri(p) =>
+            {
+                let p = points(p);
+                ShapeDef::Tri([p[0], p[1], p[2]])
+            },
+            ShapeDef::Quad(p) =>
+            {
+                let p = points(p);
+                ShapeDef::Quad([p[0], p[1], p[2], p[3]])
+            },
+            ShapeDef::Polygon(p) => ShapeDef::Polygon(points(p)),
+            &ShapeDef::SphereMesh { center, radius, subdivisions } => ShapeDef::SphereMesh
+            {
+                center: placement.point(center),
+                radius: radius * placement.scale,
+                subdivisions: subdivisions,
+            },
+            ShapeDef::Heightmap { file, corner, across, down, height, resolution } =>
+                ShapeDef::Heightmap
+                {
+                    file: file.clone(),
+                    corner: placement.point(*corner),
+                    across: placement.vector(*across),
+                    down: placement.vector(*down),
+                    height: height * placement.scale,
+                    resolution: *resolution,
+                },
+        };
+
+        SurfaceDef
+        {
+            shape: shape,
+            velocity: placement.vector(self.velocity),
+            normals: self.normals.map(|n| n.map(|n| crate::vec3::normalize(placement.vector(n)))),
+            .. self.clone()
+        }
+    }
+
+    fn read(node: &Node) -> Result<SurfaceDef, String>
+    {
+        const SHAPES: [&str; 5] = ["tri", "quad", "polygon", "sphere_mesh", "heightmap"];
+
+        node.object(&[
+            "tri", "quad", "polygon", "sphere_mesh", "heightmap", "mat", "group", "flip",
+            "velocity", "colours", "uvs", "normals"])?;
+
+        let group = match node.key("group")
+        {
+            Some(group) => match group.val.as_str()
+            {
+                Some(name) => Some(name.to_owned()),
+                None => return group.error("expected a group name"),
+            },
+            None => None,
+        };
+
+        let mat = match node.key("mat")
+        {
+            None => MatRef::Default,
+            Some(mat) => if let Some(index) = mat.val.as_u32()
+            {
+                MatRef::Index(index)
+            }
+            else if let Some(name) = mat.val.as_str()
+            {
+                MatRef::Name(name.to_owned())
+            }
+            else if mat.val.is_object()
+            {
+                MatRef::Inline(MaterialDef::read(&mat)?)
+            }
+            else
+            {
+                return mat.error("expected a material name, index or object");
+            },
+        };
+
+        let shapes = SHAPES.iter()
+            .filter_map(|&key| node.key(key).map(|n| (key, n)))
+            .collect::<Vec<_>>();
+
+        let shape = match shapes.as_slice()
+        {
+            [] => return node.error(
+                "expected a \"tri\", \"quad\", \"polygon\", \"sphere_mesh\" or \"heightmap\""),
+            [(_, _)] => &shapes[0],
+            [(a, _), (b, _), ..] => return node.error(
+                &format!("can't be both a \"{}\" and a \"{}\"", a, b)),
+        };
+
+        let shape = match shape
+        {
+            ("tri", tri) =>
+            {
+                let p = tri.points(3)?;
+                ShapeDef::Tri([p[0], p[1], p[2]])
+            },
+            ("quad", quad) =>
+            {
+                let p = quad.points(4)?;
+                ShapeDef::Quad([p[0], p[1], p[2], p[3]])
+            },
+            ("polygon", polygon) =>
+            {
+                let points = polygon.members()?.iter()
+                    .map(|p| p.vec3())
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if points.len() < 3
+                {
+                    return polygon.error("expected at least 3 points");
+                }
+
+                ShapeDef::Polygon(points)
+            },
+            ("heightmap", heightmap) => read_heightmap(heightmap)?,
+            (_, sphere) =>
+            {
+                sphere.object(&["center", "radius", "subdivisions"])?;
+
+                let radius = sphere.required("radius")?;
+                let subdivisions = sphere.required("subdivisions")?;
+
+                ShapeDef::SphereMesh
+                {
+                    center: sphere.required("center")?.vec3()?,
+                    radius: match radius.f32()?
+                    {
+                        r if r > 0.0 => r,
+                        _ => return radius.error("expected a positive radius"),
+                    },
+                    subdivisions: match subdivisions.val.as_u32()
+                    {
+                        Some(n) if n <= MAX_SPHERE_SUBDIVISIONS => n,
+                        Some(n) => return subdivisions.error(&format!(
+                            "{} subdivisions is too many, the most is {} ({} triangles)",
+                            n, MAX_SPHERE_SUBDIVISIONS, 20 << (2 * MAX_SPHERE_SUBDIVISIONS))),
+                        None => return subdivisions.error("expected a whole number"),
+                    },
+                }
+            },
+        };
+
+        let colours = match node.key("colours")
+        {
+            Some(c) if !matches!(shape, ShapeDef::Tri(_)) =>
+                return c.error("only a \"tri\" can have \"colours\""),
+            Some(c) =>
+            {
+                let p = c.points(3)?;
+                Some([p[0], p[1], p[2]])
+            },
+            None => None,
+        };
+
+        let normals = match node.key("normals")
+        {
+            Some(n) if !matches!(shape, ShapeDef::Tri(_)) =>
+                return n.error("only a \"tri\" can have \"normals\""),
+            Some(n) =>
+            {
+                let p = n.points(3)?;
+                Some([p[0], p[1], p[2]])
+            },
+            None => None,
+        };
+
+        let uvs = match (node.key("uvs"), &shape)
+        {
+            (Some(uvs), ShapeDef::Tri(_)) => Some(uvs.uvs(3)?),
+            (Some(uvs), ShapeDef::Quad(_)) => Some(uvs.uvs(4)?),
+            (Some(uvs), _) => return uvs.error("only a \"tri\" or \"quad\" can have \"uvs\""),
+            (None, _) => None,
+        };
+
+        Ok(SurfaceDef
+        {
+            shape: shape,
+            mat: mat,
+            group: group,
+            node: None,
+            flip: match node.key("flip")
+            {
+                Some(flip) => flip.bool()?,
+                None => false,
+            },
+            velocity: match node.key("velocity")
+            {
+                Some(v) => v.vec3()?,
+                None => [0.0, 0.0, 0.0],
+            },
+            colours: colours,
+            uvs: uvs,
+            normals: normals,
+            at: node.location(),
+        })
+    }
+}
+
+impl NodeDef
+{
+    /// Reads a node and everything in it, putting the nodes in `nodes` after
+    /// their parents and the surfaces in `surfaces` where the nodes put
+    /// them. `outer` is where `parent` puts things in the world.
+    fn read_all(
+        node: &Node,
+        parent: Option<usize>,
+        outer: &Transform,
+        nodes: &mut Vec<NodeDef>,
+        surfaces: &mut Vec<SurfaceDef>)
+        -> Result<(), String>
+    {
+        node.object(&["name", "translate", "rotate", "scale", "surfaces", "children"])?;
+
+        let name = match node.key("name")
+        {
+            Some(name) => match name.val.as_str()
+            {
+                Some(n) if nodes.iter().any(|other| other.name.as_deref() == Some(n)) =>
+                    return name.error(&format!("another node is already called \"{}\"", n)),
+                Some(n) => Some(n.to_owned()),
+                None => return name.error("expected a node name"),
+            },
+            None => None,
+        };
+
+        let (axis, angle) = match node.key("rotate")
+        {
+            Some(rotate) =>
+            {
+                rotate.object(&["axis", "angle"])?;
+
+                let axis = rotate.required("axis")?;
+                match axis.vec3()?
+                {
+                    [0.0, 0.0, 0.0] => return axis.error("expected a direction, not zero"),
+                    a => (crate::vec3::normalize(a), rotate.required("angle")?.f32()?.to_radians()),
+                }
+            },
+            None => (Placement::STILL.axis, 0.0),
+        };
+
+        let scale = match node.key("scale")
+        {
+            Some(scale) => match scale.f32()?
+            {
+                s if s > 0.0 && s.is_finite() => s,
+                _ => return scale.error("expected a positive number"),
+            },
+            None => 1.0,
+        };
+
+        let placement = Placement
+        {
+            scale: scale,
+            axis: axis,
+            angle: angle,
+            offset: match node.key("translate")
+            {
+                Some(translate) => translate.vec3()?,
+                None => [0.0; 3],
+            },
+        };
+        let world = placement.transform().then(outer);
+
+        let index = nodes.len();
+        nodes.push(NodeDef
+        {
+            name: name,
+            parent: parent,
+            placement: placement,
+            units: 1.0,
+            at: node.location(),
+        });
+
+        if let Some(surfs) = node.key("surfaces")
+        {
+            let mut inner = Vec::new();
+            for surface in surfs.members()?
+            {
+                SurfaceDef::read_all(&surface, &mut inner)?;
+            }
+
+            surfaces.extend(inner.iter().map(|s| SurfaceDef
+            {
+                node: Some(index),
+                .. s.placed(&world)
+            }));
+        }
+
+        if let Some(children) = node.key("children")
+        {
+            for child in children.members()?
+            {
+                NodeDef::read_all(&child, Some(index), &world, nodes, surfaces)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Context
+{
+    /// the JSON source, for finding lines
+    source: Option<Arc<str>>,
+    warnings: RefCell<Vec<String>>,
+}
+
+#[derive(Clone, Debug)]
+enum Seg
+{
+    Key(String),
+    Index(usize),
+}
+
+/// A value in the file along with the path to it.
+struct Node<'a>
+{
+    val: &'a JsonValue,
+    path: Vec<Seg>,
+    ctx: &'a Context,
+}
+
+impl<'a> Node<'a>
+{
+    fn location(&self) -> Location
+    {
+        let mut path = String::new();
+
+        for seg in &self.path
+        {
+            match seg
+            {
+                Seg::Key(key) if path.is_empty() => path.push_str(key),
+                Seg::Key(key) => path.push_str(&format!(".{}", key)),
+                Seg::Index(i) => path.push_str(&format!("[{}]", i)),
+            }
+        }
+
+        if path.is_empty()
+        {
+            path.push_str("scene");
+        }
+
+        Location
+        {
+            file: None,
+            path: path,
+            source: self.ctx.source.clone(),
+            segs: self.path.clone(),
+        }
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T, String>
+    {
+        Err(self.location().message(msg))
+    }
+
+    fn child(&self, seg: Seg, val: &'a JsonValue) -> Node<'a>
+    {
+        let mut path = self.path.clone();
+        path.push(seg);
+
+        Node
+        {
+            val: val,
+            path: path,
+            ctx: self.ctx,
+        }
+    }
+
+    fn key(&self, key: &str) -> Option<Node<'a>>
+    {
+        if self.val.has_key(key)
+        {
+            Some(self.child(Seg::Key(key.to_owned()), &self.val[key]))
+        }
+        else
+        {
+            None
+        }
+    }
+
+    fn required(&self, key: &str) -> Result<Node<'a>, String>
+    {
+        match self.key(key)
+        {
+            Some(node) => Ok(node),
+            None => self.error(&format!("missing \"{}\"", key)),
+        }
+    }
+
+    /// Checks this is an object, and warns about keys that aren't in
+    /// `known`. Objects that are maps of names have no known keys.
+    fn object(&self, known: &[&str]) -> Result<(), String>
+    {
+        if !self.val.is_object()
+        {
+            return self.error("expected an object");
+        }
+
+        if !known.is_empty()
+        {
+            for (key, _) in self.val.entries()
+            {
+                if !known.contains(&key)
+                {
+                    let msg = self.key(key).unwrap().location()
+                        .message(&format!("unknown key \"{}\"", key));
+                    self.ctx.warnings.borrow_mut().push(msg);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn entries(&self) -> Vec<(&'a str, Node<'a>)>
+    {
+        self.val.entries()
+            .map(|(key, val)| (key, self.child(Seg::Key(key.to_owned()), val)))
+            .collect()
+    }
+
+    fn members(&self) -> Result<Vec<Node<'a>>, String>
+    {
+        if !self.val.is_array()
+        {
+            return self.error("expected an array");
+        }
+
+        Ok(self.val.members()
+            .enumerate()
+            .map(|(i, val)| self.child(Seg::Index(i), val))
+            .collect())
+    }
+
+    fn f32(&self) -> Result<f32, String>
+    {
+        // as_f32 rounds twice, so some numbers to_json writes wouldn't
+        // read back the same
+        self.f64().map(|v| v as f32)
+    }
+
+    fn f64(&self) -> Result<f64, String>
+    {
+        match self.val.as_f64()
+        {
+            Some(v) => Ok(v),
+            None => self.error("expected a number"),
+        }
+    }
+
+    fn bool(&self) -> Result<bool, String>
+    {
+        match self.val.as_bool()
+        {
+            Some(v) => Ok(v),
+            None => self.error("expected true or false"),
+        }
+    }
+
+    fn vec3(&self) -> Result<[f32; 3], String>
+    {
+        if !self.val.is_array() || self.val.len() != 3
+        {
+            return self.error("expected an array of 3 numbers");
+        }
+
+        let members = self.members()?;
+
+        Ok([members[0].f32()?, members[1].f32()?, members[2].f32()?])
+    }
+
+    fn points(&self, n: usize) -> Result<Vec<[f32; 3]>, String>
+    {
+        if !self.val.is_array() || self.val.len() != n
+        {
+            return self.error(&format!("expected an array of {} points", n));
+        }
+
+        self.members()?.iter().map(|p| p.vec3()).collect()
+    }
+
+    /// Two numbers, the first no bigger than the second.
+    fn range(&self) -> Result<[f32; 2], String>
+    {
+        if !self.val.is_array() || self.val.len() != 2
+        {
+            return self.error("expected an array of 2 numbers");
+        }
+
+        let members = self.members()?;
+
+        match [members[0].f32()?, members[1].f32()?]
+        {
+            [a, b] if a <= b => Ok([a, b]),
+            _ => self.error("expected the smaller number first"),
+        }
+    }
+
+    fn uvs(&self, n: usize) -> Result<Vec<[f32; 2]>, String>
+    {
+        if !self.val.is_array() || self.val.len() != n
+        {
+            return self.error(&format!("expected an array of {} texture coordinates", n));
+        }
+
+        self.members()?.iter()
+            .map(|uv| match uv.val.is_array() && uv.val.len() == 2
+            {
+                true =>
+                {
+                    let members = uv.members()?;
+                    Ok([members[0].f32()?, members[1].f32()?])
+                },
+                false => uv.error("expected an array of 2 numbers"),
+            })
+            .collect()
+    }
+}
+
+/// A "heightmap": an image "file", where its top left pixel goes ("corner"),
+/// which way and how far its rows go ("across") and its columns go ("down"),
+/// how far white pixels are raised ("height"), and optionally how many
+/// vertices the grid has each way ("resolution").
+fn read_heightmap(node: &Node) -> Result<ShapeDef, String>
+{
+    node.object(&["file", "corner", "across", "down", "height", "resolution"])?;
+
+    let file = node.required("file")?;
+    let across = node.required("across")?.vec3()?;
+    let down = node.required("down")?;
+
+    let resolution = match node.key("resolution")
+    {
+        Some(res) => match res.val.len() == 2
+        {
+            true =>
+            {
+                let n = res.members()?.iter()
+                    .map(|n| match n.val.as_u32()
+                    {
+                        Some(n) if (2..=MAX_HEIGHTMAP_RESOLUTION).contains(&n) => Ok(n),
+                        _ => n.error(&format!(
+                            "expected a whole number from 2 to {}", MAX_HEIGHTMAP_RESOLUTION)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Some([n[0], n[1]])
+            },
+            false => return res.error("expected an array of 2 vertex counts"),
+        },
+        None => None,
+    };
+
+    Ok(ShapeDef::Heightmap
+    {
+        file: match file.val.as_str()
+        {
+            Some(file) => file.to_owned(),
+            None => return file.error("expected a file name"),
+        },
+        corner: node.required("corner")?.vec3()?,
+        across: across,
+        down: match down.vec3()?
+        {
+            d if crate::vec3::length(crate::vec3::cross(d, across)) > 0.0 => d,
+            _ => return down.error("expected a direction that isn't along \"across\""),
+        },
+        height: node.required("height")?.f32()?,
+        resolution: resolution,
+    })
+}
+
+/// The most surfaces a "repeat" can make, so a typo in a count fails instead
+/// of running out of memory.
+const MAX_REPEATED: u64 = 10_000_000;
+
+/// SplitMix64, so scattered copies land in the same places for a seed on
+/// every machine.
+struct Scatter(u64);
+
+impl Scatter
+{
+    /// From 0 up to 1.
+    fn next(&mut self) -> f32
+    {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// From `range[0]` up to `range[1]`.
+    fn between(&mut self, range: [f32; 2]) -> f32
+    {
+        range[0] + (range[1] - range[0]) * self.next()
+    }
+}
+
+/// Where every copy of a "repeat" goes. A grid has a "count" along each
+/// axis and the "spacing" between copies. Otherwise copies are scattered
+/// through the "area" box from "min" to "max", by "seed", each turned a
+/// random amount within "rotation" (in degrees) about "axis" and scaled
+/// within "scale".
+fn read_repeat(node: &Node) -> Result<Vec<Placement>, String>
+{
+    const SCATTER: [&str; 5] = ["area", "seed", "rotation", "axis", "scale"];
+
+    node.object(&["count", "spacing", "area", "seed", "rotation", "axis", "scale"])?;
+
+    let count = node.required("count")?;
+    let still = Placement::STILL;
+
+    if count.val.is_array()
+    {
+        if let Some(key) = SCATTER.iter().find(|&&key| node.key(key).is_some())
+        {
+            return node.error(&format!("a grid can't have \"{}\", only a scatter can", key));
+        }
+
+        let n = match count.val.len() == 3
+        {
+            true => count.members()?.iter()
+                .map(|n| match n.val.as_u32()
+                {
+                    Some(n) if n > 0 => Ok(n),
+                    _ => n.error("expected a positive whole number"),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            false => return count.error("expected an array of 3 counts"),
+        };
+
+        let total = n.iter().map(|&n| n as u64).product::<u64>();
+        if total > MAX_REPEATED
+        {
+            return count.error(&format!("{} copies is too many, the most is {}",
+                total, MAX_REPEATED));
+        }
+
+        let spacing = node.required("spacing")?.vec3()?;
+
+        let mut copies = Vec::with_capacity(total as usize);
+        for x in 0..n[0]
+        {
+            for y in 0..n[1]
+            {
+                for z in 0..n[2]
+                {
+                    copies.push(Placement
+                    {
+                        offset: [
+                            x as f32 * spacing[0],
+                            y as f32 * spacing[1],
+                            z as f32 * spacing[2]],
+                        .. still
+                    });
+                }
+            }
+        }
+
+        return Ok(copies);
+    }
+
+    if let Some(spacing) = node.key("spacing")
+    {
+        return spacing.error("only a grid has \"spacing\", which needs an array of 3 counts");
+    }
+
+    let n = match count.val.as_u32()
+    {
+        Some(n) if n as u64 <= MAX_REPEATED => n,
+        Some(n) => return count.error(&format!(
+            "{} copies is too many, the most is {}", n, MAX_REPEATED)),
+        None => return count.error("expected a number of copies, or an array of 3 for a grid"),
+    };
+
+    let area = node.required("area")?;
+    area.object(&["min", "max"])?;
+    let min = area.required("min")?.vec3()?;
+    let max = area.required("max")?;
+    let max = match max.vec3()?
+    {
+        m if (0..3).all(|i| m[i] >= min[i]) => m,
+        _ => return max.error("expected no less than \"min\" along every axis"),
+    };
+
+    let seed = match node.key("seed")
+    {
+        Some(seed) => match seed.val.as_u64()
+        {
+            Some(seed) => seed,
+            None => return seed.error("expected a whole number"),
+        },
+        None => 0,
+    };
+
+    let (rotation, axis) = match (node.key("rotation"), node.key("axis"))
+    {
+        (Some(rotation), Some(axis)) => match axis.vec3()?
+        {
+            [0.0, 0.0, 0.0] => return axis.error("expected a direction, not zero"),
+            a => (rotation.range()?.map(f32::to_radians), crate::vec3::normalize(a)),
+        },
+        (Some(rotation), None) => return rotation.error("needs an \"axis\" to turn about"),
+        (None, Some(axis)) => return axis.error("is only used with a \"rotation\""),
+        (None, None) => ([0.0, 0.0], still.axis),
+    };
+
+    let scale = match node.key("scale")
+    {
+        Some(scale) => match scale.range()?
+        {
+            s if s[0] > 0.0 => s,
+            _ => return scale.error("expected positive scales"),
+        },
+        None => [1.0, 1.0],
+    };
+
+    let mut rand = Scatter(seed);
+
+    Ok((0..n)
+        .map(|_|
+        {
+            let offset = [0, 1, 2].map(|i| rand.between([min[i], max[i]]));
+
+            Placement
+            {
+                scale: rand.between(scale),
+                axis: axis,
+                angle: rand.between(rotation),
+                offset: offset,
+            }
+        })
+        .collect())
+}
+
+/// Lengths in meters of the names "units" takes.
+const UNITS: [(&str, f32); 6] = [
+    ("m", 1.0), ("cm", 0.01), ("mm", 0.001), ("km", 1000.0), ("in", 0.0254), ("ft", 0.3048)];
+
+/// What an object's "units" or "scale" multiplies positions by, 1 if it has
+/// neither.
+fn read_scale(node: &Node) -> Result<f32, String>
+{
+    match (node.key("units"), node.key("scale"))
+    {
+        (Some(_), Some(scale)) => scale.error("can't be given along with \"units\""),
+        (Some(units), None) =>
+        {
+            let name = units.val.as_str();
+
+            match UNITS.iter().find(|(unit, _)| Some(*unit) == name)
+            {
+                Some(&(_, scale)) => Ok(scale),
+                None => units.error(&format!("expected one of \"{}\"",
+                    UNITS.iter().map(|(unit, _)| *unit).collect::<Vec<_>>().join("\", \""))),
+            }
+        },
+        (None, Some(scale)) => match scale.f32()?
+        {
+            s if s > 0.0 && s.is_finite() => Ok(s),
+            _ => scale.error("expected a positive number"),
+        },
+        (None, None) => Ok(1.0),
+    }
+}
+
+/// Turns relaxed JSON into strict JSON on the same lines: comments become
+/// spaces, as do trailing commas, and unquoted keys get quoted.
+fn relax(source: &str) -> String
+{
+    // first blank out comments, so they can't hide the next token
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut stripped = Vec::with_capacity(chars.len());
+    let mut i = 0;
+
+    while i < chars.len()
+    {
+        match (chars[i], chars.get(i + 1))
+        {
+            ('"', _) =>
+            {
+                let start = i;
+                i += 1;
+
+                while i < chars.len() && chars[i] != '"'
+                {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+
+                i = (i + 1).min(chars.len());
+                stripped.extend_from_slice(&chars[start..i]);
+            },
+            ('/', Some('/')) =>
+            {
+                while i < chars.len() && chars[i] != '\n'
+                {
+                    stripped.push(' ');
+                    i += 1;
+                }
+            },
+            ('/', Some('*')) =>
+            {
+                stripped.extend_from_slice(&[' ', ' ']);
+                i += 2;
+
+                while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/'))
+                {
+                    stripped.push(if chars[i] == '\n' { '\n' } else { ' ' });
+                    i += 1;
+                }
+
+                if i < chars.len()
+                {
+                    stripped.extend_from_slice(&[' ', ' ']);
+                    i += 2;
+                }
+            },
+            (c, _) =>
+            {
+                stripped.push(c);
+                i += 1;
+            },
+        }
+    }
+
+    let ident = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    let next = |from: usize| stripped[from..].iter().find(|c| !c.is_whitespace());
+
+    let mut out = String::with_capacity(source.len());
+    let mut i = 0;
+
+    while i < stripped.len()
+    {
+        match stripped[i]
+        {
+            '"' =>
+            {
+                let start = i;
+                i += 1;
+
+                while i < stripped.len() && stripped[i] != '"'
+                {
+                    i += if stripped[i] == '\\' { 2 } else { 1 };
+                }
+
+                i = (i + 1).min(stripped.len());
+                out.extend(&stripped[start..i]);
+            },
+            ',' if matches!(next(i + 1), Some(']') | Some('}')) =>
+            {
+                out.push(' ');
+                i += 1;
+            },
+            c if ident(c) =>
+            {
+                let start = i;
+
+                while i < stripped.len() && ident(stripped[i])
+                {
+                    i += 1;
+                }
+
+                let word = stripped[start..i].iter().collect::<String>();
+
+                if next(i) == Some(&':') && !c.is_ascii_digit()
+                {
+                    out.push('"');
+                    out.push_str(&word);
+                    out.push('"');
+                }
+                else
+                {
+                    out.push_str(&word);
+                }
+            },
+            c =>
+            {
+                out.push(c);
+                i += 1;
+            },
+        }
+    }
+
+    out
+}
+
+#[cfg(feature = "yaml")]
+fn parse_yaml(source: &str) -> Result<JsonValue, String>
+{
+    use serde_yaml::Value;
+
+    fn convert(val: Value) -> Result<JsonValue, String>
+    {
+        Ok(match val
+        {
+            Value::Null => JsonValue::Null,
+            Value::Bool(b) => b.into(),
+            Value::Number(n) => match (n.as_u64(), n.as_i64(), n.as_f64())
+            {
+                (Some(n), _, _) => n.into(),
+                (None, Some(n), _) => n.into(),
+                (None, None, Some(n)) => n.into(),
+                _ => return Err(format!("Unsupported number {}", n)),
+            },
+            Value::String(s) => s.into(),
+            Value::Sequence(seq) => JsonValue::Array(seq.into_iter()
+                .map(convert)
+                .collect::<Result<_, _>>()?),
+            Value::Mapping(map) =>
+            {
+                let mut obj = json::object::Object::new();
+
+                for (key, val) in map
+                {
+                    let key = key.as_str()
+                        .ok_or("Keys in a scene must be strings".to_owned())?
+                        .to_owned();
+                    obj.insert(&key, convert(val)?);
+                }
+
+                JsonValue::Object(obj)
+            },
+        })
+    }
+
+    let val = serde_yaml::from_str::<Value>(source)
+        .map_err(|e| format!("Error parsing scene YAML: {}", e))?;
+
+    convert(val)
+}
+
+#[cfg(not(feature = "yaml"))]
+fn parse_yaml(_: &str) -> Result<JsonValue, String>
+{
+    Err("YAML scenes need the \"yaml\" feature".to_owned())
+}
+
+#[cfg(feature = "toml")]
+fn parse_toml(source: &str) -> Result<JsonValue, String>
+{
+    use toml::Value;
+
+    fn convert(val: Value) -> JsonValue
+    {
+        match val
+        {
+            Value::String(s) => s.into(),
+            Value::Integer(n) => n.into(),
+            Value::Float(n) => n.into(),
+            Value::Boolean(b) => b.into(),
+            Value::Datetime(d) => d.to_string().into(),
+            Value::Array(arr) => JsonValue::Array(arr.into_iter().map(convert).collect()),
+            Value::Table(table) =>
+            {
+                let mut obj = json::object::Object::new();
+
+                for (key, val) in table
+                {
+                    obj.insert(&key, convert(val));
+                }
+
+                JsonValue::Object(obj)
+            },
+        }
+    }
+
+    let val = source.parse::<Value>()
+        .map_err(|e| format!("Error parsing scene TOML: {}", e))?;
+
+    Ok(convert(val))
+}
+
+#[cfg(not(feature = "toml"))]
+fn parse_toml(_: &str) -> Result<JsonValue, String>
+{
+    Err("TOML scenes need the \"toml\" feature".to_owned())
+}
+
+/// Finds the line of the value at `path` by scanning the source, since the
+/// parsed values don't keep their position.
+fn locate(source: &str, path: &[Seg]) -> Option<usize>
+{
+    let bytes = source.as_bytes();
+    let mut pos = skip_space(bytes, 0);
+
+    for seg in path
+    {
+        match (seg, bytes.get(pos)?)
+        {
+            (Seg::Key(key), b'{') =>
+            {
+                pos += 1;
+
+                loop
+                {
+                    pos = skip_space(bytes, pos);
+                    let end = skip_string(bytes, pos)?;
+                    let name = json::parse(&source[pos..end]).ok()?;
+
+                    pos = skip_space(bytes, end);
+                    if bytes.get(pos)? != &b':'
+                    {
+                        return None;
+                    }
+                    pos = skip_space(bytes, pos + 1);
+
+                    if name.as_str() == Some(key.as_str())
+                    {
+                        break;
+                    }
+
+                    pos = skip_space(bytes, skip_value(bytes, pos)?);
+                    if bytes.get(pos)? != &b','
+                    {
+                        return None;
+                    }
+                    pos += 1;
+                }
+            },
+            (Seg::Index(i), b'[') =>
+            {
+                pos = skip_space(bytes, pos + 1);
+
+                for _ in 0..*i
+                {
+                    pos = skip_space(bytes, skip_value(bytes, pos)?);
+                    if bytes.get(pos)? != &b','
+                    {
+                        return None;
+                    }
+                    pos = skip_space(bytes, pos + 1);
+                }
+            },
+            _ => return None,
+        }
+    }
+
+    Some(source[..pos].matches('\n').count() + 1)
+}
+
+fn skip_space(bytes: &[u8], mut pos: usize) -> usize
+{
+    while pos < bytes.len() && bytes[pos].is_ascii_whitespace()
+    {
+        pos += 1;
+    }
+
+    pos
+}
+
+/// Returns the position after the string starting at `pos`.
+fn skip_string(bytes: &[u8], pos: usize) -> Option<usize>
+{
+    if bytes.get(pos)? != &b'"'
+    {
+        return None;
+    }
+
+    let mut i = pos + 1;
+    loop
+    {
+        match bytes.get(i)?
+        {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+}
+
+/// Returns the position after the value starting at `pos`.
+fn skip_value(bytes: &[u8], pos: usize) -> Option<usize>
+{
+    match bytes.get(pos)?
+    {
+        b'"' => skip_string(bytes, pos),
+        b'{' | b'[' =>
+        {
+            let mut depth = 0;
+            let mut i = pos;
+
+            loop
+            {
+                match bytes.get(i)?
+                {
+                    b'"' =>
+                    {
+                        i = skip_string(bytes, i)?;
+                        continue;
+                    },
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' =>
+                    {
+                        depth -= 1;
+
+                        if depth == 0
+                        {
+                            return Some(i + 1);
+                        }
+                    },
+                    _ => (),
+                }
+
+                i += 1;
+            }
+        },
+        _ =>
+        {
+            let mut i = pos;
+            while i < bytes.len() && !b",}] \t\r\n".contains(&bytes[i])
+            {
+                i += 1;
+            }
+
+            Some(i)
+        },
+    }
+}
@@ -0,0 +1,89 @@
+use crate::gpu::Colour;
+
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+const ITERATIONS: u32 = 5;
+
+/// Edge-aware À-Trous wavelet filter over an accumulation buffer.
+///
+/// `image` holds the sum of `samples` samples per pixel, and the result is on
+/// the same scale, so it can go through the normal conversion afterwards.
+/// Only colour is used to find edges, so `strength` trades detail for
+/// smoothness: around 1.0 is a reasonable start, larger values blur more.
+pub fn denoise(
+    image: &[Colour],
+    width: u32,
+    height: u32,
+    samples: u32,
+    strength: f32)
+    -> Vec<Colour>
+{
+    let (w, h) = (width as i64, height as i64);
+    let scale = 1.0 / samples.max(1) as f32;
+
+    let mut current = image.to_vec();
+    let mut next = image.to_vec();
+
+    // the colour tolerance halves each pass, as the step doubles
+    let mut sigma = 0.5 * strength;
+
+    for i in 0..ITERATIONS
+    {
+        let step = 1 << i;
+
+        for y in 0..h
+        {
+            for x in 0..w
+            {
+                let p = current[(y * w + x) as usize];
+
+                let mut sum = [0.0; 3];
+                let mut total = 0.0;
+
+                for (ky, ky_weight) in KERNEL.iter().enumerate()
+                {
+                    let qy = y + (ky as i64 - 2) * step;
+                    if qy < 0 || qy >= h
+                    {
+                        continue;
+                    }
+
+                    for (kx, kx_weight) in KERNEL.iter().enumerate()
+                    {
+                        let qx = x + (kx as i64 - 2) * step;
+                        if qx < 0 || qx >= w
+                        {
+                            continue;
+                        }
+
+                        let q = current[(qy * w + qx) as usize];
+
+                        let dist = ((p.r - q.r) * scale).powi(2)
+                            + ((p.g - q.g) * scale).powi(2)
+                            + ((p.b - q.b) * scale).powi(2);
+
+                        let weight = ky_weight * kx_weight
+                            * (-dist / (sigma * sigma).max(1e-10)).exp();
+
+                        sum[0] += q.r * weight;
+                        sum[1] += q.g * weight;
+                        sum[2] += q.b * weight;
+                        total += weight;
+                    }
+                }
+
+                // the centre pixel always contributes, so total is never zero
+                next[(y * w + x) as usize] = Colour
+                {
+                    r: sum[0] / total,
+                    g: sum[1] / total,
+                    b: sum[2] / total,
+                };
+            }
+        }
+
+        std::mem::swap(&mut current, &mut next);
+        sigma *= 0.5;
+    }
+
+    current
+}
@@ -0,0 +1,119 @@
+use image::RgbImage;
+
+/// How far apart two images are, with colours from 0 to 1.
+#[derive(Copy, Clone, Debug)]
+pub struct ImageDiff
+{
+    /// root mean square error of red, green and blue
+    pub rmse: [f64; 3],
+    pub luminance_rmse: f64,
+    /// the largest difference in any channel of any pixel
+    pub max_error: f64,
+    /// pixels whose luminance differs by more than the threshold
+    pub over_threshold: usize,
+    pub pixels: usize,
+}
+
+/// Compares two images of the same size, counting the pixels whose
+/// luminance differs by more than `threshold`.
+pub fn diff_images(a: &RgbImage, b: &RgbImage, threshold: f64) -> Result<ImageDiff, String>
+{
+    if a.dimensions() != b.dimensions()
+    {
+        return Err(format!(
+            "The images are different sizes, {}x{} and {}x{}",
+            a.width(), a.height(), b.width(), b.height()));
+    }
+
+    let mut squares = [0.0; 3];
+    let mut lum_squares = 0.0;
+    let mut max_error: f64 = 0.0;
+    let mut over_threshold = 0;
+
+    for (pa, pb) in a.pixels().zip(b.pixels())
+    {
+        for c in 0..3
+        {
+            let e = channel(pa[c]) - channel(pb[c]);
+            squares[c] += e * e;
+            max_error = max_error.max(e.abs());
+        }
+
+        let e = luminance(pa) - luminance(pb);
+        lum_squares += e * e;
+
+        if e.abs() > threshold
+        {
+            over_threshold += 1;
+        }
+    }
+
+    let n = (a.width() as usize * a.height() as usize).max(1);
+    let rmse = |squares: f64| (squares / n as f64).sqrt();
+
+    Ok(ImageDiff
+    {
+        rmse: [rmse(squares[0]), rmse(squares[1]), rmse(squares[2])],
+        luminance_rmse: rmse(lum_squares),
+        max_error: max_error,
+        over_threshold: over_threshold,
+        pixels: a.width() as usize * a.height() as usize,
+    })
+}
+
+/// The difference in luminance of every pixel in false colour, from black
+/// for none through blue, red and yellow to white for the largest. The
+/// images must be the same size.
+pub fn diff_heatmap(a: &RgbImage, b: &RgbImage) -> RgbImage
+{
+    let errors = a.pixels()
+        .zip(b.pixels())
+        .map(|(pa, pb)| (luminance(pa) - luminance(pb)).abs())
+        .collect::<Vec<_>>();
+    let max = errors.iter().cloned().fold(0.0, f64::max);
+
+    let mut heat = RgbImage::new(a.width(), a.height());
+
+    for (p, e) in heat.pixels_mut().zip(errors)
+    {
+        let t = if max > 0.0 { e / max } else { 0.0 };
+        *p = image::Rgb(false_colour(t));
+    }
+
+    heat
+}
+
+/// Maps 0 to 1 onto black, blue, red, yellow and white.
+pub(crate) fn false_colour(t: f64) -> [u8; 3]
+{
+    const STOPS: [[f64; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f64;
+
+    let mut c = [0; 3];
+    for k in 0..3
+    {
+        let v = STOPS[i][k] * (1.0 - f) + STOPS[i + 1][k] * f;
+        c[k] = (v * 255.0).round() as u8;
+    }
+
+    c
+}
+
+fn channel(c: u8) -> f64
+{
+    c as f64 / 255.0
+}
+
+fn luminance(p: &image::Rgb<u8>) -> f64
+{
+    0.2126 * channel(p[0]) + 0.7152 * channel(p[1]) + 0.0722 * channel(p[2])
+}
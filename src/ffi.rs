@@ -0,0 +1,389 @@
+//! The renderer for C and C++ programs, built into the `cdylib` and
+//! `staticlib`. `include/path_tracer.h` is generated from this, see
+//! `cbindgen.toml`, and `examples/c/cornell.c` shows how to use it.
+//!
+//! Nothing here unwinds into the caller: panics are caught and returned as
+//! `PT_PANICKED`. Strings from the caller must be UTF-8.
+
+use crate::gpu::{Colour, GpuContext, GpuError};
+use crate::handle::RenderControl;
+use crate::scene::{RenderSettings, Scene};
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_void};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+/// It worked.
+pub const PT_OK: i32 = 0;
+/// A pointer was null or a number was 0, see `pt_last_error`.
+pub const PT_INVALID_ARGUMENT: i32 = 1;
+/// The render failed, see `pt_last_error`.
+pub const PT_RENDER_FAILED: i32 = 2;
+/// The renderer panicked, which is a bug, see `pt_last_error`.
+pub const PT_PANICKED: i32 = 3;
+
+/// Errors, for `pt_set_log`.
+pub const PT_LOG_ERROR: i32 = 0;
+/// Warnings, for `pt_set_log`.
+pub const PT_LOG_WARN: i32 = 1;
+/// Progress and summaries, for `pt_set_log`.
+pub const PT_LOG_INFO: i32 = 2;
+/// Adapter details, buffer sizes and timings, for `pt_set_log`.
+pub const PT_LOG_DEBUG: i32 = 3;
+/// Per-sample timings, for `pt_set_log`.
+pub const PT_LOG_TRACE: i32 = 4;
+
+/// A scene from `pt_scene_parse`.
+pub struct PtScene
+{
+    scene: Scene,
+}
+
+/// Stops a render from `pt_render_progressive` from another thread, see
+/// `pt_cancel`.
+pub struct PtCancel
+{
+    control: Arc<RenderControl>,
+}
+
+/// Called by `pt_render_progressive` with the samples finished so far, the
+/// samples asked for and the caller's `user` pointer, on the thread that
+/// called it.
+pub type PtProgress = Option<extern "C" fn(samples: u32, total: u32, user: *mut c_void)>;
+
+/// Called by the renderer with each message's level, one of the
+/// `PT_LOG_*`, the message and the `user` pointer given to `pt_set_log`,
+/// on whichever thread logged it.
+pub type PtLog = Option<extern "C" fn(level: i32, message: *const c_char, user: *mut c_void)>;
+
+thread_local!
+{
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+/// The buffers handed out by `pt_render`, by their address, so
+/// `pt_buffer_free` knows their lengths.
+static BUFFERS: Mutex<BTreeMap<usize, Vec<f32>>> = Mutex::new(BTreeMap::new());
+
+/// The context every render shares, opened by the first one.
+static CONTEXT: Mutex<Option<Arc<GpuContext>>> = Mutex::new(None);
+
+fn c_string(s: &str) -> CString
+{
+    // error messages don't have nuls, but the caller would get nothing
+    CString::new(s.replace('\0', " ")).unwrap()
+}
+
+fn set_error(message: &str)
+{
+    LAST_ERROR.with(|e| *e.borrow_mut() = c_string(message));
+}
+
+/// Runs `f`, turning a panic into an error.
+fn guard(f: impl FnOnce() -> Result<(), (i32, String)>) -> i32
+{
+    let result = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic|
+    {
+        let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+
+        Err((PT_PANICKED, format!("The renderer panicked: {}", message)))
+    });
+
+    match result
+    {
+        Ok(()) => PT_OK,
+        Err((code, message)) =>
+        {
+            set_error(&message);
+            code
+        },
+    }
+}
+
+fn context() -> Result<Arc<GpuContext>, GpuError>
+{
+    let mut ctx = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+
+    if ctx.is_none()
+    {
+        *ctx = Some(Arc::new(match GpuContext::new(None, false)
+        {
+            Ok(gpu) => gpu,
+            Err(GpuError::AdapterNotFound(e)) =>
+            {
+                pt_warn!("{}, rendering on the CPU", e);
+                GpuContext::cpu()
+            },
+            Err(e) => return Err(e),
+        }));
+    }
+
+    Ok(ctx.as_ref().unwrap().clone())
+}
+
+/// Parses a JSON scene. Returns null on failure, with the error in `*err`
+/// when `err` isn't null, which the caller frees with `pt_string_free`.
+/// Files the scene refers to are relative to the working directory.
+///
+/// # Safety
+///
+/// `json` must be a nul-terminated string and `err` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn pt_scene_parse(json: *const c_char, err: *mut *mut c_char) -> *mut PtScene
+{
+    let mut scene = None;
+
+    let code = guard(||
+    {
+        if json.is_null()
+        {
+            return Err((PT_INVALID_ARGUMENT, "The scene is null".to_owned()));
+        }
+
+        let json = CStr::from_ptr(json).to_str()
+            .map_err(|e| (PT_INVALID_ARGUMENT, format!("The scene isn't UTF-8: {}", e)))?;
+
+        scene = Some(Scene::parse(json).map_err(|e| (PT_INVALID_ARGUMENT, e))?);
+
+        Ok(())
+    });
+
+    match scene
+    {
+        Some(scene) if code == PT_OK => Box::into_raw(Box::new(PtScene
+        {
+            scene: scene,
+        })),
+        _ =>
+        {
+            if !err.is_null()
+            {
+                *err = LAST_ERROR.with(|e| e.borrow().clone()).into_raw();
+            }
+
+            std::ptr::null_mut()
+        },
+    }
+}
+
+/// Frees a scene from `pt_scene_parse`. Null is ignored.
+///
+/// # Safety
+///
+/// `scene` must be from `pt_scene_parse` and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn pt_scene_free(scene: *mut PtScene)
+{
+    if !scene.is_null()
+    {
+        drop(Box::from_raw(scene));
+    }
+}
+
+/// Renders `scene` at `width` by `height` with `samples` samples per pixel
+/// and `depth` bounces, on the GPU or on the CPU if there isn't one. On
+/// success `*pixels_out` is `width * height` RGB colours as floats, from
+/// the top left, which the caller frees with `pt_buffer_free`.
+///
+/// # Safety
+///
+/// `scene` must be from `pt_scene_parse` and `pixels_out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn pt_render(
+    scene: *const PtScene,
+    width: u32,
+    height: u32,
+    samples: u32,
+    depth: u32,
+    pixels_out: *mut *mut f32)
+    -> i32
+{
+    pt_render_progressive(scene, width, height, samples, depth, None, std::ptr::null_mut(), std::ptr::null(), pixels_out)
+}
+
+/// Like `pt_render`, calling `progress`, if it isn't null, after every
+/// sample and stopping early if `cancel`, if it isn't null, is cancelled.
+/// A cancelled render still succeeds with the samples it finished.
+///
+/// # Safety
+///
+/// As `pt_render`, and `cancel` must be null or from `pt_cancel_new`.
+#[no_mangle]
+pub unsafe extern "C" fn pt_render_progressive(
+    scene: *const PtScene,
+    width: u32,
+    height: u32,
+    samples: u32,
+    depth: u32,
+    progress: PtProgress,
+    user: *mut c_void,
+    cancel: *const PtCancel,
+    pixels_out: *mut *mut f32)
+    -> i32
+{
+    guard(||
+    {
+        if scene.is_null() || pixels_out.is_null()
+        {
+            return Err((PT_INVALID_ARGUMENT, "The scene or pixels are null".to_owned()));
+        }
+
+        if width == 0 || height == 0 || samples == 0
+        {
+            return Err((PT_INVALID_ARGUMENT, "The width, height and samples must be above 0".to_owned()));
+        }
+
+        let settings = RenderSettings
+        {
+            samples: samples,
+            depth: depth,
+            control: cancel.as_ref().map(|c| c.control.clone()),
+            .. RenderSettings::new([width, height])
+        };
+
+        let limit = settings.condition();
+        let condition = |done: u32|
+        {
+            if let Some(progress) = progress
+            {
+                if done > 0
+                {
+                    progress(done, samples, user);
+                }
+            }
+
+            limit(done)
+        };
+
+        let ctx = context().map_err(|e| (PT_RENDER_FAILED, e.to_string()))?;
+        let image = (*scene).scene.render_with(&ctx, &settings, &condition, None)
+            .map_err(|e| (PT_RENDER_FAILED, e.to_string()))?;
+
+        let pixels = image.pixels.iter()
+            .flat_map(|&Colour { r, g, b }| [r, g, b])
+            .collect::<Vec<_>>();
+
+        *pixels_out = pixels.as_ptr() as *mut f32;
+        BUFFERS.lock().unwrap_or_else(|e| e.into_inner()).insert(pixels.as_ptr() as usize, pixels);
+
+        Ok(())
+    })
+}
+
+/// Frees pixels from `pt_render`. Null and pointers that weren't from it are
+/// ignored.
+#[no_mangle]
+pub extern "C" fn pt_buffer_free(pixels: *mut f32)
+{
+    let _ = catch_unwind(||
+    {
+        BUFFERS.lock().unwrap_or_else(|e| e.into_inner()).remove(&(pixels as usize));
+    });
+}
+
+/// A token for stopping renders, freed with `pt_cancel_free`.
+#[no_mangle]
+pub extern "C" fn pt_cancel_new() -> *mut PtCancel
+{
+    Box::into_raw(Box::new(PtCancel
+    {
+        control: Arc::new(RenderControl::new()),
+    }))
+}
+
+/// Stops the renders using `cancel` after the sample they're on, and any
+/// started with it later. Safe to call from any thread.
+///
+/// # Safety
+///
+/// `cancel` must be from `pt_cancel_new` and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn pt_cancel(cancel: *const PtCancel)
+{
+    if let Some(cancel) = cancel.as_ref()
+    {
+        cancel.control.stop();
+    }
+}
+
+/// Frees a token from `pt_cancel_new`, once no render is using it. Null is
+/// ignored.
+///
+/// # Safety
+///
+/// `cancel` must be from `pt_cancel_new` and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn pt_cancel_free(cancel: *mut PtCancel)
+{
+    if !cancel.is_null()
+    {
+        drop(Box::from_raw(cancel));
+    }
+}
+
+/// Sends the renderer's messages up to `level`, one of the `PT_LOG_*`, to
+/// `log`, or to stdout and stderr if it's null. Until this is called they
+/// go to stdout and stderr, up to `PT_LOG_INFO`.
+///
+/// # Safety
+///
+/// `log` must be safe to call with `user` from any thread until this is
+/// called again.
+#[no_mangle]
+pub unsafe extern "C" fn pt_set_log(level: i32, log: PtLog, user: *mut c_void) -> i32
+{
+    use crate::log::{self, Level};
+
+    let level = match level
+    {
+        PT_LOG_ERROR => Level::Error,
+        PT_LOG_WARN => Level::Warn,
+        PT_LOG_INFO => Level::Info,
+        PT_LOG_DEBUG => Level::Debug,
+        PT_LOG_TRACE => Level::Trace,
+        _ =>
+        {
+            set_error(&format!("{} isn't a PT_LOG_* level", level));
+            return PT_INVALID_ARGUMENT;
+        },
+    };
+
+    log::set_level(level);
+
+    // so the closure can be sent between threads, as the caller promised
+    let user = user as usize;
+    log::set_sink(log.map(|log| Box::new(move |level: Level, message: &str|
+    {
+        let message = c_string(message);
+        log(level as i32, message.as_ptr(), user as *mut c_void);
+    }) as log::Sink));
+
+    PT_OK
+}
+
+/// The message for the last error on this thread, valid until the next
+/// call that fails on it, or an empty string.
+#[no_mangle]
+pub extern "C" fn pt_last_error() -> *const c_char
+{
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// Frees a string from `pt_scene_parse`. Null is ignored.
+///
+/// # Safety
+///
+/// `s` must be from this library and not freed already.
+#[no_mangle]
+pub unsafe extern "C" fn pt_string_free(s: *mut c_char)
+{
+    if !s.is_null()
+    {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,119 @@
+//! Writers for images as 32-bit floats, which the image crate can't save.
+
+/// An OpenEXR file of uncompressed 32-bit floats, with one channel for each
+/// name and values, top row first. Colours are "R", "G", "B" and "A", and
+/// depth is "Z".
+pub fn write_exr(path: &str, width: u32, height: u32, channels: &[(&str, &[f32])])
+    -> Result<(), String>
+{
+    fn attribute(bytes: &mut Vec<u8>, name: &str, kind: &str, value: &[u8])
+    {
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(kind.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(value);
+    }
+
+    // readers expect the channels in alphabetical order, in the list and
+    // in each line
+    let mut channels = channels.to_vec();
+    channels.sort_by_key(|&(name, _)| name);
+
+    let mut window = Vec::new();
+    for v in &[0, 0, width as i32 - 1, height as i32 - 1]
+    {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+
+    // name, 32-bit float, not linear, reserved, and no subsampling
+    let mut list = Vec::new();
+    for (name, _) in &channels
+    {
+        list.extend_from_slice(name.as_bytes());
+        list.push(0);
+        list.extend_from_slice(&2i32.to_le_bytes());
+        list.extend_from_slice(&[0, 0, 0, 0]);
+        list.extend_from_slice(&1i32.to_le_bytes());
+        list.extend_from_slice(&1i32.to_le_bytes());
+    }
+    list.push(0);
+
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+
+    attribute(&mut bytes, "channels", "chlist", &list);
+    attribute(&mut bytes, "compression", "compression", &[0]);
+    attribute(&mut bytes, "dataWindow", "box2i", &window);
+    attribute(&mut bytes, "displayWindow", "box2i", &window);
+    attribute(&mut bytes, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut bytes, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute(&mut bytes, "screenWindowCenter", "v2f", &[0; 8]);
+    attribute(&mut bytes, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    bytes.push(0);
+
+    // each line is its own chunk, after a table of where they start
+    let data_size = 4 * width as u64 * channels.len() as u64;
+    let line_size = 8 + data_size;
+    let table_end = bytes.len() as u64 + 8 * height as u64;
+
+    for y in 0..height as u64
+    {
+        bytes.extend_from_slice(&(table_end + y * line_size).to_le_bytes());
+    }
+
+    for y in 0..height
+    {
+        bytes.extend_from_slice(&(y as i32).to_le_bytes());
+        bytes.extend_from_slice(&(data_size as i32).to_le_bytes());
+
+        for (_, values) in &channels
+        {
+            for v in &values[(y * width) as usize..((y + 1) * width) as usize]
+            {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+
+    write(path, &bytes)
+}
+
+/// A PFM file, with `channels` as one greyscale channel or red, green and
+/// blue, top row first.
+pub fn write_pfm(path: &str, width: u32, height: u32, channels: &[&[f32]])
+    -> Result<(), String>
+{
+    let kind = match channels.len()
+    {
+        1 => "Pf",
+        3 => "PF",
+        n => return Err(format!("Could not save \"{}\": PFM can't have {} channels", path, n)),
+    };
+
+    // a negative scale means little endian
+    let mut bytes = format!("{}\n{} {}\n-1.0\n", kind, width, height).into_bytes();
+
+    // PFM starts from the bottom row, with the channels interleaved
+    for y in (0..height).rev()
+    {
+        for i in (y * width) as usize..((y + 1) * width) as usize
+        {
+            for values in channels
+            {
+                bytes.extend_from_slice(&values[i].to_le_bytes());
+            }
+        }
+    }
+
+    write(path, &bytes)
+}
+
+fn write(path: &str, bytes: &[u8]) -> Result<(), String>
+{
+    std::fs::write(path, bytes)
+        .map_err(|e| format!("Could not save \"{}\": {}", path, e))
+}
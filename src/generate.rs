@@ -0,0 +1,307 @@
+//! The `generate` subcommand, which makes random scenes for stress testing
+//! the parser and the renderer, and with `--self-test` times renders of
+//! bigger and bigger ones.
+
+use path_tracer_gpu::{GpuContext, RenderSettings, Scene};
+use path_tracer_gpu::pt_info;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::{parse_resolution, parse_time, Failure};
+
+/// What to put in a scene, from the command line.
+struct Options
+{
+    triangles: u64,
+    /// the scene fits in a cube this wide around the origin
+    extent: f64,
+    materials: u32,
+    /// the fraction of the materials that glow
+    emissive: f64,
+    /// how often to pick triangles, quads and spheres, relative to each other
+    mix: [f64; 3],
+    seed: u64,
+}
+
+/// Runs the `generate` subcommand.
+pub fn generate(matches: &clap::ArgMatches) -> Result<(), Failure>
+{
+    let options = Options::read(matches)?;
+
+    if matches.is_present("self-test")
+    {
+        return self_test(matches, &options);
+    }
+
+    let scene = generate_scene(&options).pretty(4);
+
+    match matches.value_of("output").unwrap_or("-")
+    {
+        "-" => println!("{}", scene),
+        path =>
+        {
+            std::fs::write(path, scene + "\n")
+                .map_err(|e| Failure::Io(format!("Could not write scene to \"{}\": {}", path, e)))?;
+
+            pt_info!("Wrote {} triangles to {}", options.triangles, path);
+        },
+    }
+
+    Ok(())
+}
+
+impl Options
+{
+    fn read(matches: &clap::ArgMatches) -> Result<Options, Failure>
+    {
+        fn number<T: std::str::FromStr>(matches: &clap::ArgMatches, name: &str, what: &str)
+            -> Result<T, Failure>
+        {
+            matches.value_of(name).unwrap().trim().parse::<T>()
+                .map_err(|_| Failure::Args(format!("Could not parse {}", what)))
+        }
+
+        let extent = number::<f64>(matches, "extent", "the extent")?;
+        let materials = number::<u32>(matches, "materials", "the number of materials")?;
+        let emissive = number::<f64>(matches, "emissive", "the emissive fraction")?;
+
+        if !(extent > 0.0 && extent.is_finite())
+        {
+            return Err(Failure::Args("The extent must be above 0".to_owned()));
+        }
+
+        if materials == 0
+        {
+            return Err(Failure::Args("There must be at least one material".to_owned()));
+        }
+
+        if !(0.0..=1.0).contains(&emissive)
+        {
+            return Err(Failure::Args("The emissive fraction must be from 0 to 1".to_owned()));
+        }
+
+        let mix = matches.value_of("mix").unwrap().split(':')
+            .map(|w| w.trim().parse::<f64>().ok().filter(|w| *w >= 0.0 && w.is_finite()))
+            .collect::<Option<Vec<_>>>();
+
+        let mix = match mix.as_deref()
+        {
+            Some(&[t, q, s]) if t + q + s > 0.0 => [t, q, s],
+            _ => return Err(Failure::Args(
+                "Could not parse the mix, three weights like 1:1:1 for triangles, quads and spheres".to_owned())),
+        };
+
+        Ok(Options
+        {
+            triangles: number(matches, "triangles", "the number of triangles")?,
+            extent: extent,
+            materials: materials,
+            emissive: emissive,
+            mix: mix,
+            seed: number(matches, "seed", "the seed")?,
+        })
+    }
+}
+
+/// A random scene with exactly `options.triangles` triangles, the same for
+/// the same options with the same build.
+fn generate_scene(options: &Options) -> json::JsonValue
+{
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    // keeps the numbers in the file short
+    let round = |x: f64| (x * 1000.0).round() / 1000.0;
+
+    let lights = match options.emissive
+    {
+        e if e > 0.0 => ((options.materials as f64 * e).round() as u32).max(1),
+        _ => 0,
+    };
+
+    let mut materials = json::JsonValue::new_object();
+    for i in 0..options.materials
+    {
+        let colour = [(); 3].map(|_| round(rng.gen_range(0.1..0.9)));
+
+        let material = match i < lights
+        {
+            true => json::object!
+            {
+                "glow": [(); 3].map(|_| round(rng.gen_range(1.0..10.0))).to_vec(),
+            },
+            false if rng.gen_bool(0.25) => json::object!
+            {
+                "colour": colour.to_vec(),
+                "gloss": round(rng.gen_range(0.1..1.0)),
+            },
+            false => json::object!
+            {
+                "colour": colour.to_vec(),
+            },
+        };
+
+        materials[format!("m{}", i)] = material;
+    }
+
+    // about as big as the space each one gets, so they overlap a little
+    let half = options.extent / 2.0;
+    let size = options.extent / (options.triangles.max(1) as f64).cbrt();
+
+    let point = |rng: &mut StdRng, around: [f64; 3], spread: f64|
+        around.map(|c| round((c + rng.gen_range(-spread..=spread)).clamp(-half, half)));
+
+    let mut surfaces = Vec::new();
+    let mut left = options.triangles;
+    let total = options.mix.iter().sum::<f64>();
+
+    while left > 0
+    {
+        let centre = point(&mut rng, [0.0; 3], half);
+        let mat = format!("m{}", rng.gen_range(0..options.materials));
+
+        let pick = rng.gen_range(0.0..total);
+        let shape = match pick
+        {
+            p if p < options.mix[0] => 0,
+            p if p < options.mix[0] + options.mix[1] => 1,
+            _ => 2,
+        };
+
+        // the biggest sphere that fits in the triangles left, else a quad,
+        // else a triangle
+        let subdivisions = (0..=3u32).rev().find(|s| 20 * 4u64.pow(*s) <= left);
+
+        let surface = match (shape, subdivisions)
+        {
+            (2, Some(s)) =>
+            {
+                left -= 20 * 4u64.pow(s);
+                json::object!
+                {
+                    "sphere_mesh":
+                    {
+                        "center": centre.to_vec(),
+                        "radius": round(rng.gen_range(0.1..0.5) * size * 2f64.powi(s as i32)),
+                        "subdivisions": s,
+                    },
+                    "mat": mat,
+                }
+            },
+            (1, _) | (2, None) if left >= 2 =>
+            {
+                left -= 2;
+
+                // a parallelogram, so it's flat, with rounded sides so it
+                // stays flat in the file
+                let [u, v] = [(); 2].map(|_| [(); 3].map(|_| round(rng.gen_range(-size..=size) / 2.0)));
+                let corner = |a: f64, b: f64| [0, 1, 2].map(|i| round(centre[i] + a * u[i] + b * v[i]));
+
+                json::object!
+                {
+                    "quad": vec![
+                        corner(-1.0, -1.0).to_vec(),
+                        corner(1.0, -1.0).to_vec(),
+                        corner(1.0, 1.0).to_vec(),
+                        corner(-1.0, 1.0).to_vec(),
+                    ],
+                    "mat": mat,
+                }
+            },
+            _ =>
+            {
+                left -= 1;
+                json::object!
+                {
+                    "tri": [(); 3].map(|_| point(&mut rng, centre, size).to_vec()).to_vec(),
+                    "mat": mat,
+                }
+            },
+        };
+
+        surfaces.push(surface);
+    }
+
+    json::object!
+    {
+        "camera":
+        {
+            "pos": [0.0, 0.0, round(-1.6 * options.extent)],
+            "front": [0.0, 0.0, 1.0],
+            "up": [0.0, 1.0, 0.0],
+            "fov": 60.0,
+        },
+        "materials": materials,
+        "surfaces": surfaces,
+    }
+}
+
+/// Generates scenes with more and more triangles, renders each for
+/// `--time` and prints how fast they went.
+fn self_test(matches: &clap::ArgMatches, options: &Options) -> Result<(), Failure>
+{
+    let counts = matches.value_of("sizes").unwrap().split(',')
+        .map(|n| n.trim().parse::<u64>().ok().filter(|&n| n > 0))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| Failure::Args("Could not parse the sizes, triangle counts above 0 like 100,1000".to_owned()))?;
+
+    let res = parse_resolution(matches.value_of("resolution").unwrap()).map_err(Failure::Args)?;
+    let time = parse_time(matches.value_of("time").unwrap()).map_err(Failure::Args)?;
+
+    let ctx = match matches.is_present("cpu")
+    {
+        true => GpuContext::cpu(),
+        false => GpuContext::new(matches.value_of("adapter"), false).map_err(Failure::from)?,
+    };
+    pt_info!("Rendering on {} at {}x{} for {:.1}s a scene", ctx.info().name, res[0], res[1], time.as_secs_f64());
+
+    let settings = RenderSettings::new(res);
+
+    // the triangles, then the parse time and samples/s and ms/sample, or why
+    // it failed
+    let mut results = Vec::new();
+
+    for &triangles in &counts
+    {
+        let source = generate_scene(&Options
+        {
+            triangles: triangles,
+            .. *options
+        }).dump();
+
+        let start = std::time::Instant::now();
+        let result = Scene::parse(&source)
+            .map(|scene| (scene, start.elapsed()))
+            .and_then(|(scene, parsed)|
+            {
+                let (_, bench) = scene.benchmark(&ctx, &settings, std::time::Duration::from_secs(0), time)?;
+                Ok((parsed, bench.samples_per_sec, bench.ms_per_sample))
+            });
+
+        match &result
+        {
+            Ok((_, rate, _)) => pt_info!("{} triangles: {:.2} samples/s", triangles, rate),
+            Err(e) => pt_info!("{} triangles: {}", triangles, e),
+        }
+
+        results.push((triangles, result));
+    }
+
+    println!();
+    println!("{:>10}  {:>10}  {:>10}  {:>10}", "Triangles", "Parse", "Samples/s", "ms/sample");
+    for (triangles, result) in &results
+    {
+        match result
+        {
+            Ok((parsed, rate, ms)) => println!("{:>10}  {:>9.1}ms  {:>10.2}  {:>10.2}",
+                triangles, parsed.as_secs_f64() * 1000.0, rate, ms),
+            Err(e) => println!("{:>10}  failed: {}", triangles, e),
+        }
+    }
+
+    match results.iter().filter(|(_, r)| r.is_err()).count()
+    {
+        0 => Ok(()),
+        n => Err(Failure::Render(format!("{} of the {} scenes failed", n, results.len()))),
+    }
+}
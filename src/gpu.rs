@@ -1,8 +1,15 @@
+// opening a device is left out on the web
+#[cfg_attr(target_arch = "wasm32", allow(unused_imports))]
 use wgpu::
 {
     Instance,
+    Adapter,
+    AdapterInfo,
     Backends,
     DeviceType,
+    DeviceDescriptor,
+    Features,
+    Limits,
 
     ComputePassDescriptor,
     ComputePipelineDescriptor,
@@ -18,6 +25,9 @@ use wgpu::
     BufferUsages,
     BufferDescriptor,
 
+    QuerySetDescriptor,
+    QueryType,
+
     util::
     {
         DeviceExt,
@@ -25,194 +35,2494 @@ use wgpu::
         BufferInitDescriptor,
     },
 };
+use crate::clock::Instant;
+use crate::stop::{relative_noise, StopCondition};
+use crate::texture::{Texture, DEFAULT_UVS};
+
 use pollster::block_on;
 use bytemuck::cast_slice;
 
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum GpuError
+{
+    AdapterNotFound(String),
+    RequestDevice(wgpu::RequestDeviceError),
+    /// the render needs more than the device allows
+    Limit(String),
+    /// the scene can't be rendered as it is
+    Scene(String),
+    /// a shader from `GpuContext::set_shader` didn't compile, or doesn't
+    /// take the buffers `run_shader` binds
+    Shader(String),
+    /// the shader compiled, but wgpu couldn't make a pipeline from it, or
+    /// its bindings don't match the buffers `run_shader` binds
+    PipelineCreation(String),
+    MapFailed,
+    /// a wgpu validation or out of memory error
+    Validation(String),
+    /// The device stopped responding part way through. `image` is the last
+    /// image read back, which holds `samples` samples per pixel.
+    DeviceLost
+    {
+        samples: u32,
+        image: Vec<Colour>,
+    },
+}
+
+impl std::fmt::Display for GpuError
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            GpuError::AdapterNotFound(e) => write!(f, "{}", e),
+            GpuError::RequestDevice(e) => write!(f, "Could not open the GPU: {}", e),
+            GpuError::Limit(e) => write!(f, "{}", e),
+            GpuError::Scene(e) => write!(f, "{}", e),
+            GpuError::Shader(e) => write!(f, "Invalid shader: {}", e),
+            GpuError::PipelineCreation(e) => write!(f, "Could not create the pipeline: {}", e),
+            GpuError::MapFailed => write!(f, "Could not read the image back from the GPU"),
+            GpuError::Validation(e) => write!(f, "GPU validation failed: {}", e),
+            GpuError::DeviceLost { samples, .. } =>
+                write!(f, "Lost the GPU after {} samples", samples),
+        }
+    }
+}
+
+impl std::error::Error for GpuError { }
+
+/// The device and compiled pipelines, which are slow to create, so they can
+/// be shared between renders. See `GpuContext::cpu` to render without a GPU.
+pub struct GpuContext
+{
+    info: AdapterInfo,
+    limits: Limits,
+    /// `None` to render on the CPU
+    gpu: Option<Gpu>,
+    error: Arc<Mutex<Option<GpuError>>>,
+}
+
+struct Gpu
+{
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    shader: Mutex<Shader>,
+    /// the adapter supports timestamp queries, see `Profiler`
+    timestamps: bool,
+}
+
+struct Shader
+{
+    /// the source before `specialise`, replaced by `set_shader`
+    source: String,
+    /// compiled for each set of constants rendered with so far
+    pipelines: HashMap<Specialisation, Compiled>,
+}
+
+/// A pipeline, with the bindings in group 0 its shader declares.
+#[derive(Clone)]
+struct Compiled
+{
+    pipeline: Arc<wgpu::ComputePipeline>,
+    bindings: Vec<u32>,
+}
+
+impl Gpu
+{
+    /// The pipeline for `spec`, compiling it the first time it's used, with
+    /// wgpu's errors taken from `error`, see `GpuContext::check`.
+    fn pipeline(&self, spec: Specialisation, error: &Mutex<Option<GpuError>>)
+        -> Result<Compiled, GpuError>
+    {
+        let mut shader = self.shader.lock().unwrap();
+        let Shader { source, pipelines } = &mut *shader;
+
+        if let Some(compiled) = pipelines.get(&spec)
+        {
+            return Ok(compiled.clone());
+        }
+
+        pt_debug!("Compiling the shader for {:?}", spec);
+
+        let compiled = create_pipeline(&self.device, &specialise(source, spec), error)?;
+        pipelines.insert(spec, compiled.clone());
+
+        Ok(compiled)
+    }
+}
+
+/// The constants `specialise` sets in the shader.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Specialisation
+{
+    depth: u32,
+    mode: u32,
+    moving: bool,
+    coloured: bool,
+    textured: bool,
+    smooth: bool,
+    noisy: bool,
+    matte: bool,
+    squares: bool,
+    depths: bool,
+}
+
+impl GpuContext
+{
+    /// Opens the adapter chosen like `--adapter`, see `find_adapter`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(adapter: Option<&str>, require_discrete: bool)
+        -> Result<GpuContext, GpuError>
+    {
+        let instance = Instance::new(Backends::all());
+
+        let adapter = find_adapter(&instance, adapter, require_discrete)?;
+
+        block_on(GpuContext::open(adapter))
+    }
+
+    /// Fails on the web, where reading the image back has to wait for the
+    /// page's event loop and rendering can't, so render with `cpu` there.
+    #[cfg(target_arch = "wasm32")]
+    pub fn new(_adapter: Option<&str>, _require_discrete: bool)
+        -> Result<GpuContext, GpuError>
+    {
+        Err(GpuError::AdapterNotFound(
+            "The GPU renderer isn't available on the web, use the CPU one".to_owned()))
+    }
+
+    /// Opens `adapter`'s device.
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn open(adapter: Adapter) -> Result<GpuContext, GpuError>
+    {
+        // ask for everything the adapter supports, the defaults are much lower
+        let limits = adapter.limits();
+        let features = adapter.features() & Features::TIMESTAMP_QUERY;
+
+        let info = adapter.get_info();
+        pt_debug!("Adapter {} ({:?}, {:?}), vendor {:#x}, device {:#x}",
+            info.name, info.backend, info.device_type, info.vendor, info.device);
+        pt_debug!("Max storage buffer binding size {} bytes",
+            limits.max_storage_buffer_binding_size);
+
+        let (device, queue) = adapter
+            .request_device(&DeviceDescriptor
+            {
+                label: None,
+                features: features,
+                limits: limits.clone(),
+            }, None)
+            .await
+            .map_err(GpuError::RequestDevice)?;
+
+        // wgpu panics on errors by default, so keep the first one to return
+        let error = Arc::new(Mutex::new(None));
+        {
+            let error = error.clone();
+            device.on_uncaptured_error(move |e: wgpu::Error|
+            {
+                error.lock().unwrap().get_or_insert(GpuError::Validation(e.to_string()));
+            });
+        }
+
+        let ctx = GpuContext
+        {
+            info: info,
+            limits: limits,
+            gpu: Some(Gpu
+            {
+                device: device,
+                queue: queue,
+                shader: Mutex::new(Shader
+                {
+                    source: include_str!("shader.wgsl").to_owned(),
+                    pipelines: HashMap::new(),
+                }),
+                timestamps: features.contains(Features::TIMESTAMP_QUERY),
+            }),
+            error: error,
+        };
+
+        ctx.check()?;
+
+        Ok(ctx)
+    }
+
+    /// Renders on the CPU instead, with the same algorithm as the shader, for
+    /// machines without a usable GPU. It's much slower.
+    pub fn cpu() -> GpuContext
+    {
+        GpuContext
+        {
+            info: AdapterInfo
+            {
+                name: "CPU".to_owned(),
+                vendor: 0,
+                device: 0,
+                device_type: DeviceType::Cpu,
+                backend: wgpu::Backend::Empty,
+            },
+            limits: Limits::default(),
+            gpu: None,
+            error: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Swaps the built-in shader for `source`, which must have the same
+    /// bindings. It's checked with `check_shader` first, so a broken shader
+    /// leaves the old one in place. It's specialised like the built-in one
+    /// if it declares the same constants.
+    pub fn set_shader(&self, source: &str) -> Result<(), GpuError>
+    {
+        self.use_shader(&CheckedShader::new(source)?)
+    }
+
+    /// `set_shader` with the WGSL file at `path`.
+    pub fn load_shader(&self, path: &str) -> Result<(), GpuError>
+    {
+        self.use_shader(&CheckedShader::load(path)?)
+    }
+
+    /// `set_shader` with a shader that's already been checked, so one read
+    /// of a file can go to several contexts.
+    pub fn use_shader(&self, shader: &CheckedShader) -> Result<(), GpuError>
+    {
+        let gpu = match &self.gpu
+        {
+            Some(gpu) => gpu,
+            None => return Err(GpuError::Shader(
+                "the CPU renderer doesn't use a shader".to_owned())),
+        };
+
+        *gpu.shader.lock().unwrap() = Shader
+        {
+            source: shader.source.clone(),
+            pipelines: HashMap::new(),
+        };
+
+        Ok(())
+    }
+
+    /// Whether this renders on the CPU, see `GpuContext::cpu`.
+    pub fn is_cpu(&self) -> bool
+    {
+        self.gpu.is_none()
+    }
+
+    pub fn info(&self) -> &AdapterInfo
+    {
+        &self.info
+    }
+
+    /// Returns the first error wgpu reported since the last check.
+    fn check(&self) -> Result<(), GpuError>
+    {
+        match self.error.lock().unwrap().take()
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// What the shader works out for each pixel.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RenderMode
+{
+    PathTrace,
+    /// Grey for how much of the sky the first surface sees, from `rays`
+    /// rays per sample that count as blocked if they hit something within
+    /// `distance`, or anything at all if it's `None`. Materials are ignored.
+    AmbientOcclusion
+    {
+        rays: u32,
+        distance: Option<f32>,
+    },
+    /// The first surface's normal, with x, y and z as red, green and blue.
+    Normals,
+    /// How far away the first surface is, from white up close to black at
+    /// the furthest vertex.
+    Depth,
+    /// A different colour for each material.
+    MaterialId,
+    /// Grey surfaces with the triangles' edges drawn in.
+    Wireframe,
+}
+
+impl RenderMode
+{
+    /// Whether this is one of the cheap views for checking a scene, which
+    /// only look at the first surface.
+    pub fn is_debug(&self) -> bool
+    {
+        !matches!(self, RenderMode::PathTrace | RenderMode::AmbientOcclusion { .. })
+    }
+}
+
+/// WGSL that's passed `check_shader`, for `GpuContext::use_shader`.
+#[derive(Clone, Debug)]
+pub struct CheckedShader
+{
+    source: String,
+}
+
+impl CheckedShader
+{
+    pub fn new(source: &str) -> Result<CheckedShader, GpuError>
+    {
+        check_shader(source).map_err(GpuError::Shader)?;
+
+        Ok(CheckedShader
+        {
+            source: source.to_owned(),
+        })
+    }
+
+    /// Reads and checks the WGSL file at `path`.
+    pub fn load(path: &str) -> Result<CheckedShader, GpuError>
+    {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| GpuError::Shader(format!("could not read \"{}\": {}", path, e)))?;
+
+        CheckedShader::new(&source)
+    }
+
+    pub fn source(&self) -> &str
+    {
+        &self.source
+    }
+}
+
+/// How long each part of `run_shader` took.
+#[derive(Clone, Debug, Default)]
+pub struct Timings
+{
+    /// creating the buffers and bind group
+    pub setup: Duration,
+    /// each sample of each tile, in order. Unless `wait` is set the GPU is
+    /// only waited for every few samples, so the time for the samples
+    /// before is in those ones.
+    pub samples: Vec<Duration>,
+    /// set before rendering to wait for the GPU after every sample, so each
+    /// of `samples` is that sample's own time
+    pub wait: bool,
+    /// reading images back from the GPU
+    pub readback: Duration,
+    /// set to `Some` before rendering to have it filled in, which makes
+    /// rendering wait for the GPU after every sample
+    pub profile: Option<GpuProfile>,
+}
+
+/// How long the GPU spent on each part of a render, see `Timings::profile`.
+#[derive(Clone, Debug, Default)]
+pub struct GpuProfile
+{
+    /// the times are from timestamp queries, rather than the CPU waiting
+    /// for each submission to finish because the adapter doesn't have them
+    pub timestamps: bool,
+    /// the compute passes of each sample
+    pub compute: Vec<Duration>,
+    /// each time an image was copied to be read back
+    pub copies: Vec<Duration>,
+}
+
+impl GpuProfile
+{
+    fn phases(&self) -> [(&'static str, &'static str, &[Duration]); 2]
+    {
+        [
+            ("compute", "sample", &self.compute),
+            ("copies", "copy", &self.copies),
+        ]
+    }
+
+    /// A line for each phase with its minimum, average, maximum and total.
+    pub fn summary(&self) -> Vec<String>
+    {
+        let ms = |t: Duration| t.as_secs_f64() * 1000.0;
+
+        let mut lines = vec![match self.timestamps
+        {
+            true => "GPU time from timestamp queries:".to_owned(),
+            false => "GPU time measured on the CPU, the adapter has no timestamp queries:"
+                .to_owned(),
+        }];
+
+        for (name, each, times) in self.phases()
+        {
+            let total = times.iter().sum::<Duration>();
+
+            match (times.iter().min(), times.iter().max())
+            {
+                (Some(&min), Some(&max)) => lines.push(format!(
+                    "    {}: {:.3}ms min, {:.3}ms avg, {:.3}ms max per {}, {:.1}ms in all",
+                    name, ms(min), ms(total) / times.len() as f64, ms(max), each, ms(total))),
+                _ => lines.push(format!("    {}: none", name)),
+            }
+        }
+
+        lines
+    }
+
+    /// The same numbers as `summary`, in milliseconds.
+    pub fn to_json(&self) -> json::JsonValue
+    {
+        let mut out = json::object! { "timestamps": self.timestamps };
+
+        for (name, _, times) in self.phases()
+        {
+            let ms = times.iter().map(|t| t.as_secs_f64() * 1000.0).collect::<Vec<_>>();
+            let total = ms.iter().sum::<f64>();
+
+            out[name] = json::object!
+            {
+                "count": ms.len(),
+                "min_ms": ms.iter().copied().reduce(f64::min),
+                "avg_ms": if ms.is_empty() { None } else { Some(total / ms.len() as f64) },
+                "max_ms": ms.iter().copied().reduce(f64::max),
+                "total_ms": total,
+            };
+        }
+
+        out
+    }
+}
+
+/// Images besides the colour that `run_shader` can read back for a frame,
+/// stored bottom row first like the colour.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Aovs<'a>
+{
+    /// sums of coverage, shadow catcher hits, and light on the catchers
+    /// with and without shadows
+    pub matte: Option<&'a [[f32; 4]]>,
+    /// sums of squared luminance
+    pub squares: Option<&'a [f32]>,
+    /// distance to the first surface from one sample, -1 for misses and 0
+    /// for pixels outside the region
+    pub depth: Option<&'a [f32]>,
+}
+
+/// The scene as `run_shader` takes it. Each of the slices after `materials`
+/// is either one entry per triangle, or per material for `noises`, or empty
+/// when the scene doesn't use them.
+#[derive(Copy, Clone)]
+pub struct SceneBuffers<'a>
+{
+    pub triangles : &'a [Triangle],
+    pub materials : &'a [Material],
+    pub velocities: &'a [[f32; 3]],
+    pub shutter   : f32,
+    pub colours   : &'a [[[f32; 3]; 3]],
+    pub uvs       : &'a [[[f32; 2]; 3]],
+    pub textures  : &'a [Texture],
+    pub normals   : &'a [[[f32; 3]; 3]],
+    pub noises    : &'a [Noise],
+}
+
+/// How `run_shader` renders a `SceneBuffers`.
+#[derive(Copy, Clone, Debug)]
+pub struct ShaderSettings
+{
+    pub width        : u32,
+    pub height       : u32,
+    pub mode         : RenderMode,
+    /// whether to sum the squares for `Aovs::squares`
+    pub noise        : bool,
+    /// whether to keep `Aovs::depth`
+    pub depth_map    : bool,
+    pub depth        : u32,
+    pub ray_epsilon  : f32,
+    pub seed         : u64,
+    /// how many samples `image` already holds
+    pub start_samples: u32,
+    /// the part of the image to render, bottom row first
+    pub region       : Option<[u32; 4]>,
+    pub tile         : Option<[u32; 2]>,
+    pub max_dispatch : Option<Duration>,
+}
+
+/// Where `run_shader` asks whether to go on and hands over what it's done.
+pub struct Hooks<'a>
+{
+    pub condition : &'a dyn StopCondition,
+    /// whether `on_image` wants the image after this many samples
+    pub want_image: &'a dyn Fn(u32) -> bool,
+    pub on_image  : &'a mut dyn FnMut(u32, &[Colour]),
+    pub on_frame  : &'a mut FrameHook<'a>,
+}
+
+/// Takes each camera's finished frame, by its index, with how many samples
+/// it has.
+pub type FrameHook<'a> = dyn FnMut(usize, u32, &[Colour], Aovs) + 'a;
+
 pub fn run_shader(
+    ctx: &GpuContext,
     image: &mut Vec<Colour>,
-    width: u32,
-    height: u32,
-    camera: Camera,
-    triangles: &[Triangle],
-    materials: &[Material],
-    depth: u32,
-    condition: &dyn Fn(u32) -> bool)
-    -> u32
+    cameras: &[Camera],
+    scene: &SceneBuffers,
+    settings: &ShaderSettings,
+    timings: &mut Timings,
+    hooks: Hooks)
+    -> Result<u32, GpuError>
 {
-    let instance = Instance::new(Backends::PRIMARY);
+    let gpu = match &ctx.gpu
+    {
+        Some(gpu) => gpu,
+        None => return crate::cpu::run_cpu(image, cameras, scene, settings, timings, hooks),
+    };
+
+    let SceneBuffers { triangles, materials, shutter, .. } = *scene;
+    let ShaderSettings { width, height, mode, noise, depth_map, depth, ray_epsilon, seed,
+        start_samples, region, tile, max_dispatch } = *settings;
+    let Hooks { condition, want_image, on_image, on_frame } = hooks;
+
+    // the noise is measured from the squares
+    let noise = noise || condition.noise_every().is_some();
+
+    let (device, queue) = (&gpu.device, &gpu.queue);
+    let setup_start = Instant::now();
+
+    // errors from an earlier render don't belong to this one
+    let _ = ctx.check();
+
+    let (region, tile, tiles) = tiling(width, height, region, tile);
+    let (tiles_x, tiles_y) = tiles;
+
+    let bound = Bound::new(scene, mode);
+    let budget = RenderBudget::new(
+        tile, tiles_x * tiles_y, triangles, materials, &bound, noise, depth_map);
+    let Bound { moving, coloured, textured, smooth, noisy, matte, motion, colours, uvs, texels,
+        normals, noises } = bound;
+
+    pt_info!("The GPU buffers need {:.1} MiB", mib(budget.total()));
+    for line in budget.summary()
+    {
+        pt_debug!("{}", line);
+    }
+
+    budget.check(&ctx.limits)?;
+
+    let storage = BINDINGS.iter().filter(|(_, uniform)| !uniform).count() as u32;
+    if ctx.limits.max_storage_buffers_per_shader_stage < storage
+    {
+        return Err(GpuError::Limit(format!(
+            "The shader needs {} storage buffers but the device allows {}",
+            storage, ctx.limits.max_storage_buffers_per_shader_stage)));
+    }
+
+    let mode_info = mode_info(mode);
+
+    let compiled = gpu.pipeline(Specialisation
+    {
+        depth: depth,
+        mode: mode_info.mode,
+        moving: moving,
+        coloured: coloured,
+        textured: textured,
+        smooth: smooth,
+        noisy: noisy,
+        matte: matte,
+        squares: noise,
+        depths: depth_map,
+    }, &ctx.error)?;
+    let pipeline = &compiled.pipeline;
+    ctx.check()?;
+
+    let info = Info
+    {
+        triangles: triangles.len() as u32,
+        materials: materials.len() as u32,
+        width: width,
+        height: height,
+        samples: 1,
+        depth: depth,
+        tile_x: region[0],
+        tile_y: region[1],
+        tile_w: tile[0],
+        slice_x: 0,
+        slice_y: 0,
+        slice_w: 0,
+        slice_h: 0,
+        sample: 0,
+        seed: 0,
+        shutter: shutter,
+        moving: moving as u32,
+        matte: matte as u32,
+        squares: noise as u32,
+        depths: depth_map as u32,
+        far: furthest(&cameras[0], triangles),
+        ray_eps: ray_epsilon,
+        .. mode_info
+    };
+
+    let info_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("info buffer"),
+        contents: cast_slice(&[info]),
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    });
+
+    let camera_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("camera buffer"),
+        contents: cast_slice(&cameras[..1]),
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+    });
+
+    let triangle_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("triangle buffer"),
+        contents: cast_slice(triangles),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let material_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("material buffer"),
+        contents: cast_slice(materials),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let motion_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("motion buffer"),
+        contents: cast_slice(&motion),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let colour_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("colour buffer"),
+        contents: cast_slice(colours),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let uv_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("uv buffer"),
+        contents: cast_slice(&uvs[..]),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let texel_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("texel buffer"),
+        contents: cast_slice(&texels),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let normal_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("normal buffer"),
+        contents: cast_slice(normals),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let noise_buffer = device.create_buffer_init(&BufferInitDescriptor
+    {
+        label: Some("noise buffer"),
+        contents: cast_slice(noises),
+        usage: BufferUsages::STORAGE,
+    });
+
+    let image_size = budget.size("image");
+    let image_usage = BufferUsages::STORAGE
+        | BufferUsages::COPY_SRC
+        | BufferUsages::COPY_DST;
+    let image_buffer = if image.len() == (width * height) as usize
+        && region == [0, 0, width, height]
+        && tile == [width, height]
+    {
+        // resuming: continue accumulating on top of the previous samples
+        let sums = image.iter().map(|&c| Sum::from(c)).collect::<Vec<_>>();
+
+        device.create_buffer_init(&BufferInitDescriptor
+        {
+            label: Some("image buffer"),
+            contents: cast_slice(&sums),
+            usage: image_usage,
+        })
+    }
+    else
+    {
+        device.create_buffer(&BufferDescriptor
+        {
+            label: Some("image buffer"),
+            size: image_size,
+            usage: image_usage,
+            mapped_at_creation: false,
+        })
+    };
+
+    let staging_buffer = device.create_buffer(&BufferDescriptor
+    {
+        label: None,
+        size: image_size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // coverage, shadow catcher hits, and light on the catchers with and
+    // without shadows, for each pixel of a tile
+    let matte_size = budget.size("matte");
+    let matte_buffer = device.create_buffer(&BufferDescriptor
+    {
+        label: Some("matte buffer"),
+        size: matte_size,
+        usage: image_usage,
+        mapped_at_creation: false,
+    });
+    let matte_staging = device.create_buffer(&BufferDescriptor
+    {
+        label: None,
+        size: matte_size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // the sum of each pixel's squared luminance, for its noise
+    let squares_size = budget.size("squares");
+    let squares_buffer = device.create_buffer(&BufferDescriptor
+    {
+        label: Some("squares buffer"),
+        size: squares_size,
+        usage: image_usage,
+        mapped_at_creation: false,
+    });
+    let squares_staging = device.create_buffer(&BufferDescriptor
+    {
+        label: None,
+        size: squares_size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // each pixel's distance to the first surface
+    let depths_size = budget.size("depths");
+    let depths_buffer = device.create_buffer(&BufferDescriptor
+    {
+        label: Some("depths buffer"),
+        size: depths_size,
+        usage: image_usage,
+        mapped_at_creation: false,
+    });
+    let depths_staging = device.create_buffer(&BufferDescriptor
+    {
+        label: None,
+        size: depths_size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bg_layout = pipeline.get_bind_group_layout(0);
+
+    let entries = [
+        BindGroupEntry
+        {
+            binding: 0,
+            resource: info_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 1,
+            resource: camera_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 2,
+            resource: image_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 3,
+            resource: triangle_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 4,
+            resource: material_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 5,
+            resource: motion_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 6,
+            resource: matte_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 7,
+            resource: squares_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 8,
+            resource: depths_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 9,
+            resource: colour_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 10,
+            resource: uv_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 11,
+            resource: texel_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 12,
+            resource: normal_buffer.as_entire_binding(),
+        },
+        BindGroupEntry
+        {
+            binding: 13,
+            resource: noise_buffer.as_entire_binding(),
+        },
+    ];
+    check_bindings(&entries, &compiled.bindings)?;
+
+    let bind_group = device.create_bind_group(&BindGroupDescriptor
+    {
+        label: None,
+        layout: &bg_layout,
+        entries: &entries,
+    });
+
+    timings.setup = setup_start.elapsed();
+
+    // The first tile of the first frame decides the sample count with the
+    // stop condition, and every other tile and frame renders the same count
+    // so the images are consistent. Intermediate readbacks only happen when
+    // there is a single tile.
+    let single = tiles_x * tiles_y == 1;
+    let mut total = None;
+    let mut full = vec![Colour { r: 0.0, g: 0.0, b: 0.0 }; (width * height) as usize];
+    let mut tile_image = Vec::<Sum>::new();
+    let mut full_matte = if matte
+    {
+        Some(vec![[0.0f32; 4]; (width * height) as usize])
+    }
+    else
+    {
+        None
+    };
+    let mut tile_matte = Vec::<Sum<4>>::new();
+    let mut full_squares = if noise
+    {
+        Some(vec![0.0f32; (width * height) as usize])
+    }
+    else
+    {
+        None
+    };
+    let mut tile_squares = Vec::<Sum<1>>::new();
+    // the first tile's image and squares, to measure the noise
+    let (mut noise_image, mut noise_squares) = (Vec::<Sum>::new(), Vec::<Sum<1>>::new());
+    let mut full_depths = if depth_map
+    {
+        Some(vec![0.0f32; (width * height) as usize])
+    }
+    else
+    {
+        None
+    };
+    let mut tile_depths = Vec::<f32>::new();
+
+    let dispatch = Dispatch
+    {
+        device: device,
+        queue: queue,
+        pipeline: pipeline,
+        bind_group: &bind_group,
+        info_buffer: &info_buffer,
+    };
+
+    // rows per dispatch, measured on the first sample when slicing is on
+    let mut slice_rows = None;
+
+    // images for `on_image`, read without stalling the samples after them
+    let mut readback = Readback::new(image_size);
+
+    let mut profiler = timings.profile.as_ref()
+        .map(|_| Profiler::new(device, queue, gpu.timestamps));
+
+    if profiler.is_some() && !gpu.timestamps
+    {
+        pt_warn!("The adapter doesn't support timestamp queries, \
+            so GPU time is measured on the CPU around each submission");
+    }
+
+    // samples per pixel in `full`, for a partial result if the device is lost
+    let mut last_read = 0;
+    let lost = |e: GpuError, samples: u32, full: Vec<Colour>| match e
+    {
+        GpuError::MapFailed if samples > 0 => GpuError::DeviceLost
+        {
+            samples: samples,
+            image: full,
+        },
+        e => e,
+    };
+
+    for (frame, camera) in cameras.iter().enumerate()
+    {
+        if frame > 0
+        {
+            queue.write_buffer(&camera_buffer, 0, cast_slice(&[*camera]));
+        }
+
+        // depth is shaded from each frame's own camera
+        let far = furthest(camera, triangles);
+
+        for ty in 0..tiles_y
+        {
+            for tx in 0..tiles_x
+            {
+                let x = region[0] + tx * tile[0];
+                let y = region[1] + ty * tile[1];
+                let size = [
+                    tile[0].min(region[0] + region[2] - x),
+                    tile[1].min(region[1] + region[3] - y)];
+                let size_bytes = std::mem::size_of::<Sum>() as u64
+                    * size[0] as u64
+                    * size[1] as u64;
+
+                if !single
+                {
+                    pt_info!("Rendering tile {}/{} ({}x{} at {},{})",
+                        ty * tiles_x + tx + 1, tiles_x * tiles_y,
+                        size[0], size[1],
+                        x, y);
+                }
+
+                let tile_info = Info
+                {
+                    tile_x: x,
+                    tile_y: y,
+                    tile_w: size[0],
+                    seed: frame_seed(seed, frame),
+                    far: far,
+                    .. info
+                };
+
+                let mut samples = match total
+                {
+                    // the first tile may be resuming from a checkpoint
+                    None => start_samples,
+                    Some(_) =>
+                    {
+                        queue.write_buffer(
+                            &image_buffer, 0, &vec![0; image_size as usize]);
+                        queue.write_buffer(
+                            &matte_buffer, 0, &vec![0; matte_size as usize]);
+                        queue.write_buffer(
+                            &squares_buffer, 0, &vec![0; squares_size as usize]);
+                        queue.write_buffer(
+                            &depths_buffer, 0, &vec![0; depths_size as usize]);
+
+                        0
+                    },
+                };
+
+                while match total
+                {
+                    Some(total) => samples < total,
+                    None => condition.go_on(samples),
+                }
+                {
+                    samples += 1;
+
+                    let sample_start = Instant::now();
+
+                    if let Err(e) = run_sample(
+                        &dispatch, Info { sample: samples, .. tile_info },
+                        size, max_dispatch, &mut slice_rows,
+                        timings.wait || samples.is_multiple_of(MAX_IN_FLIGHT), profiler.as_mut())
+                    {
+                        return Err(lost(e, last_read, full));
+                    }
+                    ctx.check()?;
+
+                    let sample_time = sample_start.elapsed();
+                    timings.samples.push(sample_time);
+
+                    pt_trace!("Sample {} took {:.2}ms",
+                        samples, sample_time.as_secs_f64() * 1000.0);
+
+                    if total.is_none() && matches!(condition.noise_every(), Some(every) if samples.is_multiple_of(every))
+                    {
+                        let read_start = Instant::now();
+                        let read = read_image(
+                            device, queue, &image_buffer, &staging_buffer,
+                            size_bytes, &mut noise_image, profiler.as_mut())
+                            .and_then(|_| read_image(
+                                device, queue, &squares_buffer, &squares_staging,
+                                8 * size[0] as u64 * size[1] as u64, &mut noise_squares,
+                                profiler.as_mut()));
+                        timings.readback += read_start.elapsed();
+
+                        if let Err(e) = read
+                        {
+                            return Err(lost(e, last_read, full));
+                        }
+
+                        if let Some(noise) = relative_noise(
+                            &Sum::totals(&noise_image), &squares_of(&noise_squares), samples)
+                        {
+                            pt_debug!("Noise {:.4} after {} samples", noise, samples);
+                            condition.noise(samples, noise);
+                        }
+                    }
+
+                    if single
+                    {
+                        let read_start = Instant::now();
+                        let ready = match want_image(samples)
+                        {
+                            true => readback.copy(
+                                device, queue, &image_buffer, samples, profiler.as_mut()),
+                            false => Ok(Vec::new()),
+                        }.and_then(|mut ready|
+                        {
+                            ready.extend(readback.receive(device, false)?);
+                            Ok(ready)
+                        });
+                        timings.readback += read_start.elapsed();
+
+                        let ready = match ready
+                        {
+                            Ok(ready) => ready,
+                            Err(e) => return Err(lost(e, last_read, full)),
+                        };
+
+                        for (samples, tile_image) in ready
+                        {
+                            paste(&mut full, width, &tile_image, [x, y], size);
+                            last_read = samples;
+                            on_image(samples, &full);
+                        }
+                    }
+                }
+                total = Some(samples);
+
+                // copies still on their way are older than the final image,
+                // but whoever asked for them still gets them
+                if single
+                {
+                    let read_start = Instant::now();
+                    let ready = match readback.receive(device, true)
+                    {
+                        Ok(ready) => ready,
+                        Err(e) => return Err(lost(e, last_read, full)),
+                    };
+                    timings.readback += read_start.elapsed();
+
+                    for (samples, tile_image) in ready
+                    {
+                        paste(&mut full, width, &tile_image, [x, y], size);
+                        last_read = samples;
+                        on_image(samples, &full);
+                    }
+                }
+
+                let read_start = Instant::now();
+                if let Err(e) = read_image(
+                    device, queue, &image_buffer, &staging_buffer,
+                    size_bytes, &mut tile_image, profiler.as_mut())
+                {
+                    return Err(lost(e, last_read, full));
+                }
+                timings.readback += read_start.elapsed();
+                paste(&mut full, width, &Sum::totals(&tile_image), [x, y], size);
+                last_read = samples;
+
+                if let Some(full_matte) = &mut full_matte
+                {
+                    let read_start = Instant::now();
+                    if let Err(e) = read_image(
+                        device, queue, &matte_buffer, &matte_staging,
+                        32 * size[0] as u64 * size[1] as u64, &mut tile_matte,
+                        profiler.as_mut())
+                    {
+                        return Err(lost(e, last_read, full));
+                    }
+                    timings.readback += read_start.elapsed();
+
+                    paste(full_matte, width, &Sum::values(&tile_matte), [x, y], size);
+                }
+
+                if let Some(full_squares) = &mut full_squares
+                {
+                    let read_start = Instant::now();
+                    if let Err(e) = read_image(
+                        device, queue, &squares_buffer, &squares_staging,
+                        8 * size[0] as u64 * size[1] as u64, &mut tile_squares,
+                        profiler.as_mut())
+                    {
+                        return Err(lost(e, last_read, full));
+                    }
+                    timings.readback += read_start.elapsed();
+
+                    paste(full_squares, width, &squares_of(&tile_squares), [x, y], size);
+                }
+
+                if let Some(full_depths) = &mut full_depths
+                {
+                    let read_start = Instant::now();
+                    if let Err(e) = read_image(
+                        device, queue, &depths_buffer, &depths_staging,
+                        4 * size[0] as u64 * size[1] as u64, &mut tile_depths,
+                        profiler.as_mut())
+                    {
+                        return Err(lost(e, last_read, full));
+                    }
+                    timings.readback += read_start.elapsed();
+
+                    paste(full_depths, width, &tile_depths, [x, y], size);
+                }
+            }
+        }
+
+        on_frame(frame, total.unwrap_or(0), &full, Aovs
+        {
+            matte: full_matte.as_deref(),
+            squares: full_squares.as_deref(),
+            depth: full_depths.as_deref(),
+        });
+    }
+
+    *image = full;
+
+    if let Some(profiler) = profiler
+    {
+        timings.profile = Some(profiler.profile);
+    }
+
+    Ok(total.unwrap_or(0))
+}
+
+/// `source` with the value from `spec` for each constant it declares, in
+/// lines like `let DEPTH: u32 = 5u;`. Other lines are left alone.
+fn specialise(source: &str, spec: Specialisation) -> String
+{
+    let constants = [
+        ("DEPTH", "u32", format!("{}u", spec.depth)),
+        ("MODE", "u32", format!("{}u", spec.mode)),
+        ("MOVING", "bool", spec.moving.to_string()),
+        ("COLOURED", "bool", spec.coloured.to_string()),
+        ("TEXTURED", "bool", spec.textured.to_string()),
+        ("SMOOTH", "bool", spec.smooth.to_string()),
+        ("NOISY", "bool", spec.noisy.to_string()),
+        ("MATTE", "bool", spec.matte.to_string()),
+        ("SQUARES", "bool", spec.squares.to_string()),
+        ("DEPTHS", "bool", spec.depths.to_string()),
+    ];
+
+    let mut out = String::with_capacity(source.len());
+
+    for line in source.lines()
+    {
+        let name = line.strip_prefix("let ")
+            .and_then(|rest| rest.split(':').next())
+            .map(str::trim);
+
+        match constants.iter().find(|(n, _, _)| Some(*n) == name)
+        {
+            Some((name, ty, value)) =>
+                out.push_str(&format!("let {}: {} = {};", name, ty, value)),
+            None => out.push_str(line),
+        }
+
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Compiles `source`, checking it with naga first so mistakes point at the
+/// line. The errors wgpu reports to the device's handler while making the
+/// shader module and the pipeline are taken from `error` and returned as
+/// which of them failed, as wgpu 0.10 doesn't have error scopes.
+fn create_pipeline(device: &wgpu::Device, source: &str, error: &Mutex<Option<GpuError>>)
+    -> Result<Compiled, GpuError>
+{
+    let compile_start = Instant::now();
+
+    let module = parse_shader(source).map_err(GpuError::Shader)?;
+
+    let caught = |wrap: fn(String) -> GpuError| match error.lock().unwrap().take()
+    {
+        Some(GpuError::Validation(e)) => Err(wrap(e)),
+        Some(e) => Err(e),
+        None => Ok(()),
+    };
+
+    let shader = device.create_shader_module(&ShaderModuleDescriptor
+    {
+        label: Some("compute"),
+        source: ShaderSource::Wgsl(source.into()),
+    });
+    caught(GpuError::Shader)?;
+
+    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor
+    {
+        label: None,
+        layout: None,
+        module: &shader,
+        entry_point: "main",
+    });
+    caught(GpuError::PipelineCreation)?;
+
+    pt_debug!("Compiled the shader in {:.1}ms",
+        compile_start.elapsed().as_secs_f64() * 1000.0);
+
+    let mut bindings = module.global_variables.iter()
+        .filter_map(|(_, var)| var.binding.as_ref())
+        .filter(|binding| binding.group == 0)
+        .map(|binding| binding.binding)
+        .collect::<Vec<_>>();
+    bindings.sort_unstable();
+
+    Ok(Compiled
+    {
+        pipeline: Arc::new(pipeline),
+        bindings: bindings,
+    })
+}
+
+/// Checks the buffers in `entries` are the ones in `declared`, as wgpu's own
+/// error for a mismatch doesn't say which binding it is.
+fn check_bindings(entries: &[BindGroupEntry], declared: &[u32]) -> Result<(), GpuError>
+{
+    let provided = entries.iter().map(|e| e.binding).collect::<Vec<_>>();
+
+    let problems = declared.iter()
+        .filter(|b| !provided.contains(b))
+        .map(|b| format!("binding {} declared in shader but not provided", b))
+        .chain(provided.iter()
+            .filter(|b| !declared.contains(b))
+            .map(|b| format!("binding {} provided but not declared in shader", b)))
+        .collect::<Vec<_>>();
+
+    match problems.is_empty()
+    {
+        true => Ok(()),
+        false => Err(GpuError::PipelineCreation(problems.join(", "))),
+    }
+}
+
+/// The buffers `run_shader` binds in group 0, by binding: their names in the
+/// built-in shader, and whether they're uniforms rather than storage.
+const BINDINGS: [(&str, bool); 14] = [
+    ("info", true),
+    ("camera", true),
+    ("image", false),
+    ("triangles", false),
+    ("materials", false),
+    ("motions", false),
+    ("matte", false),
+    ("squares", false),
+    ("depths", false),
+    ("colours", false),
+    ("uvs", false),
+    ("texels", false),
+    ("normals", false),
+    ("noises", false),
+];
+
+/// Checks WGSL source compiles and has a compute entry point called `main`
+/// with the bindings `run_shader` provides. Errors point at the line.
+pub fn check_shader(source: &str) -> Result<(), String>
+{
+    let module = parse_shader(source)?;
+
+    let mut problems = Vec::new();
+    let mut found = [false; BINDINGS.len()];
+
+    for (_, var) in module.global_variables.iter()
+    {
+        let binding = match &var.binding
+        {
+            Some(binding) => binding,
+            None => continue,
+        };
+        let name = var.name.as_deref().unwrap_or("(unnamed)");
+
+        let uniform = match BINDINGS.get(binding.binding as usize)
+        {
+            Some((_, uniform)) if binding.group == 0 => *uniform,
+            _ =>
+            {
+                problems.push(format!(
+                    "\"{}\" is bound to group {} binding {}, which isn't provided",
+                    name, binding.group, binding.binding));
+                continue;
+            },
+        };
+
+        found[binding.binding as usize] = true;
+
+        let is_uniform = match var.class
+        {
+            naga::StorageClass::Uniform => true,
+            naga::StorageClass::Storage { .. } => false,
+            _ =>
+            {
+                problems.push(format!(
+                    "\"{}\" at binding {} should be a buffer", name, binding.binding));
+                continue;
+            },
+        };
+
+        if is_uniform != uniform
+        {
+            problems.push(format!(
+                "\"{}\" at binding {} should be {}",
+                name, binding.binding,
+                if uniform { "var<uniform>" } else { "var<storage>" }));
+        }
+    }
+
+    for (i, (name, _)) in BINDINGS.iter().enumerate()
+    {
+        if !found[i]
+        {
+            problems.push(format!("binding {} (\"{}\") is missing", i, name));
+        }
+    }
+
+    problems.extend(check_layouts(&module));
+
+    match problems.is_empty()
+    {
+        true => Ok(()),
+        false => Err(format!(
+            "it doesn't match what run_shader binds:\n    {}", problems.join("\n    "))),
+    }
+}
+
+/// Parses and validates WGSL source, which needs a compute entry point
+/// called `main`.
+fn parse_shader(source: &str) -> Result<naga::Module, String>
+{
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| e.emit_to_string(source))?;
+
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+        .validate(&module)
+        .map_err(|e| describe_invalid(source, &e))?;
+
+    if !module.entry_points.iter()
+        .any(|e| e.name == "main" && e.stage == naga::ShaderStage::Compute)
+    {
+        return Err("there's no compute entry point called \"main\"".to_owned());
+    }
+
+    Ok(module)
+}
+
+/// A validation error with the line and column of the function, variable,
+/// type or constant it's about, since naga's validation errors don't say
+/// where they are in the source.
+fn describe_invalid(source: &str, e: &naga::valid::ValidationError) -> String
+{
+    use naga::valid::ValidationError as Invalid;
+
+    // how its declaration starts, and why it's invalid
+    let (start, name, cause) = match e
+    {
+        Invalid::Function { name, error, .. } => (format!("fn {}(", name), name, causes(error)),
+        Invalid::EntryPoint { name, error, .. } => (format!("fn {}(", name), name, causes(error)),
+        Invalid::GlobalVariable { name, error, .. } => (format!(" {}:", name), name, causes(error)),
+        Invalid::Type { name, error, .. } => (format!("struct {}", name), name, causes(error)),
+        Invalid::Constant { name, error, .. } => (format!("let {}", name), name, causes(error)),
+        e => return causes(e),
+    };
+    let global = matches!(e, Invalid::GlobalVariable { .. });
+
+    let found = source.lines().enumerate()
+        .find(|(_, line)| line.contains(&start) && (!global || line.contains("var")));
+
+    match found
+    {
+        Some((i, line)) =>
+        {
+            // at the name, not the keyword before it
+            let column = line.find(&start).unwrap() + start.find(name.as_str()).unwrap();
+
+            format!(
+                "{}: {}\n  --> line {}, column {}\n     |\n{:>4} | {}\n     | {}^",
+                e, cause, i + 1, column + 1, i + 1, line.trim_end(), " ".repeat(column))
+        },
+        None => format!("{}: {}", e, cause),
+    }
+}
+
+/// An error and everything it says caused it, outermost first.
+fn causes(e: &dyn std::error::Error) -> String
+{
+    let mut causes = vec![e.to_string()];
+    let mut source = e.source();
+
+    while let Some(e) = source
+    {
+        causes.push(e.to_string());
+        source = e.source();
+    }
+
+    causes.join(": ")
+}
+
+/// A struct `run_shader` writes to a buffer, by its name in the shader, with
+/// its size and where each field starts.
+type Layout = (&'static str, usize, Vec<(&'static str, usize)>);
+
+fn layouts() -> Vec<Layout>
+{
+    macro_rules! layout
+    {
+        ($name:literal, $ty:ty, $($field:ident),*) =>
+        ((
+            $name,
+            std::mem::size_of::<$ty>(),
+            vec![$((stringify!($field), std::mem::offset_of!($ty, $field))),*]
+        ))
+    }
+
+    vec![
+        layout!("Info", Info,
+            triangles, materials, width, height, samples, depth, tile_x, tile_y, tile_w,
+            slice_x, slice_y, slice_w, slice_h, sample, seed, shutter, moving, matte,
+            squares, depths, mode, ao_rays, ao_dist, far, ray_eps),
+        layout!("Camera", Camera, pos, front, up, fov),
+        layout!("Colour", Colour, r, g, b),
+        layout!("Sum", Sum, sum, lost),
+        layout!("MatteSum", Sum<4>, sum, lost),
+        layout!("SquareSum", Sum<1>, sum, lost),
+        layout!("Triangle", Triangle, a, b, c, mat),
+        layout!("Material", Material,
+            colour, glow, gloss, reflect_c, flags, glow_texture, normal_texture, normal_strength,
+            alpha_texture, alpha_cutoff),
+        layout!("Motion", Motion, a, b, c),
+        // the colours at a triangle's points are laid out like its motion
+        layout!("Corners", Motion, a, b, c),
+        layout!("CornerUvs", CornerUvs, a, b, c),
+        layout!("Noise", Noise, a, b, scale, lacunarity, gain, octaves),
+    ]
+}
+
+/// Checks the shader's structs that share a name with one in `layouts`
+/// have the same fields in the same places, and arrays of them the same
+/// stride, since a mismatch garbles everything after it rather than
+/// failing.
+fn check_layouts(module: &naga::Module) -> Vec<String>
+{
+    let layouts = layouts();
+    let find = |ty: naga::Handle<naga::Type>| module.types[ty].name.as_deref()
+        .and_then(|name| layouts.iter().find(|(n, _, _)| *n == name));
+
+    let mut problems = Vec::new();
+
+    for (handle, ty) in module.types.iter()
+    {
+        match &ty.inner
+        {
+            naga::TypeInner::Struct { members, span, .. } =>
+            {
+                let (name, size, fields) = match find(handle)
+                {
+                    Some(layout) => layout,
+                    None => continue,
+                };
+
+                let found = members.iter()
+                    .map(|m| (m.name.as_deref().unwrap_or(""), m.offset as usize))
+                    .collect::<Vec<_>>();
+
+                if found != *fields || *span as usize != *size
+                {
+                    let describe = |fields: &[(&str, usize)], size: usize| format!(
+                        "{} bytes of {}",
+                        size,
+                        fields.iter()
+                            .map(|(f, offset)| format!("{} at {}", f, offset))
+                            .collect::<Vec<_>>()
+                            .join(", "));
+
+                    problems.push(format!(
+                        "struct {} should be {}, but it's {}",
+                        name, describe(fields, *size), describe(&found, *span as usize)));
+                }
+            },
+            naga::TypeInner::Array { base, stride, .. } =>
+            {
+                if let Some((name, size, _)) = find(*base)
+                {
+                    if *stride as usize != *size
+                    {
+                        problems.push(format!(
+                            "arrays of {} should have a stride of {}, not {}",
+                            name, size, stride));
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+
+    problems
+}
+
+/// wgpu doesn't report the dispatch size limit, so this is the WebGPU default.
+pub const MAX_WORKGROUPS_PER_DIMENSION: u32 = 65535;
+
+/// Misses are at least this far away, in the shader too. It's far beyond
+/// any scene, but small enough that its square fits in an f32.
+pub(crate) const MISS: f32 = 1.0e18;
+
+/// Pixels in each workgroup across and down, which must match the shader's
+/// `workgroup_size`.
+const WORKGROUP_SIZE: [u32; 2] = [8, 8];
+
+/// The region to render and the size of its tiles, and how many tiles there
+/// are each way. Without tiling the region is a single tile.
+fn tiling(width: u32, height: u32, region: Option<[u32; 4]>, tile: Option<[u32; 2]>)
+    -> ([u32; 4], [u32; 2], (u32, u32))
+{
+    let region = region.unwrap_or([0, 0, width, height]);
+    let tile = match tile
+    {
+        Some(tile) => [tile[0].min(region[2]), tile[1].min(region[3])],
+        None => [region[2], region[3]],
+    };
+    let tiles_x = region[2].div_ceil(tile[0]);
+    let tiles_y = region[3].div_ceil(tile[1]);
+
+    (region, tile, (tiles_x, tiles_y))
+}
+
+/// The scene as the shader binds it, with which of its optional buffers it
+/// reads.
+struct Bound<'a>
+{
+    moving: bool,
+    coloured: bool,
+    textured: bool,
+    smooth: bool,
+    noisy: bool,
+    matte: bool,
+    motion: Vec<Motion>,
+    colours: &'a [[[f32; 3]; 3]],
+    uvs: Cow<'a, [[[f32; 2]; 3]]>,
+    texels: Vec<[f32; 4]>,
+    normals: &'a [[[f32; 3]; 3]],
+    noises: &'a [Noise],
+}
+
+impl<'a> Bound<'a>
+{
+    fn new(scene: &SceneBuffers<'a>, mode: RenderMode) -> Bound<'a>
+    {
+        let SceneBuffers { triangles, materials, velocities, shutter, colours, uvs, textures,
+            normals, noises } = *scene;
+
+        // static scenes bind a single unused entry, and the shader skips it
+        let moving = shutter > 0.0 && velocities.len() == triangles.len()
+            && velocities.iter().any(|v| *v != [0.0, 0.0, 0.0]);
+        let motion = if moving
+        {
+            let add = crate::vec3::add;
+
+            triangles.iter()
+                .zip(velocities)
+                .map(|(t, &v)| Motion
+                {
+                    a: add(t.a, v),
+                    b: add(t.b, v),
+                    c: add(t.c, v),
+                })
+                .collect::<Vec<_>>()
+        }
+        else
+        {
+            vec![Motion { a: [0.0; 3], b: [0.0; 3], c: [0.0; 3] }]
+        };
+
+        // uncoloured scenes bind a single unused entry too
+        let coloured = colours.len() == triangles.len()
+            && colours.iter().any(|c| *c != [[1.0; 3]; 3]);
+        let colours = match coloured
+        {
+            true => colours,
+            false => &[[[1.0; 3]; 3]],
+        };
+
+        // so are untextured ones, and triangles without uvs get the defaults
+        let textured = !textures.is_empty()
+            && materials.iter().any(Material::textured);
+        let uvs = match textured
+        {
+            true if uvs.len() == triangles.len() => Cow::Borrowed(uvs),
+            true => Cow::Owned(vec![DEFAULT_UVS; triangles.len()]),
+            false => Cow::Borrowed(&[DEFAULT_UVS][..]),
+        };
+        let texels = match textured
+        {
+            true => crate::texture::pack(textures),
+            false => vec![[0.0; 4]],
+        };
+
+        // and so are scenes with only flat triangles
+        let smooth = normals.len() == triangles.len()
+            && normals.iter().any(|n| *n != [[0.0; 3]; 3]);
+        let normals = match smooth
+        {
+            true => normals,
+            false => &[[[0.0; 3]; 3]],
+        };
+
+        // and so are scenes without noise materials
+        let noisy = noises.len() == materials.len()
+            && noises.iter().any(|n| n.octaves != 0);
+        let noises = match noisy
+        {
+            true => noises,
+            false => &[Noise::NONE],
+        };
+
+        // shadow catchers need a matte to put the shadows in
+        let matte = mode == RenderMode::PathTrace && triangles.iter().any(|t| matches!(
+            materials.get(t.mat as usize),
+            Some(m) if m.flags & Material::SHADOW_CATCHER != 0));
+
+        Bound
+        {
+            moving: moving,
+            coloured: coloured,
+            textured: textured,
+            smooth: smooth,
+            noisy: noisy,
+            matte: matte,
+            motion: motion,
+            colours: colours,
+            uvs: uvs,
+            texels: texels,
+            normals: normals,
+            noises: noises,
+        }
+    }
+}
+
+fn mib(bytes: u64) -> f64
+{
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+/// The buffers a render allocates on the GPU, worked out before any of them
+/// are so a render that can't fit fails straight away. See `Scene::budget`.
+#[derive(Clone, Debug)]
+pub struct RenderBudget
+{
+    /// each buffer's name, size in bytes, and whether it's bound as storage,
+    /// which `Limits::max_storage_buffer_binding_size` applies to
+    pub buffers: Vec<(&'static str, u64, bool)>,
+    /// in all the textures, which the shader finds with f32s
+    pub texels: usize,
+}
+
+impl RenderBudget
+{
+    /// The most texels the shader can index.
+    pub const MAX_TEXELS: usize = 1 << 24;
+
+    /// The buffers for `tiles` tiles of `tile` pixels and the scene as it's
+    /// bound. `squares` and `depths` are whether those images are wanted.
+    fn new(
+        tile: [u32; 2],
+        tiles: u32,
+        triangles: &[Triangle],
+        materials: &[Material],
+        bound: &Bound,
+        squares: bool,
+        depths: bool)
+        -> RenderBudget
+    {
+        use std::mem::{size_of, size_of_val};
+
+        let pixels = tile[0] as u64 * tile[1] as u64;
+        // images that aren't wanted bind a single pixel
+        let image = |pixel: u64, wanted: bool| pixel * if wanted { pixels } else { 1 };
+
+        let image_size = image(size_of::<Sum>() as u64, true);
+        let matte_size = image(size_of::<Sum<4>>() as u64, bound.matte);
+        let squares_size = image(size_of::<Sum<1>>() as u64, squares);
+        let depths_size = image(4, depths);
+
+        let mut buffers = vec![
+            ("info", size_of::<Info>() as u64, false),
+            ("camera", size_of::<Camera>() as u64, false),
+            ("image", image_size, true),
+            ("triangles", size_of_val(triangles) as u64, true),
+            ("materials", size_of_val(materials) as u64, true),
+            ("motions", size_of_val(&bound.motion[..]) as u64, true),
+            ("matte", matte_size, true),
+            ("squares", squares_size, true),
+            ("depths", depths_size, true),
+            ("colours", size_of_val(bound.colours) as u64, true),
+            ("uvs", size_of_val(&bound.uvs[..]) as u64, true),
+            ("texels", size_of_val(&bound.texels[..]) as u64, true),
+            ("normals", size_of_val(bound.normals) as u64, true),
+            ("noises", size_of_val(bound.noises) as u64, true),
+            ("image staging", image_size, false),
+            ("matte staging", matte_size, false),
+            ("squares staging", squares_size, false),
+            ("depths staging", depths_size, false),
+        ];
+
+        // only a single tile is read back while it renders, see `Readback`
+        if tiles == 1
+        {
+            buffers.push(("readback", 2 * image_size, false));
+        }
+
+        RenderBudget
+        {
+            buffers: buffers,
+            texels: bound.texels.len(),
+        }
+    }
+
+    /// The size of the buffer called `name`, or 0 if there's none.
+    pub fn size(&self, name: &str) -> u64
+    {
+        self.buffers.iter()
+            .find(|(n, _, _)| *n == name)
+            .map_or(0, |&(_, size, _)| size)
+    }
+
+    /// Bytes in every buffer.
+    pub fn total(&self) -> u64
+    {
+        self.buffers.iter().map(|&(_, size, _)| size).sum()
+    }
+
+    /// A line for each buffer with its size, largest first.
+    pub fn summary(&self) -> Vec<String>
+    {
+        let mut buffers = self.buffers.clone();
+        buffers.sort_by_key(|&(_, size, _)| std::cmp::Reverse(size));
+
+        buffers.iter()
+            .map(|&(name, size, _)| match size
+            {
+                0..=1023 => format!("    {}: {} bytes", name, size),
+                1024..=1048575 => format!("    {}: {:.1} KiB", name, size as f64 / 1024.0),
+                _ => format!("    {}: {:.1} MiB", name, mib(size)),
+            })
+            .collect()
+    }
+
+    /// Checks every buffer fits within `limits`, since wgpu's own errors for
+    /// these don't say what to change. Dispatches are split to fit by
+    /// `run_sample`.
+    pub fn check(&self, limits: &Limits) -> Result<(), GpuError>
+    {
+        let max = limits.max_storage_buffer_binding_size as u64;
+
+        for &(name, size, storage) in &self.buffers
+        {
+            if storage && size > max
+            {
+                // the images grow with the tile, the rest with the scene
+                let fix = match name
+                {
+                    "image" | "matte" | "squares" | "depths" => "try --tile or a lower resolution",
+                    _ => "try a simpler scene",
+                };
+
+                return Err(GpuError::Limit(format!(
+                    "The render needs {:.1} MiB of buffers, with {:.1} MiB in the {} buffer, \
+                     but the device allows {:.1} MiB per buffer; {}",
+                    mib(self.total()), mib(size), name, mib(max), fix)));
+            }
+        }
+
+        if self.texels > RenderBudget::MAX_TEXELS
+        {
+            return Err(GpuError::Limit(format!(
+                "Textures have {} texels but the most is {}; try smaller textures",
+                self.texels, RenderBudget::MAX_TEXELS)));
+        }
+
+        Ok(())
+    }
+}
+
+/// The `RenderBudget` for `run_shader` with the same arguments.
+pub(crate) fn render_budget(scene: &SceneBuffers, settings: &ShaderSettings) -> RenderBudget
+{
+    let s = settings;
+    let (_, tile, (tiles_x, tiles_y)) = tiling(s.width, s.height, s.region, s.tile);
+    let bound = Bound::new(scene, s.mode);
+
+    RenderBudget::new(
+        tile, tiles_x * tiles_y, scene.triangles, scene.materials, &bound, s.noise, s.depth_map)
+}
+
+/// Every adapter on every backend, in the order `--adapter` indexes them.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn list_adapters() -> Vec<(AdapterInfo, Limits)>
+{
+    let instance = Instance::new(Backends::all());
+
+    instance
+        .enumerate_adapters(Backends::all())
+        .map(|a| (a.get_info(), a.limits()))
+        .collect()
+}
+
+/// Finds an adapter by its index or part of its name. Without a choice this
+/// prefers discrete GPUs, then integrated GPUs, then anything else (such as
+/// software renderers), unless `require_discrete` is set.
+#[cfg(not(target_arch = "wasm32"))]
+fn find_adapter(instance: &Instance, choice: Option<&str>, require_discrete: bool)
+    -> Result<Adapter, GpuError>
+{
+    let mut adapters = instance
+        .enumerate_adapters(Backends::all())
+        .collect::<Vec<_>>();
+
+    let index = match choice
+    {
+        Some(choice) => match choice.trim().parse::<usize>()
+        {
+            Ok(index) if index < adapters.len() => Some(index),
+            Ok(_) => None,
+            Err(_) =>
+            {
+                let choice = choice.to_lowercase();
+
+                adapters.iter().position(|a| a.get_info().name
+                    .to_lowercase()
+                    .contains(&choice))
+            },
+        },
+        None =>
+        {
+            let rank = |t: DeviceType| match t
+            {
+                DeviceType::DiscreteGpu => 0,
+                DeviceType::IntegratedGpu => 1,
+                DeviceType::VirtualGpu => 2,
+                DeviceType::Cpu => 3,
+                DeviceType::Other => 4,
+            };
+
+            adapters.iter()
+                .enumerate()
+                .filter(|(_, a)| !require_discrete
+                    || a.get_info().device_type == DeviceType::DiscreteGpu)
+                .min_by_key(|(i, a)| (rank(a.get_info().device_type), *i))
+                .map(|(i, _)| i)
+        },
+    };
+
+    match index
+    {
+        Some(index) => Ok(adapters.swap_remove(index)),
+        None =>
+        {
+            let mut err = match choice
+            {
+                Some(choice) => format!("No adapter matches \"{}\"", choice),
+                None if require_discrete => "No discrete GPU found".to_owned(),
+                None => "No GPU found".to_owned(),
+            };
+
+            if adapters.is_empty()
+            {
+                err.push_str(", and no adapters are available");
+            }
+            else
+            {
+                err.push_str(", available adapters are:");
+
+                for (i, a) in adapters.iter().enumerate()
+                {
+                    let info = a.get_info();
+                    err.push_str(&format!("\n    {}: {} ({:?}, {:?})",
+                        i, info.name, info.backend, info.device_type));
+                }
+            }
+
+            Err(GpuError::AdapterNotFound(err))
+        },
+    }
+}
+
+fn paste<T: Copy>(
+    image: &mut [T],
+    width: u32,
+    tile: &[T],
+    pos: [u32; 2],
+    size: [u32; 2])
+{
+    for row in 0..size[1]
+    {
+        let src = (row * size[0]) as usize;
+        let dst = ((pos[1] + row) * width + pos[0]) as usize;
+
+        image[dst..dst + size[0] as usize]
+            .copy_from_slice(&tile[src..src + size[0] as usize]);
+    }
+}
+
+/// The `Info` fields for `mode`, with zeros for the rest.
+fn mode_info(mode: RenderMode) -> Info
+{
+    let info = bytemuck::Zeroable::zeroed();
+
+    match mode
+    {
+        RenderMode::PathTrace => info,
+        RenderMode::AmbientOcclusion { rays, distance } => Info
+        {
+            mode: 1,
+            ao_rays: rays,
+            ao_dist: distance.unwrap_or(MISS).min(MISS),
+            .. info
+        },
+        RenderMode::Normals => Info { mode: 2, .. info },
+        RenderMode::Depth => Info { mode: 3, .. info },
+        RenderMode::MaterialId => Info { mode: 4, .. info },
+        RenderMode::Wireframe => Info { mode: 5, .. info },
+    }
+}
+
+/// How far the furthest vertex is from the camera, the black end of depth
+/// mode.
+pub(crate) fn furthest(camera: &Camera, triangles: &[Triangle]) -> f32
+{
+    let dist = |v: [f32; 3]| crate::vec3::length(crate::vec3::sub(v, camera.pos));
+
+    triangles.iter()
+        .map(|t| dist(t.a).max(dist(t.b)).max(dist(t.c)))
+        .fold(0.0_f32, f32::max)
+        .clamp(0.001, MISS)
+}
+
+/// The shader's seed for one frame, mixed from the render's seed so every
+/// frame has different noise, and the same noise every time the render is
+/// repeated. The shader keys its random numbers on this, the pixel, the
+/// sample and the bounce.
+pub(crate) fn frame_seed(seed: u64, frame: usize) -> u32
+{
+    let mut x = seed ^ ((frame as u64) << 48);
+
+    // the splitmix64 finaliser
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+
+    (x ^ (x >> 31)) as u32
+}
+
+/// Rows in the dispatch used to measure how long a row takes.
+const PROBE_ROWS: u32 = 8;
+
+/// Samples submitted before waiting for the GPU to finish them, so the
+/// queue doesn't grow without limit.
+const MAX_IN_FLIGHT: u32 = 4;
+
+/// What `run_sample` dispatches the shader with.
+struct Dispatch<'a>
+{
+    device: &'a wgpu::Device,
+    queue: &'a wgpu::Queue,
+    pipeline: &'a wgpu::ComputePipeline,
+    bind_group: &'a wgpu::BindGroup,
+    info_buffer: &'a wgpu::Buffer,
+}
+
+/// Renders one sample of a tile. With `max_dispatch` the tile is split into
+/// slices of rows, each submitted separately so no single submission runs
+/// long enough for the OS to reset the GPU. The first slice of the first
+/// sample is timed to decide `slice_rows`. Slices are also split to stay
+/// within the dispatch size limit, in workgroups of `WORKGROUP_SIZE` pixels.
+/// It only waits for the GPU to finish if `wait` is set or it's profiled.
+fn run_sample(
+    dispatch: &Dispatch,
+    info: Info,
+    size: [u32; 2],
+    max_dispatch: Option<Duration>,
+    slice_rows: &mut Option<u32>,
+    wait: bool,
+    mut profiler: Option<&mut Profiler>)
+    -> Result<(), GpuError>
+{
+    let Dispatch { device, queue, pipeline, bind_group, info_buffer } = *dispatch;
+
+    let mut y = 0;
+    while y < size[1]
+    {
+        let probe = max_dispatch.is_some() && slice_rows.is_none();
+        let rows = match (*slice_rows, probe)
+        {
+            (Some(rows), _) => rows,
+            (None, true) => PROBE_ROWS,
+            (None, false) => size[1],
+        }.min(size[1] - y).min(MAX_WORKGROUPS_PER_DIMENSION * WORKGROUP_SIZE[1]);
+
+        let start = Instant::now();
+
+        let mut x = 0;
+        while x < size[0]
+        {
+            let cols = (size[0] - x).min(MAX_WORKGROUPS_PER_DIMENSION * WORKGROUP_SIZE[0]);
+
+            queue.write_buffer(info_buffer, 0, cast_slice(&[Info
+            {
+                slice_x: x,
+                slice_y: y,
+                slice_w: cols,
+                slice_h: rows,
+                .. info
+            }]));
+
+            let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor
+            {
+                label: None,
+            });
+
+            if let Some(profiler) = &mut profiler
+            {
+                profiler.begin(device, queue, &mut encoder)?;
+            }
+
+            {
+                let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor
+                {
+                    label: None
+                });
+                cpass.set_pipeline(pipeline);
+                cpass.set_bind_group(0, bind_group, &[]);
+                cpass.dispatch(
+                    cols.div_ceil(WORKGROUP_SIZE[0]),
+                    rows.div_ceil(WORKGROUP_SIZE[1]),
+                    1);
+            }
+
+            if let Some(profiler) = &mut profiler
+            {
+                profiler.end(&mut encoder);
+            }
+
+            queue.submit(Some(encoder.finish()));
+
+            x += cols;
+        }
+
+        if let (true, Some(max)) = (probe, max_dispatch)
+        {
+            device.poll(wgpu::Maintain::Wait);
+
+            let per_row = start.elapsed().as_secs_f64() / rows as f64;
+            *slice_rows = Some((max.as_secs_f64() / per_row.max(1e-9))
+                .min(u32::MAX as f64)
+                .max(1.0) as u32);
+
+            pt_debug!("A row takes {:.3}ms, dispatching {} rows at a time",
+                per_row * 1000.0, slice_rows.unwrap());
+        }
+
+        y += rows;
+    }
+
+    match profiler
+    {
+        Some(profiler) =>
+        {
+            let compute_time = profiler.finish(device, queue)?;
+            profiler.profile.compute.push(compute_time);
+        },
+        None =>
+        {
+            device.poll(match wait
+            {
+                true => wgpu::Maintain::Wait,
+                false => wgpu::Maintain::Poll,
+            });
+        },
+    }
+
+    Ok(())
+}
+
+type MapFuture = std::pin::Pin<Box<dyn Future<Output = Result<(), wgpu::BufferAsyncError>>>>;
+
+/// Copies of the image read back while rendering carries on. Two staging
+/// buffers take turns, so a copy can be made while the last one is still
+/// being mapped, and each is handed over once the GPU gets to it.
+struct Readback
+{
+    /// bytes in the image
+    size: u64,
+    /// created by the first copy
+    buffers: Vec<wgpu::Buffer>,
+    /// the samples in each buffer's copy, while it's being mapped
+    pending: [Option<(u32, MapFuture)>; 2],
+    /// the buffer for the next copy, which holds the older copy if both
+    /// are pending
+    next: usize,
+}
+
+impl Readback
+{
+    fn new(size: u64) -> Readback
+    {
+        Readback
+        {
+            size: size,
+            buffers: Vec::new(),
+            pending: [None, None],
+            next: 0,
+        }
+    }
+
+    /// Copies `image` after the work submitted so far, which holds `samples`
+    /// samples. If both buffers are busy, this waits for them and returns
+    /// what they held. When profiled it waits for the copy too.
+    fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &wgpu::Buffer,
+        samples: u32,
+        mut profiler: Option<&mut Profiler>)
+        -> Result<Vec<(u32, Vec<Colour>)>, GpuError>
+    {
+        let ready = match self.pending[self.next]
+        {
+            Some(_) => self.receive(device, true)?,
+            None => Vec::new(),
+        };
+
+        if self.buffers.is_empty()
+        {
+            for _ in 0..2
+            {
+                self.buffers.push(device.create_buffer(&BufferDescriptor
+                {
+                    label: Some("readback buffer"),
+                    size: self.size,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+        }
+
+        let buffer = &self.buffers[self.next];
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor
+        {
+            label: None,
+        });
+
+        if let Some(profiler) = &mut profiler
+        {
+            profiler.begin(device, queue, &mut encoder)?;
+        }
+
+        encoder.copy_buffer_to_buffer(image, 0, buffer, 0, self.size);
+
+        if let Some(profiler) = &mut profiler
+        {
+            profiler.end(&mut encoder);
+        }
+
+        queue.submit(Some(encoder.finish()));
+
+        if let Some(profiler) = profiler
+        {
+            let copy_time = profiler.finish(device, queue)?;
+            profiler.profile.copies.push(copy_time);
+        }
+
+        let future = buffer.slice(..).map_async(wgpu::MapMode::Read);
+        self.pending[self.next] = Some((samples, Box::pin(future)));
+        self.next = 1 - self.next;
+
+        Ok(ready)
+    }
+
+    /// The copies that have been mapped, oldest first, with how many samples
+    /// they hold. With `wait` this waits for all of them.
+    fn receive(&mut self, device: &wgpu::Device, wait: bool)
+        -> Result<Vec<(u32, Vec<Colour>)>, GpuError>
+    {
+        struct NoWake;
+
+        impl std::task::Wake for NoWake
+        {
+            fn wake(self: Arc<Self>) { }
+        }
+
+        let waker = std::task::Waker::from(Arc::new(NoWake));
+        let mut cx = std::task::Context::from_waker(&waker);
+
+        if wait
+        {
+            device.poll(wgpu::Maintain::Wait);
+        }
+
+        let mut ready = Vec::new();
+
+        // the older copy is in the buffer the next copy goes to
+        for i in [self.next, 1 - self.next]
+        {
+            let result = match &mut self.pending[i]
+            {
+                Some((_, future)) if wait => block_on(future.as_mut()),
+                Some((_, future)) => match future.as_mut().poll(&mut cx)
+                {
+                    std::task::Poll::Ready(result) => result,
+                    // the newer copy can't be ready before the older one
+                    std::task::Poll::Pending => break,
+                },
+                None => continue,
+            };
 
-    let adapter = instance
-        .enumerate_adapters(Backends::PRIMARY)
-        .filter(|a| a.get_info().device_type == DeviceType::DiscreteGpu)
-        .next()
-        .unwrap();
+            let (samples, _) = self.pending[i].take().unwrap();
 
-    let (device, queue) = block_on(adapter
-        .request_device(&Default::default(), None))
-        .unwrap();
+            if result.is_err()
+            {
+                return Err(GpuError::MapFailed);
+            }
 
-    let shader = device.create_shader_module(&ShaderModuleDescriptor
-    {
-        label: Some("compute"),
-        source: ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-    });
+            let slice = self.buffers[i].slice(..);
+            let data = slice.get_mapped_range();
+            let image = Sum::totals(cast_slice::<u8, Sum>(&data[..]));
 
-    let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor
-    {
-        label: None,
-        layout: None,
-        module: &shader,
-        entry_point: "main",
-    });
+            drop(data);
+            self.buffers[i].unmap();
 
-    let info_buffer = device.create_buffer_init(&BufferInitDescriptor
-    {
-        label: Some("info buffer"),
-        contents: cast_slice(&[Info
-        {
-            triangles: triangles.len() as u32,
-            materials: materials.len() as u32,
-            width: width,
-            height: height,
-            samples: 1,
-            depth: depth,
-        }]),
-        usage: BufferUsages::UNIFORM,
-    });
+            ready.push((samples, image));
+        }
 
-    let camera_buffer = device.create_buffer_init(&BufferInitDescriptor
-    {
-        label: Some("camera buffer"),
-        contents: cast_slice(&[camera]),
-        usage: BufferUsages::UNIFORM,
-    });
+        Ok(ready)
+    }
+}
 
-    let triangle_buffer = device.create_buffer_init(&BufferInitDescriptor
-    {
-        label: Some("triangle buffer"),
-        contents: cast_slice(triangles),
-        usage: BufferUsages::STORAGE,
-    });
+/// Times GPU work into a `GpuProfile`. Each submission is wrapped in `begin`
+/// and `end`, and `finish` waits for them and adds up how long they took.
+/// Without timestamp queries it times from the first `begin` to the GPU
+/// going idle.
+struct Profiler
+{
+    profile: GpuProfile,
+    /// the query set, the buffer it's resolved to and nanoseconds per tick
+    queries: Option<(wgpu::QuerySet, wgpu::Buffer, f64)>,
+    /// queries written since they were last read
+    used: u32,
+    /// time read from the queries early because they ran out
+    elapsed: Duration,
+    /// when the first `begin` was without queries
+    started: Option<Instant>,
+}
 
-    let material_buffer = device.create_buffer_init(&BufferInitDescriptor
-    {
-        label: Some("material buffer"),
-        contents: cast_slice(materials),
-        usage: BufferUsages::STORAGE,
-    });
+impl Profiler
+{
+    /// queries in the set, two for each submission
+    const QUERIES: u32 = 256;
 
-    let seed_buffer = device.create_buffer_init(&BufferInitDescriptor
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, timestamps: bool) -> Profiler
     {
-        label: Some("seed buffer"),
-        contents: cast_slice(&[rand::random::<u32>()]),
-        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
-    });
+        let queries = match timestamps
+        {
+            true => Some((
+                device.create_query_set(&QuerySetDescriptor
+                {
+                    label: Some("profile queries"),
+                    ty: QueryType::Timestamp,
+                    count: Self::QUERIES,
+                }),
+                device.create_buffer(&BufferDescriptor
+                {
+                    label: Some("profile buffer"),
+                    size: 8 * Self::QUERIES as u64,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                queue.get_timestamp_period() as f64)),
+            false => None,
+        };
 
-    let image_size = std::mem::size_of::<Colour>() as u64
-        * width as u64
-        * height as u64;
-    let image_buffer = device.create_buffer(&BufferDescriptor
-    {
-        label: Some("image buffer"),
-        size: image_size,
-        usage: BufferUsages::STORAGE
-            | BufferUsages::COPY_SRC
-            | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+        Profiler
+        {
+            profile: GpuProfile
+            {
+                timestamps: timestamps,
+                .. GpuProfile::default()
+            },
+            queries: queries,
+            used: 0,
+            elapsed: Duration::ZERO,
+            started: None,
+        }
+    }
 
-    let staging_buffer = device.create_buffer(&BufferDescriptor
+    fn begin(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder)
+        -> Result<(), GpuError>
     {
-        label: None,
-        size: image_size,
-        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    let bg_layout = pipeline.get_bind_group_layout(0);
+        if self.used == Self::QUERIES
+        {
+            let elapsed = self.read(device, queue)?;
+            self.elapsed += elapsed;
+        }
 
-    let bind_group = device.create_bind_group(&BindGroupDescriptor
-    {
-        label: None,
-        layout: &bg_layout,
-        entries: &[
-            BindGroupEntry
-            {
-                binding: 0,
-                resource: info_buffer.as_entire_binding(),
-            },
-            BindGroupEntry
-            {
-                binding: 1,
-                resource: camera_buffer.as_entire_binding(),
-            },
-            BindGroupEntry
-            {
-                binding: 2,
-                resource: image_buffer.as_entire_binding(),
-            },
-            BindGroupEntry
+        match &self.queries
+        {
+            Some((set, _, _)) =>
             {
-                binding: 3,
-                resource: triangle_buffer.as_entire_binding(),
+                encoder.write_timestamp(set, self.used);
+                self.used += 1;
             },
-            BindGroupEntry
+            None =>
             {
-                binding: 4,
-                resource: material_buffer.as_entire_binding(),
+                self.started.get_or_insert_with(Instant::now);
             },
-            BindGroupEntry
+        }
+
+        Ok(())
+    }
+
+    fn end(&mut self, encoder: &mut wgpu::CommandEncoder)
+    {
+        if let Some((set, _, _)) = &self.queries
+        {
+            encoder.write_timestamp(set, self.used);
+            self.used += 1;
+        }
+    }
+
+    /// Waits for everything since the last `finish` and returns how long
+    /// the GPU spent on it.
+    fn finish(&mut self, device: &wgpu::Device, queue: &wgpu::Queue)
+        -> Result<Duration, GpuError>
+    {
+        let elapsed = match self.started.take()
+        {
+            Some(started) =>
             {
-                binding: 5,
-                resource: seed_buffer.as_entire_binding(),
+                device.poll(wgpu::Maintain::Wait);
+                started.elapsed()
             },
-        ]
-    });
+            None => self.read(device, queue)?,
+        };
 
-    let mut samples = 0;
-    while condition(samples)
+        Ok(std::mem::take(&mut self.elapsed) + elapsed)
+    }
+
+    /// The time between each pair of queries written so far.
+    fn read(&mut self, device: &wgpu::Device, queue: &wgpu::Queue)
+        -> Result<Duration, GpuError>
     {
-        samples += 1;
+        let (set, buffer, period) = match &self.queries
+        {
+            Some(queries) if self.used > 0 => queries,
+            _ => return Ok(Duration::ZERO),
+        };
 
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor
         {
             label: None,
         });
 
-        queue.write_buffer(&seed_buffer, 0, cast_slice(&[rand::random::<u32>()]));
+        encoder.resolve_query_set(set, 0..self.used, buffer, 0);
+
+        queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..8 * self.used as u64);
+        let future = slice.map_async(wgpu::MapMode::Read);
+
+        device.poll(wgpu::Maintain::Wait);
 
+        if block_on(future).is_err()
         {
-            let mut cpass = encoder.begin_compute_pass(&ComputePassDescriptor
-            {
-                label: None
-            });
-            cpass.set_pipeline(&pipeline);
-            cpass.set_bind_group(0, &bind_group, &[]);
-            cpass.dispatch(width, height, 1);
+            return Err(GpuError::MapFailed);
         }
 
-        encoder.copy_buffer_to_buffer(
-            &image_buffer, 0,
-            &staging_buffer, 0,
-            image_size);
+        let data = slice.get_mapped_range();
+        let ticks = cast_slice::<u8, u64>(&data).chunks(2)
+            .map(|pair| pair[1].saturating_sub(pair[0]))
+            .sum::<u64>();
 
-        queue.submit(Some(encoder.finish()));
+        drop(data);
+        buffer.unmap();
+        self.used = 0;
 
-        device.poll(wgpu::Maintain::Wait);
+        Ok(Duration::from_nanos((ticks as f64 * period) as u64))
     }
+}
 
+fn read_image<T: bytemuck::Pod>(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    image_buffer: &wgpu::Buffer,
+    staging_buffer: &wgpu::Buffer,
+    image_size: u64,
+    image: &mut Vec<T>,
+    mut profiler: Option<&mut Profiler>)
+    -> Result<(), GpuError>
+{
     let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor
     {
         label: None,
     });
 
+    if let Some(profiler) = &mut profiler
+    {
+        profiler.begin(device, queue, &mut encoder)?;
+    }
+
     encoder.copy_buffer_to_buffer(
-        &image_buffer, 0,
-        &staging_buffer, 0,
+        image_buffer, 0,
+        staging_buffer, 0,
         image_size);
 
+    if let Some(profiler) = &mut profiler
+    {
+        profiler.end(&mut encoder);
+    }
+
     queue.submit(Some(encoder.finish()));
 
+    if let Some(profiler) = profiler
+    {
+        let copy_time = profiler.finish(device, queue)?;
+        profiler.profile.copies.push(copy_time);
+    }
+
     let buf_slice = staging_buffer.slice(..);
     let buf_future = buf_slice.map_async(wgpu::MapMode::Read);
 
@@ -220,18 +2530,18 @@ pub fn run_shader(
 
     if block_on(buf_future).is_err()
     {
-        panic!("GPU Error!");
+        return Err(GpuError::MapFailed);
     }
 
     image.clear();
     let data = buf_slice.get_mapped_range();
 
-    image.extend_from_slice(cast_slice::<u8, Colour>(&data));
+    image.extend_from_slice(cast_slice::<u8, T>(&data));
 
     drop(data);
     staging_buffer.unmap();
 
-    return samples;
+    Ok(())
 }
 
 #[repr(C)]
@@ -244,6 +2554,59 @@ pub struct Info
     height   : u32,
     samples  : u32,
     depth    : u32,
+    tile_x   : u32,
+    tile_y   : u32,
+    tile_w   : u32,
+    slice_x  : u32,
+    slice_y  : u32,
+    slice_w  : u32,
+    slice_h  : u32,
+    /// which sample this is, counting from 1
+    sample   : u32,
+    /// the render's seed mixed with the frame number
+    seed     : u32,
+    /// fraction of a frame to blur moving triangles over
+    shutter  : f32,
+    /// whether to read the motion buffer
+    moving   : u32,
+    /// whether to write the matte buffer
+    matte    : u32,
+    /// whether to write the squares buffer
+    squares  : u32,
+    /// whether to write the depths buffer
+    depths   : u32,
+    /// 0 to path trace, 1 for ambient occlusion, 2 to 5 for normals,
+    /// depth, materials and wireframe. This and `depth`, `moving`, `matte`,
+    /// `squares` and `depths` are also compiled in, see `specialise`, along
+    /// with whether the triangles have colours, textures or normals and
+    /// whether any materials have noise.
+    mode     : u32,
+    ao_rays  : u32,
+    /// no further than `MISS`
+    ao_dist  : f32,
+    /// the furthest vertex from the camera
+    far      : f32,
+    /// see `RenderSettings::ray_epsilon`
+    ray_eps  : f32,
+}
+
+/// Where a moving triangle's vertices are at the end of a frame.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+struct Motion
+{
+    a: [f32; 3],
+    b: [f32; 3],
+    c: [f32; 3],
+}
+
+/// How the shader reads each of `Scene::uvs`.
+#[repr(C)]
+struct CornerUvs
+{
+    a: [f32; 2],
+    b: [f32; 2],
+    c: [f32; 2],
 }
 
 #[repr(C)]
@@ -255,6 +2618,110 @@ pub struct Colour
     pub b: f32,
 }
 
+/// A pixel of the accumulated image: the sum of its samples, and what
+/// rounding has lost from the sum so far. After hundreds of thousands of
+/// samples a sample is too small next to an f32 sum to be added to it
+/// exactly, so the losses are kept separately, Neumaier's way, and `total`
+/// is as close to the true sum as an f32 gets. The shader's `add_exact`
+/// must do the same. The matte and squares are summed this way too, with
+/// 4 and 1 values.
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Sum<const N: usize = 3>
+{
+    sum : [f32; N],
+    lost: [f32; N],
+}
+
+impl<const N: usize> Default for Sum<N>
+{
+    fn default() -> Sum<N>
+    {
+        Sum
+        {
+            sum: [0.0; N],
+            lost: [0.0; N],
+        }
+    }
+}
+
+impl<const N: usize> Sum<N>
+{
+    pub fn add(&mut self, c: [f32; N])
+    {
+        for ((sum, lost), x) in self.sum.iter_mut().zip(&mut self.lost).zip(c)
+        {
+            let t = *sum + x;
+
+            *lost += match sum.abs() >= x.abs()
+            {
+                true => (*sum - t) + x,
+                false => (x - t) + *sum,
+            };
+
+            // moves what fits of the losses into the sum, so they stay
+            // small enough to be added to exactly too
+            *sum = t + *lost;
+            *lost -= *sum - t;
+        }
+    }
+
+    pub fn value(&self) -> [f32; N]
+    {
+        let mut value = self.sum;
+
+        for (v, lost) in value.iter_mut().zip(&self.lost)
+        {
+            *v += lost;
+        }
+
+        value
+    }
+
+    /// The values of `sums`.
+    pub fn values(sums: &[Sum<N>]) -> Vec<[f32; N]>
+    {
+        sums.iter().map(Sum::value).collect()
+    }
+}
+
+/// The squares' values, as the rest of the renderer sees them.
+pub(crate) fn squares_of(sums: &[Sum<1>]) -> Vec<f32>
+{
+    sums.iter().map(|s| s.value()[0]).collect()
+}
+
+impl Sum
+{
+    pub fn total(&self) -> Colour
+    {
+        Colour
+        {
+            r: self.sum[0] + self.lost[0],
+            g: self.sum[1] + self.lost[1],
+            b: self.sum[2] + self.lost[2],
+        }
+    }
+
+    /// The totals of `sums`, which is how the rest of the renderer sees them.
+    pub fn totals(sums: &[Sum]) -> Vec<Colour>
+    {
+        sums.iter().map(Sum::total).collect()
+    }
+}
+
+impl From<Colour> for Sum
+{
+    fn from(c: Colour) -> Sum
+    {
+        Sum
+        {
+            sum: [c.r, c.g, c.b],
+            lost: [0.0; 3],
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 pub struct Triangle
@@ -265,14 +2732,102 @@ pub struct Triangle
     pub mat: u32,
 }
 
+/// How a surface scatters and gives off light. Make one with
+/// `Material::new`, and set the rest of its fields from there.
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
+#[non_exhaustive]
 pub struct Material
 {
-    pub colour   : [f32; 3],
-    pub glow     : [f32; 3],
-    pub gloss    : f32,
-    pub reflect_c: [f32; 3],
+    pub colour      : [f32; 3],
+    pub glow        : [f32; 3],
+    pub gloss       : f32,
+    pub reflect_c   : [f32; 3],
+    pub flags       : u32,
+    /// which of `Scene::textures` the glow is multiplied by, counting from
+    /// 1, or 0 for none
+    pub glow_texture: u32,
+    /// which of `Scene::textures` tilts the shading normal, like
+    /// `glow_texture`. Its texels are tangent space normals mapped from -1..1
+    /// to 0..1, with u along x and v along y.
+    pub normal_texture: u32,
+    /// how much `normal_texture` tilts the normal, 1 as it's drawn and 0
+    /// not at all
+    pub normal_strength: f32,
+    /// which of `Scene::textures` cuts holes in the surface, like
+    /// `glow_texture`. Where its alpha is below `alpha_cutoff`, rays go
+    /// through as if the surface weren't there.
+    pub alpha_texture: u32,
+    pub alpha_cutoff: f32,
+}
+
+impl Material
+{
+    /// Only glows when hit from the front, the side the triangle's points go
+    /// anticlockwise on.
+    pub const ONE_SIDED: u32 = 1;
+    /// Invisible to the camera except for the shadows on it, which go in
+    /// `Framebuffer::alpha`. Other rays see a plain surface.
+    pub const SHADOW_CATCHER: u32 = 2;
+
+    /// A double-sided material without textures, which reflects `gloss` of
+    /// the light like a mirror, tinted by `reflect_c`, scatters the rest
+    /// tinted by `colour` and glows `glow`.
+    pub fn new(colour: [f32; 3], glow: [f32; 3], gloss: f32, reflect_c: [f32; 3]) -> Material
+    {
+        Material
+        {
+            colour: colour,
+            glow: glow,
+            gloss: gloss,
+            reflect_c: reflect_c,
+            flags: 0,
+            glow_texture: 0,
+            normal_texture: 0,
+            normal_strength: 1.0,
+            alpha_texture: 0,
+            alpha_cutoff: 0.5,
+        }
+    }
+
+    /// Whether it uses any of `Scene::textures`.
+    pub fn textured(&self) -> bool
+    {
+        self.glow_texture != 0 || self.normal_texture != 0 || self.alpha_texture != 0
+    }
+}
+
+/// A material's colour varying across space, as fractal noise blended
+/// between two colours. It replaces the material's `colour`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Noise
+{
+    /// the colours at the lowest and highest noise
+    pub a         : [f32; 3],
+    pub b         : [f32; 3],
+    /// how many times the noise repeats per unit of world space
+    pub scale     : f32,
+    /// how much finer each octave is than the last
+    pub lacunarity: f32,
+    /// how much weaker each octave is than the last
+    pub gain      : f32,
+    /// layers of noise added together, 0 for a material without noise
+    pub octaves   : u32,
+}
+
+impl Noise
+{
+    /// A material's noise when it has none.
+    pub const NONE: Noise = Noise
+    {
+        a: [0.0; 3],
+        b: [0.0; 3],
+        scale: 1.0,
+        lacunarity: 2.0,
+        gain: 0.5,
+        octaves: 0,
+    };
 }
 
 #[repr(C)]
@@ -285,13 +2840,247 @@ pub struct Camera
     pub fov  : f32,
 }
 
+// The shader's versions of these are made of u32s, f32s and arrays of f32,
+// which pack without padding, so these are the strides it declares for them.
+// `check_shader` compares every field.
+const _: () = assert!(std::mem::size_of::<Info>() == 100);
+const _: () = assert!(std::mem::size_of::<Camera>() == 40);
+const _: () = assert!(std::mem::size_of::<Colour>() == 12);
+const _: () = assert!(std::mem::size_of::<Sum>() == 24);
+const _: () = assert!(std::mem::size_of::<Triangle>() == 40);
+const _: () = assert!(std::mem::size_of::<Material>() == 64);
+const _: () = assert!(std::mem::size_of::<Motion>() == 36);
+const _: () = assert!(std::mem::size_of::<CornerUvs>() == 24);
+const _: () = assert!(std::mem::size_of::<Noise>() == 40);
+
 unsafe impl bytemuck::Zeroable for Info { }
 unsafe impl bytemuck::Pod for Info { }
 unsafe impl bytemuck::Zeroable for Colour { }
 unsafe impl bytemuck::Pod for Colour { }
+unsafe impl<const N: usize> bytemuck::Zeroable for Sum<N> { }
+unsafe impl<const N: usize> bytemuck::Pod for Sum<N> { }
 unsafe impl bytemuck::Zeroable for Triangle { }
 unsafe impl bytemuck::Pod for Triangle { }
 unsafe impl bytemuck::Zeroable for Material { }
 unsafe impl bytemuck::Pod for Material { }
+unsafe impl bytemuck::Zeroable for Noise { }
+unsafe impl bytemuck::Pod for Noise { }
+unsafe impl bytemuck::Zeroable for Motion { }
+unsafe impl bytemuck::Pod for Motion { }
 unsafe impl bytemuck::Zeroable for Camera { }
 unsafe impl bytemuck::Pod for Camera { }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{RenderSettings, Scene};
+
+    const SAMPLES: u32 = 1_000_000;
+    const COLOUR: [f32; 3] = [0.3, 0.7, 0.1];
+
+    fn close(a: f32, b: f64) -> bool
+    {
+        ((a as f64 - b) / b).abs() < 1e-6
+    }
+
+    #[test]
+    fn shader_layouts_match()
+    {
+        let source = include_str!("shader.wgsl");
+        let module = parse_shader(source).unwrap();
+
+        assert_eq!(check_layouts(&module), Vec::<String>::new());
+
+        // otherwise a renamed struct would pass without being checked
+        for (name, _, _) in layouts()
+        {
+            assert!(module.types.iter().any(|(_, ty)| ty.name.as_deref() == Some(name)),
+                "the shader has no struct {}", name);
+        }
+
+        let stride = source.replace("[[stride(24)]] array<Sum>", "[[stride(32)]] array<Sum>");
+        let problems = check_layouts(&parse_shader(&stride).unwrap());
+        assert!(problems.iter().any(|p| p.contains("arrays of Sum")), "{:?}", problems);
+
+        let fields = source.replacen("    sum : array<f32, 3>;\n    lost: array<f32, 3>;",
+            "    lost: array<f32, 3>;\n    sum : array<f32, 3>;", 1);
+        let problems = check_layouts(&parse_shader(&fields).unwrap());
+        assert!(problems.iter().any(|p| p.starts_with("struct Sum ")), "{:?}", problems);
+    }
+
+    #[test]
+    fn points_at_invalid_functions()
+    {
+        let source = "[[stage(compute), workgroup_size(1)]]\nfn main() {}\n\n  fn half() -> f32\n  {\n    return 1u;\n  }\n";
+        let e = parse_shader(source).unwrap_err();
+
+        assert!(e.starts_with("Function [1] 'half' is invalid: "), "{}", e);
+        assert!(e.ends_with("  --> line 4, column 6\n     |\n   4 |   fn half() -> f32\n     |      ^"), "{}", e);
+        assert!(!e.contains("{ "), "{}", e);
+    }
+
+    #[test]
+    fn specialises_the_constants()
+    {
+        let spec = Specialisation
+        {
+            depth: 9,
+            mode: 0,
+            moving: true,
+            coloured: false,
+            textured: false,
+            smooth: false,
+            noisy: false,
+            matte: true,
+            squares: false,
+            depths: false,
+        };
+
+        let source = specialise(include_str!("shader.wgsl"), spec);
+
+        for line in ["let DEPTH: u32 = 9u;", "let MOVING: bool = true;", "let MATTE: bool = true;",
+            "let SQUARES: bool = false;"]
+        {
+            assert!(source.lines().any(|l| l == line), "no \"{}\"", line);
+        }
+        check_shader(&source).unwrap();
+    }
+
+    #[test]
+    fn sums_a_million_samples()
+    {
+        let mut sum = Sum::default();
+        let mut square = Sum::<1>::default();
+
+        for _ in 0..SAMPLES
+        {
+            sum.add(COLOUR);
+            square.add([COLOUR[0] * COLOUR[0]]);
+        }
+
+        let total = sum.total();
+        for (got, c) in [total.r, total.g, total.b].iter().zip(COLOUR)
+        {
+            assert!(close(*got, c as f64 * SAMPLES as f64), "{} for {}", got, c);
+        }
+        assert!(close(square.value()[0], (COLOUR[0] * COLOUR[0]) as f64 * SAMPLES as f64));
+    }
+
+    #[test]
+    fn renders_a_million_samples()
+    {
+        // a dark wall glowing the colour fills the view, so every sample
+        // is exactly the colour
+        let mut scene = Scene::new([0.0, 0.0, -1.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 0.5);
+        let wall = scene.add_material(Material::new([0.0, 0.0, 0.0], COLOUR, 0.0, [0.0, 0.0, 0.0]));
+        scene.add_quad(
+            [-10.0, -10.0, 1.0], [10.0, -10.0, 1.0], [10.0, 10.0, 1.0], [-10.0, 10.0, 1.0], wall);
+
+        let settings = RenderSettings
+        {
+            samples: SAMPLES,
+            depth: 1,
+            seed: Some(1),
+            cpu: true,
+            .. RenderSettings::new([1, 1])
+        };
+
+        let frame = crate::render(&scene, &settings).unwrap();
+        let px = frame.pixels[0];
+
+        assert_eq!(frame.samples, SAMPLES);
+        for (got, c) in [px.r, px.g, px.b].iter().zip(COLOUR)
+        {
+            assert!(close(*got, c as f64), "{} for {}", got, c);
+        }
+    }
+
+    fn quads(n: usize) -> Scene
+    {
+        let quad = r#"{ "quad": [[-1, -1, 1], [1, -1, 1], [1, 1, 1], [-1, 1, 1]], "mat": "white" }"#;
+
+        Scene::parse(&format!(
+            r#"{{
+                "camera": {{ "pos": [0, 0, 0], "front": [0, 0, 1], "up": [0, 1, 0], "fov": 50 }},
+                "materials": {{ "white": {{ "colour": [1, 1, 1] }} }},
+                "surfaces": [{}]
+            }}"#,
+            vec![quad; n].join(","))).unwrap()
+    }
+
+    #[test]
+    fn budgets_the_buffers()
+    {
+        use std::mem::size_of;
+
+        let scene = quads(50);
+        let sum = size_of::<Sum>() as u64;
+
+        let budget = scene.budget(&RenderSettings::new([100, 50])).unwrap();
+        assert_eq!(budget.size("image"), 100 * 50 * sum);
+        assert_eq!(budget.size("image staging"), 100 * 50 * sum);
+        assert_eq!(budget.size("readback"), 2 * 100 * 50 * sum);
+        assert_eq!(budget.size("triangles"), 100 * size_of::<Triangle>() as u64);
+        // unwanted images still bind a pixel
+        assert_eq!(budget.size("depths"), 4);
+        assert_eq!(budget.size("nothing"), 0);
+        assert_eq!(budget.total(), budget.buffers.iter().map(|b| b.1).sum::<u64>());
+
+        // tiles only need a tile's worth, and aren't read back as they go
+        let tiled = RenderSettings
+        {
+            tile: Some([32, 16]),
+            depth_map: true,
+            .. RenderSettings::new([100, 50])
+        };
+        let budget = scene.budget(&tiled).unwrap();
+        assert_eq!(budget.size("image"), 32 * 16 * sum);
+        assert_eq!(budget.size("depths"), 32 * 16 * 4);
+        assert_eq!(budget.size("readback"), 0);
+
+        let summary = budget.summary();
+        assert_eq!(summary.len(), budget.buffers.len());
+        assert!(summary[0].starts_with("    image: "), "{:?}", summary);
+    }
+
+    #[test]
+    fn refuses_what_wont_fit()
+    {
+        let limits = |max: u32| Limits
+        {
+            max_storage_buffer_binding_size: max,
+            .. Limits::default()
+        };
+        let message = |budget: &RenderBudget, max: u32| match budget.check(&limits(max))
+        {
+            Ok(()) => panic!("{} bytes fit in {}", budget.total(), max),
+            Err(e) => e.to_string(),
+        };
+
+        // 2048x1024 pixels of 24 bytes is 48 MiB
+        let budget = quads(1).budget(&RenderSettings::new([2048, 1024])).unwrap();
+        let image = budget.size("image") as u32;
+        assert_eq!(budget.check(&limits(image)).map_err(|e| e.to_string()), Ok(()));
+
+        let e = message(&budget, 32 << 20);
+        assert!(e.starts_with(&format!("The render needs {:.1} MiB of buffers, ", mib(budget.total()))), "{}", e);
+        assert!(e.ends_with(
+            "with 48.0 MiB in the image buffer, but the device allows 32.0 MiB per buffer; \
+             try --tile or a lower resolution"), "{}", e);
+
+        // the staging buffers aren't bound as storage
+        assert!(budget.size("image staging") > 1 << 20);
+        let budget = quads(10_000).budget(&RenderSettings::new([16, 16])).unwrap();
+        let e = message(&budget, 1 << 19);
+        assert!(e.contains("in the triangles buffer"), "{}", e);
+        assert!(e.ends_with("try a simpler scene"), "{}", e);
+
+        let budget = RenderBudget
+        {
+            buffers: Vec::new(),
+            texels: RenderBudget::MAX_TEXELS + 1,
+        };
+        assert!(message(&budget, u32::MAX).starts_with("Textures have 16777217 texels"));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::clock::Instant;
+use crate::gpu::{GpuContext, GpuError};
+use crate::scene::{Framebuffer, RenderSettings, Scene};
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Asks a render for things while it runs, shared with it through
+/// `RenderSettings::control`.
+#[derive(Debug, Default)]
+pub struct RenderControl
+{
+    saves: Mutex<Vec<String>>,
+    stop: AtomicBool,
+}
+
+impl RenderControl
+{
+    pub fn new() -> RenderControl
+    {
+        RenderControl::default()
+    }
+
+    /// Saves the image so far to `path` after the next sample, like a
+    /// snapshot, without stopping the render. `Scene::render_multi` ignores
+    /// this.
+    pub fn save(&self, path: &str)
+    {
+        self.saves.lock().unwrap().push(path.to_owned());
+    }
+
+    /// Stops the render after the sample it's on, which still finishes and
+    /// is saved and checkpointed like any other. Tiles and frames after the
+    /// first still get its samples.
+    pub fn stop(&self)
+    {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `stop` has been called.
+    pub fn stopping(&self) -> bool
+    {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn save_due(&self) -> bool
+    {
+        !self.saves.lock().unwrap().is_empty()
+    }
+
+    pub(crate) fn take_saves(&self) -> Vec<String>
+    {
+        std::mem::take(&mut *self.saves.lock().unwrap())
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ProgressInfo
+{
+    /// samples finished so far
+    pub samples: u32,
+    pub elapsed: Duration,
+}
+
+/// A render running on a background thread, from `Scene::render_async`.
+pub struct RenderHandle
+{
+    progress: Arc<Mutex<ProgressInfo>>,
+    control: Arc<RenderControl>,
+    thread: JoinHandle<Result<Framebuffer, GpuError>>,
+}
+
+/// What `RenderHandle::spawn` calls with the progress.
+type ProgressHook = Box<dyn Fn(ProgressInfo) + Send>;
+
+impl RenderHandle
+{
+    /// Starts rendering `scene` on `ctx`, or a context opened for it, calling
+    /// `on_progress` every `every` samples.
+    pub(crate) fn spawn(
+        scene: Scene,
+        mut settings: RenderSettings,
+        ctx: Option<Arc<GpuContext>>,
+        on_progress: Option<(u32, ProgressHook)>)
+        -> RenderHandle
+    {
+        let progress = Arc::new(Mutex::new(ProgressInfo
+        {
+            samples: 0,
+            elapsed: Duration::from_secs(0),
+        }));
+        // cancelling goes through the settings' control, or one made for it
+        let control = settings.control.get_or_insert_with(Default::default).clone();
+
+        let thread =
+        {
+            let progress = progress.clone();
+
+            std::thread::spawn(move ||
+            {
+                let start = Instant::now();
+                let limit = settings.condition();
+
+                // called before every sample, so it doubles as the progress hook
+                let condition = |samples: u32|
+                {
+                    let info = ProgressInfo
+                    {
+                        samples: samples,
+                        elapsed: start.elapsed(),
+                    };
+                    *progress.lock().unwrap() = info;
+
+                    if let Some((every, on_progress)) = &on_progress
+                    {
+                        if samples > 0 && samples.is_multiple_of((*every).max(1))
+                        {
+                            on_progress(info);
+                        }
+                    }
+
+                    limit(samples)
+                };
+
+                match ctx
+                {
+                    Some(ctx) => scene.render_with(&ctx, &settings, &condition, None),
+                    None => scene.render(&settings, &condition, None),
+                }
+            })
+        };
+
+        RenderHandle
+        {
+            progress: progress,
+            control: control,
+            thread: thread,
+        }
+    }
+
+    pub fn progress(&self) -> ProgressInfo
+    {
+        *self.progress.lock().unwrap()
+    }
+
+    /// Stops the render after the current sample. `join` still returns the
+    /// image with the samples finished so far.
+    pub fn cancel(&self)
+    {
+        self.control.stop();
+    }
+
+    /// Waits for the render to finish.
+    pub fn join(self) -> Result<Framebuffer, GpuError>
+    {
+        match self.thread.join()
+        {
+            Ok(result) => result,
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::def::SceneDef;
+use crate::gpu::Camera;
+use crate::scene::Scene;
+use crate::vec3::{cross, length, sub};
+
+/// A summary of a scene, for looking over it before rendering.
+#[derive(Clone, Debug)]
+pub struct SceneInfo
+{
+    pub triangles: usize,
+    pub materials: Vec<MaterialInfo>,
+    /// see `Scene::bounds`
+    pub bounds: Option<([f32; 3], [f32; 3])>,
+    pub cameras: Vec<(String, Camera)>,
+    /// every file the scene reads besides itself, with its size in bytes, or
+    /// `None` if it can't be read
+    pub files: Vec<(String, Option<u64>)>,
+    /// see `Scene::validate`
+    pub problems: Vec<String>,
+}
+
+/// How much of the scene one material covers.
+#[derive(Clone, Debug)]
+pub struct MaterialInfo
+{
+    /// the name in "materials", `None` for inline materials and the default
+    pub name: Option<String>,
+    pub triangles: usize,
+    pub area: f32,
+    pub glow: [f32; 3],
+    /// glow times area, a rough idea of how much light it gives off that
+    /// leaves out glow textures and which sides glow
+    pub power: [f32; 3],
+}
+
+impl SceneInfo
+{
+    /// `scene` is the one made from `def`, which has the material names and
+    /// the files.
+    pub fn new(def: &SceneDef, scene: &Scene) -> SceneInfo
+    {
+        let mut materials = scene.materials.iter().enumerate()
+            .map(|(i, mat)| MaterialInfo
+            {
+                name: def.materials.get(i).map(|(name, _)| name.clone()),
+                triangles: 0,
+                area: 0.0,
+                glow: mat.glow,
+                power: [0.0; 3],
+            })
+            .collect::<Vec<_>>();
+
+        // missing materials are one of the problems
+        for tri in &scene.triangles
+        {
+            if let Some(mat) = materials.get_mut(tri.mat as usize)
+            {
+                mat.triangles += 1;
+                mat.area += length(cross(sub(tri.b, tri.a), sub(tri.c, tri.a))) / 2.0;
+            }
+        }
+
+        for mat in &mut materials
+        {
+            mat.power = [mat.glow[0] * mat.area, mat.glow[1] * mat.area, mat.glow[2] * mat.area];
+        }
+
+        let files = def.files().into_iter()
+            .map(|file|
+            {
+                let size = std::fs::metadata(&file).ok().map(|m| m.len());
+                (file, size)
+            })
+            .collect();
+
+        SceneInfo
+        {
+            triangles: scene.triangles.len(),
+            materials: materials,
+            bounds: scene.bounds(),
+            cameras: scene.cameras.clone(),
+            files: files,
+            problems: scene.validate(),
+        }
+    }
+
+    /// The length across the bounds, from corner to corner.
+    pub fn diagonal(&self) -> Option<f32>
+    {
+        self.bounds.map(|(min, max)| length(sub(max, min)))
+    }
+
+    /// The materials that glow, with their indices.
+    pub fn lights(&self) -> impl Iterator<Item = (usize, &MaterialInfo)>
+    {
+        self.materials.iter().enumerate().filter(|(_, m)| m.glow.iter().any(|&c| c > 0.0))
+    }
+
+    /// The summary as one line of JSON, for scripts. Fovs are in degrees.
+    pub fn to_json(&self) -> String
+    {
+        let materials = self.materials.iter().map(|m| json::object!
+        {
+            "name": m.name.clone(),
+            "triangles": m.triangles,
+            "area": m.area,
+            "glow": &m.glow[..],
+            "power": &m.power[..],
+        }).collect::<Vec<_>>();
+
+        let cameras = self.cameras.iter().map(|(name, c)| json::object!
+        {
+            "name": name.as_str(),
+            "pos": &c.pos[..],
+            "front": &c.front[..],
+            "up": &c.up[..],
+            "fov": c.fov.to_degrees(),
+        }).collect::<Vec<_>>();
+
+        let files = self.files.iter().map(|(path, size)| json::object!
+        {
+            "path": path.as_str(),
+            "bytes": *size,
+        }).collect::<Vec<_>>();
+
+        let bounds = match self.bounds
+        {
+            Some((min, max)) => json::object!
+            {
+                "min": &min[..],
+                "max": &max[..],
+                "diagonal": self.diagonal(),
+            },
+            None => json::JsonValue::Null,
+        };
+
+        json::object!
+        {
+            "triangles": self.triangles,
+            "materials": materials,
+            "lights": self.lights().map(|(i, _)| i).collect::<Vec<_>>(),
+            "bounds": bounds,
+            "cameras": cameras,
+            "files": files,
+            "problems": self.problems.clone(),
+        }.dump()
+    }
+}
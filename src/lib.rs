@@ -0,0 +1,117 @@
+//! A path tracer that runs on the GPU.
+//!
+//! Build a scene, either by parsing a scene file with `Scene::parse` or in
+//! code, like the ones from `builtin_scene`, then render it:
+//!
+//! ```no_run
+//! use path_tracer_gpu::{Material, RenderSettings, Scene};
+//!
+//! let mut scene = Scene::new(
+//!     [0.0, 1.0, -3.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 1.2);
+//!
+//! let floor = scene.add_material(Material::new(
+//!     [0.8, 0.8, 0.8], [0.0, 0.0, 0.0], 0.0, [0.0, 0.0, 0.0]));
+//! let light = scene.add_material(Material::new(
+//!     [1.0, 1.0, 1.0], [4.0, 4.0, 4.0], 0.0, [0.0, 0.0, 0.0]));
+//!
+//! scene
+//!     .add_quad(
+//!         [-2.0, 0.0, -2.0], [2.0, 0.0, -2.0], [2.0, 0.0, 2.0], [-2.0, 0.0, 2.0],
+//!         floor)
+//!     .add_quad(
+//!         [-0.5, 2.0, -0.5], [0.5, 2.0, -0.5], [0.5, 2.0, 0.5], [-0.5, 2.0, 0.5],
+//!         light);
+//!
+//! let settings = RenderSettings
+//! {
+//!     samples: 100,
+//!     .. RenderSettings::new([320, 240])
+//! };
+//!
+//! let frame = path_tracer_gpu::render(&scene, &settings)?;
+//! frame.to_rgb_image().save("thumbnail.png")?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#[macro_use]
+pub mod log;
+
+mod animation;
+mod benchmark;
+mod builtin;
+mod checkpoint;
+mod clock;
+mod cpu;
+mod def;
+mod denoise;
+mod diff;
+#[cfg(not(target_arch = "wasm32"))]
+mod ffi;
+mod float;
+mod gpu;
+mod handle;
+mod info;
+mod mesh;
+mod metadata;
+mod scene;
+mod stop;
+mod text;
+mod texture;
+mod transform;
+mod vec3;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+pub use animation::{Animation, Orbit};
+pub use benchmark::{Benchmark, Estimate};
+pub use builtin::{builtin_scene, BUILTIN_SCENES};
+pub use checkpoint::Checkpoint;
+pub use def::
+{
+    CameraDef,
+    Format,
+    Location,
+    MatRef,
+    MaterialDef,
+    NodeDef,
+    NoiseDef,
+    SceneDef,
+    ShapeDef,
+    SurfaceDef,
+};
+pub use diff::{diff_heatmap, diff_images, ImageDiff};
+pub use gpu::
+{
+    check_shader,
+    Camera,
+    CheckedShader,
+    Colour,
+    GpuContext,
+    GpuError,
+    GpuProfile,
+    Material,
+    Noise,
+    RenderBudget,
+    RenderMode,
+    Timings,
+    Triangle,
+    MAX_WORKGROUPS_PER_DIMENSION,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use gpu::list_adapters;
+pub use handle::{ProgressInfo, RenderControl, RenderHandle};
+pub use info::{MaterialInfo, SceneInfo};
+pub use mesh::{Heightmap, WindingReport, MAX_SPHERE_SUBDIVISIONS};
+pub use metadata::{read_metadata, save_image};
+pub use scene::{Every, Framebuffer, RenderSettings, Scene, WhiteBalance};
+pub use stop::{relative_noise, NoiseTarget, StopCondition, NOISE_FLOOR};
+pub use transform::{Placement, Transform};
+pub use text::{draw_overlay, draw_text, text_size, Corner, Overlay};
+
+/// Renders `scene` on the adapter chosen in `settings`, until it reaches
+/// `settings.samples` or `settings.time_limit`. See `Scene::render_async` to
+/// render without blocking.
+pub fn render(scene: &Scene, settings: &RenderSettings) -> Result<Framebuffer, GpuError>
+{
+    scene.render_async(settings.clone()).join()
+}
@@ -0,0 +1,130 @@
+//! Leveled output for the renderer and the command line tool. Errors and
+//! warnings go to stderr, everything else to stdout, unless a host without
+//! a terminal gives a `Sink` to send them to instead.
+//!
+//! The macros are `pt_error!` to `pt_trace!`, so they don't clash with the
+//! `log` crate's in programs that use both.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level
+{
+    Error,
+    Warn,
+    Info,
+    /// adapter details, buffer sizes and timings
+    Debug,
+    /// per-sample timings
+    Trace,
+}
+
+/// Takes each message that's shown, with its level.
+pub type Sink = Box<dyn Fn(Level, &str) + Send + Sync>;
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+static SINK: RwLock<Option<Sink>> = RwLock::new(None);
+
+/// Only messages at `level` or more important are shown.
+pub fn set_level(level: Level)
+{
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Sends messages to `sink` instead of stdout and stderr, or back to them
+/// with `None`. `set_level` still decides which are sent.
+pub fn set_sink(sink: Option<Sink>)
+{
+    *SINK.write().unwrap_or_else(|e| e.into_inner()) = sink;
+}
+
+pub fn enabled(level: Level) -> bool
+{
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+#[doc(hidden)]
+pub fn log(level: Level, args: std::fmt::Arguments)
+{
+    if !enabled(level)
+    {
+        return;
+    }
+
+    if let Some(sink) = &*SINK.read().unwrap_or_else(|e| e.into_inner())
+    {
+        return sink(level, &args.to_string());
+    }
+
+    match level
+    {
+        Level::Error => eprintln!("Error: {}", args),
+        Level::Warn => eprintln!("Warning: {}", args),
+        Level::Info => println!("{}", args),
+        Level::Debug | Level::Trace => println!("[{:?}] {}", level, args),
+    }
+}
+
+#[macro_export]
+macro_rules! pt_error
+{
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Error, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! pt_warn
+{
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Warn, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! pt_info
+{
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Info, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! pt_debug
+{
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Debug, format_args!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! pt_trace
+{
+    ($($arg:tt)*) => { $crate::log::log($crate::log::Level::Trace, format_args!($($arg)*)) };
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn sends_to_the_sink()
+    {
+        let got = Arc::new(Mutex::new(Vec::new()));
+
+        let sink = got.clone();
+        set_sink(Some(Box::new(move |level, message|
+            sink.lock().unwrap().push((level, message.to_owned())))));
+
+        pt_info!("{} samples", 16);
+        pt_debug!("hidden at the info level");
+        pt_warn!("a warning");
+        set_sink(None);
+        pt_info!("back on stdout");
+
+        // other tests log too, so only this test's messages are looked for
+        let got = got.lock().unwrap();
+        let mine = |m: &(Level, String)|
+            ["16 samples", "hidden at the info level", "a warning", "back on stdout"]
+                .contains(&m.1.as_str());
+        assert_eq!(
+            got.iter().filter(|m| mine(m)).cloned().collect::<Vec<_>>(),
+            [(Level::Info, "16 samples".to_owned()), (Level::Warn, "a warning".to_owned())]);
+    }
+}
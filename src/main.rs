@@ -1,258 +1,4440 @@
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, ArgGroup, Shell, SubCommand};
 
-mod gpu;
-mod scene;
+use std::process::ExitCode;
 
-use scene::Scene;
+use path_tracer_gpu::{Checkpoint, Every, Format, Framebuffer, GpuContext, GpuError};
+use path_tracer_gpu::{NoiseTarget, RenderControl, RenderMode, RenderSettings, StopCondition};
+use path_tracer_gpu::{Camera, Corner, MaterialInfo, Orbit, Overlay, Scene, SceneDef, SceneInfo, WhiteBalance};
+use path_tracer_gpu::{CheckedShader, Placement, Transform, BUILTIN_SCENES};
+use path_tracer_gpu::{builtin_scene, diff_heatmap, diff_images, read_metadata, save_image};
+use path_tracer_gpu::{pt_error, pt_info, pt_warn};
+use path_tracer_gpu::log::{self, Level};
 
-fn main()
+mod generate;
+mod serve;
+mod stats;
+
+fn main() -> ExitCode
+{
+    match run()
+    {
+        Ok(code) => code,
+        Err(e) =>
+        {
+            pt_error!("{}", e);
+            ExitCode::from(e.code())
+        },
+    }
+}
+
+/// The command line tool, returning errors for `main` to report.
+fn run() -> Result<ExitCode, Failure>
+{
+    if std::env::args().len() < 2
+    {
+        app().print_help().unwrap();
+        println!();
+        return Ok(ExitCode::from(Failure::ARGS));
+    }
+
+    let args = std::env::args().collect::<Vec<_>>();
+    let mut defaults = Defaults::read(&args).map_err(Failure::Args)?;
+
+    let matches = match defaults.apply(&args)
+    {
+        Ok(matches) => matches,
+        // clap's own message, which has the usage
+        Err(e) if e.use_stderr() =>
+        {
+            eprintln!("{}", e.message);
+
+            if matches!(std::env::args().nth(1), Some(arg) if arg.starts_with('-'))
+            {
+                eprintln!("\n{}", NEW_SYNTAX);
+            }
+
+            return Ok(ExitCode::from(Failure::ARGS));
+        },
+        // --help and --version
+        Err(e) => e.exit(),
+    };
+
+    let options = matches.subcommand_matches("render").unwrap_or(&matches);
+
+    log::set_level(match (options.is_present("quiet"), options.occurrences_of("verbose"))
+    {
+        (true, _) => Level::Warn,
+        (false, 0) => Level::Info,
+        (false, 1) => Level::Debug,
+        (false, _) => Level::Trace,
+    });
+
+    if options.is_present("print-config")
+    {
+        defaults.print();
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    match matches.subcommand()
+    {
+        ("render", Some(matches)) => render(matches)?,
+        ("check", Some(matches)) => check_scene(
+            matches.value_of("scene").unwrap(), scene_format(matches.value_of("scene").unwrap(), matches),
+            matches.value_of("resolution"))?,
+        ("info", Some(matches)) => scene_info(
+            matches.value_of("scene").unwrap(), scene_format(matches.value_of("scene").unwrap(), matches),
+            matches.is_present("json"))?,
+        ("list-adapters", _) => list_adapters(),
+        ("diff", Some(matches)) => return Ok(ExitCode::from(diff(matches))),
+        ("merge", Some(matches)) => merge(matches)?,
+        ("meta", Some(matches)) => print_metadata(matches.value_of("image").unwrap())?,
+        ("serve", Some(matches)) => serve::serve(matches)?,
+        ("generate", Some(matches)) => generate::generate(matches)?,
+        ("completions", Some(matches)) =>
+        {
+            let shell = matches.value_of("shell").unwrap().parse::<Shell>().unwrap();
+            app().gen_completions_to("path-tracer-gpu", shell, &mut std::io::stdout());
+        },
+        // the options without a subcommand, from before there were any
+        _ if matches.is_present("list-adapters") =>
+        {
+            pt_warn!("--list-adapters is now the list-adapters subcommand");
+            list_adapters();
+        },
+        _ if matches.is_present("check") =>
+        {
+            let file = matches.value_of("scene").unwrap();
+            pt_warn!("--check is now the check subcommand: path-tracer-gpu check {}", file);
+            check_scene(file, scene_format(file, &matches), matches.value_of("resolution"))?;
+        },
+        _ => render(&matches)?,
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Printed after mistakes in options given without a subcommand.
+const NEW_SYNTAX: &str = "Rendering now has its own subcommand, with the same options as before: \
+    path-tracer-gpu render -s SCENE -o OUTPUT -r RESOLUTION. --check and --list-adapters are \
+    the check and list-adapters subcommands. See --help for the others.";
+
+/// The top level help only lists the subcommands, as the render options
+/// given without one are there for older scripts.
+const HELP_TEMPLATE: &str = "{bin} {version}
+{about}
+
+USAGE:
+    {usage}
+
+SUBCOMMANDS:
+{subcommands}
+
+Use help SUBCOMMAND or SUBCOMMAND --help for its options.
+
+{after-help}";
+
+fn app() -> App<'static, 'static>
 {
-    let matches = App::new("GPU Path Tracer")
+    let app = App::new("GPU Path Tracer")
         .version("1.0")
         .about("A path tracer on the GPU")
+        .usage("path-tracer-gpu <SUBCOMMAND>")
+        .template(HELP_TEMPLATE)
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(Arg::with_name("check")
+            .long("check")
+            .help("Check the scene for problems and exit without rendering"))
+        .arg(Arg::with_name("list-adapters")
+            .long("list-adapters")
+            .help("List the available GPUs and exit"))
+        .subcommand(render_args(SubCommand::with_name("render")
+            .about("Render a scene, also what happens with these options and no subcommand"), false))
+        .subcommand(SubCommand::with_name("check")
+            .about("Check a scene for problems without rendering it")
+            .arg(Arg::with_name("scene")
+                .help("The scene to check")
+                .required(true))
+            .arg(Arg::with_name("resolution")
+                .short("r")
+                .long("resolution")
+                .help("The resolution to work out the GPU buffers for, like render's")
+                .value_name("RESOLUTION")
+                .takes_value(true)
+                .default_value("1080p"))
+            .arg(Arg::with_name("strict-json")
+                .long("strict-json")
+                .help("Read a JSON scene without allowing comments, trailing commas or unquoted keys")))
+        .subcommand(SubCommand::with_name("info")
+            .about("Summarise a scene's triangles, materials, lights, cameras and files")
+            .arg(Arg::with_name("scene")
+                .help("The scene to summarise")
+                .required(true))
+            .arg(Arg::with_name("json")
+                .long("json")
+                .help("Print the summary as JSON"))
+            .arg(Arg::with_name("strict-json")
+                .long("strict-json")
+                .help("Read a JSON scene without allowing comments, trailing commas or unquoted keys")))
+        .subcommand(SubCommand::with_name("list-adapters")
+            .about("List the available GPUs"))
+        .subcommand(SubCommand::with_name("diff")
+            .about("Compare two images, exiting with 1 if they differ by too much or 2 on errors")
+            .arg(Arg::with_name("a")
+                .help("The first image")
+                .required(true))
+            .arg(Arg::with_name("b")
+                .help("The second image")
+                .required(true))
+            .arg(Arg::with_name("out")
+                .long("out")
+                .help("Write a heatmap of the difference in luminance")
+                .value_name("FILE")
+                .takes_value(true))
+            .arg(Arg::with_name("threshold")
+                .long("threshold")
+                .help("Count pixels whose luminance differs by more than this, from 0 to 1")
+                .value_name("ERROR")
+                .takes_value(true)
+                .default_value("0.01"))
+            .arg(Arg::with_name("fail-above")
+                .long("fail-above")
+                .help("Exit with 1 if the luminance RMSE is above this")
+                .value_name("RMSE")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("merge")
+            .about("Add up checkpoints from renders of the same scene with different seeds")
+            .arg(Arg::with_name("checkpoints")
+                .help("The checkpoints to merge")
+                .required(true)
+                .multiple(true)
+                .min_values(2))
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("Write the merged image. For the scene's exposure and white balance, \
+                       --resume the merged checkpoint with -m 0 instead")
+                .value_name("FILE")
+                .takes_value(true))
+            .arg(Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .help("Write the merged checkpoint, to resume or merge again")
+                .value_name("FILE")
+                .takes_value(true))
+            .group(ArgGroup::with_name("outputs")
+                .args(&["output", "checkpoint"])
+                .multiple(true)
+                .required(true))
+            .arg(Arg::with_name("exposure")
+                .long("exposure")
+                .help("Brighten or darken the merged image by this many stops (default 0)")
+                .value_name("STOPS")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .requires("output")))
+        .subcommand(SubCommand::with_name("meta")
+            .about("Print how an image was rendered, from the details saved in it")
+            .arg(Arg::with_name("image")
+                .help("The image to read")
+                .required(true)))
+        .subcommand(SubCommand::with_name("serve")
+            .about("Render scenes sent over HTTP, one after another")
+            .after_help(serve::SERVE_HELP)
+            .arg(Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .help("The port to listen on")
+                .value_name("PORT")
+                .takes_value(true)
+                .default_value("8080"))
+            .arg(Arg::with_name("address")
+                .long("address")
+                .help("The address to listen on, 0.0.0.0 for every network")
+                .value_name("ADDRESS")
+                .takes_value(true)
+                .default_value("127.0.0.1"))
+            .arg(Arg::with_name("max-resolution")
+                .long("max-resolution")
+                .help("Refuse jobs with more pixels than this, like render's --resolution")
+                .value_name("RESOLUTION")
+                .takes_value(true)
+                .default_value("4k"))
+            .arg(Arg::with_name("max-samples")
+                .long("max-samples")
+                .help("Refuse jobs that ask for more samples than this")
+                .value_name("SAMPLES")
+                .takes_value(true)
+                .default_value("10000"))
+            .arg(Arg::with_name("max-triangles")
+                .long("max-triangles")
+                .help("Refuse scenes that make more triangles than this [default: 20000000]")
+                .value_name("COUNT")
+                .takes_value(true))
+            .arg(Arg::with_name("no-triangle-limit")
+                .long("no-triangle-limit")
+                .help("Render scenes with any number of triangles")
+                .conflicts_with("max-triangles"))
+            .arg(Arg::with_name("max-queued")
+                .long("max-queued")
+                .help("Turn jobs away while this many are waiting to render")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("16"))
+            .arg(Arg::with_name("scene-root")
+                .long("scene-root")
+                .help("The directory to find the scenes' includes, meshes and textures in, instead \
+                       of the current directory. Scenes can't read files outside it")
+                .value_name("DIR")
+                .takes_value(true))
+            .arg(Arg::with_name("dir")
+                .long("dir")
+                .help("The directory to keep the finished images in, instead of a temporary one")
+                .value_name("DIR")
+                .takes_value(true))
+            .arg(Arg::with_name("keep-jobs")
+                .long("keep-jobs")
+                .help("How many finished jobs to remember, deleting the oldest one's images after that")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("100"))
+            .arg(Arg::with_name("adapter")
+                .long("adapter")
+                .help("The GPU to render with, by index or part of its name")
+                .value_name("ADAPTER")
+                .takes_value(true))
+            .arg(Arg::with_name("cpu")
+                .long("cpu")
+                .help("Render on the CPU instead of a GPU, much more slowly")
+                .conflicts_with("adapter")))
+        .subcommand(SubCommand::with_name("generate")
+            .about("Write a random scene for stress testing, the same every time for the same seed")
+            .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("The file to write the scene to, or - for stdout")
+                .value_name("FILE")
+                .takes_value(true)
+                .default_value("-"))
+            .arg(Arg::with_name("triangles")
+                .short("t")
+                .long("triangles")
+                .help("How many triangles the scene makes")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("1000"))
+            .arg(Arg::with_name("extent")
+                .long("extent")
+                .help("The width of the cube around the origin the surfaces are put in")
+                .value_name("SIZE")
+                .takes_value(true)
+                .default_value("10"))
+            .arg(Arg::with_name("materials")
+                .long("materials")
+                .help("How many materials to make")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("8"))
+            .arg(Arg::with_name("emissive")
+                .long("emissive")
+                .help("The fraction of the materials that glow, at least one unless it's 0")
+                .value_name("FRACTION")
+                .takes_value(true)
+                .default_value("0.1"))
+            .arg(Arg::with_name("mix")
+                .long("mix")
+                .help("How often to pick triangles, quads and spheres, as weights like 2:1:1. Spheres \
+                       are smaller when there aren't enough triangles left, then quads and triangles")
+                .value_name("TRI:QUAD:SPHERE")
+                .takes_value(true)
+                .default_value("1:1:1"))
+            .arg(Arg::with_name("seed")
+                .long("seed")
+                .help("The seed for the scene, which is the same for the same seed and options")
+                .value_name("SEED")
+                .takes_value(true)
+                .default_value("0"))
+            .arg(Arg::with_name("self-test")
+                .long("self-test")
+                .help("Instead of writing a scene, render scenes with each of --sizes triangles for \
+                       --time and print a table of how fast they went")
+                .conflicts_with("output"))
+            .arg(Arg::with_name("sizes")
+                .long("sizes")
+                .help("The triangle counts for --self-test")
+                .value_name("COUNTS")
+                .takes_value(true)
+                .default_value("100,1000,10000,100000"))
+            .arg(Arg::with_name("time")
+                .long("time")
+                .help("How long --self-test renders each scene, as seconds or a time like 1m")
+                .value_name("TIME")
+                .takes_value(true)
+                .default_value("5s"))
+            .arg(Arg::with_name("resolution")
+                .short("r")
+                .long("resolution")
+                .help("The resolution --self-test renders at, like render's")
+                .value_name("RESOLUTION")
+                .takes_value(true)
+                .default_value("512x512"))
+            .arg(Arg::with_name("adapter")
+                .long("adapter")
+                .help("The GPU for --self-test, by index or part of its name")
+                .value_name("ADAPTER")
+                .takes_value(true))
+            .arg(Arg::with_name("cpu")
+                .long("cpu")
+                .help("Run --self-test on the CPU instead of a GPU, much more slowly")
+                .conflicts_with("adapter")))
+        .subcommand(SubCommand::with_name("completions")
+            .about("Print a completion script for a shell")
+            .arg(Arg::with_name("shell")
+                .help("The shell to complete in")
+                .required(true)
+                .possible_values(&Shell::variants())))
+        .after_help(EXIT_CODES);
+
+    render_args(app, true)
+}
+
+/// Arguments that make `--scene`, `--output` and `--resolution` optional.
+struct Exempt
+{
+    scene: &'static [&'static str],
+    output: &'static [&'static str],
+    resolution: &'static [&'static str],
+}
+
+/// Adds the render options to `app`. Without a subcommand, `--check` and
+/// `--list-adapters` need fewer of them.
+fn render_args(app: App<'static, 'static>, legacy: bool) -> App<'static, 'static>
+{
+    let exempt = if legacy
+    {
+        Exempt
+        {
+            scene: &["list-adapters", "print-config", "builtin"],
+            output: &["list-adapters", "check", "dump-scene", "benchmark", "estimate", "print-config", "builtin"],
+            resolution: &["list-adapters", "check", "dump-scene", "print-config", "builtin"],
+        }
+    }
+    else
+    {
+        Exempt
+        {
+            scene: &["print-config", "builtin"],
+            output: &["dump-scene", "benchmark", "estimate", "print-config", "builtin"],
+            resolution: &["dump-scene", "print-config", "builtin"],
+        }
+    };
+
+    app
         .arg(Arg::with_name("scene")
             .short("s")
             .long("scene")
-            .help("The scene to render")
+            .help("The scene to render, as JSON, YAML (.yaml, .yml) or TOML (.toml), or - for JSON from stdin. \
+                   Repeat it, or give a directory or a quoted pattern like \"scenes/*.json\", to render \
+                   several one after another, naming the outputs with {scene} or an output directory")
             .value_name("SCENE")
             .takes_value(true)
-            .required(true))
+            .multiple(true)
+            .number_of_values(1)
+            .required_unless_one(exempt.scene))
+        .arg(Arg::with_name("builtin")
+            .long("builtin")
+            .help("Render a scene built into the renderer instead of one from a file, or list them with \"list\"")
+            .value_name("NAME")
+            .takes_value(true)
+            .possible_values(&["cornell", "spheres", "furnace", "mirror-box", "list"])
+            .conflicts_with("scene"))
         .arg(Arg::with_name("output")
             .short("o")
             .long("output")
-            .help("The file to render to")
+            .help("The file to render to, in the format of its extension: .exr, .pfm and .hdr get the \
+                   light as floats, before the exposure and white balance, and the others 8-bit colour. \
+                   Repeat it or separate files with commas to save several, and add :exposure=STOPS \
+                   to give one its own exposure. {scene} is replaced by the scene's file name, and a \
+                   directory gets SCENE.png")
             .value_name("OUTPUT")
             .takes_value(true)
-            .required(true))
+            .multiple(true)
+            .number_of_values(1)
+            .requires("resolution")
+            .required_unless_one(exempt.output))
+        .arg(Arg::with_name("force")
+            .long("force")
+            .help("Overwrite the output if it already exists")
+            .requires("output"))
+        .arg(Arg::with_name("auto-number")
+            .long("auto-number")
+            .help("If the output already exists, save to OUTPUT_0001, OUTPUT_0002 and so on instead")
+            .requires("output")
+            .conflicts_with("force"))
         .arg(Arg::with_name("resolution")
             .short("r")
             .long("resolution")
-            .help("The resolution of the render, as width:height")
+            .help("The resolution of the render, as width:height or widthxheight, or one of \
+                   480p, 720p, 1080p, 1440p, 4k, 8k and square256 to square4096")
             .value_name("RESOLUTION")
             .takes_value(true)
-            .required(true))
+            .required_unless_one(exempt.resolution))
         .arg(Arg::with_name("max-samples")
             .short("m")
             .long("max-samples")
             .help("The maximum number of samples to process")
             .value_name("SAMPLES")
             .takes_value(true))
+        .arg(Arg::with_name("target-noise")
+            .long("target-noise")
+            .help("Stop once the noise drops to this, as the mean relative standard error of \
+                   the pixels (e.g. 0.01), measured every 32 samples. The maximum samples and \
+                   time limit still apply")
+            .value_name("NOISE")
+            .takes_value(true)
+            .conflicts_with_all(&["resume", "adapters"]))
         .arg(Arg::with_name("time-limit")
             .short("t")
             .long("time-limit")
-            .help("The maximum number of time to render for, as h:m:s")
+            .help("The longest to render for, as h:m:s, m:s, seconds, or with units like \
+                   1h30m, 20m or 90.5s")
             .value_name("TIME")
             .takes_value(true))
         .arg(Arg::with_name("progressive")
             .short("p")
             .long("progressive")
             .help("Perform a progressive render that will continue until stopped"))
+        .arg(Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .help("Only print warnings and errors")
+            .conflicts_with("verbose"))
+        .arg(Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .help("Print details about the GPU and timings, twice for every sample")
+            .multiple(true))
+        .arg(Arg::with_name("progress-interval")
+            .long("progress-interval")
+            .help("Seconds between progress updates, or 0 to stay quiet")
+            .value_name("SECONDS")
+            .takes_value(true)
+            .default_value("5"))
         .arg(Arg::with_name("debug")
             .short("d")
             .long("debug")
             .help("Add information about the scene and render to image"))
-        .get_matches();
+        .arg(Arg::with_name("debug-pos")
+            .long("debug-pos")
+            .help("The corner for the debug information, with --annotate text in the one \
+                   above or below it (default bl)")
+            .value_name("CORNER")
+            .takes_value(true)
+            .possible_values(&["tl", "tr", "bl", "br"]))
+        .arg(Arg::with_name("debug-colour")
+            .long("debug-colour")
+            .help("The colour of the debug and annotation text, as hex RRGGBB (default ffffff)")
+            .value_name("COLOUR")
+            .takes_value(true))
+        .arg(Arg::with_name("debug-opacity")
+            .long("debug-opacity")
+            .help("How much the black behind the text covers the image, from 0 to 1 (default 1)")
+            .value_name("OPACITY")
+            .takes_value(true))
+        .arg(Arg::with_name("debug-scale")
+            .long("debug-scale")
+            .help("Draw the text this many times bigger, e.g. 2 for 4K images (default 1)")
+            .value_name("SCALE")
+            .takes_value(true))
+        .arg(Arg::with_name("annotate")
+            .long("annotate")
+            .help("Write this text in the top left corner of the image")
+            .value_name("TEXT")
+            .takes_value(true))
+        .arg(Arg::with_name("only")
+            .long("only")
+            .help("Render only the surfaces in this group, can be given more than once")
+            .value_name("GROUP")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("hide")
+            .long("hide")
+            .help("Leave out the surfaces in this group, can be given more than once")
+            .value_name("GROUP")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1))
+        .arg(Arg::with_name("checkpoint")
+            .long("checkpoint")
+            .help("Periodically save the render to a checkpoint file")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("checkpoint-every")
+            .long("checkpoint-every")
+            .help("The number of samples between checkpoints (default 100)")
+            .value_name("SAMPLES")
+            .takes_value(true)
+            .requires("checkpoint"))
+        .arg(Arg::with_name("resume")
+            .long("resume")
+            .help("Continue a render from a checkpoint file")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("snapshot-every")
+            .long("snapshot-every")
+            .help("Periodically write the render so far to OUTPUT.partial, \
+                   as a number of samples or seconds (e.g. 100 or 30s)")
+            .value_name("INTERVAL")
+            .takes_value(true))
+        .arg(Arg::with_name("denoise")
+            .long("denoise")
+            .help("Filter noise out of the final image, optionally with a \
+                   strength (default 1.0)")
+            .value_name("STRENGTH")
+            .takes_value(true)
+            .min_values(0))
+        .arg(Arg::with_name("heatmap")
+            .long("heatmap")
+            .help("Also write how noisy each pixel is as a false colour image, with a legend")
+            .value_name("FILE")
+            .takes_value(true)
+            .conflicts_with_all(&["resume", "benchmark"]))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("Write what the render did as JSON when it ends, even if it fails or is \
+                   interrupted: samples, times, adapter, buffers, the files saved and how it ended")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("depth-map")
+            .long("depth-map")
+            .help("Also write each pixel's distance to the first surface, as floats to .exr \
+                   or .pfm files and 16-bit greyscale to others")
+            .value_name("FILE")
+            .takes_value(true)
+            .conflicts_with("benchmark"))
+        .arg(Arg::with_name("depth-near")
+            .long("depth-near")
+            .help("The distance that's black in a greyscale depth map, \
+                   the nearest point of the scene without it")
+            .value_name("DISTANCE")
+            .takes_value(true)
+            .requires("depth-map"))
+        .arg(Arg::with_name("depth-far")
+            .long("depth-far")
+            .help("The distance that's white in a greyscale depth map, \
+                   the furthest point of the scene without it")
+            .value_name("DISTANCE")
+            .takes_value(true)
+            .requires("depth-map"))
+        .arg(Arg::with_name("mode")
+            .long("mode")
+            .help("What to render: a path traced image, or ambient occlusion, normals, depth, \
+                   material colours or a wireframe to check the scene, which take one sample \
+                   unless --max-samples is given")
+            .value_name("MODE")
+            .takes_value(true)
+            .possible_values(&["pathtrace", "ao", "normals", "depth", "matid", "wireframe"])
+            .default_value("pathtrace"))
+        .arg(Arg::with_name("ao-rays")
+            .long("ao-rays")
+            .help("Occlusion rays per sample in ao mode")
+            .value_name("RAYS")
+            .takes_value(true)
+            .default_value("4"))
+        .arg(Arg::with_name("ao-distance")
+            .long("ao-distance")
+            .help("How close something must be to occlude in ao mode, anything counts without it")
+            .value_name("DISTANCE")
+            .takes_value(true))
+        .arg(Arg::with_name("exposure")
+            .long("exposure")
+            .help("Brighten or darken the image by this many stops. To re-expose a finished \
+                   render, --resume its checkpoint with -m 0")
+            .value_name("STOPS")
+            .takes_value(true)
+            .allow_hyphen_values(true))
+        .arg(Arg::with_name("white-balance")
+            .long("white-balance")
+            .help("Take the colour of light at this temperature out of the image")
+            .value_name("KELVIN")
+            .takes_value(true)
+            .conflicts_with_all(&["wb-rgb", "wb-from-pixel"]))
+        .arg(Arg::with_name("wb-rgb")
+            .long("wb-rgb")
+            .help("Multiply the red, green and blue of the image by these")
+            .value_name("R,G,B")
+            .takes_value(true)
+            .conflicts_with("wb-from-pixel"))
+        .arg(Arg::with_name("wb-from-pixel")
+            .long("wb-from-pixel")
+            .help("Balance the image so the pixel at x,y from the top left is grey")
+            .value_name("X,Y")
+            .takes_value(true))
+        .arg(Arg::with_name("supersample")
+            .long("supersample")
+            .help("Render this many times the resolution across and down, and filter it down \
+                   to the resolution before saving, for smoother edges. --tile and checkpoints \
+                   are in the rendered pixels")
+            .value_name("FACTOR")
+            .takes_value(true)
+            .default_value("1"))
+        .arg(Arg::with_name("tile")
+            .long("tile")
+            .help("Render the image in tiles of this size, as size or width:height")
+            .value_name("SIZE")
+            .takes_value(true)
+            .conflicts_with_all(&["checkpoint", "resume", "snapshot-every"]))
+        .arg(Arg::with_name("max-dispatch-ms")
+            .long("max-dispatch-ms")
+            .help("Split each sample into GPU submissions of about this many \
+                   milliseconds, so the OS doesn't reset the GPU (0 to disable)")
+            .value_name("MS")
+            .takes_value(true)
+            .default_value("500"))
+        .arg(Arg::with_name("profile-gpu")
+            .long("profile-gpu")
+            .help("Time the GPU's work with timestamp queries and print it at the end; \
+                   this waits for the GPU after every sample, so rendering is slower"))
+        .arg(Arg::with_name("ray-epsilon")
+            .long("ray-epsilon")
+            .help("How far bounced rays start off the surface, as a fraction of the largest \
+                   coordinate of the hit; raise it if surfaces are speckled with shadow acne")
+            .value_name("EPSILON")
+            .takes_value(true)
+            .default_value("0.001"))
+        .arg(Arg::with_name("region")
+            .long("region")
+            .help("Only render the pixels in a region of the image, as x,y,w,h \
+                   from the top left")
+            .value_name("REGION")
+            .takes_value(true)
+            .conflicts_with("resume"))
+        .arg(Arg::with_name("crop")
+            .long("crop")
+            .help("Crop the output image to the rendered region")
+            .requires("region"))
+        .arg(Arg::with_name("frames")
+            .long("frames")
+            .help("Render frames of the scene's animation, as first..last, \
+                   to numbered files")
+            .value_name("FRAMES")
+            .takes_value(true)
+            .conflicts_with_all(&["checkpoint", "resume", "snapshot-every"]))
+        .arg(Arg::with_name("turntable")
+            .long("turntable")
+            .help("Render this many frames with the camera circling the scene once, \
+                   to numbered files")
+            .value_name("FRAMES")
+            .takes_value(true)
+            .conflicts_with_all(&["frames", "checkpoint", "resume", "snapshot-every"]))
+        .arg(Arg::with_name("orbit-center")
+            .long("orbit-center")
+            .help("The point the turntable circles and looks at, as x,y,z \
+                   (default the middle of the scene)")
+            .value_name("POINT")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .requires("turntable"))
+        .arg(Arg::with_name("orbit-radius")
+            .long("orbit-radius")
+            .help("How far from the center the turntable circles, across the camera's up \
+                   (default far enough to see the whole scene)")
+            .value_name("DISTANCE")
+            .takes_value(true)
+            .requires("turntable"))
+        .arg(Arg::with_name("orbit-height")
+            .long("orbit-height")
+            .help("How far above the center the turntable circles, along the camera's up \
+                   (default 20 degrees above)")
+            .value_name("DISTANCE")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .requires("turntable"))
+        .arg(Arg::with_name("stereo")
+            .long("stereo")
+            .help("Render a left and right eye this far apart, side by side in an image twice \
+                   as wide. The sample and time limits are for both, so each eye gets half")
+            .value_name("IPD")
+            .takes_value(true)
+            .conflicts_with_all(&[
+                "frames", "turntable", "all-cameras", "checkpoint", "resume", "snapshot-every"]))
+        .arg(Arg::with_name("converge")
+            .long("converge")
+            .help("Turn the eyes in to look at the point this far in front of the camera, \
+                   instead of straight ahead")
+            .value_name("DISTANCE")
+            .takes_value(true)
+            .requires("stereo"))
+        .arg(Arg::with_name("camera")
+            .long("camera")
+            .help("The named camera from the scene to render with")
+            .value_name("NAME")
+            .takes_value(true))
+        .arg(Arg::with_name("camera-pos")
+            .long("camera-pos")
+            .help("Move the camera to x,y,z, facing the same way unless --camera-look-at is given")
+            .value_name("POINT")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .conflicts_with_all(&["all-cameras", "auto-camera"]))
+        .arg(Arg::with_name("camera-look-at")
+            .long("camera-look-at")
+            .help("Turn the camera to face x,y,z")
+            .value_name("POINT")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .conflicts_with_all(&["all-cameras", "auto-camera"]))
+        .arg(Arg::with_name("camera-up")
+            .long("camera-up")
+            .help("Turn the camera so x,y,z is up")
+            .value_name("DIRECTION")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .conflicts_with_all(&["all-cameras", "auto-camera"]))
+        .arg(Arg::with_name("camera-fov")
+            .long("camera-fov")
+            .help("The camera's field of view across the image, in degrees")
+            .value_name("DEGREES")
+            .takes_value(true)
+            .conflicts_with("all-cameras"))
+        .arg(Arg::with_name("all-cameras")
+            .long("all-cameras")
+            .help("Render once with every camera in the scene, adding the \
+                   camera's name to the output file")
+            .conflicts_with_all(&[
+                "camera", "frames", "turntable", "progressive", "checkpoint", "resume"]))
+        .arg(Arg::with_name("adapter")
+            .long("adapter")
+            .help("The GPU to render with, by index or part of its name")
+            .value_name("ADAPTER")
+            .takes_value(true))
+        .arg(Arg::with_name("adapters")
+            .long("adapters")
+            .help("Render on several GPUs at once, as a list of indexes or parts of names \
+                   (e.g. 0,1)")
+            .value_name("ADAPTERS")
+            .takes_value(true)
+            .conflicts_with_all(&[
+                "adapter", "frames", "turntable", "stereo", "all-cameras", "resume",
+                "checkpoint", "snapshot-every", "benchmark"]))
+        .arg(Arg::with_name("require-discrete")
+            .long("require-discrete")
+            .help("Fail instead of falling back to an integrated or software GPU"))
+        .arg(Arg::with_name("cpu")
+            .long("cpu")
+            .help("Render on the CPU instead of a GPU, much more slowly")
+            .conflicts_with_all(&["adapter", "adapters", "require-discrete"]))
+        .arg(Arg::with_name("shader")
+            .long("shader")
+            .help("Render with this WGSL file instead of the built-in shader, reloading it \
+                   when it changes during a progressive render")
+            .value_name("FILE")
+            .takes_value(true)
+            .conflicts_with("cpu"))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .help("Seed for the noise, the same seed renders the same image")
+            .value_name("SEED")
+            .takes_value(true))
+        .arg(Arg::with_name("auto-camera")
+            .long("auto-camera")
+            .help("Point the camera at the whole scene, keeping its fov"))
+        .arg(Arg::with_name("fix-winding")
+            .long("fix-winding")
+            .help("Flip triangles so their fronts agree with their neighbours' and face out"))
+        .arg(Arg::with_name("transform")
+            .long("transform")
+            .help("Scale, turn and move everything in the scene but the cameras, like \
+                   \"scale=0.01,rotate_x=-90,translate=0,0,1\". Whatever order they're written in, \
+                   the scale comes first, then the turns in the order given, then the move. Turns \
+                   are rotate_x, rotate_y and rotate_z in degrees, or rotate=X,Y,Z,DEGREES about an axis")
+            .value_name("TRANSFORM")
+            .takes_value(true))
+        .arg(Arg::with_name("transform-camera")
+            .long("transform-camera")
+            .help("Like --transform, but moving the cameras with everything else")
+            .value_name("TRANSFORM")
+            .takes_value(true)
+            .conflicts_with("transform"))
+        .arg(Arg::with_name("max-triangles")
+            .long("max-triangles")
+            .help("Refuse scenes that make more triangles than this [default: 20000000]")
+            .value_name("COUNT")
+            .takes_value(true))
+        .arg(Arg::with_name("no-triangle-limit")
+            .long("no-triangle-limit")
+            .help("Render scenes with any number of triangles")
+            .conflicts_with("max-triangles"))
+        .arg(Arg::with_name("strict-json")
+            .long("strict-json")
+            .help("Read a JSON scene without allowing comments, trailing commas or unquoted keys"))
+        .arg(Arg::with_name("config")
+            .long("config")
+            .help("Read defaults for these options from this TOML file, instead of ./path-tracer.toml \
+                or path-tracer/config.toml in the user's config directory")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("print-config")
+            .long("print-config")
+            .help("Print the defaults from the config file and PATH_TRACER_* environment variables, \
+                and where each came from, then exit"))
+        .arg(Arg::with_name("scene-root")
+            .long("scene-root")
+            .help("The directory to find includes, meshes and textures in, instead of the scene's own, \
+                or the current directory for -s -")
+            .value_name("DIR")
+            .takes_value(true))
+        .arg(Arg::with_name("dump-scene")
+            .long("dump-scene")
+            .help("Write the scene as JSON, with includes and quads expanded")
+            .value_name("FILE")
+            .takes_value(true))
+        .arg(Arg::with_name("benchmark")
+            .long("benchmark")
+            .help("Time the render and report samples per second, only saving the image with -o")
+            .conflicts_with_all(&[
+                "progressive", "frames", "turntable", "stereo", "all-cameras", "resume",
+                "checkpoint", "snapshot-every", "tile"]))
+        .arg(Arg::with_name("benchmark-warmup")
+            .long("benchmark-warmup")
+            .help("How long to render before timing, like --time-limit")
+            .value_name("TIME")
+            .takes_value(true)
+            .default_value("5"))
+        .arg(Arg::with_name("benchmark-time")
+            .long("benchmark-time")
+            .help("How long to time the render for, like --time-limit")
+            .value_name("TIME")
+            .takes_value(true)
+            .default_value("20"))
+        .arg(Arg::with_name("estimate")
+            .long("estimate")
+            .help("Time a short render at half the width and height and print how long this one \
+                would take, without rendering it")
+            .conflicts_with("benchmark"))
+        .arg(Arg::with_name("preview-pass")
+            .long("preview-pass")
+            .help("First render a quick preview at a quarter of the width and height with 16 \
+                   samples to NAME.preview.png, then ask whether to go on with the full render, \
+                   going on by itself after 10 seconds or without a terminal")
+            .conflicts_with_all(&[
+                "progressive", "frames", "turntable", "stereo", "all-cameras", "benchmark",
+                "estimate"]))
+        .arg(Arg::with_name("preview-only")
+            .long("preview-only")
+            .help("Stop after the preview")
+            .requires("preview-pass"))
+        .arg(Arg::with_name("benchmark-json")
+            .long("benchmark-json")
+            .help("Also print the benchmark results as a line of JSON, use -q for only that")
+            .requires("benchmark"))
+}
+
+/// A default for a render option, from a config file or a `PATH_TRACER_*`
+/// environment variable.
+struct Setting
+{
+    /// the option's long name
+    key: String,
+    /// the option's values, one for each time it's given, or none for a flag
+    values: Vec<String>,
+    /// the value as it was written, for `--print-config`
+    shown: String,
+    /// the file or environment variable
+    source: String,
+    /// why it isn't used, if it isn't
+    unused: Option<String>,
+}
+
+impl Setting
+{
+    /// `--key value` once for each value.
+    fn args(&self) -> Vec<String>
+    {
+        match self.values.as_slice()
+        {
+            [] => vec![format!("--{}", self.key)],
+            values => values.iter().flat_map(|v| vec![format!("--{}", self.key), v.clone()]).collect(),
+        }
+    }
+}
+
+/// Defaults for the render options. Options given on the command line win
+/// over the environment, which wins over the config file, which wins over
+/// the built-in defaults.
+struct Defaults
+{
+    /// the config file, if there is one
+    file: Option<String>,
+    /// the files looked for when there isn't
+    searched: Vec<String>,
+    settings: Vec<Setting>,
+}
+
+impl Defaults
+{
+    /// Finds the settings for a render, ignoring unknown options with a
+    /// warning. `args` are the command line arguments, which can name the
+    /// config file.
+    fn read(args: &[String]) -> Result<Defaults, String>
+    {
+        let mut defaults = Defaults
+        {
+            file: None,
+            searched: Vec::new(),
+            settings: Vec::new(),
+        };
+
+        if Defaults::start(args).is_none()
+        {
+            return Ok(defaults);
+        }
+
+        let given = args.iter().enumerate().find_map(|(i, arg)| match arg.strip_prefix("--config")
+        {
+            Some("") => args.get(i + 1).cloned(),
+            Some(rest) => rest.strip_prefix('=').map(str::to_owned),
+            None => None,
+        });
+
+        let file = match given
+        {
+            Some(file) => Some(file),
+            None =>
+            {
+                let user = ["XDG_CONFIG_HOME", "APPDATA"].iter()
+                    .find_map(|var| std::env::var_os(var).map(std::path::PathBuf::from))
+                    .or_else(|| std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".config")))
+                    .map(|dir| dir.join("path-tracer").join("config.toml").display().to_string());
+
+                defaults.searched = std::iter::once("./path-tracer.toml".to_owned()).chain(user).collect();
+                defaults.searched.iter().find(|file| std::path::Path::new(file).is_file()).cloned()
+            },
+        };
+
+        let mut settings = match &file
+        {
+            Some(file) =>
+            {
+                let source = std::fs::read_to_string(file)
+                    .map_err(|e| format!("Could not read config \"{}\": {}", file, e))?;
+                config_settings(&source, file)?
+            },
+            None => Vec::new(),
+        };
+
+        let mut vars = std::env::vars()
+            .filter_map(|(var, value)| var.strip_prefix("PATH_TRACER_")
+                .map(|key| (key.to_lowercase().replace('_', "-"), value, var.clone())))
+            .collect::<Vec<_>>();
+        vars.sort();
+
+        for (key, value, var) in vars
+        {
+            settings.retain(|s| s.key != key);
+            settings.push(match value.parse::<bool>()
+            {
+                Ok(on) => Setting
+                {
+                    key: key,
+                    values: Vec::new(),
+                    shown: value,
+                    source: var,
+                    unused: if on { None } else { Some("it's false".to_owned()) },
+                },
+                Err(_) => Setting
+                {
+                    key: key,
+                    values: vec![value.clone()],
+                    shown: value,
+                    source: var,
+                    unused: None,
+                },
+            });
+        }
+
+        // each on its own, with --print-config so nothing else is needed and
+        // the only problems are with the setting itself
+        for setting in settings.iter_mut().filter(|s| s.unused.is_none())
+        {
+            let mut alone = vec!["path-tracer-gpu".to_owned(), "render".to_owned(), "--print-config".to_owned()];
+            alone.extend(setting.args());
+
+            let problem = match app().get_matches_from_safe(alone)
+            {
+                Err(e) => match e.kind
+                {
+                    clap::ErrorKind::UnknownArgument
+                        if e.info.as_ref().is_some_and(|i| i[0] == format!("--{}", setting.key)) =>
+                        Some(format!("Unknown option \"{}\" in {}, ignoring it", setting.key, setting.source)),
+                    clap::ErrorKind::UnknownArgument =>
+                        Some(format!("\"{}\" in {} is a flag, so it can only be true or false, ignoring it",
+                            setting.key, setting.source)),
+                    clap::ErrorKind::EmptyValue =>
+                        Some(format!("\"{}\" in {} needs a value, ignoring it", setting.key, setting.source)),
+                    clap::ErrorKind::InvalidValue =>
+                        Some(format!("\"{}\" isn't one of the values for \"{}\" in {}, ignoring it",
+                            setting.shown, setting.key, setting.source)),
+                    _ => None,
+                },
+                Ok(_) => None,
+            };
+
+            if let Some(problem) = problem
+            {
+                pt_warn!("{}", problem);
+                setting.unused = Some("it isn't valid".to_owned());
+            }
+        }
+
+        defaults.file = file;
+        defaults.settings = settings;
+
+        Ok(defaults)
+    }
+
+    /// Where the settings go in `args`, after `render` or at the start for
+    /// the options without a subcommand. Other subcommands have none.
+    fn start(args: &[String]) -> Option<usize>
+    {
+        match args.get(1).map(String::as_str)
+        {
+            Some("render") => Some(2),
+            Some(arg) if arg.starts_with('-') => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Parses `args` with the settings ahead of them. Settings for options
+    /// that are also on the command line, or that conflict with them, are
+    /// dropped.
+    fn apply(&mut self, args: &[String]) -> Result<clap::ArgMatches<'static>, clap::Error>
+    {
+        let start = match Defaults::start(args)
+        {
+            Some(start) => start,
+            None => return app().get_matches_from_safe(args),
+        };
+
+        // one setting is dropped each time around
+        loop
+        {
+            let used = self.settings.iter().filter(|s| s.unused.is_none()).flat_map(Setting::args);
+            let full = args[..start].iter().cloned()
+                .chain(used)
+                .chain(args[start..].iter().cloned())
+                .collect::<Vec<_>>();
+
+            let named = |name: &str, s: &Setting| s.unused.is_none()
+                && (name == s.key || name == format!("--{}", s.key) || name.starts_with(&format!("--{} ", s.key)));
+
+            let (i, why) = match app().get_matches_from_safe(full)
+            {
+                Ok(matches) =>
+                {
+                    let options = matches.subcommand_matches("render").unwrap_or(&matches);
 
-    let file = std::fs::read_to_string(
-        matches.value_of("scene").unwrap()).unwrap();
+                    // options that can be given more than once add up instead
+                    // of clashing
+                    let twice = self.settings.iter().position(|s| s.unused.is_none()
+                        && options.occurrences_of(&s.key) > s.values.len().max(1) as u64);
+
+                    match twice
+                    {
+                        Some(i) => (i, "it's on the command line".to_owned()),
+                        None => return Ok(matches),
+                    }
+                },
+                Err(e) =>
+                {
+                    let info = e.info.clone().unwrap_or_default();
+                    let find = |name: &str| self.settings.iter().position(|s| named(name, s));
+
+                    match e.kind
+                    {
+                        clap::ErrorKind::UnexpectedMultipleUsage => match find(&info[0])
+                        {
+                            Some(i) => (i, "it's on the command line".to_owned()),
+                            None => return Err(e),
+                        },
+                        clap::ErrorKind::ArgumentConflict => match (find(&info[0]), info.get(1))
+                        {
+                            (Some(i), Some(other)) => (i, format!("it conflicts with {}", other)),
+                            (Some(i), None) => (i, "it conflicts with another option".to_owned()),
+                            (None, Some(other)) => match find(other)
+                            {
+                                Some(i) => (i, format!("it conflicts with --{}", info[0])),
+                                None => return Err(e),
+                            },
+                            (None, None) => return Err(e),
+                        },
+                        _ => return Err(e),
+                    }
+                },
+            };
+
+            self.settings[i].unused = Some(why);
+        }
+    }
+
+    /// Prints every setting and where it came from.
+    fn print(&self)
+    {
+        match &self.file
+        {
+            Some(file) => println!("Config file: {}", file),
+            None if self.searched.is_empty() => println!("No config file"),
+            None => println!("No config file, looked for {}", self.searched.join(" and ")),
+        }
+
+        for s in &self.settings
+        {
+            match &s.unused
+            {
+                None => println!("{} = {} (from {})", s.key, s.shown, s.source),
+                Some(why) => println!("{} = {} (from {}, not used as {})", s.key, s.shown, s.source, why),
+            }
+        }
+
+        println!("Everything else is from the command line or the built-in defaults");
+    }
+}
+
+/// Reads a TOML config file's settings, one for each option, in order.
+#[cfg(feature = "toml")]
+fn config_settings(source: &str, file: &str) -> Result<Vec<Setting>, String>
+{
+    use toml::Value;
+
+    let table = match source.parse::<Value>()
+    {
+        Ok(Value::Table(table)) => table,
+        Ok(_) => return Err(format!("Config \"{}\" isn't a table of options", file)),
+        Err(e) => return Err(format!("Could not read config \"{}\": {}", file, e)),
+    };
+
+    let mut settings = Vec::new();
+
+    for (key, value) in table
+    {
+        let shown = value.to_string();
+
+        let (values, unused) = match value
+        {
+            Value::Boolean(true) => (Vec::new(), None),
+            Value::Boolean(false) => (Vec::new(), Some("it's false".to_owned())),
+            Value::String(s) => (vec![s], None),
+            Value::Array(items) => (items.into_iter().map(|v| match v
+            {
+                Value::String(s) => s,
+                v => v.to_string(),
+            }).collect(), None),
+            Value::Table(_) =>
+            {
+                pt_warn!("Unknown option \"{}\" in {}, ignoring it", key, file);
+                continue;
+            },
+            v => (vec![v.to_string()], None),
+        };
+
+        settings.push(Setting
+        {
+            key: key,
+            values: values,
+            shown: shown,
+            source: file.to_owned(),
+            unused: unused,
+        });
+    }
+
+    Ok(settings)
+}
+
+#[cfg(not(feature = "toml"))]
+fn config_settings(_: &str, file: &str) -> Result<Vec<Setting>, String>
+{
+    Err(format!("Could not read config \"{}\": config files need the \"toml\" feature", file))
+}
+
+/// Reads `--strict-json` for the scene's format.
+fn scene_format(file: &str, matches: &clap::ArgMatches) -> Format
+{
+    match Format::from_path(file)
+    {
+        Format::Json if matches.is_present("strict-json") => Format::StrictJson,
+        format => format,
+    }
+}
+
+/// Runs the `render` subcommand, or the options without one, for each scene
+/// in turn. One scene failing doesn't stop the others.
+fn render(matches: &clap::ArgMatches) -> Result<(), Failure>
+{
+    if let Some(name) = matches.value_of("builtin")
+    {
+        return render_builtin(matches, name);
+    }
+
+    let files = expand_scenes(matches.values_of("scene").unwrap()).map_err(Failure::Args)?;
+
+    let control = std::sync::Arc::new(RenderControl::new());
+    catch_ctrl_c(control.clone());
+
+    // opened by the first scene that renders, then kept for the rest
+    let mut ctxs = None;
+
+    if files.len() == 1
+    {
+        let job = Job::new(&files[0], String::new());
+        return render_scene(matches, &job, &control, &mut ctxs).map(|_| ());
+    }
+
+    for arg in ["resume", "checkpoint", "dump-scene", "progressive"]
+    {
+        if matches.is_present(arg)
+        {
+            return Err(Failure::Args(format!("--{} only works with one scene", arg)));
+        }
+    }
+
+    let per_scene = |path: &str| path.contains("{scene}") || std::path::Path::new(path).is_dir();
+    let outputs = matches.values_of("output").into_iter().flatten()
+        .map(|value| parse_outputs(value).map_err(Failure::Args))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for path in outputs.iter().flatten().map(|o| o.path.as_str())
+        .chain(matches.value_of("heatmap"))
+        .chain(matches.value_of("depth-map"))
+        .chain(matches.value_of("stats"))
+    {
+        if !per_scene(path)
+        {
+            return Err(Failure::Args(format!(
+                "\"{}\" would be overwritten by every scene, add {{scene}} to it or give a directory",
+                path)));
+        }
+    }
+
+    let jobs = files.iter().enumerate()
+        .map(|(i, file)| Job::new(file, format!("{}/{} ", i + 1, files.len())))
+        .collect::<Vec<_>>();
+
+    if let Some((a, b)) = jobs.iter().enumerate()
+        .find_map(|(i, a)| jobs[..i].iter().find(|b| b.name == a.name).map(|b| (a, b)))
+    {
+        return Err(Failure::Args(format!(
+            "\"{}\" and \"{}\" would be saved to the same files", b.file, a.file)));
+    }
+
+    // the scene, then its samples, time and status
+    let mut results = Vec::new();
+    let mut first_failure = None;
+
+    for (i, job) in jobs.iter().enumerate()
+    {
+        if control.stopping()
+        {
+            pt_warn!("Interrupted, so the scenes from \"{}\" on aren't rendered", job.file);
+
+            results.extend(jobs[i..].iter().map(|job| (job.file, None, None, "skipped")));
+            break;
+        }
+
+        pt_info!("Scene {}/{}: {}", i + 1, jobs.len(), job.file);
+
+        let start = std::time::Instant::now();
+        let (samples, status) = match render_scene(matches, job, &control, &mut ctxs)
+        {
+            Ok(samples) => (samples, "done"),
+            // the other scenes are still worth rendering
+            Err(e) =>
+            {
+                pt_error!("{}: {}", job.file, e);
+                first_failure.get_or_insert(e);
+                (None, "failed")
+            },
+        };
+
+        results.push((job.file, samples, Some(start.elapsed()), status));
+    }
+
+    let width = results.iter().map(|r| r.0.len()).max().unwrap_or(0).max("Scene".len());
+
+    println!();
+    println!("{:<w$}  {:>8}  {:>10}  Status", "Scene", "Samples", "Time", w = width);
+    for (file, samples, time, status) in &results
+    {
+        println!("{:<w$}  {:>8}  {:>10}  {}",
+            file,
+            samples.map_or("-".to_owned(), |s| s.to_string()),
+            time.map_or("-".to_owned(), |t| format!("{:.2}s", t.as_secs_f64())),
+            status,
+            w = width);
+    }
+
+    let failed = results.iter().filter(|r| r.3 == "failed").count();
+
+    match first_failure
+    {
+        Some(e) => Err(e.retold(format!("{} of the {} scenes failed", failed, jobs.len()))),
+        None => Ok(()),
+    }
+}
+
+/// Renders `--builtin`, or lists the built-in scenes for "list". They're
+/// exempt from `--output` and `--resolution` for that, so they're checked
+/// here instead.
+fn render_builtin(matches: &clap::ArgMatches, name: &str) -> Result<(), Failure>
+{
+    if name == "list"
+    {
+        let width = BUILTIN_SCENES.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+        for (name, about) in BUILTIN_SCENES
+        {
+            println!("{:<w$}  {}", name, about, w = width);
+        }
+
+        return Ok(());
+    }
+
+    if !matches.is_present("resolution") && !matches.is_present("dump-scene")
+    {
+        return Err(Failure::Args("--builtin needs --resolution to render".to_owned()));
+    }
+
+    if !matches.is_present("output")
+        && !["dump-scene", "benchmark", "estimate"].iter().any(|arg| matches.is_present(arg))
+    {
+        return Err(Failure::Args("--builtin needs --output to render".to_owned()));
+    }
+
+    let control = std::sync::Arc::new(RenderControl::new());
+    catch_ctrl_c(control.clone());
+
+    let file = format!("builtin:{}", name);
+    let job = Job
+    {
+        builtin: true,
+        name: name.to_owned(),
+        .. Job::new(&file, String::new())
+    };
+
+    render_scene(matches, &job, &control, &mut None).map(|_| ())
+}
+
+/// One scene of a render, with what's needed to tell it apart from the
+/// others.
+struct Job<'a>
+{
+    /// the scene file, or builtin:NAME for `--builtin`
+    file: &'a str,
+    /// the file name without its extension, for `{scene}`
+    name: String,
+    /// put before the progress, empty for a single scene
+    label: String,
+    /// whether it's one of `BUILTIN_SCENES`, by name
+    builtin: bool,
+}
+
+impl<'a> Job<'a>
+{
+    fn new(file: &'a str, label: String) -> Job<'a>
+    {
+        let name = match file
+        {
+            "-" => "scene".to_owned(),
+            file => std::path::Path::new(file).file_stem()
+                .map_or("scene".to_owned(), |stem| stem.to_string_lossy().into_owned()),
+        };
+
+        Job
+        {
+            file: file,
+            name: name,
+            label: label,
+            builtin: false,
+        }
+    }
+
+    /// An output path for this scene: `{scene}` is replaced by its name, and
+    /// a directory gets `NAME.png` in it.
+    fn path(&self, path: &str) -> String
+    {
+        self.path_as(path, "png")
+    }
+
+    /// Like `path`, with a directory getting `NAME.extension`.
+    fn path_as(&self, path: &str, extension: &str) -> String
+    {
+        let dir = std::path::Path::new(path);
+
+        match dir.is_dir()
+        {
+            true => dir.join(format!("{}.{}", self.name, extension)).to_string_lossy().into_owned(),
+            false => path.replace("{scene}", &self.name),
+        }
+    }
+}
+
+/// Whether to keep a file, by its name.
+type Keep<'a> = Box<dyn Fn(&str) -> bool + 'a>;
+
+/// The scene files in `--scene`. Directories give the scenes in them and
+/// patterns with `*` or `?` in the file name the files they match, both in
+/// order by name. Anything else is kept as is, for loading to complain about.
+fn expand_scenes<'a>(values: impl Iterator<Item = &'a str>) -> Result<Vec<String>, String>
+{
+    use std::path::Path;
+
+    let mut files = Vec::new();
+
+    for value in values
+    {
+        let path = Path::new(value);
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let pattern = name.contains(['*', '?']);
+
+        let (dir, keep): (&Path, Keep) = if path.is_dir()
+        {
+            (path, Box::new(|file: &str| matches!(
+                Path::new(file).extension().map(|e| e.to_string_lossy().to_lowercase()).as_deref(),
+                Some("json") | Some("yaml") | Some("yml") | Some("toml"))))
+        }
+        else if pattern
+        {
+            let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            (dir, Box::new(|file: &str| glob_match(&name, file)))
+        }
+        else
+        {
+            files.push(value.to_owned());
+            continue;
+        };
+
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Could not read the directory \"{}\": {}", dir.display(), e))?;
+
+        let mut found = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter(|entry| keep(&entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        found.sort();
+
+        if found.is_empty()
+        {
+            return Err(format!("There are no scenes in \"{}\"", value));
+        }
+
+        files.extend(found);
+    }
+
+    Ok(files)
+}
+
+/// Whether `name` matches `pattern`, where `*` is any number of characters
+/// and `?` is one.
+fn glob_match(pattern: &str, name: &str) -> bool
+{
+    let (p, n) = (pattern.chars().collect::<Vec<_>>(), name.chars().collect::<Vec<_>>());
+
+    // the last `*`, and where in the name it's matched up to
+    let (mut i, mut j, mut star) = (0, 0, None);
+
+    while j < n.len()
+    {
+        match p.get(i)
+        {
+            Some('*') =>
+            {
+                star = Some((i, j));
+                i += 1;
+            },
+            Some(&c) if c == '?' || c == n[j] =>
+            {
+                i += 1;
+                j += 1;
+            },
+            _ => match star
+            {
+                Some((si, sj)) =>
+                {
+                    star = Some((si, sj + 1));
+                    i = si + 1;
+                    j = sj + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+
+    p[i..].iter().all(|&c| c == '*')
+}
+
+/// Renders one scene, returning how many samples its image got, or `None`
+/// when nothing was rendered, like for `--dump-scene`, `--benchmark` and
+/// `--estimate`. `--stats` is written however it ends.
+fn render_scene(
+    matches: &clap::ArgMatches,
+    job: &Job,
+    control: &std::sync::Arc<RenderControl>,
+    ctxs: &mut Option<Vec<GpuContext>>)
+    -> Result<Option<u32>, Failure>
+{
+    let mut stats = stats::Stats::new(job.file);
+    let result = run_scene(matches, job, control, ctxs, &mut stats);
+
+    let path = match matches.value_of("stats")
+    {
+        Some(path) => job.path_as(path, "json"),
+        None => return result,
+    };
+
+    // progressive renders are always stopped
+    let interrupted = control.stopping() && !matches.is_present("progressive");
+
+    match (stats.write(&path, &result, interrupted), result)
+    {
+        (Ok(()), result) => result,
+        (Err(e), Ok(_)) => Err(Failure::Io(e)),
+        // the render's failure matters more
+        (Err(e), Err(failure)) =>
+        {
+            pt_error!("{}", e);
+            Err(failure)
+        },
+    }
+}
+
+/// A stop condition, and the most samples it can go to.
+type Limit<'a> = (Box<dyn Fn(u32) -> bool + 'a>, Box<dyn Fn() -> u32 + 'a>);
+
+/// `render_scene`, filling in `stats` as it goes.
+fn run_scene(
+    matches: &clap::ArgMatches,
+    job: &Job,
+    control: &std::sync::Arc<RenderControl>,
+    ctxs: &mut Option<Vec<GpuContext>>,
+    stats: &mut stats::Stats)
+    -> Result<Option<u32>, Failure>
+{
+    let file = job.file;
+    let format = scene_format(file, matches);
+
+    let max_triangles = match matches.value_of("max-triangles").map(|n| n.trim().parse::<u64>())
+    {
+        _ if matches.is_present("no-triangle-limit") => None,
+        Some(Ok(n)) => Some(n),
+        Some(Err(_)) => return Err(Failure::Args("Could not parse the maximum number of triangles".to_owned())),
+        None => Some(DEFAULT_MAX_TRIANGLES),
+    };
+
+    let loaded = match job.builtin
+    {
+        true => Ok((builtin_scene(&job.name).unwrap(), Vec::new())),
+        false => load_scene(file, format, matches.value_of("scene-root"), max_triangles),
+    };
+
+    let mut scene = match loaded
+    {
+        Ok((s, warnings)) =>
+        {
+            for w in &warnings
+            {
+                pt_warn!("{}", w);
+            }
+
+            s
+        },
+        Err(e) =>
+        {
+            return Err(Failure::Scene(e));
+        }
+    };
+
+    if matches.is_present("fix-winding")
+    {
+        let report = scene.fix_winding();
+
+        pt_info!("Flipped {} of {} triangles, in {} connected pieces",
+            report.flipped, scene.triangles.len(), report.components);
+
+        if let Some(at) = report.example
+        {
+            pt_warn!("{} edges are shared by more than two triangles, one is at {:?}",
+                report.non_manifold, at);
+        }
+    }
+
+    let transform = match (matches.value_of("transform"), matches.value_of("transform-camera"))
+    {
+        (Some(t), _) => Some((t, false)),
+        (_, Some(t)) => Some((t, true)),
+        _ => None,
+    };
+
+    if let Some((text, cameras)) = transform
+    {
+        let t = parse_transform(text).map_err(Failure::Args)?;
+
+        if cameras && scene.animation.is_some()
+        {
+            pt_warn!("--transform-camera doesn't move the animation's camera");
+        }
+
+        scene.transform(&t, cameras);
+    }
+
+    if let Some(path) = matches.value_of("dump-scene")
+    {
+        if let Err(e) = std::fs::write(path, scene.to_json())
+        {
+            return Err(Failure::Io(format!("Could not write scene to \"{}\": {}", path, e)));
+        }
+
+        if !matches.is_present("output")
+        {
+            return Ok(None);
+        }
+    }
+
+    if !matches.is_present("all-cameras")
+    {
+        if let Err(e) = scene.select_camera(matches.value_of("camera"))
+        {
+            return Err(Failure::Scene(e));
+        }
+    }
+
+    let frames = match matches.value_of("frames")
+    {
+        Some(f) => match parse_frames(f)
+        {
+            Ok(f) => Some(f),
+            Err(e) => return Err(Failure::Args(e)),
+        },
+        None => None,
+    };
+
+    let turntable = match matches.value_of("turntable").map(|n| n.trim().parse::<u32>())
+    {
+        Some(Ok(n)) if n > 0 => Some(n),
+        Some(_) => return Err(Failure::Args(
+            "Could not parse turntable frames, a whole number above 0".to_owned())),
+        None => None,
+    };
+
+    // numbered files from either
+    let frames = frames.or(turntable.map(|n| 1..=n));
+
+    // every file the render would write, so none of them are overwritten
+    let files = |base: &str| match (&frames, matches.is_present("all-cameras"))
+    {
+        (Some(frames), _) => frames.clone().map(|f| frame_path(base, f)).collect(),
+        (None, true) => scene.cameras.iter()
+            .map(|(name, _)| with_suffix(base, &format!("_{}", name)))
+            .collect(),
+        (None, false) => vec![base.to_owned()],
+    };
+
+    let mut outputs = Vec::new();
+    for value in matches.values_of("output").into_iter().flatten()
+    {
+        for mut output in parse_outputs(value).map_err(Failure::Args)?
+        {
+            output.path = pick_output(
+                &job.path(&output.path),
+                &files,
+                matches.is_present("force"),
+                matches.is_present("auto-number"))
+                .map_err(Failure::Io)?;
+
+            outputs.push(output);
+        }
+    }
+    // snapshots and progress are saved next to the first
+    let output = outputs.first().map(|o| o.path.as_str());
+
+    let res = match parse_resolution(matches.value_of("resolution").unwrap())
+    {
+        Ok(res) => res,
+        Err(e) => return Err(Failure::Args(e)),
+    };
+
+    let overridden = override_camera(matches, &mut scene).map_err(Failure::Args)?;
+
+    // a scene's "auto" camera gives way to one placed on the command line
+    let placed = ["camera-pos", "camera-look-at", "camera-up"].iter().any(|&a| matches.is_present(a));
+
+    if matches.is_present("auto-camera") || (scene.auto_camera && !placed)
+    {
+        match scene.frame_camera(res[0] as f32 / res[1] as f32)
+        {
+            // in scene file form, to copy into the scene
+            Ok(c) => pt_info!(
+                "Camera: {{ \"pos\": {:?}, \"front\": {:?}, \"up\": {:?}, \"fov\": {} }}",
+                c.pos, c.front, c.up, c.fov.to_degrees()),
+            Err(e) => return Err(Failure::Scene(e)),
+        }
+    }
+
+    let orbit = match turntable
+    {
+        Some(n) => Some(parse_orbit(matches, &scene, n, res[0] as f32 / res[1] as f32)?),
+        None => None,
+    };
+
+    let (samples, def_samples) = match matches.value_of("max-samples")
+    {
+        Some(s) => match s.trim().parse::<u32>()
+        {
+            Ok(s) => (s, false),
+            Err(_) => return Err(Failure::Args("Could not parse maximum samples".to_owned())),
+        }
+        None => (100_000, true),
+    };
+
+    let time = match matches.value_of("time-limit")
+    {
+        Some(t) => Some(match parse_time(t)
+        {
+            Ok(t) => t,
+            Err(e) => return Err(Failure::Args(e)),
+        }),
+        None => None,
+    };
+
+    let target_noise = match matches.value_of("target-noise").map(|n| n.trim().parse::<f32>())
+    {
+        Some(Ok(n)) if n > 0.0 => Some(n),
+        Some(_) => return Err(Failure::Args("Could not parse the target noise, above 0".to_owned())),
+        None => None,
+    };
+
+    let stereo = match matches.value_of("stereo").map(|ipd| ipd.trim().parse::<f32>())
+    {
+        Some(Ok(ipd)) if ipd > 0.0 => Some(ipd),
+        Some(_) => return Err(Failure::Args(
+            "Could not parse the distance between the eyes, above 0".to_owned())),
+        None => None,
+    };
+
+    let supersample = match parse_supersample(matches.value_of("supersample").unwrap(), res)
+    {
+        Ok(factor) => factor,
+        Err(e) => return Err(Failure::Args(e)),
+    };
+
+    let converge = match matches.value_of("converge").map(|d| d.trim().parse::<f32>())
+    {
+        Some(Ok(d)) if d > 0.0 => Some(d),
+        Some(_) => return Err(Failure::Args(
+            "Could not parse the convergence distance, above 0".to_owned())),
+        None => None,
+    };
+
+    let checkpoint_every = match matches.value_of("checkpoint-every")
+    {
+        Some(n) => match n.trim().parse::<u32>()
+        {
+            Ok(n) if n > 0 => n,
+            _ => return Err(Failure::Args("Could not parse checkpoint interval".to_owned())),
+        },
+        None => 100,
+    };
+    let checkpoint = matches.value_of("checkpoint")
+        .map(|path| (path.to_owned(), checkpoint_every));
+
+    let groups = |name| matches.values_of(name)
+        .map(|v| v.map(|g| g.to_owned()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let (only, hide) = (groups("only"), groups("hide"));
+
+    // checkpoints are of the triangles that were rendered
+    let hash = match scene.filter_groups(&only, &hide)
+    {
+        Ok(visible) => visible.hash(),
+        Err(e) => return Err(Failure::Args(e)),
+    };
+
+    let resume = match matches.value_of("resume")
+    {
+        Some(path) => match Checkpoint::load(path)
+        {
+            // checkpoints are of the rendered pixels
+            Ok(c) => match c.check(res.map(|n| n * supersample), hash)
+            {
+                Ok(()) => Some(c),
+                Err(e) => return Err(Failure::Args(e)),
+            },
+            Err(e) => return Err(Failure::Io(e)),
+        },
+        None => None,
+    };
+
+    let snapshot = match matches.value_of("snapshot-every")
+    {
+        Some(every) => match Every::parse(every)
+        {
+            Ok(every) => output.map(|output| (partial_path(output), every)),
+            Err(e) => return Err(Failure::Args(e)),
+        },
+        None => None,
+    };
+
+    let denoise = if matches.is_present("denoise")
+    {
+        match matches.value_of("denoise").map(|s| s.trim().parse::<f32>())
+        {
+            None => Some(1.0),
+            Some(Ok(s)) if s > 0.0 => Some(s),
+            Some(_) => return Err(Failure::Args("Could not parse denoise strength".to_owned())),
+        }
+    }
+    else
+    {
+        None
+    };
+
+    let mode = match matches.value_of("mode").unwrap()
+    {
+        "ao" =>
+        {
+            let rays = match matches.value_of("ao-rays").unwrap().trim().parse::<u32>()
+            {
+                Ok(rays) if rays > 0 => rays,
+                _ => return Err(Failure::Args("Could not parse ao rays".to_owned())),
+            };
+
+            let distance = match matches.value_of("ao-distance").map(|d| d.trim().parse::<f32>())
+            {
+                Some(Ok(d)) if d > 0.0 => Some(d),
+                Some(_) => return Err(Failure::Args("Could not parse ao distance".to_owned())),
+                None => None,
+            };
+
+            RenderMode::AmbientOcclusion { rays: rays, distance: distance }
+        },
+        "normals" => RenderMode::Normals,
+        "depth" => RenderMode::Depth,
+        "matid" => RenderMode::MaterialId,
+        "wireframe" => RenderMode::Wireframe,
+        _ => RenderMode::PathTrace,
+    };
+
+    // the debug modes don't have noise, so one sample does, jittered like
+    // any other
+    let samples = if def_samples && mode.is_debug() { 1 } else { samples };
+
+    let exposure = match matches.value_of("exposure").map(|s| s.trim().parse::<f32>())
+    {
+        Some(Ok(stops)) if stops.is_finite() => Some(stops),
+        Some(_) => return Err(Failure::Args("Could not parse exposure".to_owned())),
+        None => None,
+    };
+
+    let white_balance = match parse_white_balance(matches, res)
+    {
+        Ok(wb) => wb,
+        Err(e) => return Err(Failure::Args(e)),
+    };
+
+    let tile = match matches.value_of("tile")
+    {
+        Some(t) => match parse_tile(t)
+        {
+            Ok(t) => Some(t),
+            Err(e) => return Err(Failure::Args(e)),
+        },
+        None => None,
+    };
+
+    let max_dispatch = match matches.value_of("max-dispatch-ms").unwrap().trim().parse::<u64>()
+    {
+        Ok(0) => None,
+        Ok(ms) => Some(std::time::Duration::from_millis(ms)),
+        Err(_) => return Err(Failure::Args("Could not parse max dispatch time".to_owned())),
+    };
+
+    let ray_epsilon = match matches.value_of("ray-epsilon").unwrap().trim().parse::<f32>()
+    {
+        Ok(e) if e > 0.0 && e.is_finite() => e,
+        _ => return Err(Failure::Args("Ray epsilon must be a positive number".to_owned())),
+    };
+
+    let region = match matches.value_of("region")
+    {
+        Some(r) => match parse_region(r, res)
+        {
+            Ok(r) => Some(r),
+            Err(e) => return Err(Failure::Args(e)),
+        },
+        None => None,
+    };
+
+    let p = matches.is_present("progressive");
+
+    let progress_interval = match matches.value_of("progress-interval").unwrap().trim().parse::<f32>()
+    {
+        Ok(_) if !log::enabled(Level::Info) => None,
+        Ok(secs) if secs > 0.0 => Some(std::time::Duration::from_secs_f32(secs)),
+        Ok(_) => None,
+        Err(_) => return Err(Failure::Args("Could not parse progress interval".to_owned())),
+    };
+
+    let overlay = match parse_overlay(matches)
+    {
+        Ok(overlay) => overlay,
+        Err(e) => return Err(Failure::Args(e)),
+    };
+
+    let depth_range = match parse_depth_range(matches)
+    {
+        Ok(range) => range,
+        Err(e) => return Err(Failure::Args(e)),
+    };
+
+    let seed = match matches.value_of("seed").map(|s| s.trim().parse::<u64>())
+    {
+        Some(Ok(seed)) => seed,
+        Some(Err(_)) => return Err(Failure::Args("Could not parse seed".to_owned())),
+        None => rand::random(),
+    };
+    pt_info!("Seed: {}", seed);
+
+    stats.hash = Some(scene.hash());
+    stats.res = Some(res);
+    stats.requested_samples = Some(samples);
+    stats.seed = Some(seed);
+
+    // the limits are for both eyes
+    let (eye_samples, eye_time) = match stereo
+    {
+        Some(_) => (samples.div_ceil(2), time.map(|t| t / 2)),
+        None => (samples, time),
+    };
+
+    let settings = RenderSettings
+    {
+        mode: mode,
+        region: region,
+        crop: matches.is_present("crop"),
+        supersample: supersample,
+        samples: eye_samples,
+        time_limit: eye_time,
+        tile: tile,
+        max_dispatch: max_dispatch,
+        profile_gpu: matches.is_present("profile-gpu"),
+        ray_epsilon: ray_epsilon,
+        debug: matches.is_present("debug"),
+        annotate: matches.value_of("annotate").map(|a| a.to_owned()),
+        overlay: overlay,
+        seed: Some(seed),
+        only: only,
+        hide: hide,
+        checkpoint: checkpoint,
+        snapshot: snapshot,
+        denoise: denoise,
+        exposure: exposure,
+        white_balance: white_balance,
+        noise: matches.is_present("heatmap"),
+        depth_map: matches.is_present("depth-map"),
+        adapter: matches.value_of("adapter").map(|a| a.to_owned()),
+        require_discrete: matches.is_present("require-discrete"),
+        cpu: matches.is_present("cpu"),
+        shader: matches.value_of("shader").map(|s| s.to_owned()),
+        control: Some(control.clone()),
+        .. RenderSettings::new(res)
+    };
+
+    stats.depth = Some(settings.depth);
+    stats.budget = scene.budget(&settings).ok();
+
+    let ctxs = open_contexts(matches, &settings, ctxs)?;
+    let ctx = &ctxs[0];
+    let adapter = ctx.info();
+    stats.adapter = Some((adapter.name.clone(), format!("{:?}", adapter.backend)));
+
+    if matches.is_present("benchmark")
+    {
+        benchmark(&scene, ctx, &settings, matches, file, &outputs)?;
+        return Ok(None);
+    }
+
+    if matches.is_present("estimate")
+    {
+        return estimate(&scene, ctx, &settings, time).map(|_| None);
+    }
+
+    // only optional with --benchmark and --estimate
+    let output = output.unwrap();
+    let heatmap = matches.value_of("heatmap").map(|path| job.path(path));
+    let heatmap = heatmap.as_deref();
+    let depth_map = matches.value_of("depth-map").map(|path| job.path(path));
+    let depth_map = depth_map.as_deref();
+
+    print_intro(
+        res, samples, def_samples, time.zip(matches.value_of("time-limit")), p, adapter,
+        Some(&scene.camera).filter(|_| overridden));
+
+    if matches.value_of("adapter").is_none() && !settings.cpu
+    {
+        match adapter.device_type
+        {
+            wgpu::DeviceType::DiscreteGpu => (),
+            wgpu::DeviceType::IntegratedGpu => pt_warn!(
+                "No discrete GPU found, using an integrated GPU \
+                 which may be slow"),
+            _ => pt_warn!(
+                "No hardware GPU found, using a virtual or software \
+                 adapter which will be very slow"),
+        }
+    }
+
+    if let Some(resume) = &resume
+    {
+        pt_info!("Resuming from {} samples", resume.samples);
+    }
+
+    if matches.is_present("preview-pass")
+        && !preview_pass(matches, &Preview
+        {
+            scene: &scene,
+            ctx: ctx,
+            settings: &settings,
+            file: file,
+            adapter: &adapter.name,
+            output: &outputs[0],
+        }, control, stats)?
+    {
+        return Ok(None);
+    }
+
+    if stereo.is_some()
+    {
+        pt_info!("Rendering each eye at {}x{} with up to {} samples{}",
+            res[0], res[1], eye_samples,
+            eye_time.map_or(String::new(), |t| format!(" for up to {}", fmt_time(t))));
+    }
+
+    let make_condition = || -> Box<dyn Fn(u32) -> bool>
+    {
+        let (condition, max): Limit =
+            match p
+            {
+                true =>
+                {
+                    let (condition, max) =
+                        progressive(eye_samples, eye_time, control.clone(), partial_path(output));
+                    (Box::new(condition), Box::new(max))
+                },
+                false => (Box::new(settings.condition()), Box::new(move || eye_samples)),
+            };
+
+        match progress_interval
+        {
+            Some(interval) => Box::new(
+                with_progress(condition, max, eye_time, interval, job.label.clone())),
+            None => condition,
+        }
+    };
+
+    if matches.is_present("all-cameras")
+    {
+        let names = scene.cameras.iter()
+            .map(|(n, _)| n.clone())
+            .collect::<Vec<_>>();
+        let mut failed = 0;
+        let mut samples = None;
+
+        for name in names
+        {
+            if control.stopping()
+            {
+                pt_warn!("Interrupted, so the cameras from \"{}\" on aren't rendered", name);
+                break;
+            }
+
+            scene.select_camera(Some(&name)).unwrap();
+
+            pt_info!("Rendering camera \"{}\"", name);
+
+            let limit = make_condition();
+            let target = target_noise.map(|t| NoiseTarget::new(t, &limit));
+            let condition: &dyn StopCondition = match &target
+            {
+                Some(target) => target,
+                None => &limit,
+            };
+
+            let image = match scene.render_with(ctx, &settings, condition, None)
+            {
+                Ok(image) => image,
+                Err(e) => return Err(Failure::from(e)),
+            };
+            if let Some(target) = &target
+            {
+                report_noise(target);
+                stats.noise = Some((target.target(), target.measured()));
+            }
+            samples = Some(image.samples);
+            stats.samples = samples;
+            stats.render_time = Some(image.time);
+            stats.profile = image.profile.clone();
+
+            let meta = metadata(file, &scene, &settings, &adapter.name, &image);
+
+            let saved = save_outputs(
+                &image, &outputs, &|path| with_suffix(path, &format!("_{}", name)), &meta,
+                &|path|
+                {
+                    stats.saved(path);
+                    pt_info!("Saved camera \"{}\" to {}", name, path);
+                })
+                .and_then(|_| match heatmap
+                {
+                    Some(heatmap) =>
+                    {
+                        let path = with_suffix(heatmap, &format!("_{}", name));
+                        save_heatmap(&image, &path).map(|_| stats.saved(&path))
+                    },
+                    None => Ok(()),
+                })
+                .and_then(|_| match depth_map
+                {
+                    Some(depth_map) =>
+                    {
+                        let path = with_suffix(depth_map, &format!("_{}", name));
+                        save_depth_map(&image, &path, &scene, &scene.camera, depth_range)
+                            .map(|_| stats.saved(&path))
+                    },
+                    None => Ok(()),
+                });
+
+            // the other cameras are still worth rendering
+            if let Err(e) = saved
+            {
+                pt_error!("{}", e);
+                failed += 1;
+            }
+        }
+
+        return match failed
+        {
+            0 => Ok(samples),
+            n => Err(Failure::Io(format!("{} of the cameras couldn't be saved", n))),
+        };
+    }
+
+    let limit = make_condition();
+    let target = target_noise.map(|t| NoiseTarget::new(t, &limit));
+    let condition: &dyn StopCondition = match &target
+    {
+        Some(target) => target,
+        None => &limit,
+    };
+
+    if let Some(frames) = frames
+    {
+        match (&orbit, &scene.animation)
+        {
+            (Some(orbit), _) => pt_info!(
+                "Rendering {} frames circling {:?}, {} away and {} above",
+                orbit.frames, orbit.center, orbit.radius, orbit.height),
+            (None, Some(anim)) => pt_info!("Rendering frames {}..{} of {} ({} fps)",
+                frames.start(), frames.end(), anim.frames, anim.fps),
+            (None, None) => (),
+        }
+
+        let last = *frames.end();
+        let failed = std::cell::Cell::new(0);
+        let samples = std::cell::Cell::new(None);
+        let time = std::cell::Cell::new(None);
+        let frame_stats = &*stats;
+        let mut on_frame = |frame, image: Framebuffer|
+            {
+                samples.set(Some(image.samples));
+                time.set(Some(image.time));
+                let meta = metadata(file, &scene, &settings, &adapter.name, &image);
+
+                let saved = save_outputs(
+                    &image, &outputs, &|path| frame_path(path, frame), &meta,
+                    &|path|
+                    {
+                        frame_stats.saved(path);
+                        pt_info!("Saved frame {}/{} to {}", frame, last, path);
+                    })
+                    .and_then(|_| match heatmap
+                    {
+                        Some(heatmap) =>
+                        {
+                            let path = frame_path(heatmap, frame);
+                            save_heatmap(&image, &path).map(|_| frame_stats.saved(&path))
+                        },
+                        None => Ok(()),
+                    })
+                    .and_then(|_|
+                    {
+                        let camera = match (&orbit, &scene.animation)
+                        {
+                            (Some(orbit), _) => orbit.camera_at_frame(frame),
+                            (None, Some(anim)) => anim.camera_at_frame(frame),
+                            (None, None) => return Ok(()),
+                        };
+
+                        match depth_map
+                        {
+                            Some(depth_map) =>
+                            {
+                                let path = frame_path(depth_map, frame);
+                                save_depth_map(&image, &path, &scene, &camera, depth_range)
+                                    .map(|_| frame_stats.saved(&path))
+                            },
+                            None => Ok(()),
+                        }
+                    });
+
+                // the other frames are still worth rendering
+                if let Err(e) = saved
+                {
+                    pt_error!("{}", e);
+                    failed.set(failed.get() + 1);
+                }
+            };
+
+        let result = match &orbit
+        {
+            Some(orbit) => scene.render_turntable(
+                ctx, orbit, &settings, condition, &mut on_frame),
+            None => scene.render_frames(
+                ctx, frames, &settings, condition, &mut on_frame),
+        };
+
+        stats.samples = samples.get();
+        stats.render_time = time.get();
+
+        if let Err(e) = result
+        {
+            return Err(Failure::from(e));
+        }
+
+        if let Some(target) = &target
+        {
+            report_noise(target);
+            stats.noise = Some((target.target(), target.measured()));
+        }
+
+        return match failed.get()
+        {
+            0 => Ok(samples.get()),
+            n => Err(Failure::Io(format!("{} of the frames couldn't be saved", n))),
+        };
+    }
+
+    // progressive renders start again when the shader is edited
+    let reload = std::cell::RefCell::new(None);
+    let watched;
+    let condition: &dyn StopCondition = match (p, &settings.shader)
+    {
+        (true, Some(shader)) =>
+        {
+            watched = watch_shader(condition, shader, &reload);
+            &watched
+        },
+        _ => condition,
+    };
+
+    let mut resume = resume;
+    let result = loop
+    {
+        let result = match ctxs.len()
+        {
+            1 => match stereo
+            {
+                Some(ipd) => scene.render_stereo(ctx, ipd, converge, &settings, condition),
+                None => scene.render_with(ctx, &settings, condition, resume.take()),
+            },
+            _ => scene.render_multi(ctxs, &settings, condition),
+        };
+
+        let source = match reload.borrow_mut().take()
+        {
+            Some(source) => source,
+            None => break result,
+        };
+
+        for ctx in ctxs
+        {
+            if let Err(e) = ctx.use_shader(&source)
+            {
+                pt_error!("{}", e);
+            }
+        }
+
+        if let Some(target) = &target
+        {
+            target.reset();
+        }
+    };
+
+    if let Some(target) = &target
+    {
+        report_noise(target);
+        stats.noise = Some((target.target(), target.measured()));
+    }
+
+    let image = match result
+    {
+        Ok(image) => image,
+        Err(e) =>
+        {
+            if let GpuError::DeviceLost { samples, .. } = &e
+            {
+                stats.samples = Some(*samples);
+            }
+
+            if let (GpuError::DeviceLost { .. }, Some((path, _)))
+                = (&e, &settings.checkpoint)
+            {
+                pt_info!("Progress was saved to {}, continue with --resume", path);
+            }
+
+            return Err(Failure::from(e));
+        },
+    };
+
+    stats.samples = Some(image.samples);
+    stats.render_time = Some(image.time);
+    stats.profile = image.profile.clone();
+
+    // progressive renders are always stopped
+    if control.stopping() && !p
+    {
+        pt_info!("The render was interrupted at {} samples", image.samples);
+    }
+
+    let meta = metadata(file, &scene, &settings, &adapter.name, &image);
+
+    let saved = |path: &str|
+    {
+        stats.saved(path);
+        pt_info!("Saved to {}", path);
+    };
+
+    save_outputs(&image, &outputs, &|path| path.to_owned(), &meta, &saved)
+        .map_err(Failure::Io)?;
+
+    if let Some(heatmap) = heatmap
+    {
+        save_heatmap(&image, heatmap).map_err(Failure::Io)?;
+        stats.saved(heatmap);
+    }
+
+    if let Some(depth_map) = depth_map
+    {
+        save_depth_map(&image, depth_map, &scene, &scene.camera, depth_range)
+            .map_err(Failure::Io)?;
+        stats.saved(depth_map);
+    }
+
+    Ok(Some(image.samples))
+}
+
+/// The GPUs to render on, opened by the first scene and then shared by the
+/// rest, more than one of them only with `--adapters`.
+fn open_contexts<'a>(
+    matches: &clap::ArgMatches,
+    settings: &RenderSettings,
+    ctxs: &'a mut Option<Vec<GpuContext>>)
+    -> Result<&'a [GpuContext], Failure>
+{
+    if let Some(ctxs) = ctxs
+    {
+        return Ok(ctxs.as_slice());
+    }
+
+    let choices = match matches.value_of("adapters")
+    {
+        Some(list) => list.split(',').map(|a| Some(a.trim())).collect(),
+        None => vec![settings.adapter.as_deref()],
+    };
+    let opened = match choices.into_iter()
+        .map(|choice| match settings.cpu
+        {
+            true => Ok(GpuContext::cpu()),
+            false => GpuContext::new(choice, settings.require_discrete),
+        })
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(opened) => opened,
+        Err(e) => return Err(Failure::from(e)),
+    };
+
+    if let Some(shader) = &settings.shader
+    {
+        let source = match CheckedShader::load(shader)
+        {
+            Ok(source) => source,
+            Err(e) => return Err(Failure::from(e)),
+        };
+
+        for ctx in &opened
+        {
+            if let Err(e) = ctx.use_shader(&source)
+            {
+                return Err(Failure::from(e));
+            }
+        }
+
+        pt_info!("Using the shader from {}", shader);
+    }
+
+    if opened.len() > 1
+    {
+        pt_info!("Rendering on {}",
+            opened.iter().map(|c| c.info().name.as_str()).collect::<Vec<_>>().join(", "));
+    }
+
+    Ok(ctxs.insert(opened).as_slice())
+}
+
+/// Makes the first Ctrl-C stop the render through `control` after the
+/// sample it's on, so it's saved as normal, and the second quit at once.
+fn catch_ctrl_c(control: std::sync::Arc<RenderControl>)
+{
+    let caught = ctrlc::set_handler(move ||
+    {
+        if control.stopping()
+        {
+            pt_error!("Quitting without saving");
+            std::process::exit(130);
+        }
+
+        control.stop();
+        pt_warn!("Stopping after this sample, press Ctrl-C again to quit without saving");
+    });
+
+    if let Err(e) = caught
+    {
+        pt_warn!("Could not catch Ctrl-C, so it will quit without saving: {}", e);
+    }
+}
+
+/// Wraps `condition` to also stop when the shader at `path` changes to one
+/// that compiles, leaving it in `reload`. Broken edits are reported
+/// and rendering carries on with the old shader.
+fn watch_shader<'a>(
+    condition: &'a dyn StopCondition,
+    path: &'a str,
+    reload: &'a std::cell::RefCell<Option<CheckedShader>>)
+    -> impl Fn(u32) -> bool + 'a
+{
+    let modified = |path: &str| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let last = std::cell::Cell::new(modified(path));
+
+    move |samples|
+    {
+        let now = modified(path);
+
+        if now != last.get()
+        {
+            last.set(now);
+
+            match CheckedShader::load(path)
+            {
+                Ok(source) =>
+                {
+                    pt_info!("{} changed, restarting the render", path);
+                    *reload.borrow_mut() = Some(source);
+
+                    return false;
+                },
+                Err(e) => pt_error!("Still using the last shader. {}", e),
+            }
+        }
+
+        condition.go_on(samples)
+    }
+}
+
+/// Says whether a render reached its `--target-noise`.
+fn report_noise(target: &NoiseTarget)
+{
+    match target.measured()
+    {
+        Some((samples, noise)) if target.reached() => pt_info!(
+            "The noise reached {:.4} after {} samples, within the target of {}",
+            noise, samples, target.target()),
+        Some((samples, noise)) => pt_info!(
+            "The noise was {:.4} after {} samples, above the target of {}",
+            noise, samples, target.target()),
+        None => pt_warn!(
+            "The noise was never measured, as it needs at least {} samples of something \
+             brighter than black",
+            NoiseTarget::EVERY),
+    }
+}
+
+/// Writes the depth map, with the parts of `range` that weren't given
+/// worked out from how far the scene is from `camera`.
+fn save_depth_map(
+    image: &Framebuffer,
+    path: &str,
+    scene: &Scene,
+    camera: &Camera,
+    range: [Option<f32>; 2])
+    -> Result<(), String>
+{
+    let auto = scene.depth_range(camera).unwrap_or([0.0, 1.0]);
+    let near = range[0].unwrap_or(auto[0]);
+    let far = range[1].unwrap_or(auto[1]).max(near);
+
+    image.save_depth(path, [near, far])?;
+    pt_info!("Saved depth map to {}", path);
+
+    Ok(())
+}
+
+/// Writes the noise heatmap, if the render measured its noise.
+fn save_heatmap(image: &Framebuffer, path: &str) -> Result<(), String>
+{
+    match image.to_heatmap().map(|heat| heat.save(path))
+    {
+        Some(Ok(_)) => pt_info!("Saved noise heatmap to {}", path),
+        Some(Err(e)) => return Err(format!("Could not save \"{}\": {}", path, e)),
+        None => (),
+    }
+
+    Ok(())
+}
+
+/// Runs the `merge` subcommand.
+fn merge(matches: &clap::ArgMatches) -> Result<(), Failure>
+{
+    let exposure = match matches.value_of("exposure").map(|s| s.trim().parse::<f32>())
+    {
+        Some(Ok(stops)) if stops.is_finite() => stops,
+        Some(_) => return Err(Failure::Args("Could not parse exposure".to_owned())),
+        None => 0.0,
+    };
+
+    let checkpoints = matches.values_of("checkpoints").unwrap()
+        .map(|path| Checkpoint::load(path).map(|c| (path, c)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Failure::Io)?;
+
+    for (path, c) in &checkpoints
+    {
+        if c.seeds.is_empty()
+        {
+            pt_warn!("\"{}\" is from an older version that didn't save seeds, so it can't be \
+                   checked for samples that are in another checkpoint too", path);
+        }
+    }
+
+    let merged = Checkpoint::merge(&checkpoints).map_err(Failure::Args)?;
+
+    pt_info!("Merged {} checkpoints into {} samples", checkpoints.len(), merged.samples);
+
+    if let Some(path) = matches.value_of("checkpoint")
+    {
+        merged.save(path).map_err(Failure::Io)?;
+        pt_info!("Saved the checkpoint to {}", path);
+    }
+
+    if let Some(output) = matches.value_of("output")
+    {
+        let meta = [
+            ("Software", format!("path-tracer-gpu {}", env!("CARGO_PKG_VERSION"))),
+            ("Scene hash", format!("{:016x}", merged.scene_hash)),
+            ("Resolution", format!("{}x{}", merged.width, merged.height)),
+            ("Samples", merged.samples.to_string()),
+        ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect::<Vec<_>>();
+
+        let image = Framebuffer::from_checkpoint(&merged, exposure);
+        save_image(&image.to_image(), output, &meta).map_err(Failure::Io)?;
+        pt_info!("Saved to {}", output);
+    }
+
+    Ok(())
+}
+
+/// Runs the `diff` subcommand, returning the exit code.
+fn diff(matches: &clap::ArgMatches) -> u8
+{
+    let number = |name| match matches.value_of(name).map(|s| s.trim().parse::<f64>())
+    {
+        Some(Ok(x)) if x >= 0.0 => Ok(Some(x)),
+        Some(_) => Err(format!("Could not parse {}", name)),
+        None => Ok(None),
+    };
+
+    let (threshold, fail_above) = match (number("threshold"), number("fail-above"))
+    {
+        (Ok(t), Ok(f)) => (t.unwrap(), f),
+        (Err(e), _) | (_, Err(e)) =>
+        {
+            pt_error!("{}", e);
+            return 2;
+        },
+    };
+
+    let open = |name| -> Result<image::RgbImage, String>
+    {
+        let path = matches.value_of(name).unwrap();
+
+        image::open(path)
+            .map(|i| i.to_rgb8())
+            .map_err(|e| format!("Could not open \"{}\": {}", path, e))
+    };
+
+    let (a, b) = match (open("a"), open("b"))
+    {
+        (Ok(a), Ok(b)) => (a, b),
+        (Err(e), _) | (_, Err(e)) =>
+        {
+            pt_error!("{}", e);
+            return 2;
+        },
+    };
+
+    let diff = match diff_images(&a, &b, threshold)
+    {
+        Ok(diff) => diff,
+        Err(e) =>
+        {
+            pt_error!("{}", e);
+            return 2;
+        },
+    };
+
+    pt_info!("RMSE: red {:.6}, green {:.6}, blue {:.6}, luminance {:.6}",
+        diff.rmse[0], diff.rmse[1], diff.rmse[2], diff.luminance_rmse);
+    pt_info!("Max error: {:.6}", diff.max_error);
+    pt_info!("{} of {} pixels ({:.2}%) differ by more than {}",
+        diff.over_threshold, diff.pixels,
+        100.0 * diff.over_threshold as f64 / diff.pixels.max(1) as f64,
+        threshold);
+
+    if let Some(out) = matches.value_of("out")
+    {
+        if let Err(e) = diff_heatmap(&a, &b).save(out)
+        {
+            pt_error!("Could not save \"{}\": {}", out, e);
+            return 2;
+        }
+    }
+
+    match fail_above
+    {
+        Some(max) if diff.luminance_rmse > max =>
+        {
+            pt_warn!("Luminance RMSE {:.6} is above {}", diff.luminance_rmse, max);
+            1
+        },
+        _ => 0,
+    }
+}
+
+/// Runs `--benchmark` and prints the results, saving the image if there are
+/// output files.
+fn benchmark(
+    scene: &Scene,
+    ctx: &GpuContext,
+    settings: &RenderSettings,
+    matches: &clap::ArgMatches,
+    file: &str,
+    outputs: &[Output])
+    -> Result<(), Failure>
+{
+    let time = |name| parse_time(matches.value_of(name).unwrap()).map_err(Failure::Args);
+    let (warmup, measure) = (time("benchmark-warmup")?, time("benchmark-time")?);
+
+    pt_info!("Benchmarking {}x{} on {} for {} after a {} warm-up",
+        settings.res[0], settings.res[1],
+        ctx.info().name,
+        fmt_duration(measure),
+        fmt_duration(warmup));
+
+    let (image, bench) = scene.benchmark(ctx, settings, warmup, measure)
+        .map_err(Failure::Render)?;
+
+    pt_info!("Setup took {:.2}ms, then {} warm-up samples",
+        bench.setup.as_secs_f64() * 1000.0, bench.warmup_samples);
+    pt_info!("{} samples in {:.2}s: {:.2} samples/s, {:.3e} rays/s at most",
+        bench.samples,
+        bench.measured.as_secs_f64(),
+        bench.samples_per_sec,
+        bench.max_rays_per_sec);
+    pt_info!("{:.2}ms per sample, standard deviation {:.2}ms",
+        bench.ms_per_sample, bench.ms_std_dev);
+
+    if matches.is_present("benchmark-json")
+    {
+        println!("{}", bench.to_json());
+    }
+
+    if !outputs.is_empty()
+    {
+        let meta = metadata(file, scene, settings, &ctx.info().name, &image);
+
+        save_outputs(&image, outputs, &|path| path.to_owned(), &meta, &|path| pt_info!("Saved to {}", path))
+            .map_err(Failure::Io)?;
+    }
+
+    Ok(())
+}
+
+/// Runs `--estimate` and prints how long the render should take, or how many
+/// samples should fit in `time`.
+fn estimate(
+    scene: &Scene,
+    ctx: &GpuContext,
+    settings: &RenderSettings,
+    time: Option<std::time::Duration>)
+    -> Result<(), Failure>
+{
+    let estimate = scene.estimate(ctx, settings).map_err(Failure::Render)?;
+
+    pt_info!("Probe: {} samples at {}x{} in {:.2}s, {:.2} samples/s",
+        estimate.probe_samples,
+        estimate.probe_res[0], estimate.probe_res[1],
+        estimate.probe_time.as_secs_f64(),
+        estimate.probe_samples_per_sec);
+    pt_info!("Estimated {:.2} samples/s at {}x{}, so {} samples should take {}",
+        estimate.samples_per_sec,
+        estimate.res[0], estimate.res[1],
+        settings.samples,
+        fmt_duration(estimate.time_for(settings.samples)));
+
+    if let Some(time) = time
+    {
+        let fit = estimate.samples_in(time);
+
+        pt_info!("About {} samples should fit in the {} time limit{}",
+            fit,
+            fmt_duration(time),
+            if fit > settings.samples { ", more than the maximum" } else { "" });
+    }
+
+    Ok(())
+}
+
+/// The full render a `preview_pass` is a quick look at.
+struct Preview<'a>
+{
+    scene: &'a Scene,
+    ctx: &'a GpuContext,
+    settings: &'a RenderSettings,
+    /// the scene file, for the metadata
+    file: &'a str,
+    adapter: &'a str,
+    /// the first output, which the preview is saved next to
+    output: &'a Output,
+}
+
+/// Renders a quick look at the whole frame, at a quarter of the width and
+/// height with `PREVIEW_SAMPLES`, and saves it as a PNG next to `output`
+/// with its exposure. Everything else, like the camera, white balance and
+/// denoising, is the same as the full render. Returns whether to go on to
+/// the full render, which is asked when there's a terminal to ask in.
+fn preview_pass(
+    matches: &clap::ArgMatches,
+    full: &Preview,
+    control: &RenderControl,
+    stats: &stats::Stats)
+    -> Result<bool, Failure>
+{
+    use std::io::{IsTerminal, Write};
+
+    const PREVIEW_SAMPLES: u32 = 16;
+    // how long the prompt waits before going on by itself
+    const WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+
+    let Preview { scene, ctx, settings, file, adapter, output } = *full;
+
+    let preview = RenderSettings
+    {
+        res: settings.res.map(|n| (n / 4).max(1)),
+        region: None,
+        crop: false,
+        supersample: 1,
+        tile: None,
+        samples: PREVIEW_SAMPLES,
+        time_limit: None,
+        checkpoint: None,
+        snapshot: None,
+        noise: false,
+        depth_map: false,
+        profile_gpu: false,
+        .. settings.clone()
+    };
+
+    pt_info!("Rendering a {}x{} preview with {} samples", preview.res[0], preview.res[1], PREVIEW_SAMPLES);
+
+    let image = scene.render_with(ctx, &preview, &preview.condition(), None)?;
+    let image = match output.exposure
+    {
+        Some(exposure) => image.with_exposure(exposure),
+        None => image,
+    };
+
+    let path = with_suffix(
+        &std::path::Path::new(&output.path).with_extension("png").to_string_lossy(),
+        ".preview");
+    image.save(&path, &metadata(file, scene, &preview, adapter, &image)).map_err(Failure::Io)?;
+    stats.saved(&path);
+    pt_info!("Saved the preview to {}", path);
+
+    if matches.is_present("preview-only")
+    {
+        return Ok(false);
+    }
+
+    // scripts and pipes get the full render without asking
+    let go_on = match std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+    {
+        true =>
+        {
+            eprint!("Continue with the full render? [Y/n] (yes in {}s) ", WAIT.as_secs());
+            let _ = std::io::stderr().flush();
+
+            let (send, answer) = std::sync::mpsc::channel();
+            std::thread::spawn(move ||
+            {
+                let mut line = String::new();
+                let _ = std::io::stdin().read_line(&mut line);
+                let _ = send.send(line);
+            });
+
+            await_answer(&answer, control, WAIT)
+        },
+        false => true,
+    };
+
+    if control.stopping()
+    {
+        pt_warn!("Interrupted, so the full render won't start");
+        return Ok(false);
+    }
+
+    if !go_on
+    {
+        pt_info!("Stopping after the preview");
+    }
+
+    Ok(go_on)
+}
+
+/// Waits up to `wait` for a line of `answer` to a yes or no question, which
+/// is yes unless it's "n" or "no". Running out of time is yes too, but a
+/// Ctrl-C through `control` is no, straight away.
+fn await_answer(
+    answer: &std::sync::mpsc::Receiver<String>,
+    control: &RenderControl,
+    wait: std::time::Duration)
+    -> bool
+{
+    use std::sync::mpsc::RecvTimeoutError;
+
+    // how often it looks for a Ctrl-C
+    const POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+    let asked = std::time::Instant::now();
+    loop
+    {
+        match answer.recv_timeout(POLL.min(wait))
+        {
+            Ok(line) => return !matches!(line.trim().to_lowercase().as_str(), "n" | "no"),
+            Err(RecvTimeoutError::Timeout) if !control.stopping() && asked.elapsed() < wait => (),
+            Err(_) =>
+            {
+                eprintln!();
+                return !control.stopping();
+            },
+        }
+    }
+}
+
+/// What's written into saved images, to find out later how they were made.
+fn metadata(
+    file: &str,
+    scene: &Scene,
+    settings: &RenderSettings,
+    adapter: &str,
+    image: &Framebuffer)
+    -> Vec<(String, String)>
+{
+    let mut meta = vec![
+        ("Software", format!("path-tracer-gpu {}", env!("CARGO_PKG_VERSION"))),
+        ("Scene", file.to_owned()),
+        ("Scene hash", format!("{:016x}", scene.hash())),
+        ("Resolution", format!("{}x{}", image.width, image.height)),
+        ("Samples", image.samples.to_string()),
+        ("Depth", settings.depth.to_string()),
+        ("Render time", format!("{:.2}s", image.time.as_secs_f64())),
+        ("Adapter", adapter.to_owned()),
+    ];
+
+    if let Some(seed) = settings.seed
+    {
+        meta.insert(6, ("Seed", seed.to_string()));
+    }
+
+    meta.into_iter()
+        .map(|(k, v)| (k.to_owned(), v))
+        .collect()
+}
+
+/// Listed at the end of `--help`.
+const EXIT_CODES: &str = "EXIT CODES:
+    0    Success
+    2    The arguments are invalid
+    3    The scene couldn't be loaded, or has problems
+    4    The GPU or the render failed
+    5    A file couldn't be read or written
+    The diff subcommand exits with 1 when the images differ by too much, and 2 on errors.";
+
+/// Why the tool failed, which decides its exit code, see `EXIT_CODES`.
+#[derive(Debug)]
+enum Failure
+{
+    Args(String),
+    Scene(String),
+    Render(String),
+    Io(String),
+}
+
+impl Failure
+{
+    const ARGS: u8 = 2;
+    const SCENE: u8 = 3;
+    const RENDER: u8 = 4;
+    const IO: u8 = 5;
+
+    fn code(&self) -> u8
+    {
+        match self
+        {
+            Failure::Args(_) => Failure::ARGS,
+            Failure::Scene(_) => Failure::SCENE,
+            Failure::Render(_) => Failure::RENDER,
+            Failure::Io(_) => Failure::IO,
+        }
+    }
+
+    /// The same kind of failure, and so exit code, with another message.
+    fn retold(self, message: String) -> Failure
+    {
+        match self
+        {
+            Failure::Args(_) => Failure::Args(message),
+            Failure::Scene(_) => Failure::Scene(message),
+            Failure::Render(_) => Failure::Render(message),
+            Failure::Io(_) => Failure::Io(message),
+        }
+    }
+}
+
+impl std::fmt::Display for Failure
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result
+    {
+        match self
+        {
+            Failure::Args(e) | Failure::Scene(e) | Failure::Render(e) | Failure::Io(e) =>
+                write!(f, "{}", e),
+        }
+    }
+}
+
+/// Scenes the GPU code refuses are the scene's fault, not the GPU's.
+impl From<GpuError> for Failure
+{
+    fn from(e: GpuError) -> Failure
+    {
+        match e
+        {
+            GpuError::Scene(e) => Failure::Scene(e),
+            e => Failure::Render(e.to_string()),
+        }
+    }
+}
+
+/// Scenes that make more triangles than this are refused unless
+/// `--max-triangles` or `--no-triangle-limit` say otherwise.
+const DEFAULT_MAX_TRIANGLES: u64 = 20_000_000;
+
+/// Loads a scene, refusing it before making any triangles if it would make
+/// more than `max_triangles`.
+fn load_scene(file: &str, format: Format, root: Option<&str>, max_triangles: Option<u64>)
+    -> Result<(Scene, Vec<String>), String>
+{
+    let (def, mut warnings) = read_scene(file, format, root)?;
+
+    let triangles = def.triangle_count();
+    pt_info!("The scene makes {} triangles", triangles);
+
+    if let Some(max) = max_triangles.filter(|&max| triangles > max)
+    {
+        return Err(format!(
+            "The scene makes {} triangles, more than the limit of {}; \
+             raise it with --max-triangles or use --no-triangle-limit",
+            triangles, max));
+    }
+
+    let (scene, more) = Scene::from_def(&def)?;
+    warnings.extend(more);
+
+    Ok((scene, warnings))
+}
+
+/// Reads a scene's definition from `file`, or from stdin if it's "-".
+/// Relative paths go from `root` if it's given, otherwise from the file's
+/// directory, or the current directory for stdin.
+fn read_scene(file: &str, format: Format, root: Option<&str>) -> Result<(SceneDef, Vec<String>), String>
+{
+    use std::io::Read;
+    use std::path::Path;
+
+    let source = match (file, root)
+    {
+        ("-", _) =>
+        {
+            let mut source = String::new();
+            std::io::stdin().read_to_string(&mut source)
+                .map_err(|e| format!("Could not read scene from stdin: {}", e))?;
+            source
+        },
+        (_, Some(_)) => std::fs::read_to_string(file)
+            .map_err(|e| format!("Could not read scene \"{}\": {}", file, e))?,
+        (_, None) => return SceneDef::load_as(file, format),
+    };
+
+    SceneDef::load_source(&source, format, Path::new(root.unwrap_or(".")))
+}
+
+/// Loads a scene for `check` and `info`, printing its warnings.
+fn inspect(file: &str, format: Format) -> Result<(SceneInfo, Scene), Failure>
+{
+    let (def, mut warnings) = read_scene(file, format, None).map_err(Failure::Scene)?;
+    let (scene, more) = Scene::from_def(&def).map_err(Failure::Scene)?;
+    warnings.extend(more);
+
+    for w in &warnings
+    {
+        pt_warn!("{}", w);
+    }
+
+    Ok((SceneInfo::new(&def, &scene), scene))
+}
+
+/// Prints every problem with a scene, or a summary and the GPU buffers it
+/// needs at `res`, 1080p by default, if there are none.
+fn check_scene(file: &str, format: Format, res: Option<&str>) -> Result<(), Failure>
+{
+    let res = parse_resolution(res.unwrap_or("1080p")).map_err(Failure::Args)?;
+    let (info, scene) = inspect(file, format)?;
+
+    for p in &info.problems
+    {
+        pt_error!("{}", p);
+    }
+
+    if !info.problems.is_empty()
+    {
+        return Err(Failure::Scene(format!("The scene has {} problems", info.problems.len())));
+    }
+
+    print!("OK: ");
+    print_summary(&info);
+
+    let budget = scene.budget(&RenderSettings::new(res)).map_err(Failure::Scene)?;
+
+    println!("GPU buffers at {}x{}: {:.1} MiB",
+        res[0], res[1], budget.total() as f64 / (1024.0 * 1024.0));
+    for line in budget.summary()
+    {
+        println!("{}", line);
+    }
+
+    // the least every adapter allows, render checks against the one it uses
+    if let Err(e) = budget.check(&wgpu::Limits::default())
+    {
+        pt_warn!("Some adapters can't render this: {}", e);
+    }
+
+    Ok(())
+}
+
+/// Runs the `info` subcommand, mentioning any problems `check` would find.
+fn scene_info(file: &str, format: Format, as_json: bool) -> Result<(), Failure>
+{
+    let (info, _) = inspect(file, format)?;
+
+    if as_json
+    {
+        println!("{}", info.to_json());
+        return Ok(());
+    }
+
+    print_summary(&info);
+
+    let label = |i: usize, m: &MaterialInfo| match &m.name
+    {
+        Some(name) => format!("{} \"{}\"", i, name),
+        None => format!("{} (unnamed)", i),
+    };
+
+    println!("Materials:");
+    for (i, m) in info.materials.iter().enumerate()
+    {
+        println!("    {}: {} triangles, area {:.2}", label(i, m), m.triangles, m.area);
+    }
+
+    if info.lights().next().is_some()
+    {
+        println!("Lights:");
+    }
+    for (i, m) in info.lights()
+    {
+        println!("    {}: glow {:?} over an area of {:.2}, about {:.2?} in all",
+            label(i, m), m.glow, m.area, m.power);
+    }
+
+    println!("Cameras:");
+    for (name, c) in &info.cameras
+    {
+        println!("    \"{}\": at {:?}, facing {:?}, up {:?}, fov {:.1} degrees",
+            name, c.pos, c.front, c.up, c.fov.to_degrees());
+    }
+
+    if !info.files.is_empty()
+    {
+        println!("Files:");
+    }
+    for (path, size) in &info.files
+    {
+        match size
+        {
+            Some(size) => println!("    {}: {}", path, fmt_size(*size)),
+            None => println!("    {}: can't be read", path),
+        }
+    }
+
+    if !info.problems.is_empty()
+    {
+        pt_warn!("The scene has {} problems, see the check subcommand", info.problems.len());
+    }
+
+    Ok(())
+}
+
+fn print_summary(info: &SceneInfo)
+{
+    println!("{} triangles, {} materials, {} cameras",
+        info.triangles, info.materials.len(), info.cameras.len());
+
+    if let (Some((min, max)), Some(diagonal)) = (info.bounds, info.diagonal())
+    {
+        println!("Bounds: {:?} to {:?}, {:.2} across", min, max, diagonal);
+    }
+}
+
+fn fmt_size(bytes: u64) -> String
+{
+    match bytes
+    {
+        0..=1023 => format!("{} bytes", bytes),
+        1024..=1048575 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// Runs the `meta` subcommand.
+fn print_metadata(path: &str) -> Result<(), Failure>
+{
+    match read_metadata(path)
+    {
+        Ok(fields) if fields.is_empty() => pt_info!("\"{}\" has no metadata", path),
+        Ok(fields) =>
+        {
+            for (key, value) in fields
+            {
+                println!("{}: {}", key, value);
+            }
+        },
+        Err(e) => return Err(Failure::Io(e)),
+    }
+
+    Ok(())
+}
+
+fn list_adapters()
+{
+    let adapters = path_tracer_gpu::list_adapters();
+
+    if adapters.is_empty()
+    {
+        println!("No adapters found");
+        return;
+    }
+
+    for (i, (info, limits)) in adapters.iter().enumerate()
+    {
+        println!("{}: {}", i, info.name);
+        println!("    backend: {:?}", info.backend);
+        println!("    type: {:?}", info.device_type);
+        println!("    max storage buffer binding size: {} MiB",
+            limits.max_storage_buffer_binding_size / (1024 * 1024));
+        println!("    max compute workgroups per dimension: {} (WebGPU default)",
+            path_tracer_gpu::MAX_WORKGROUPS_PER_DIMENSION);
+    }
+
+    if !adapters.iter().any(|(info, _)| info.device_type == wgpu::DeviceType::DiscreteGpu)
+    {
+        pt_warn!("No discrete GPU found");
+    }
+}
+
+/// Shows how far through the render is while `condition` is being checked.
+/// On a terminal this is a bar redrawn in place, otherwise a plain line every
+/// `interval`. Progress is whichever of the sample or time limit is closer,
+/// with `max` giving the sample limit as it changes, and `label` goes before
+/// it to say which scene it's for.
+fn with_progress(
+    condition: impl Fn(u32) -> bool,
+    max: impl Fn() -> u32,
+    time: Option<std::time::Duration>,
+    interval: std::time::Duration,
+    label: String)
+    -> impl Fn(u32) -> bool
+{
+    use std::cell::{Cell, RefCell};
+    use std::collections::VecDeque;
+    use std::io::{IsTerminal, Write};
+    use std::time::{Duration, Instant};
+
+    const WINDOW: Duration = Duration::from_secs(30);
+    const REDRAW: Duration = Duration::from_millis(250);
+    const BAR_WIDTH: usize = 30;
+
+    let start = Instant::now();
+    let tty = std::io::stdout().is_terminal();
+    let interval = if tty { interval.min(REDRAW) } else { interval };
+
+    let last = Cell::new(start);
+    let drawn = Cell::new(false);
+    let window = RefCell::new(VecDeque::new());
+
+    move |samples|
+    {
+        let now = Instant::now();
+        let go = condition(samples);
+
+        let mut window = window.borrow_mut();
+        window.push_back((now, samples));
+        while now - window[0].0 > WINDOW
+        {
+            window.pop_front();
+        }
+
+        if go && now - last.get() >= interval
+        {
+            last.set(now);
+
+            let (then, then_samples) = window[0];
+            let rate = (samples - then_samples) as f32 / (now - then).as_secs_f32();
+            let elapsed = now - start;
+            let max = max();
+
+            let mut done = samples as f32 / max as f32;
+            let mut eta = if rate > 0.0
+            {
+                Some(Duration::from_secs_f32(max.saturating_sub(samples) as f32 / rate))
+            }
+            else
+            {
+                None
+            };
+            if let Some(time) = time
+            {
+                let left = time.saturating_sub(elapsed);
+                done = done.max(elapsed.as_secs_f32() / time.as_secs_f32());
+                eta = Some(eta.map_or(left, |eta| eta.min(left)));
+            }
+            let done = done.min(1.0);
+
+            let status = format!(
+                "{}{:.1}% {}/{} samples, {:.2} samples/s, ETA {}",
+                label,
+                done * 100.0,
+                samples,
+                max,
+                rate,
+                eta.map_or("unknown".to_owned(), fmt_duration));
+
+            if tty
+            {
+                let filled = (done * BAR_WIDTH as f32) as usize;
+
+                // clear the line first, in case something was typed over it
+                print!("\r\x1b[2K[{}{}] {}",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    status);
+                let _ = std::io::stdout().flush();
+                drawn.set(true);
+            }
+            else
+            {
+                pt_info!("{}", status);
+            }
+        }
+
+        // clear the bar before anything else is printed
+        if !go && drawn.replace(false)
+        {
+            print!("\r\x1b[2K");
+            let _ = std::io::stdout().flush();
+        }
+
+        go
+    }
+}
+
+fn fmt_duration(d: std::time::Duration) -> String
+{
+    let s = d.as_secs();
+
+    format!("{}:{:02}:{:02}", s / 3600, (s % 3600) / 60, s % 60)
+}
+
+/// Whether the progressive render is paused, changed by commands typed on
+/// the console. Stopping goes through the `RenderControl`, like Ctrl-C.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State
+{
+    Running,
+    Paused,
+}
+
+/// The progressive render as the console sees it, shared with the stop
+/// condition.
+#[derive(Debug)]
+struct Console
+{
+    state: State,
+    /// the sample limit, which "limit" changes
+    max: u32,
+    /// samples finished so far
+    samples: u32,
+    /// samples when the render started, from a checkpoint or 0
+    first: Option<u32>,
+    /// time spent paused, which doesn't count towards the time limit
+    paused: std::time::Duration,
+}
+
+impl Console
+{
+    /// Carries out any command but "status", saying what it did if it did
+    /// anything. Saving and stopping go through `control`.
+    fn apply(&mut self, command: Command, control: &RenderControl, snapshot: &str)
+        -> Option<String>
+    {
+        match command
+        {
+            Command::Status => None,
+            Command::Save(path) =>
+            {
+                let path = path.unwrap_or_else(|| snapshot.to_owned());
+                control.save(&path);
+                Some(format!("Saving to {} after this sample", path))
+            },
+            Command::Limit(n) if n <= self.samples =>
+            {
+                self.max = n;
+                Some(format!("Stopping after this sample, {} are already done", self.samples))
+            },
+            Command::Limit(n) =>
+            {
+                self.max = n;
+                Some(format!("Stopping at {} samples", n))
+            },
+            Command::Pause if self.state == State::Running =>
+            {
+                self.state = State::Paused;
+                Some("Paused, enter 'resume' to carry on".to_owned())
+            },
+            Command::Resume if self.state == State::Paused =>
+            {
+                self.state = State::Running;
+                Some("Resumed".to_owned())
+            },
+            Command::Pause | Command::Resume => None,
+            Command::Stop =>
+            {
+                control.stop();
+                Some("Stopping after this sample".to_owned())
+            },
+        }
+    }
+
+    /// Whether the render is still inside the sample limit and, not counting
+    /// the time it spent paused, the time limit.
+    fn within(&self, samples: u32, elapsed: std::time::Duration, time: Option<std::time::Duration>)
+        -> bool
+    {
+        samples < self.max
+            && time.is_none_or(|time| elapsed.saturating_sub(self.paused) <= time)
+    }
+}
+
+/// A line typed during a progressive render.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Command
+{
+    Status,
+    /// save the image so far, to the snapshot path if none is given
+    Save(Option<String>),
+    Limit(u32),
+    Pause,
+    Resume,
+    Stop,
+}
+
+const COMMANDS: &str = "Commands: status, save [PATH], limit SAMPLES, pause, resume, stop";
+
+impl Command
+{
+    /// The first letter of each is enough, as before there were more.
+    fn parse(line: &str) -> Result<Command, String>
+    {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("").to_lowercase();
+        let rest = words.collect::<Vec<_>>().join(" ");
+
+        match (command.as_str(), rest.as_str())
+        {
+            ("status" | "i", "") => Ok(Command::Status),
+            ("save", "") => Ok(Command::Save(None)),
+            ("save", path) => Ok(Command::Save(Some(path.to_owned()))),
+            ("limit", n) => match n.parse::<u32>()
+            {
+                Ok(n) => Ok(Command::Limit(n)),
+                Err(_) => Err(format!("\"{}\" isn't a number of samples", n)),
+            },
+            ("pause" | "p", "") => Ok(Command::Pause),
+            ("resume" | "r", "") => Ok(Command::Resume),
+            ("stop" | "s" | "q", "") => Ok(Command::Stop),
+            _ => Err(format!("Unknown command \"{}\"", line.trim())),
+        }
+    }
+}
+
+/// The stop condition for a progressive render, which takes commands from
+/// stdin until it stops, and the sample limit as they change it. Images
+/// asked for with "save" go through `control`, to `snapshot` by default.
+fn progressive(
+    max: u32,
+    time: Option<std::time::Duration>,
+    control: std::sync::Arc<RenderControl>,
+    snapshot: String)
+    -> (impl Fn(u32) -> bool, impl Fn() -> u32)
+{
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    let start = Instant::now();
+
+    let console = Arc::new(Mutex::new(Console
+    {
+        state: State::Running,
+        max: max,
+        samples: 0,
+        first: None,
+        paused: Duration::from_secs(0),
+    }));
+
+    // printed here rather than in the thread so it can't land on the progress bar
+    pt_info!("Progressive Render: enter 'stop' to finish, 'status' for how far it's got, \
+           or 'help' for the other commands.");
+
+    let stop = control.clone();
+
+    {
+        let console = console.clone();
+
+        std::thread::spawn(move ||
+        {
+            use std::io::BufRead;
+
+            for line in std::io::stdin().lock().lines().map_while(Result::ok)
+            {
+                if line.trim().is_empty()
+                {
+                    continue;
+                }
+
+                let command = match Command::parse(&line)
+                {
+                    Ok(command) => command,
+                    Err(e) =>
+                    {
+                        if line.trim() != "help"
+                        {
+                            pt_warn!("{}", e);
+                        }
+                        pt_info!("{}", COMMANDS);
+                        continue;
+                    },
+                };
+
+                let mut console = console.lock().unwrap();
+
+                if control.stopping()
+                {
+                    pt_info!("Already stopping");
+                    continue;
+                }
+
+                match command
+                {
+                    Command::Status =>
+                    {
+                        let elapsed = start.elapsed().saturating_sub(console.paused);
+                        let done = console.samples - console.first.unwrap_or(0);
+                        let rate = done as f64 / elapsed.as_secs_f64();
+
+                        let mut eta = if rate > 0.0
+                        {
+                            Some(Duration::from_secs_f64(
+                                console.max.saturating_sub(console.samples) as f64 / rate))
+                        }
+                        else
+                        {
+                            None
+                        };
+                        if let Some(time) = time
+                        {
+                            let left = time.saturating_sub(elapsed);
+                            eta = Some(eta.map_or(left, |eta| eta.min(left)));
+                        }
+
+                        pt_info!("{}/{} samples after {}, {:.2} samples/s, ETA {}{}",
+                            console.samples,
+                            console.max,
+                            fmt_duration(elapsed),
+                            rate,
+                            eta.map_or("unknown".to_owned(), fmt_duration),
+                            if console.state == State::Paused { ", paused" } else { "" });
+                    },
+                    command =>
+                    {
+                        if let Some(done) = console.apply(command, &control, &snapshot)
+                        {
+                            pt_info!("{}", done);
+                        }
+                    },
+                }
+            }
+        });
+    }
+
+    let limit = console.clone();
+
+    let condition = move |samples|
+    {
+        loop
+        {
+            let mut current = console.lock().unwrap();
+            current.samples = samples;
+            current.first.get_or_insert(samples);
+
+            match current.state
+            {
+                State::Running => break,
+                // stopping while paused
+                State::Paused if stop.stopping() => return false,
+                State::Paused => (),
+            }
+
+            drop(current);
+
+            let wait = Instant::now();
+            std::thread::sleep(Duration::from_millis(100));
+            console.lock().unwrap().paused += wait.elapsed();
+        }
+
+        console.lock().unwrap().within(samples, start.elapsed(), time)
+    };
+
+    (condition, move || limit.lock().unwrap().max)
+}
+
+/// `output`, or with `--auto-number` the first of `output_0001` and so on
+/// where none of the files from `outputs` exist yet. Fails if the files
+/// exist without `--force` or `--auto-number`, or if their directory
+/// doesn't exist.
+fn pick_output(
+    output: &str,
+    outputs: &dyn Fn(&str) -> Vec<String>,
+    force: bool,
+    auto_number: bool)
+    -> Result<String, String>
+{
+    use std::path::Path;
+
+    if let Some(dir) = Path::new(output).parent()
+    {
+        if !dir.as_os_str().is_empty() && !dir.is_dir()
+        {
+            return Err(format!("The directory \"{}\" doesn't exist", dir.display()));
+        }
+    }
+
+    let taken = |base: &str| outputs(base).into_iter().find(|p| Path::new(p).exists());
+
+    match taken(output)
+    {
+        None => Ok(output.to_owned()),
+        Some(_) if force => Ok(output.to_owned()),
+        Some(path) if auto_number =>
+        {
+            let numbered = (1..)
+                .map(|n| with_suffix(output, &format!("_{:04}", n)))
+                .find(|base| taken(base).is_none())
+                .unwrap();
+
+            pt_info!("\"{}\" already exists, saving to {}", path, numbered);
+            Ok(numbered)
+        },
+        Some(path) => Err(format!(
+            "\"{}\" already exists, use --force to overwrite it or --auto-number \
+             to save to a new name",
+            path)),
+    }
+}
+
+/// `render.png` -> `render.partial.png`. Snapshots are always 8-bit, so
+/// they're PNGs for the float formats, `render.exr` -> `render.partial.png`.
+fn partial_path(output: &str) -> String
+{
+    match Framebuffer::saves_radiance(output)
+    {
+        true => with_suffix(
+            &std::path::Path::new(output).with_extension("png").to_string_lossy(),
+            ".partial"),
+        false => with_suffix(output, ".partial"),
+    }
+}
+
+/// One of the files a render is saved to.
+struct Output
+{
+    path: String,
+    /// in stops, instead of the render's exposure
+    exposure: Option<f32>,
+}
+
+/// The outputs in an `--output`, separated by commas. Each is a path that
+/// can be followed by options, like `render.png:exposure=1`, with more of
+/// them after commas.
+fn parse_outputs(value: &str) -> Result<Vec<Output>, String>
+{
+    let mut outputs: Vec<Output> = Vec::new();
+
+    for part in value.split(',').map(str::trim).filter(|p| !p.is_empty())
+    {
+        // only a ':' before an option starts them, so Windows drives are paths
+        let (path, option) = match part.rsplit_once(':')
+        {
+            Some((path, option)) if option.contains('=') => (Some(path), Some(option)),
+            None if part.contains('=') => (None, Some(part)),
+            _ => (Some(part), None),
+        };
+
+        if let Some(path) = path
+        {
+            outputs.push(Output
+            {
+                path: path.to_owned(),
+                exposure: None,
+            });
+        }
+
+        if let Some(option) = option
+        {
+            let output = outputs.last_mut()
+                .ok_or(format!("The output option \"{}\" isn't after a file", option))?;
+            let (key, value) = option.split_once('=').unwrap();
+
+            match key.trim()
+            {
+                "exposure" => output.exposure = Some(value.trim().parse().map_err(|_| format!(
+                    "Could not parse the exposure for \"{}\", a number of stops", output.path))?),
+                key => return Err(format!(
+                    "Unknown option \"{}\" for \"{}\", the only one is exposure", key, output.path)),
+            }
+        }
+    }
+
+    Ok(outputs)
+}
+
+/// Saves `image` to every one of `outputs`, at the path `name` makes from
+/// each, calling `saved` with the ones that worked. One failing doesn't stop
+/// the rest, and the failures are logged once they've all been tried.
+fn save_outputs(
+    image: &Framebuffer,
+    outputs: &[Output],
+    name: &dyn Fn(&str) -> String,
+    meta: &[(String, String)],
+    saved: &dyn Fn(&str))
+    -> Result<(), String>
+{
+    let results = outputs.iter()
+        .map(|output|
+        {
+            let path = name(&output.path);
+            let result = match output.exposure
+            {
+                Some(exposure) => image.with_exposure(exposure).save(&path, meta),
+                None => image.save(&path, meta),
+            };
+
+            (path, result)
+        })
+        .collect::<Vec<_>>();
+
+    let mut errors = Vec::new();
+    for (path, result) in results
+    {
+        match result
+        {
+            Ok(()) => saved(&path),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    match (errors.len(), outputs.len())
+    {
+        (0, _) => Ok(()),
+        (1, 1) => Err(errors.remove(0)),
+        (n, total) =>
+        {
+            for e in &errors
+            {
+                pt_error!("{}", e);
+            }
+
+            Err(format!("{} of the {} outputs couldn't be saved", n, total))
+        },
+    }
+}
+
+/// `render.png` -> `render_0001.png`
+fn frame_path(output: &str, frame: u32) -> String
+{
+    with_suffix(output, &format!("_{:04}", frame))
+}
+
+/// Adds `suffix` to the end of the file name, before the extension.
+fn with_suffix(output: &str, suffix: &str) -> String
+{
+    let path = std::path::Path::new(output);
+
+    match (path.file_stem(), path.extension())
+    {
+        (Some(stem), Some(ext)) => path
+            .with_file_name(format!(
+                "{}{}.{}",
+                stem.to_string_lossy(),
+                suffix,
+                ext.to_string_lossy()))
+            .to_string_lossy()
+            .into_owned(),
+        _ => format!("{}{}", output, suffix),
+    }
+}
+
+/// How to draw the debug information and annotation.
+fn parse_overlay(matches: &clap::ArgMatches) -> Result<Overlay, String>
+{
+    let corner = match matches.value_of("debug-pos")
+    {
+        Some("tl") => Corner::TopLeft,
+        Some("tr") => Corner::TopRight,
+        Some("br") => Corner::BottomRight,
+        _ => Corner::BottomLeft,
+    };
+
+    let mut overlay = Overlay
+    {
+        corner: corner,
+        .. Overlay::default()
+    };
+
+    if let Some(c) = matches.value_of("debug-colour")
+    {
+        let hex = c.trim().trim_start_matches('#');
+
+        overlay.colour = match u32::from_str_radix(hex, 16)
+        {
+            Ok(rgb) if hex.len() == 6 => [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8],
+            _ => return Err("Could not parse debug colour, expected RRGGBB".to_owned()),
+        };
+    }
+
+    if let Some(o) = matches.value_of("debug-opacity")
+    {
+        overlay.opacity = match o.trim().parse::<f32>()
+        {
+            Ok(o) if (0.0..=1.0).contains(&o) => o,
+            _ => return Err("Debug opacity must be from 0 to 1".to_owned()),
+        };
+    }
+
+    if let Some(s) = matches.value_of("debug-scale")
+    {
+        overlay.scale = match s.trim().parse::<u32>()
+        {
+            Ok(s) if s > 0 => s,
+            _ => return Err("Could not parse debug scale".to_owned()),
+        };
+    }
+
+    Ok(overlay)
+}
+
+/// `--depth-near` and `--depth-far`, if they were given.
+fn parse_depth_range(matches: &clap::ArgMatches) -> Result<[Option<f32>; 2], String>
+{
+    let parse = |name: &str| match matches.value_of(name).map(|d| d.trim().parse::<f32>())
+    {
+        Some(Ok(d)) if d >= 0.0 && d.is_finite() => Ok(Some(d)),
+        Some(_) => Err(format!("Could not parse {}", name.replace('-', " "))),
+        None => Ok(None),
+    };
+
+    let range = [parse("depth-near")?, parse("depth-far")?];
+
+    match range
+    {
+        [Some(near), Some(far)] if far <= near =>
+            Err("The depth far distance must be more than the near distance".to_owned()),
+        _ => Ok(range),
+    }
+}
+
+fn parse_frames(frames: &str) -> Result<std::ops::RangeInclusive<u32>, String>
+{
+    let parse = |f: &str| f.trim()
+        .parse::<u32>()
+        .map_err(|_| "Could not parse frames, expected first..last".to_owned());
 
-    let scene = match Scene::parse(&file)
+    match frames.find("..")
     {
-        Ok(s) => s,
-        Err(e) =>
+        Some(i) => Ok(parse(&frames[..i])?..=parse(&frames[i + 2..])?),
+        None =>
         {
-            println!("Error: {}", e);
-            return;
-        }
-    };
+            let frame = parse(frames)?;
+
+            Ok(frame..=frame)
+        },
+    }
+}
 
-    let output = matches.value_of("output").unwrap();
+/// Resolutions `--resolution` has names for, compared ignoring case.
+const RESOLUTION_PRESETS: [(&str, [u32; 2]); 11] = [
+    ("480p", [854, 480]),
+    ("720p", [1280, 720]),
+    ("1080p", [1920, 1080]),
+    ("1440p", [2560, 1440]),
+    ("4k", [3840, 2160]),
+    ("8k", [7680, 4320]),
+    ("square256", [256, 256]),
+    ("square512", [512, 512]),
+    ("square1024", [1024, 1024]),
+    ("square2048", [2048, 2048]),
+    ("square4096", [4096, 4096]),
+];
 
+/// A `--transform` like "scale=0.01,rotate_x=-90,translate=0,0,1". Whatever
+/// order they're written in, the scale comes first, then the turns in the
+/// order they're given, then the move.
+fn parse_transform(text: &str) -> Result<Transform, String>
+{
+    let invalid = |why: String| format!("Could not parse transform \"{}\": {}", text, why);
+
+    // a part without '=' is another number for the one before
+    let mut parts: Vec<(&str, Vec<&str>)> = Vec::new();
+    for part in text.split(',').map(str::trim)
     {
-        if let Err(e) = std::fs::File::create(output)
+        match (part.split_once('='), parts.last_mut())
         {
-            println!("Error: {}", e);
+            (Some((key, value)), _) => parts.push((key.trim(), vec![value.trim()])),
+            (None, Some((_, values))) => values.push(part),
+            (None, None) => return Err(invalid(format!("expected name=value, not \"{}\"", part))),
         }
     }
 
-    let res = match parse_resolution(matches.value_of("resolution").unwrap())
+    let mut scale = None;
+    let mut turns = Vec::new();
+    let mut offset = None;
+
+    for (key, values) in parts
     {
-        Ok(res) => res,
-        Err(e) =>
+        let numbers = values.iter()
+            .map(|v| v.parse::<f32>().ok().filter(|n| n.is_finite()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid(format!("{}'s value \"{}\" isn't all numbers", key, values.join(","))))?;
+
+        let turn = |axis: [f32; 3], degrees: f32| Placement
         {
-            println!("Error: {}", e);
-            return;
-        },
-    };
+            axis: axis,
+            angle: degrees.to_radians(),
+            .. Placement::STILL
+        };
 
-    let (samples, def_samples) = match matches.value_of("max-samples")
-    {
-        Some(s) => match s.trim().parse::<u32>()
+        match (key, numbers.as_slice())
         {
-            Ok(s) => (s, false),
-            Err(_) =>
+            ("scale", _) if scale.is_some() => return Err(invalid("scale is given twice".to_owned())),
+            ("scale", &[s]) if s > 0.0 => scale = Some(s),
+            ("scale", &[_]) => return Err(invalid("the scale must be above 0".to_owned())),
+            ("scale", _) => return Err(invalid(
+                "scale takes one number, the same along every axis so spheres stay spheres".to_owned())),
+            ("rotate_x", &[d]) => turns.push(turn([1.0, 0.0, 0.0], d)),
+            ("rotate_y", &[d]) => turns.push(turn([0.0, 1.0, 0.0], d)),
+            ("rotate_z", &[d]) => turns.push(turn([0.0, 0.0, 1.0], d)),
+            ("rotate_x" | "rotate_y" | "rotate_z", _) => return Err(invalid(format!(
+                "{} takes one number of degrees", key))),
+            ("rotate", &[x, y, z, d]) if [x, y, z] != [0.0; 3] =>
             {
-                println!("Error: Could not parse maximum samples");
-                return;
+                let length = (x * x + y * y + z * z).sqrt();
+                turns.push(turn([x / length, y / length, z / length], d));
             },
+            ("rotate", &[_, _, _, _]) => return Err(invalid("the axis can't be 0,0,0".to_owned())),
+            ("rotate", _) => return Err(invalid(
+                "rotate takes an axis and an angle, X,Y,Z,DEGREES".to_owned())),
+            ("translate", _) if offset.is_some() => return Err(invalid("translate is given twice".to_owned())),
+            ("translate", &[x, y, z]) => offset = Some([x, y, z]),
+            ("translate", _) => return Err(invalid("translate takes three numbers, X,Y,Z".to_owned())),
+            (key, _) => return Err(invalid(format!(
+                "unknown \"{}\", expected scale, rotate_x, rotate_y, rotate_z, rotate or translate",
+                key))),
         }
-        None => (100_000, true),
+    }
+
+    let transform = turns.iter()
+        .fold(Transform::scaling(scale.unwrap_or(1.0)), |t, turn| t.then(&turn.transform()));
+
+    Ok(transform.then(&Transform
+    {
+        offset: offset.unwrap_or([0.0; 3]),
+        .. Transform::IDENTITY
+    }))
+}
+
+/// What can go between a resolution's width and height.
+const RESOLUTION_SEPARATORS: [char; 3] = [':', 'x', 'X'];
+
+/// A resolution as width:height, widthxheight or a preset's name.
+fn parse_resolution(res: &str) -> Result<[u32; 2], String>
+{
+    let trimmed = res.trim();
+
+    if let Some((_, preset)) = RESOLUTION_PRESETS.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(trimmed))
+    {
+        return Ok(*preset);
+    }
+
+    let invalid = |why: &str| format!(
+        "Could not parse resolution \"{}\": {}; use width:height or widthxheight, \
+         like 1920x1080, or a name like 1080p",
+        res, why);
+
+    let parts = trimmed.split(RESOLUTION_SEPARATORS).collect::<Vec<_>>();
+    let (w, h) = match parts.as_slice()
+    {
+        [w, h] => (w, h),
+        [_] => return Err(invalid("expected a width and a height")),
+        _ => return Err(invalid("expected only a width and a height")),
     };
 
-    let time = match matches.value_of("time-limit")
+    let parse = |part: &str, name: &str| match part.trim().parse::<u32>()
     {
-        Some(t) => Some(match parse_time(t)
-        {
-            Ok(t) => t,
-            Err(e) =>
-            {
-                println!("Error: {}", e);
-                return;
-            },
-        }),
-        None => None,
+        Ok(0) => Err(invalid(&format!("the {} can't be 0", name))),
+        Ok(n) => Ok(n),
+        Err(_) => Err(invalid(&format!(
+            "the {} \"{}\" isn't a whole number", name, part.trim()))),
     };
 
-    let p = matches.is_present("progressive");
-    let debug = matches.is_present("debug");
+    Ok([parse(w, "width")?, parse(h, "height")?])
+}
 
-    print_intro(res, samples, def_samples, time, p);
+/// `--supersample`, a whole number that `res` can be multiplied by.
+fn parse_supersample(factor: &str, res: [u32; 2]) -> Result<u32, String>
+{
+    let factor = match factor.trim().parse::<u32>()
+    {
+        Ok(0) => return Err("The supersampling factor can't be 0".to_owned()),
+        Ok(n) => n,
+        Err(_) if factor.trim().parse::<f32>().is_ok() => return Err(format!(
+            "The supersampling factor must be a whole number, not {}", factor.trim())),
+        Err(_) => return Err(format!("Could not parse supersampling factor \"{}\"", factor)),
+    };
 
-    let image = if p
+    match res.iter().any(|n| n.checked_mul(factor).is_none())
     {
-        scene.render(res, 5, &progressive(samples, time), debug)
+        true => Err(format!("{}x{} is too big to supersample {} times", res[0], res[1], factor)),
+        false => Ok(factor),
     }
-    else if let Some(time) = time
+}
+
+fn parse_region(region: &str, res: [u32; 2]) -> Result<[u32; 4], String>
+{
+    let values = region.split(",")
+        .map(|v| v.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| "Could not parse region".to_owned())?;
+
+    if values.len() != 4
     {
-        scene.render(res, 5, &time_limit(samples, time), debug)
+        return Err("Region must have 4 values, as x,y,w,h".to_owned());
     }
-    else
+
+    let (x, y) = (values[0], values[1]);
+    let w = values[2].min(res[0].saturating_sub(x));
+    let h = values[3].min(res[1].saturating_sub(y));
+
+    if w == 0 || h == 0
     {
-        scene.render(res, 5, &samples_limit(samples), debug)
-    };
+        return Err(format!(
+            "Region {},{},{},{} doesn't cover any pixels of the {}x{} image",
+            x, y, values[2], values[3], res[0], res[1]));
+    }
+
+    if w != values[2] || h != values[3]
+    {
+        pt_warn!("Region clipped to {},{},{},{}", x, y, w, h);
+    }
 
-    image.save(output).unwrap();
+    Ok([x, y, w, h])
 }
 
-fn samples_limit(max: u32) -> impl Fn(u32) -> bool
+/// Applies `--camera-pos`, `--camera-look-at`, `--camera-up` and
+/// `--camera-fov` to the scene's camera, which keeps its own for any that
+/// aren't given, facing the same way without `--camera-look-at`. Returns
+/// whether there were any.
+fn override_camera(matches: &clap::ArgMatches, scene: &mut Scene) -> Result<bool, String>
 {
-    move |samples| samples < max
+    let vec3 = |name: &str| match matches.value_of(name)
+    {
+        Some(v) => parse_vec3(v).map(Some).ok_or(format!("Could not parse --{} as x,y,z", name)),
+        None => Ok(None),
+    };
+
+    let pos = vec3("camera-pos")?;
+    let look_at = vec3("camera-look-at")?;
+    let up = vec3("camera-up")?;
+    let fov = match matches.value_of("camera-fov").map(|f| f.trim().parse::<f32>())
+    {
+        Some(Ok(f)) => Some(f.to_radians()),
+        Some(Err(_)) => return Err("Could not parse --camera-fov, in degrees".to_owned()),
+        None => None,
+    };
+
+    if pos.is_none() && look_at.is_none() && up.is_none() && fov.is_none()
+    {
+        return Ok(false);
+    }
+
+    let mut camera = scene.camera;
+    camera.pos = pos.unwrap_or(camera.pos);
+    camera.up = up.unwrap_or(camera.up);
+    camera.fov = fov.unwrap_or(camera.fov);
+
+    if let Some(target) = look_at
+    {
+        camera.front = [0, 1, 2].map(|i| target[i] - camera.pos[i]);
+    }
+
+    if look_at.is_some() && camera.front == [0.0; 3]
+    {
+        return Err("The camera can't look at where it is".to_owned());
+    }
+
+    scene.set_camera(camera)?;
+
+    Ok(true)
 }
 
-fn time_limit(max: u32, time: std::time::Duration) -> impl Fn(u32) -> bool
+/// Three numbers as x,y,z.
+fn parse_vec3(text: &str) -> Option<[f32; 3]>
 {
-    let start = std::time::Instant::now();
+    let parsed = text.split(',')
+        .map(|x| x.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>();
 
-    move |samples| samples < max && std::time::Instant::now() - start < time
+    match parsed.as_deref()
+    {
+        Ok(&[x, y, z]) => Some([x, y, z]),
+        _ => None,
+    }
 }
 
-fn progressive(max: u32, time: Option<std::time::Duration>) -> impl Fn(u32) -> bool
+/// The turntable of `frames` around `scene` from `--orbit-center`,
+/// `--orbit-radius` and `--orbit-height`, which `Scene::orbit` fills in when
+/// they're missing.
+fn parse_orbit(matches: &clap::ArgMatches, scene: &Scene, frames: u32, aspect: f32)
+    -> Result<Orbit, Failure>
 {
-    use std::sync::*;
+    let fail = |e: &str| Err(Failure::Args(e.to_owned()));
 
-    let start = std::time::Instant::now();
+    let center = match matches.value_of("orbit-center")
+    {
+        Some(center) => match parse_vec3(center)
+        {
+            Some(center) => Some(center),
+            None => return fail("Could not parse the orbit's center as x,y,z"),
+        },
+        None => None,
+    };
 
-    let flag = Arc::new(atomic::AtomicBool::new(true));
-    let flag_c = flag.clone();
+    let radius = match matches.value_of("orbit-radius").map(|r| r.trim().parse::<f32>())
+    {
+        Some(Ok(r)) if r > 0.0 => Some(r),
+        Some(_) => return fail("Could not parse the orbit's radius, a distance above 0"),
+        None => None,
+    };
 
-    std::thread::spawn(move ||
+    let height = match matches.value_of("orbit-height").map(|h| h.trim().parse::<f32>())
     {
-        use std::io::{self, BufRead};
+        Some(Ok(h)) => Some(h),
+        Some(Err(_)) => return fail("Could not parse the orbit's height"),
+        None => None,
+    };
 
-        println!("Progressive Render: enter 's' or 'S' to stop the render.");
+    scene.orbit(frames, center, radius, height, aspect).map_err(Failure::Scene)
+}
+
+/// Reads whichever of the white balance options was given.
+fn parse_white_balance(matches: &clap::ArgMatches, res: [u32; 2])
+    -> Result<Option<WhiteBalance>, String>
+{
+    let numbers = |s: &str, n: usize, what: &str| -> Result<Vec<f32>, String>
+    {
+        let parsed = s.split(',')
+            .map(|x| x.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>();
 
-        let stdin = io::stdin();
-        for line in stdin.lock().lines()
+        match parsed
         {
-            if let Ok(line) = line
-            {
-                if line.trim().to_lowercase() == "s"
-                {
-                    flag.store(true, atomic::Ordering::Relaxed);
-                }
-            }
+            Ok(v) if v.len() == n => Ok(v),
+            _ => Err(format!("Could not parse {}", what)),
         }
-    });
+    };
 
-    move |samples|
+    if let Some(k) = matches.value_of("white-balance")
     {
-        if samples >= max
+        return match k.trim().trim_end_matches(['K', 'k']).parse::<f32>()
         {
-            return false;
-        }
+            Ok(k) if (1000.0..=40000.0).contains(&k) => Ok(Some(WhiteBalance::Kelvin(k))),
+            Ok(_) => Err("White balance must be from 1000 to 40000 kelvin".to_owned()),
+            Err(_) => Err("Could not parse white balance".to_owned()),
+        };
+    }
+
+    if let Some(rgb) = matches.value_of("wb-rgb")
+    {
+        let v = numbers(rgb, 3, "white balance gains as r,g,b")?;
 
-        if let Some(time) = time
+        if v.iter().any(|g| !(g.is_finite() && *g > 0.0))
         {
-            if std::time::Instant::now() - start > time
-            {
-                return false;
-            }
+            return Err("White balance gains must be greater than 0".to_owned());
         }
 
-        if !flag_c.load(atomic::Ordering::Relaxed)
+        return Ok(Some(WhiteBalance::Gains([v[0], v[1], v[2]])));
+    }
+
+    if let Some(xy) = matches.value_of("wb-from-pixel")
+    {
+        let v = numbers(xy, 2, "white balance pixel as x,y")?;
+
+        if v.iter().any(|c| c.fract() != 0.0 || *c < 0.0)
+            || v[0] as u32 >= res[0] || v[1] as u32 >= res[1]
         {
-            return false;
+            return Err(format!(
+                "White balance pixel {} isn't in the {}x{} image", xy, res[0], res[1]));
         }
 
-        true
+        return Ok(Some(WhiteBalance::Pixel([v[0] as u32, v[1] as u32])));
     }
+
+    Ok(None)
 }
 
-fn parse_resolution(res: &str) -> Result<[u32; 2], String>
+fn parse_tile(tile: &str) -> Result<[u32; 2], String>
 {
-    let mut split = res.split(":");
-    let w = split.next()
-        .ok_or("Could not parse resolution".to_owned())?
-        .trim()
-        .parse::<u32>()
-        .map_err(|_| "Could not parse resolution width".to_owned())?;
-    let h = split.next()
-        .ok_or("Could not parse resolution height".to_owned())?
-        .trim()
-        .parse::<u32>()
-        .map_err(|_| "Could not parse resolution height".to_owned())?;
+    let tile = if tile.contains(RESOLUTION_SEPARATORS)
+    {
+        parse_resolution(tile).map_err(|_| "Could not parse tile size".to_owned())?
+    }
+    else
+    {
+        let size = tile.trim()
+            .parse::<u32>()
+            .map_err(|_| "Could not parse tile size".to_owned())?;
+
+        [size, size]
+    };
 
-    Ok([w, h])
+    if tile[0] == 0 || tile[1] == 0
+    {
+        return Err("Tile size must be greater than 0".to_owned());
+    }
+
+    Ok(tile)
 }
 
+/// A time as h:m:s, m:s, a number of seconds, or numbers with units in
+/// order, like 1h30m, 20m or 0.5h. Any of the numbers can have fractions.
 fn parse_time(time: &str) -> Result<std::time::Duration, String>
 {
-    let mut split = time.split(":");
-    let first = split.next()
-        .ok_or("Could not parse time-limit".to_owned())?
-        .trim()
-        .parse::<u64>()
-        .map_err(|_| "Could not parse time-limit".to_owned())?;
-    let second = if let Some(second) = split.next()
-    {
-        Some(second.trim()
-            .parse::<u64>()
-            .map_err(|_| "Could not parse time-limit".to_owned())?)
-    } else { None };
-    let third = if let Some(third) = split.next()
-    {
-        Some(third.trim()
-            .parse::<u64>()
-            .map_err(|_| "Could not parse time-limit".to_owned())?)
-    } else { None };
-
-    Ok(std::time::Duration::from_secs(match (first, second, third)
-    {
-        (seconds, None, None) => seconds,
-        (minutes, Some(seconds), None) => minutes * 60 + seconds,
-        (hours, Some(minutes), Some(seconds))
-            => hours * 3600 + minutes * 60 + seconds,
-        _ => unreachable!()
-    }))
+    let trimmed = time.trim();
+
+    let invalid = |why: &str| format!(
+        "Could not parse time \"{}\": {}; use h:m:s, m:s or units like 1h30m, 20m or 90.5s",
+        time, why);
+
+    let number = |n: &str| match n.trim().parse::<f64>()
+    {
+        Ok(n) if n.is_finite() && n >= 0.0 => Ok(n),
+        Ok(_) => Err(invalid(&format!("\"{}\" can't be negative", n.trim()))),
+        Err(_) => Err(invalid(&format!("\"{}\" isn't a number", n.trim()))),
+    };
+
+    // "1:90" could be a typo for 1:09 or 1:30, so it's refused
+    let below_60 = |n: f64, name: &str| match n < 60.0
+    {
+        true => Ok(n),
+        false => Err(invalid(&format!("the {} must be below 60", name))),
+    };
+
+    let secs = if trimmed.is_empty()
+    {
+        return Err(invalid("it's empty"));
+    }
+    else if trimmed.contains(':')
+    {
+        let fields = trimmed.split(':').map(number).collect::<Result<Vec<_>, _>>()?;
+
+        match *fields.as_slice()
+        {
+            [m, s] => m * 60.0 + below_60(s, "seconds")?,
+            [h, m, s] =>
+                h * 3600.0 + below_60(m, "minutes")? * 60.0 + below_60(s, "seconds")?,
+            _ => return Err(invalid("expected at most 3 fields, as h:m:s")),
+        }
+    }
+    else if !trimmed.contains(|c: char| c.is_ascii_alphabetic())
+    {
+        number(trimmed)?
+    }
+    else
+    {
+        const UNITS: [(&str, f64); 3] = [("h", 3600.0), ("m", 60.0), ("s", 1.0)];
+
+        let mut total = 0.0;
+        let mut next = 0;
+        let mut rest = trimmed;
+
+        while !rest.is_empty()
+        {
+            let start = match rest.find(|c: char| c.is_ascii_alphabetic())
+            {
+                Some(start) => start,
+                None => return Err(invalid(&format!("\"{}\" has no unit", rest))),
+            };
+            let (n, after) = rest.split_at(start);
+            let end = after.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(after.len());
+            let unit = &after[..end];
+
+            if n.trim().is_empty()
+            {
+                return Err(invalid(&format!("\"{}\" needs a number before it", unit)));
+            }
+
+            let i = match UNITS.iter().position(|(u, _)| u.eq_ignore_ascii_case(unit))
+            {
+                Some(i) => i,
+                None => return Err(invalid(&format!(
+                    "\"{}\" isn't a unit, only h, m and s are", unit))),
+            };
+
+            if i < next
+            {
+                return Err(invalid(
+                    "hours, minutes and seconds can each come once, in that order"));
+            }
+
+            total += number(n)? * UNITS[i].1;
+            next = i + 1;
+            rest = after[end..].trim_start();
+        }
+
+        total
+    };
+
+    std::time::Duration::try_from_secs_f64(secs).map_err(|_| invalid("it's too long"))
+}
+
+/// A time as hours, minutes and seconds, like 1h 2m 3.5s, leaving out the
+/// ones that are 0.
+fn fmt_time(time: std::time::Duration) -> String
+{
+    let secs = time.as_secs();
+    let parts = [
+        (secs / 3600) as f64,
+        ((secs % 3600) / 60) as f64,
+        (secs % 60) as f64 + time.subsec_nanos() as f64 / 1e9,
+    ];
+
+    match parts.iter().zip(["h", "m", "s"]).filter(|(&n, _)| n > 0.0).collect::<Vec<_>>()
+    {
+        parts if parts.is_empty() => "0s".to_owned(),
+        parts => parts.iter()
+            .map(|(n, unit)| format!("{}{}", n, unit))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
 }
 
 fn print_intro(
     res: [u32; 2],
     samples: u32,
     def_samples: bool,
-    time: Option<std::time::Duration>,
-    progressive: bool)
+    time: Option<(std::time::Duration, &str)>,
+    progressive: bool,
+    adapter: &wgpu::AdapterInfo,
+    camera: Option<&Camera>)
 {
+    match adapter.backend
+    {
+        // GpuContext::cpu
+        wgpu::Backend::Empty => pt_info!("Using the CPU, with {} threads",
+            rayon::current_num_threads()),
+        _ => pt_info!("Using {} ({:?}, {:?})",
+            adapter.name, adapter.backend, adapter.device_type),
+    }
+
     let samples = if def_samples
     {
         format!("{} (default) samples", samples)
@@ -262,40 +4444,26 @@ fn print_intro(
         format!("{} samples", samples)
     };
 
-    if let Some(time) = time
+    if let Some((time, text)) = time
     {
-        let time =
-        {
-            let secs = time.as_secs();
+        let time = fmt_time(time);
 
-            if secs >= 3600
-            {
-                format!("{}h:{}m:{}s ({}s)",
-                    secs / 3600,
-                    (secs % 3600) / 60,
-                    secs % 60,
-                    secs)
-            }
-            else if secs >= 60
-            {
-                format!("{}m:{}s ({}s)", secs / 60, secs % 60, secs)
-            }
-            else
-            {
-                format!("{}s", secs)
-            }
-        };
+        // so a time that meant something else is caught before it runs out
+        if !text.replace(' ', "").eq_ignore_ascii_case(&time.replace(' ', ""))
+        {
+            pt_info!("Read the time limit \"{}\" as {}", text.trim(), time);
+        }
 
         if progressive
         {
-            println!("Rendering at {}x{} progressively for {}, maximum {}",
+            pt_info!("Rendering at {}x{} progressively for {}, maximum {}",
                 res[0], res[1],
                 time,
                 samples);
         }
         else
         {
-            println!("Rendering at {}x{} for {}, maximum {}",
+            pt_info!("Rendering at {}x{} for {}, maximum {}",
                 res[0], res[1],
                 time,
                 samples);
@@ -305,15 +4473,265 @@ fn print_intro(
     {
         if progressive
         {
-            println!("Rendering at {}x{} progressively, maximum {}",
+            pt_info!("Rendering at {}x{} progressively, maximum {}",
                 res[0], res[1],
                 samples);
         }
         else
         {
-            println!("Rendering at {}x{}, maximum {}",
+            pt_info!("Rendering at {}x{}, maximum {}",
                 res[0], res[1],
                 samples);
         }
     }
+
+    if let Some(c) = camera
+    {
+        pt_info!("Camera from the command line: at {:?}, facing {:?}, up {:?}, fov {:.1} degrees",
+            c.pos, c.front, c.up, c.fov.to_degrees());
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn resolutions()
+    {
+        let good = [
+            ("1920x1080", [1920, 1080]),
+            ("1920X1080", [1920, 1080]),
+            ("1920:1080", [1920, 1080]),
+            (" 640 x 480 ", [640, 480]),
+            ("1x1", [1, 1]),
+            ("1080p", [1920, 1080]),
+            ("4K", [3840, 2160]),
+            ("square512", [512, 512]),
+        ];
+
+        for (res, expected) in good
+        {
+            assert_eq!(parse_resolution(res), Ok(expected), "{}", res);
+        }
+
+        let bad = [
+            ("", "expected a width and a height"),
+            ("1920", "expected a width and a height"),
+            ("1920:1080:7", "expected only a width and a height"),
+            ("1920x1080x2", "expected only a width and a height"),
+            ("0x1080", "the width can't be 0"),
+            ("1920x0", "the height can't be 0"),
+            ("1920x", "the height \"\" isn't a whole number"),
+            ("-1x10", "the width \"-1\" isn't a whole number"),
+            ("19.5x10", "the width \"19.5\" isn't a whole number"),
+            ("1920x1080p", "the height \"1080p\" isn't a whole number"),
+            ("5000000000x1", "the width \"5000000000\" isn't a whole number"),
+            ("1080i", "expected a width and a height"),
+        ];
+
+        for (res, why) in bad
+        {
+            match parse_resolution(res)
+            {
+                Ok(r) => panic!("\"{}\" parsed as {:?}", res, r),
+                Err(e) =>
+                {
+                    assert!(e.contains(&format!("\"{}\"", res)), "{}", e);
+                    assert!(e.contains(why), "\"{}\": {}", res, e);
+                    assert!(e.contains("like 1920x1080"), "{}", e);
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn times()
+    {
+        let good = [
+            ("90", 90.0),
+            ("90.5", 90.5),
+            ("1:30", 90.0),
+            ("1:02:03", 3723.0),
+            ("0:59.5", 59.5),
+            ("45s", 45.0),
+            ("90.5s", 90.5),
+            ("20m", 1200.0),
+            ("2h", 7200.0),
+            ("0.5h", 1800.0),
+            ("1h30m", 5400.0),
+            ("1h 30m 15s", 5415.0),
+            ("1H30M", 5400.0),
+            ("0", 0.0),
+        ];
+
+        for (time, secs) in good
+        {
+            assert_eq!(parse_time(time), Ok(std::time::Duration::from_secs_f64(secs)), "{}", time);
+        }
+
+        let bad = [
+            ("", "it's empty"),
+            ("   ", "it's empty"),
+            ("1:90", "the seconds must be below 60"),
+            ("1:60:00", "the minutes must be below 60"),
+            ("1:2:3:4", "expected at most 3 fields"),
+            ("1::2", "\"\" isn't a number"),
+            ("30m1h", "in that order"),
+            ("1h1h", "in that order"),
+            ("10d", "\"d\" isn't a unit"),
+            ("h", "\"h\" needs a number before it"),
+            ("1h30", "\"30\" has no unit"),
+            ("-5", "\"-5\" can't be negative"),
+            ("-5m", "\"-5\" can't be negative"),
+            ("abc", "\"abc\" needs a number before it"),
+        ];
+
+        for (time, why) in bad
+        {
+            match parse_time(time)
+            {
+                Ok(t) => panic!("\"{}\" parsed as {:?}", time, t),
+                Err(e) => assert!(e.contains(why), "\"{}\": {}", time, e),
+            }
+        }
+    }
+
+    #[test]
+    fn transforms()
+    {
+        let near = |got: [f32; 3], want: [f32; 3]|
+            (0..3).all(|k| (got[k] - want[k]).abs() < 1e-5);
+
+        // scale, then every rotation in the order given, then translate,
+        // however the parts are written
+        let good = [
+            ("scale=2", [2.0, 0.0, 0.0]),
+            ("rotate_z=90", [0.0, 1.0, 0.0]),
+            ("translate=0,0,5", [1.0, 0.0, 5.0]),
+            ("scale=2,rotate_z=90,translate=0,0,5", [0.0, 2.0, 5.0]),
+            ("translate=0,0,5,rotate_z=90,scale=2", [0.0, 2.0, 5.0]),
+            ("rotate_z=90,rotate_x=90", [0.0, 0.0, 1.0]),
+            ("rotate_x=90,rotate_z=90", [0.0, 1.0, 0.0]),
+            ("rotate=0,0,3,90", [0.0, 1.0, 0.0]),
+            (" scale = 0.5 , translate = 1 , 2 , 3 ", [1.5, 2.0, 3.0]),
+        ];
+
+        for (text, want) in good
+        {
+            let got = parse_transform(text).unwrap().point([1.0, 0.0, 0.0]);
+            assert!(near(got, want), "\"{}\" put (1, 0, 0) at {:?}, not {:?}", text, got, want);
+        }
+
+        let bad = [
+            ("", "expected name=value"),
+            ("2", "expected name=value, not \"2\""),
+            ("scale=a", "isn't all numbers"),
+            ("scale=inf", "isn't all numbers"),
+            ("scale=0", "must be above 0"),
+            ("scale=-1", "must be above 0"),
+            ("scale=1,2,3", "scale takes one number"),
+            ("scale=2,scale=3", "scale is given twice"),
+            ("rotate_x=1,2", "rotate_x takes one number"),
+            ("rotate=0,0,0,90", "the axis can't be 0,0,0"),
+            ("rotate=0,0,1", "rotate takes an axis and an angle"),
+            ("translate=1,2", "translate takes three numbers"),
+            ("translate=1,2,3,translate=1,2,3", "translate is given twice"),
+            ("shear=1", "unknown \"shear\""),
+        ];
+
+        for (text, why) in bad
+        {
+            match parse_transform(text)
+            {
+                Ok(_) => panic!("\"{}\" parsed", text),
+                Err(e) => assert!(e.contains(why), "\"{}\": {}", text, e),
+            }
+        }
+    }
+
+    #[test]
+    fn answers()
+    {
+        use std::sync::mpsc::channel;
+        use std::time::{Duration, Instant};
+
+        let control = RenderControl::new();
+        let wait = Duration::from_millis(50);
+
+        for (line, go_on) in [("\n", true), ("y\n", true), ("n\n", false), (" No \n", false)]
+        {
+            let (send, answer) = channel();
+            send.send(line.to_owned()).unwrap();
+            assert_eq!(await_answer(&answer, &control, wait), go_on, "{:?}", line);
+        }
+
+        // nobody answers
+        let (_send, answer) = channel();
+        assert!(await_answer(&answer, &control, wait));
+
+        // a Ctrl-C doesn't wait out the rest of the time
+        let (_send, answer) = channel();
+        control.stop();
+        let start = Instant::now();
+        assert!(!await_answer(&answer, &control, Duration::from_secs(60)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn console_commands()
+    {
+        use std::time::Duration;
+
+        for (line, command) in [
+            ("s", Command::Stop),
+            ("q", Command::Stop),
+            (" STOP ", Command::Stop),
+            ("p", Command::Pause),
+            ("r", Command::Resume),
+            ("i", Command::Status),
+            ("limit 64", Command::Limit(64)),
+            ("save a b.png", Command::Save(Some("a b.png".to_owned()))),
+        ]
+        {
+            assert_eq!(Command::parse(line), Ok(command), "{:?}", line);
+        }
+        assert!(Command::parse("limit many").is_err());
+        assert!(Command::parse("go").is_err());
+
+        let control = RenderControl::new();
+        let mut console = Console
+        {
+            state: State::Running,
+            max: 100,
+            samples: 10,
+            first: Some(0),
+            paused: Duration::from_secs(0),
+        };
+
+        assert!(console.apply(Command::Pause, &control, "snap.png").is_some());
+        assert_eq!(console.state, State::Paused);
+        // pausing twice does nothing
+        assert!(console.apply(Command::Pause, &control, "snap.png").is_none());
+        assert_eq!(console.state, State::Paused);
+        assert!(console.apply(Command::Resume, &control, "snap.png").is_some());
+        assert_eq!(console.state, State::Running);
+        assert!(console.apply(Command::Resume, &control, "snap.png").is_none());
+
+        console.apply(Command::Limit(50), &control, "snap.png");
+        assert!(console.within(49, Duration::from_secs(0), None));
+        assert!(!console.within(50, Duration::from_secs(0), None));
+
+        // time spent paused doesn't count against the time limit
+        console.paused = Duration::from_secs(5);
+        let time = Some(Duration::from_secs(10));
+        assert!(console.within(20, Duration::from_secs(14), time));
+        assert!(!console.within(20, Duration::from_secs(16), time));
+
+        // stopping is what "s" used to fail to do
+        assert!(!control.stopping());
+        console.apply(Command::Stop, &control, "snap.png");
+        assert!(control.stopping());
+    }
 }
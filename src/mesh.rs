@@ -0,0 +1,605 @@
+//! Generating triangle meshes, and fixing up ones that came from elsewhere.
+
+use crate::gpu::Triangle;
+use crate::vec3::{add, cross, dot, length, normalize, scale, sub};
+
+use std::collections::HashMap;
+
+/// 20 * 4^7, about 330k triangles.
+pub const MAX_SPHERE_SUBDIVISIONS: u32 = 7;
+
+/// The most vertices a heightmap's grid can have each way, about 130M
+/// triangles at most, so a typo in a resolution fails quickly.
+pub const MAX_HEIGHTMAP_RESOLUTION: u32 = 8192;
+
+/// A triangle's three points, or something at each of them.
+pub type Corners = [[f32; 3]; 3];
+
+/// A grid of `size[0]` by `size[1]` vertices from `corner` to `corner +
+/// across + down`, each raised by `height` times its entry in `heights`
+/// (rows first) along the normal of `down` and `across`.
+#[derive(Copy, Clone, Debug)]
+pub struct Heightmap<'a>
+{
+    pub heights: &'a [f32],
+    pub size   : [u32; 2],
+    pub corner : [f32; 3],
+    pub across : [f32; 3],
+    pub down   : [f32; 3],
+    pub height : f32,
+}
+
+/// Two triangles for each cell of `map`, wound anticlockwise seen from
+/// above, and the smooth normals at their points from the slope around each
+/// vertex.
+pub fn height_grid(map: &Heightmap) -> (Vec<Corners>, Vec<Corners>)
+{
+    let Heightmap { heights, size, corner, across, down, height } = *map;
+    let (cols, rows) = (size[0] as usize, size[1] as usize);
+    let up = normalize(cross(down, across));
+
+    let point = |x: usize, y: usize| add(add(add(
+        corner,
+        scale(across, x as f32 / (cols - 1) as f32)),
+        scale(down, y as f32 / (rows - 1) as f32)),
+        scale(up, height * heights[y * cols + x]));
+
+    let points = (0..rows)
+        .flat_map(|y| (0..cols).map(move |x| (x, y)))
+        .map(|(x, y)| point(x, y))
+        .collect::<Vec<_>>();
+
+    // central differences, or one-sided at the edges
+    let normals = (0..rows)
+        .flat_map(|y| (0..cols).map(move |x| (x, y)))
+        .map(|(x, y)|
+        {
+            let at = |x: usize, y: usize| points[y * cols + x];
+            let dx = sub(at((x + 1).min(cols - 1), y), at(x.saturating_sub(1), y));
+            let dy = sub(at(x, (y + 1).min(rows - 1)), at(x, y.saturating_sub(1)));
+
+            normalize(cross(dy, dx))
+        })
+        .collect::<Vec<_>>();
+
+    let mut triangles = Vec::with_capacity(2 * (cols - 1) * (rows - 1));
+    let mut corners = Vec::with_capacity(triangles.capacity());
+
+    for y in 0..rows - 1
+    {
+        for x in 0..cols - 1
+        {
+            let (a, b, c, d) = (
+                y * cols + x,
+                (y + 1) * cols + x,
+                (y + 1) * cols + x + 1,
+                y * cols + x + 1);
+
+            for [i, j, k] in [[a, b, c], [a, c, d]]
+            {
+                triangles.push([points[i], points[j], points[k]]);
+                corners.push([normals[i], normals[j], normals[k]]);
+            }
+        }
+    }
+
+    (triangles, corners)
+}
+
+/// A sphere of radius 1 around the origin, wound anticlockwise seen from
+/// outside. Neighbouring triangles share exactly the same vertices.
+pub fn icosphere(subdivisions: u32) -> Vec<[[f32; 3]; 3]>
+{
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+
+    let mut verts = vec![
+        [-1.0, t, 0.0], [1.0, t, 0.0], [-1.0, -t, 0.0], [1.0, -t, 0.0],
+        [0.0, -1.0, t], [0.0, 1.0, t], [0.0, -1.0, -t], [0.0, 1.0, -t],
+        [t, 0.0, -1.0], [t, 0.0, 1.0], [-t, 0.0, -1.0], [-t, 0.0, 1.0],
+    ]
+        .into_iter()
+        .map(normalize)
+        .collect::<Vec<_>>();
+
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions
+    {
+        // each edge is split once, whichever face gets to it first
+        let mut midpoints = HashMap::new();
+        let mut midpoint = |a: usize, b: usize|
+        {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(||
+            {
+                verts.push(normalize(add(verts[a], verts[b])));
+                verts.len() - 1
+            })
+        };
+
+        faces = faces.iter()
+            .flat_map(|&[a, b, c]|
+            {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+
+                vec![[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    faces.iter()
+        .map(|&[a, b, c]| [verts[a], verts[b], verts[c]])
+        .collect()
+}
+
+/// The normal of a polygon by Newell's method, with length twice its area.
+/// Works for concave and slightly bent polygons.
+pub fn polygon_normal(points: &[[f32; 3]]) -> [f32; 3]
+{
+    let mut n = [0.0; 3];
+
+    for (i, a) in points.iter().enumerate()
+    {
+        let b = points[(i + 1) % points.len()];
+
+        n[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        n[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        n[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+
+    n
+}
+
+/// Splits a polygon into triangles by ear clipping, returning indices into
+/// `points` wound the same way as the polygon. It can be concave but not
+/// self-intersecting, and is triangulated as seen along its normal.
+pub fn triangulate(points: &[[f32; 3]]) -> Result<Vec<[usize; 3]>, String>
+{
+    let normal = polygon_normal(points);
+
+    if length(normal) == 0.0
+    {
+        return Err("polygon has no area, or its edges cross".to_owned());
+    }
+
+    // a right handed basis with the normal, so the polygon goes anticlockwise
+    let n = normalize(normal);
+    let axis = if n[0].abs() < 0.9 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] };
+    let u = normalize(cross(n, axis));
+    let v = cross(n, u);
+
+    let flat = points.iter()
+        .map(|&p| [dot(sub(p, points[0]), u), dot(sub(p, points[0]), v)])
+        .collect::<Vec<_>>();
+    let len = flat.len();
+
+    for i in 0..len
+    {
+        for j in i + 1..len
+        {
+            let adjacent = j == i + 1 || (i == 0 && j == len - 1);
+            let (a, b) = (flat[i], flat[(i + 1) % len]);
+            let (c, d) = (flat[j], flat[(j + 1) % len]);
+
+            if (adjacent && (a == b || c == d)) || (!adjacent && crosses(a, b, c, d))
+            {
+                return Err(format!(
+                    "polygon edges {} and {} touch or cross, the edges can't intersect \
+                     and the points must go around the edge in order", i, j));
+            }
+        }
+    }
+
+    let mut left = (0..len).collect::<Vec<_>>();
+    let mut tris = Vec::with_capacity(len - 2);
+
+    while left.len() > 3
+    {
+        let m = left.len();
+        let corner = |i: usize| (left[(i + m - 1) % m], left[i], left[(i + 1) % m]);
+
+        let ear = (0..m).find(|&i|
+        {
+            let (a, b, c) = corner(i);
+
+            orient(flat[a], flat[b], flat[c]) > 0.0 && left.iter()
+                .filter(|&&p| p != a && p != b && p != c)
+                .all(|&p| !inside(flat[p], flat[a], flat[b], flat[c]))
+        });
+
+        match ear
+        {
+            Some(i) =>
+            {
+                let (a, b, c) = corner(i);
+                tris.push([a, b, c]);
+                left.remove(i);
+            },
+            None => match (0..m).find(|&i|
+            {
+                let (a, b, c) = corner(i);
+                orient(flat[a], flat[b], flat[c]) == 0.0
+            })
+            {
+                // a point in the middle of a straight edge makes no triangle
+                Some(i) =>
+                {
+                    left.remove(i);
+                },
+                None => return Err("polygon couldn't be triangulated".to_owned()),
+            },
+        }
+    }
+
+    if orient(flat[left[0]], flat[left[1]], flat[left[2]]) > 0.0
+    {
+        tris.push([left[0], left[1], left[2]]);
+    }
+
+    Ok(tris)
+}
+
+/// Twice the signed area of the triangle, positive when anticlockwise.
+fn orient(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32
+{
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Whether `p` is in the anticlockwise triangle or on its edge.
+fn inside(p: [f32; 2], a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> bool
+{
+    orient(a, b, p) >= 0.0 && orient(b, c, p) >= 0.0 && orient(c, a, p) >= 0.0
+}
+
+/// Whether the segments touch or cross.
+fn crosses(a: [f32; 2], b: [f32; 2], c: [f32; 2], d: [f32; 2]) -> bool
+{
+    let (d1, d2) = (orient(c, d, a), orient(c, d, b));
+    let (d3, d4) = (orient(a, b, c), orient(a, b, d));
+
+    // within the bounding box of a segment it's on, when collinear
+    let on = |p: [f32; 2], q: [f32; 2], r: [f32; 2]|
+        r[0] >= p[0].min(q[0]) && r[0] <= p[0].max(q[0])
+            && r[1] >= p[1].min(q[1]) && r[1] <= p[1].max(q[1]);
+
+    (d1 * d2 < 0.0 && d3 * d4 < 0.0)
+        || (d1 == 0.0 && on(c, d, a))
+        || (d2 == 0.0 && on(c, d, b))
+        || (d3 == 0.0 && on(a, b, c))
+        || (d4 == 0.0 && on(a, b, d))
+}
+
+/// What `fix_winding` found and changed.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct WindingReport
+{
+    pub flipped: usize,
+    /// groups of triangles joined by shared edges
+    pub components: usize,
+    /// edges shared by more than two triangles, which orientation doesn't
+    /// spread across
+    pub non_manifold: usize,
+    /// the middle of one non-manifold edge
+    pub example: Option<[f32; 3]>,
+}
+
+/// Flips triangles so neighbours agree on which side is the front, then
+/// turns each closed piece to face outwards. Triangles are neighbours when
+/// they share two vertices at exactly the same positions.
+pub fn fix_winding(triangles: &mut [Triangle]) -> WindingReport
+{
+    let mut report = WindingReport::default();
+
+    // vertices by position, so equal positions are the same vertex
+    let mut ids = HashMap::new();
+    let mut id = |p: [f32; 3]|
+    {
+        let next = ids.len();
+        *ids.entry([p[0].to_bits(), p[1].to_bits(), p[2].to_bits()]).or_insert(next)
+    };
+
+    let verts = triangles.iter()
+        .map(|t| [id(t.a), id(t.b), id(t.c)])
+        .collect::<Vec<_>>();
+    let edges_of = |v: [usize; 3]| [(v[0], v[1]), (v[1], v[2]), (v[2], v[0])];
+
+    // the triangles on each edge, with whether they go along it from the
+    // lower vertex
+    let mut edges: HashMap<(usize, usize), Vec<(usize, bool)>> = HashMap::new();
+
+    for (t, &v) in verts.iter().enumerate()
+    {
+        for (a, b) in edges_of(v).iter().copied()
+        {
+            edges.entry((a.min(b), a.max(b))).or_default().push((t, a < b));
+        }
+    }
+
+    for (&(a, b), tris) in &edges
+    {
+        if tris.len() > 2
+        {
+            report.non_manifold += 1;
+
+            if report.example.is_none()
+            {
+                let t = tris[0].0;
+                let tri = &triangles[t];
+                let pos = [tri.a, tri.b, tri.c];
+                let v = verts[t];
+                let (pa, pb) = (
+                    pos[v.iter().position(|&x| x == a).unwrap()],
+                    pos[v.iter().position(|&x| x == b).unwrap()]);
+
+                report.example = Some(scale(add(pa, pb), 0.5));
+            }
+        }
+    }
+
+    let mut flip = vec![false; triangles.len()];
+    let mut seen = vec![false; triangles.len()];
+
+    for start in 0..triangles.len()
+    {
+        if seen[start]
+        {
+            continue;
+        }
+
+        report.components += 1;
+        seen[start] = true;
+
+        let mut component = vec![start];
+        let mut closed = true;
+        let mut next = 0;
+
+        while next < component.len()
+        {
+            let t = component[next];
+            next += 1;
+
+            for (a, b) in edges_of(verts[t]).iter().copied()
+            {
+                let shared = &edges[&(a.min(b), a.max(b))];
+
+                if shared.len() != 2
+                {
+                    closed = false;
+                    continue;
+                }
+
+                let &(n, n_forward) = shared.iter().find(|(n, _)| *n != t).unwrap_or(&shared[0]);
+
+                if n == t || seen[n]
+                {
+                    continue;
+                }
+
+                // neighbours agree when they go along the edge opposite ways
+                flip[n] = flip[t] ^ ((a < b) == n_forward);
+                seen[n] = true;
+                component.push(n);
+            }
+        }
+
+        if closed
+        {
+            let volume = component.iter()
+                .map(|&t|
+                {
+                    let tri = &triangles[t];
+                    let (b, c) = if flip[t] { (tri.c, tri.b) } else { (tri.b, tri.c) };
+
+                    dot(tri.a, cross(b, c)) as f64
+                })
+                .sum::<f64>();
+
+            if volume < 0.0
+            {
+                for &t in &component
+                {
+                    flip[t] = !flip[t];
+                }
+            }
+        }
+    }
+
+    for (tri, &flip) in triangles.iter_mut().zip(&flip)
+    {
+        if flip
+        {
+            std::mem::swap(&mut tri.b, &mut tri.c);
+            report.flipped += 1;
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// The area of the triangles `triangulate` makes, checking that each is
+    /// wound the same way as the polygon.
+    fn triangulated_area(points: &[[f32; 3]]) -> f32
+    {
+        let normal = polygon_normal(points);
+
+        triangulate(points).unwrap().iter()
+            .map(|&[a, b, c]|
+            {
+                let n = cross(sub(points[b], points[a]), sub(points[c], points[a]));
+                assert!(dot(n, normal) > 0.0, "{:?} is wound backwards", [a, b, c]);
+                length(n) / 2.0
+            })
+            .sum()
+    }
+
+    /// Puts points from the xy plane onto a tilted one through (1, 2, 3).
+    fn tilt(flat: &[[f32; 2]]) -> Vec<[f32; 3]>
+    {
+        let u = normalize([1.0, 1.0, 0.0]);
+        let v = normalize([-1.0, 1.0, 1.0]);
+
+        flat.iter()
+            .map(|p| add([1.0, 2.0, 3.0], add(scale(u, p[0]), scale(v, p[1]))))
+            .collect()
+    }
+
+    /// A star with `n` points, alternating between radius 2 and 1.
+    fn star(n: usize) -> Vec<[f32; 2]>
+    {
+        (0..n * 2)
+            .map(|i|
+            {
+                let angle = i as f32 * std::f32::consts::PI / n as f32;
+                let r = if i % 2 == 0 { 2.0 } else { 1.0 };
+                [r * angle.cos(), r * angle.sin()]
+            })
+            .collect()
+    }
+
+    /// A cube of side 2 around `center`, with every face wound outwards.
+    fn cube(center: [f32; 3]) -> Vec<Triangle>
+    {
+        let mut tris = Vec::new();
+
+        let axis = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+        for k in 0..3
+        {
+            let (n, u, v) = (axis[k], axis[(k + 1) % 3], axis[(k + 2) % 3]);
+
+            for side in [1.0, -1.0]
+            {
+                // anticlockwise seen from outside, since u x v = n
+                let mut corners = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]]
+                    .map(|[x, y]| add(center, add(scale(n, side), add(scale(u, x), scale(v, y)))));
+                if side < 0.0
+                {
+                    corners.reverse();
+                }
+
+                for [a, b, c] in [[0, 1, 2], [0, 2, 3]]
+                {
+                    tris.push(Triangle { a: corners[a], b: corners[b], c: corners[c], mat: 0 });
+                }
+            }
+        }
+
+        tris
+    }
+
+    fn faces_out(t: &Triangle, center: [f32; 3]) -> bool
+    {
+        let n = cross(sub(t.b, t.a), sub(t.c, t.a));
+        let mid = scale(add(t.a, add(t.b, t.c)), 1.0 / 3.0);
+
+        dot(n, sub(mid, center)) > 0.0
+    }
+
+    fn flipped(t: &Triangle) -> Triangle
+    {
+        Triangle { a: t.a, b: t.c, c: t.b, mat: t.mat }
+    }
+
+    #[test]
+    fn scrambled_cube_faces_out()
+    {
+        let center = [0.5, 1.0, -2.0];
+        let cube = cube(center);
+        assert!(cube.iter().all(|t| faces_out(t, center)));
+
+        // every third triangle backwards, and the whole cube inside out
+        let scrambled = cube.iter().enumerate()
+            .map(|(i, t)| if i % 3 == 0 { flipped(t) } else { *t })
+            .collect::<Vec<_>>();
+        let inside_out = cube.iter().map(flipped).collect::<Vec<_>>();
+
+        for (mut tris, flips) in [(scrambled, 4), (inside_out, 12), (cube.clone(), 0)]
+        {
+            let report = fix_winding(&mut tris);
+
+            assert_eq!(report.flipped, flips);
+            assert_eq!(report.components, 1);
+            assert_eq!(report.non_manifold, 0);
+            assert!(report.example.is_none());
+            assert!(tris.iter().all(|t| faces_out(t, center)), "{:?}", tris);
+        }
+    }
+
+    #[test]
+    fn separate_pieces_and_non_manifold_edges()
+    {
+        let (left, right) = ([-3.0, 0.0, 0.0], [3.0, 0.0, 0.0]);
+        let mut tris = cube(left);
+        tris.extend(cube(right).iter().map(flipped));
+
+        let report = fix_winding(&mut tris);
+        assert_eq!(report.components, 2);
+        assert_eq!(report.flipped, 12);
+        assert!(tris[..12].iter().all(|t| faces_out(t, left)));
+        assert!(tris[12..].iter().all(|t| faces_out(t, right)));
+
+        // a fin on one of the cube's edges, which is then shared three ways
+        let mut tris = cube([0.0; 3]);
+        let edge = (tris[0].a, tris[0].b);
+        tris.push(Triangle { a: edge.0, b: edge.1, c: [5.0, 5.0, 5.0], mat: 0 });
+
+        let report = fix_winding(&mut tris);
+        assert_eq!(report.non_manifold, 1);
+        assert_eq!(report.example, Some(scale(add(edge.0, edge.1), 0.5)));
+    }
+
+    #[test]
+    fn concave_l_shape()
+    {
+        let l = [[0.0, 0.0], [3.0, 0.0], [3.0, 1.0], [1.0, 1.0], [1.0, 3.0], [0.0, 3.0]];
+
+        let points = tilt(&l);
+        assert_eq!(triangulate(&points).unwrap().len(), 4);
+        assert!((triangulated_area(&points) - 5.0).abs() < 1e-4);
+
+        // going the other way round just faces the other way
+        let reversed = points.iter().rev().copied().collect::<Vec<_>>();
+        assert!((triangulated_area(&reversed) - 5.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn stars()
+    {
+        for n in [5, 15]
+        {
+            let points = tilt(&star(n));
+
+            // each point is two triangles from the middle, of sides 2 and 1
+            // with the angle between them half a point across
+            let want = n as f32 * 2.0 * 0.5 * 2.0 * (std::f32::consts::PI / n as f32).sin();
+
+            assert_eq!(triangulate(&points).unwrap().len(), n * 2 - 2);
+            assert!((triangulated_area(&points) - want).abs() < 1e-3 * want,
+                "{}-pointed star has area {}, not {}", n, triangulated_area(&points), want);
+        }
+    }
+
+    #[test]
+    fn straight_edges_and_bad_polygons()
+    {
+        // a square with a point halfway along one side
+        let square = tilt(&[[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [2.0, 2.0], [0.0, 2.0]]);
+        assert!((triangulated_area(&square) - 4.0).abs() < 1e-4);
+
+        let bow_tie = tilt(&[[0.0, 0.0], [2.0, 2.0], [2.0, 0.0], [0.0, 1.0]]);
+        let err = triangulate(&bow_tie).unwrap_err();
+        assert!(err.contains("edges 0 and 2"), "{}", err);
+
+        let line = tilt(&[[0.0, 0.0], [1.0, 0.0], [2.0, 0.0]]);
+        assert!(triangulate(&line).is_err());
+    }
+}
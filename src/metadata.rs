@@ -0,0 +1,164 @@
+//! Text about how an image was rendered, kept in the image file.
+
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+/// Saves `image` to `path`, with `metadata` as iTXt chunks if it's a PNG.
+/// Other formats are saved without it.
+pub fn save_image(image: &image::DynamicImage, path: &str, metadata: &[(String, String)])
+    -> Result<(), String>
+{
+    let is_png = std::path::Path::new(path)
+        .extension()
+        .map(|e| e.eq_ignore_ascii_case("png")) == Some(true);
+
+    if !is_png
+    {
+        return image.save(path)
+            .map_err(|e| format!("Could not save \"{}\": {}", path, e));
+    }
+
+    let mut png = Vec::new();
+    image.write_to(&mut png, image::ImageOutputFormat::Png)
+        .map_err(|e| format!("Could not save \"{}\": {}", path, e))?;
+
+    let png = add_png_text(&png, metadata)?;
+
+    std::fs::write(path, png)
+        .map_err(|e| format!("Could not save \"{}\": {}", path, e))
+}
+
+/// The tEXt and iTXt chunks of a PNG file, in the order they appear.
+pub fn read_metadata(path: &str) -> Result<Vec<(String, String)>, String>
+{
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("Could not read \"{}\": {}", path, e))?;
+
+    let mut fields = Vec::new();
+
+    for (kind, data) in png_chunks(&bytes).map_err(|e| format!("\"{}\" {}", path, e))?
+    {
+        let split = |data: &[u8]| match data.iter().position(|&b| b == 0)
+        {
+            Some(i) => Ok((String::from_utf8_lossy(&data[..i]).into_owned(), i + 1)),
+            None => Err(format!("\"{}\" has a broken text chunk", path)),
+        };
+
+        match kind
+        {
+            b"tEXt" =>
+            {
+                // Latin-1, which maps straight onto the first 256 chars
+                let (key, start) = split(data)?;
+                let text = data[start..].iter().map(|&b| b as char).collect();
+
+                fields.push((key, text));
+            },
+            b"iTXt" =>
+            {
+                let (key, start) = split(data)?;
+
+                // compressed text isn't written by `save_image`
+                if data.get(start) != Some(&0)
+                {
+                    continue;
+                }
+
+                // skip the compression method, language and translated key
+                let mut rest = start + 2;
+                for _ in 0..2
+                {
+                    rest += split(&data[rest.min(data.len())..])?.1;
+                }
+
+                fields.push((key, String::from_utf8_lossy(&data[rest..]).into_owned()));
+            },
+            _ => (),
+        }
+    }
+
+    Ok(fields)
+}
+
+/// `png` with an uncompressed iTXt chunk for each of `fields` after its
+/// header.
+fn add_png_text(png: &[u8], fields: &[(String, String)]) -> Result<Vec<u8>, String>
+{
+    // the signature and the IHDR chunk, which always comes first
+    let header_end = 8 + 12 + 13;
+
+    if png.len() < header_end || &png[..8] != PNG_SIGNATURE
+    {
+        return Err("The encoder didn't write a PNG".to_owned());
+    }
+
+    let mut out = png[..header_end].to_vec();
+
+    for (key, text) in fields
+    {
+        // keyword, no compression, no language or translated keyword
+        let mut data = key.as_bytes().to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0, 0]);
+        data.extend_from_slice(text.as_bytes());
+
+        let mut chunk = b"iTXt".to_vec();
+        chunk.extend_from_slice(&data);
+
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(&chunk);
+        out.extend_from_slice(&crc32(&chunk).to_be_bytes());
+    }
+
+    out.extend_from_slice(&png[header_end..]);
+
+    Ok(out)
+}
+
+/// A PNG chunk's type and data.
+type Chunk<'a> = (&'a [u8], &'a [u8]);
+
+/// Every chunk of a PNG.
+fn png_chunks(bytes: &[u8]) -> Result<Vec<Chunk<'_>>, String>
+{
+    if bytes.len() < 8 || &bytes[..8] != PNG_SIGNATURE
+    {
+        return Err("isn't a PNG".to_owned());
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = 8;
+
+    while pos + 12 <= bytes.len()
+    {
+        let mut len = [0; 4];
+        len.copy_from_slice(&bytes[pos..pos + 4]);
+        let len = u32::from_be_bytes(len) as usize;
+
+        if pos + 12 + len > bytes.len()
+        {
+            return Err("is cut off".to_owned());
+        }
+
+        chunks.push((&bytes[pos + 4..pos + 8], &bytes[pos + 8..pos + 8 + len]));
+        pos += 12 + len;
+    }
+
+    Ok(chunks)
+}
+
+/// The CRC-32 that PNG chunks end with.
+fn crc32(bytes: &[u8]) -> u32
+{
+    let mut crc = 0xffff_ffffu32;
+
+    for &b in bytes
+    {
+        crc ^= b as u32;
+
+        for _ in 0..8
+        {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
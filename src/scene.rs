@@ -1,4 +1,19 @@
-use crate::gpu::{run_shader, Camera, Triangle, Material};
+use crate::gpu::{
+    render_budget, run_shader, Aovs, Camera, Colour, GpuContext, GpuError, GpuProfile, Hooks,
+    RenderBudget, RenderMode, SceneBuffers, ShaderSettings, Timings, Triangle, Material, Noise};
+use crate::benchmark::{Benchmark, Estimate};
+use crate::checkpoint::{self, Checkpoint};
+use crate::clock::Instant;
+use crate::animation::{Animation, Orbit};
+use crate::def::{CameraDef, Format, MatRef, MaterialDef, NodeDef, SceneDef, ShapeDef, TextureDef};
+use crate::handle::{ProgressInfo, RenderControl, RenderHandle};
+use crate::mesh::{
+    height_grid, icosphere, polygon_normal, triangulate, Heightmap, WindingReport,
+    MAX_HEIGHTMAP_RESOLUTION, MAX_SPHERE_SUBDIVISIONS};
+use crate::stop::{Controlled, StopCondition};
+use crate::text::{draw_overlay, draw_text, text_size, Overlay, CHAR_WIDTH};
+use crate::texture::{Texture, DEFAULT_UVS};
+use crate::transform::Transform;
 
 #[derive(Clone, Debug)]
 pub struct Scene
@@ -6,636 +21,3646 @@ pub struct Scene
     pub camera: Camera,
     pub triangles: Vec<Triangle>,
     pub materials: Vec<Material>,
+    pub animation: Option<Animation>,
+    /// every camera in the scene file, with the singular "camera" as "default"
+    pub cameras: Vec<(String, Camera)>,
+    /// the triangles of each "group" in the scene file, and of each named
+    /// node with everything in it
+    pub groups: Vec<(String, Vec<usize>)>,
+    /// the scene file's nodes with the triangles directly in each, for
+    /// `Scene::at_frame`
+    pub nodes: Vec<(NodeDef, Vec<usize>)>,
+    /// the scene file's camera was "auto", so it should be framed again
+    /// with `frame_camera` once the shape of the image is known
+    pub auto_camera: bool,
+    /// brightness in stops, the image is multiplied by 2^exposure before
+    /// it's quantized
+    pub exposure: f32,
+    /// how far each triangle moves in a frame, or empty when none move
+    pub velocities: Vec<[f32; 3]>,
+    /// how much of a frame the shutter is open for, moving triangles are
+    /// blurred over it
+    pub shutter: f32,
+    /// the colours at each triangle's points, which its material's colour
+    /// is multiplied by across the face, or empty when none have them
+    pub colours: Vec<[[f32; 3]; 3]>,
+    /// where each triangle's points are on its material's textures, with u
+    /// going right and v going up, or empty when none have them
+    pub uvs: Vec<[[f32; 2]; 3]>,
+    /// the images materials refer to, see `Material::glow_texture`
+    pub textures: Vec<Texture>,
+    /// the normals at each triangle's points, which light bounces off as
+    /// they blend across the face, or empty when all are flat. Zeroes use
+    /// the face's normal.
+    pub normals: Vec<[[f32; 3]; 3]>,
+    /// each material's noise, which replaces its colour, or empty when none
+    /// have any
+    pub noises: Vec<Noise>,
+    /// what `transform` has moved the triangles by since they were placed
+    pub transformed: Transform,
 }
 
-impl Scene
+/// Everything about a render besides the scene.
+#[derive(Clone, Debug)]
+pub struct RenderSettings
 {
-    pub fn new(pos: [f32; 3], front: [f32; 3], up: [f32; 3], fov: f32) -> Scene
+    pub res: [u32; 2],
+    pub mode: RenderMode,
+    /// bounces per path when path tracing
+    pub depth: u32,
+    /// how far rays start off the surface they bounce from, as a fraction of
+    /// the largest coordinate of where they hit, or of 1 near the origin
+    pub ray_epsilon: f32,
+    /// stop after this many samples, or earlier if `time_limit` runs out
+    pub samples: u32,
+    pub time_limit: Option<std::time::Duration>,
+    /// x, y, width, height from the top left of the image
+    pub region: Option<[u32; 4]>,
+    pub crop: bool,
+    /// render this many times `res` across and down, and filter it down to
+    /// `res` before the debug information and annotation are drawn, or 1 to
+    /// render at `res`
+    pub supersample: u32,
+    /// in rendered pixels, see `supersample`
+    pub tile: Option<[u32; 2]>,
+    pub debug: bool,
+    /// text to write in the top left corner of the image, or the corner
+    /// above or below the debug information
+    pub annotate: Option<String>,
+    /// where and how to draw `debug` and `annotate`
+    pub overlay: Overlay,
+    /// the same seed, scene and settings render the same image, a random
+    /// seed is used when it's `None`
+    pub seed: Option<u64>,
+    /// groups to render alone, or every triangle when empty
+    pub only: Vec<String>,
+    /// groups to leave out
+    pub hide: Vec<String>,
+    /// path and number of samples between checkpoints
+    pub checkpoint: Option<(String, u32)>,
+    pub snapshot: Option<(String, Every)>,
+    pub denoise: Option<f32>,
+    /// brightness in stops, instead of the scene's `exposure`
+    pub exposure: Option<f32>,
+    pub white_balance: Option<WhiteBalance>,
+    /// measure how noisy each pixel is, for `Framebuffer::noise`
+    pub noise: bool,
+    /// record each pixel's distance to the first surface, for
+    /// `Framebuffer::depth`
+    pub depth_map: bool,
+    /// index or part of the name of the GPU to use
+    pub adapter: Option<String>,
+    /// fail rather than fall back to an integrated or software adapter
+    pub require_discrete: bool,
+    /// render on the CPU instead of a GPU, ignoring `adapter`
+    pub cpu: bool,
+    /// a WGSL file to use instead of the built-in shader, see
+    /// `GpuContext::set_shader`
+    pub shader: Option<String>,
+    /// longest a single GPU submission should take, or `None` to dispatch
+    /// whole tiles
+    pub max_dispatch: Option<std::time::Duration>,
+    /// time the GPU's work and log it at the end, see `GpuProfile`
+    pub profile_gpu: bool,
+    /// for asking the render to save the image while it runs
+    pub control: Option<std::sync::Arc<RenderControl>>,
+}
+
+impl RenderSettings
+{
+    /// The default `ray_epsilon`, thousands of times an f32's precision.
+    pub const RAY_EPSILON: f32 = 0.001;
+
+    pub fn new(res: [u32; 2]) -> RenderSettings
     {
-        Scene
+        RenderSettings
         {
-            camera: Camera
-            {
-                pos: pos,
-                front: front,
-                up: up,
-                fov: fov,
-            },
-            triangles: Vec::new(),
-            materials: Vec::new(),
+            res: res,
+            mode: RenderMode::PathTrace,
+            depth: 5,
+            ray_epsilon: RenderSettings::RAY_EPSILON,
+            samples: 100,
+            time_limit: None,
+            region: None,
+            crop: false,
+            supersample: 1,
+            tile: None,
+            debug: false,
+            annotate: None,
+            overlay: Overlay::default(),
+            seed: None,
+            only: Vec::new(),
+            hide: Vec::new(),
+            checkpoint: None,
+            snapshot: None,
+            denoise: None,
+            exposure: None,
+            white_balance: None,
+            noise: false,
+            depth_map: false,
+            adapter: None,
+            require_discrete: false,
+            cpu: false,
+            shader: None,
+            max_dispatch: Some(std::time::Duration::from_millis(500)),
+            profile_gpu: false,
+            control: None,
         }
     }
 
-    pub fn render(
-        &self,
-        res: [u32; 2],
-        depth: u32,
-        condition: &dyn Fn(u32) -> bool,
-        debug: bool)
-        -> image::RgbImage
+    /// The resolution that's rendered, `res` times `supersample`.
+    pub fn render_res(&self) -> [u32; 2]
     {
-        let start = std::time::Instant::now();
-        let mut image = Vec::with_capacity((res[0] * res[1]) as usize);
+        self.res.map(|n| n * self.supersample)
+    }
 
-        let samples = run_shader(
-            &mut image,
-            res[0],
-            res[1],
-            self.camera,
-            &self.triangles,
-            &self.materials,
-            depth,
-            condition);
+    /// `region` at `render_res`.
+    pub fn render_region(&self) -> Option<[u32; 4]>
+    {
+        self.region.map(|r| r.map(|n| n * self.supersample))
+    }
 
-        let mut file = image::RgbImage::new(res[0], res[1]);
+    /// The stop condition from `samples` and `time_limit`, with the time
+    /// counted from when this is called.
+    pub fn condition(&self) -> impl Fn(u32) -> bool
+    {
+        let (max, time) = (self.samples, self.time_limit);
+        let start = Instant::now();
 
-        for y in 0..res[1]
+        move |samples| samples < max && match time
         {
-            for x in 0..res[0]
-            {
-                let px = image[(y * res[0] + x) as usize];
-
-                file.put_pixel(x, res[1] - y - 1, image::Rgb([
-                    (px.r * 255.0 / samples as f32) as u8,
-                    (px.g * 255.0 / samples as f32) as u8,
-                    (px.b * 255.0 / samples as f32) as u8,
-                ]));
-            }
+            Some(time) => start.elapsed() < time,
+            None => true,
         }
+    }
 
-        let time = std::time::Instant::now() - start;
-        println!(
-            "Finished {}x{} render with {} samples in {} ({:0.02}s/sample average)",
-            res[0], res[1],
-            samples,
-            fmt_time(time),
-            time.as_secs_f32() / samples as f32);
+    /// What `run_shader` needs of these, for a render from `seed` that
+    /// carries on from `start_samples`.
+    fn shader_settings(&self, seed: u64, start_samples: u32) -> ShaderSettings
+    {
+        let res = self.render_res();
 
-        if debug
+        ShaderSettings
         {
-            add_debug_info(&mut file, self.triangles.len(), samples, time);
+            width: res[0],
+            height: res[1],
+            mode: self.mode,
+            noise: self.noise,
+            depth_map: self.depth_map,
+            depth: self.depth,
+            ray_epsilon: self.ray_epsilon,
+            seed: seed,
+            start_samples: start_samples,
+            // the image is stored bottom row first
+            region: self.render_region().map(|r| [r[0], res[1] - r[1] - r[3], r[2], r[3]]),
+            tile: self.tile,
+            max_dispatch: self.max_dispatch,
         }
-
-        file
     }
+}
 
-    pub fn add_triangle(
-        &mut self, a: [f32; 3], b: [f32; 3], c: [f32; 3], mat: u32)
-        -> &mut Self
-    {
-        self.triangles.push(Triangle
-        {
-            a: a,
-            b: b,
-            c: c,
-            mat: mat,
-        });
-
-        self
-    }
+/// What `Scene::render_cameras` renders: a frame from each of `cameras`
+/// until `condition`, the first carrying on from `resume`.
+struct Shot<'a>
+{
+    cameras: &'a [Camera],
+    condition: &'a dyn StopCondition,
+    resume: Option<Checkpoint>,
+}
 
-    pub fn add_quad(
-        &mut self, a: [f32; 3], b: [f32; 3], c: [f32; 3], d: [f32; 3], mat: u32)
-        -> &mut Self
-    {
-        self
-            .add_triangle(a, b, c, mat)
-            .add_triangle(a, d, c, mat)
-    }
+/// A finished render, as the average colour of each pixel from the top left.
+#[derive(Clone, Debug)]
+pub struct Framebuffer
+{
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub pixels: Vec<Colour>,
+    /// how opaque each pixel is, when the scene has a shadow catcher, with
+    /// `pixels` already multiplied by it
+    pub alpha: Option<Vec<f32>>,
+    /// the standard error of each pixel's luminance, when
+    /// `RenderSettings::noise` is set
+    pub noise: Option<Vec<f32>>,
+    /// each pixel's distance to the first surface, or infinity where
+    /// nothing was hit, when `RenderSettings::depth_map` is set
+    pub depth: Option<Vec<f32>>,
+    /// how long the frame took to render
+    pub time: std::time::Duration,
+    /// how long the GPU spent on it, when `RenderSettings::profile_gpu` is
+    /// set and it's from `Scene::render_with`
+    pub profile: Option<GpuProfile>,
+    /// the stops `pixels` were scaled by
+    exposure: f32,
+    /// the white balance's gains, which `pixels` were multiplied by
+    gains: [f32; 3],
+    /// triangles, render time and mode, when the debug overlay is on
+    debug: Option<(usize, std::time::Duration, RenderMode)>,
+    /// from `RenderSettings::annotate`
+    annotation: Option<String>,
+    /// how the debug information and annotation are drawn
+    overlay: Overlay,
+}
 
-    pub fn add_material(&mut self, mat: Material) -> u32
+impl Framebuffer
+{
+    /// The image a checkpoint has so far, scaled by 2^`exposure`.
+    pub fn from_checkpoint(checkpoint: &Checkpoint, exposure: f32) -> Framebuffer
     {
-        self.materials.push(mat);
-
-        (self.materials.len() - 1) as u32
+        Framebuffer::new(
+            &checkpoint.pixels,
+            [checkpoint.width, checkpoint.height],
+            checkpoint.samples,
+            exposure)
     }
 
-    pub fn parse(s: &str) -> Result<Scene, String>
+    /// Normalizes accumulated samples, which are stored bottom row first,
+    /// and scales them by 2^`exposure`.
+    fn new(image: &[Colour], res: [u32; 2], samples: u32, exposure: f32) -> Framebuffer
     {
-        use json::JsonValue;
+        let scale = exposure.exp2() / samples as f32;
 
-        use std::collections::HashMap;
-
-        let top = json::parse(s)
-            .map_err(|e| format!(
-                "Error parsing scene JSON: {}", e))?;
+        let pixels = (0..res[1]).rev()
+            .flat_map(|y| &image[(y * res[0]) as usize..((y + 1) * res[0]) as usize])
+            .map(|px| Colour
+            {
+                r: px.r * scale,
+                g: px.g * scale,
+                b: px.b * scale,
+            })
+            .collect();
 
-        if !top.is_object()
+        Framebuffer
         {
-            return Err("Scene wasn't a JSON object".to_owned());
+            width: res[0],
+            height: res[1],
+            samples: samples,
+            pixels: pixels,
+            alpha: None,
+            noise: None,
+            depth: None,
+            time: std::time::Duration::from_secs(0),
+            profile: None,
+            exposure: exposure,
+            gains: [1.0; 3],
+            debug: None,
+            annotation: None,
+            overlay: Overlay::default(),
         }
+    }
 
-        let mut scene = if top.has_key("camera")
-        {
-            let camera = &top["camera"];
+    /// Works out `alpha` from the sums of the shader's matte, which is
+    /// stored bottom row first and has `samples` samples. Surfaces are
+    /// opaque, and shadow catchers are as opaque as the light they lose.
+    fn set_matte(&mut self, matte: &[[f32; 4]], samples: u32)
+    {
+        let res = [self.width, self.height];
 
-            if !camera.is_object()
+        self.alpha = Some((0..res[1]).rev()
+            .flat_map(|y| &matte[(y * res[0]) as usize..((y + 1) * res[0]) as usize])
+            .map(|&[covered, caught, lit, open]|
             {
-                return Err("\"camera\" entry in Scene wasn't a object".to_owned());
-            }
+                let shadow = if open > 0.0 { (1.0 - lit / open).max(0.0) } else { 0.0 };
 
-            let pos = if camera.has_key("pos")
-            {
-                parse_vec3(&camera["pos"], "camera", "pos")?
-            }
-            else
-            {
-                return Err("\"camera\" didn't contain \"pos\" array".to_owned());
-            };
+                ((covered + caught * shadow) / samples as f32).min(1.0)
+            })
+            .collect());
+    }
 
-            let front = if camera.has_key("front")
-            {
-                parse_vec3(&camera["front"], "camera", "front")?
-            }
-            else
-            {
-                return Err("\"camera\" didn't contain \"front\" array".to_owned());
-            };
+    /// Works out `noise` from the shader's sums of squared luminance and the
+    /// accumulated `image` they came from, both stored bottom row first with
+    /// `samples` samples.
+    fn set_noise(&mut self, image: &[Colour], squares: &[f32], samples: u32, exposure: f32)
+    {
+        let res = [self.width, self.height];
+        let n = samples as f32;
+        let scale = exposure.exp2();
 
-            let up = if camera.has_key("up")
-            {
-                parse_vec3(&camera["up"], "camera", "up")?
-            }
-            else
+        self.noise = Some((0..res[1]).rev()
+            .flat_map(|y| (y * res[0]) as usize..((y + 1) * res[0]) as usize)
+            .map(|i|
             {
-                return Err("\"camera\" didn't contain \"up\" array".to_owned());
-            };
+                let px = image[i];
+                let mean = (0.2126 * px.r + 0.7152 * px.g + 0.0722 * px.b) / n;
+                let variance = (squares[i] / n - mean * mean).max(0.0) * n / (n - 1.0);
 
-            let fov = if camera.has_key("fov")
-            {
-                let fov = &camera["fov"];
-                if let Some(fov) = fov.as_f32()
-                {
-                    fov.to_radians()
-                }
-                else
-                {
-                    return Err("\"fov\" entry in \"camera\" wasn't an f32".to_owned());
-                }
-            }
-            else
-            {
-                return Err("\"camera\" didn't contain \"fov\" f32".to_owned());
-            };
+                (variance / n).sqrt() * scale
+            })
+            .collect());
+    }
 
-            Scene::new(pos, front, up, fov)
-        }
-        else
+    /// Sets `depth` from the shader's distances, which are stored bottom row
+    /// first and are -1 for misses and 0 outside the region.
+    fn set_depth(&mut self, depth: &[f32])
+    {
+        let res = [self.width, self.height];
+
+        self.depth = Some((0..res[1]).rev()
+            .flat_map(|y| &depth[(y * res[0]) as usize..((y + 1) * res[0]) as usize])
+            .map(|&d| if d > 0.0 { d } else { f32::INFINITY })
+            .collect());
+    }
+
+    fn white_balance(&mut self, wb: Option<WhiteBalance>) -> Result<(), String>
+    {
+        let gains = match wb
         {
-            return Err("Scene didn't contain \"camera\" object".to_owned());
+            Some(wb) => wb.gains(self)?,
+            None => return Ok(()),
         };
 
-        let materials = if top.has_key("materials")
+        for px in &mut self.pixels
         {
-            let mats = &top["materials"];
+            px.r *= gains[0];
+            px.g *= gains[1];
+            px.b *= gains[2];
+        }
 
-            if !mats.is_object()
-            {
-                return Err("\"materials\" entry in Scene wasn't an object".to_owned());
-            }
+        self.gains = gains;
 
-            let mut map: HashMap<String, u32> = HashMap::new();
+        Ok(())
+    }
 
-            for (name, mat) in mats.entries()
-            {
-                if map.contains_key(name)
-                {
-                    return Err(format!("Duplicate material \"{}\"", name));
-                }
+    /// The image at `exposure` stops instead of the one it was made with.
+    pub fn with_exposure(&self, exposure: f32) -> Framebuffer
+    {
+        let scale = (exposure - self.exposure).exp2();
 
-                let index = if mat.is_object()
+        Framebuffer
+        {
+            pixels: self.pixels.iter()
+                .map(|px| Colour
                 {
-                    let colour = if mat.has_key("colour")
-                    {
-                        parse_vec3(&mat["colour"], name, "colour")?
-                    }
-                    else
-                    {
-                        [0.0, 0.0, 0.0]
-                    };
+                    r: px.r * scale,
+                    g: px.g * scale,
+                    b: px.b * scale,
+                })
+                .collect(),
+            exposure: exposure,
+            .. self.clone()
+        }
+    }
 
-                    let glow = if mat.has_key("glow")
-                    {
-                        parse_vec3(&mat["glow"], name, "glow")?
-                    }
-                    else
-                    {
-                        [0.0, 0.0, 0.0]
-                    };
+    /// Puts two images of the same size next to each other, for stereo.
+    /// The debug information and annotation are drawn once, from `left`.
+    pub fn side_by_side(left: Framebuffer, right: Framebuffer) -> Framebuffer
+    {
+        fn join<T: Copy>(a: &[T], b: &[T], width: u32) -> Vec<T>
+        {
+            a.chunks(width as usize)
+                .zip(b.chunks(width as usize))
+                .flat_map(|(a, b)| a.iter().chain(b).copied())
+                .collect()
+        }
 
-                    let gloss = if mat.has_key("gloss")
-                    {
-                        let gloss= &mat["gloss"];
-                        if let Some(gloss) = gloss.as_f32()
-                        {
-                            gloss
-                        }
-                        else
-                        {
-                            return Err(format!(
-                                "\"gloss\" entry in \"{}\" wasn't an f32", name));
-                        }
-                    }
-                    else
-                    {
-                        0.0
-                    };
+        let width = left.width;
+        let both = |a: &Option<Vec<f32>>, b: &Option<Vec<f32>>| match (a, b)
+        {
+            (Some(a), Some(b)) => Some(join(a, b, width)),
+            _ => None,
+        };
 
-                    let reflect_c = if  mat.has_key("reflect_c")
-                    {
-                        parse_vec3(&mat["reflect_c"], name, "reflect_c")?
-                    }
-                    else
-                    {
-                        [1.0, 1.0, 1.0]
-                    };
+        Framebuffer
+        {
+            width: width + right.width,
+            pixels: join(&left.pixels, &right.pixels, width),
+            alpha: both(&left.alpha, &right.alpha),
+            noise: both(&left.noise, &right.noise),
+            depth: both(&left.depth, &right.depth),
+            time: left.time + right.time,
+            .. left
+        }
+    }
 
-                    scene.add_material(Material
-                    {
-                        colour: colour,
-                        glow: glow,
-                        gloss: gloss,
-                        reflect_c: reflect_c,
-                    })
-                }
-                else
+    /// Keeps only the pixels in `r`, as x, y, width, height.
+    fn crop(&mut self, r: [u32; 4])
+    {
+        fn crop<T: Copy>(v: &[T], width: u32, r: [u32; 4]) -> Vec<T>
+        {
+            (r[1]..r[1] + r[3])
+                .flat_map(|y|
                 {
-                    return Err(format!("Material \"{}\" wasn't an object", name));
-                };
+                    let row = (y * width + r[0]) as usize;
+                    v[row..row + r[2] as usize].iter().copied()
+                })
+                .collect()
+        }
 
-                map.insert(name.to_owned(), index);
-            }
+        self.pixels = crop(&self.pixels, self.width, r);
+        self.alpha = self.alpha.as_ref().map(|a| crop(a, self.width, r));
+        self.noise = self.noise.as_ref().map(|n| crop(n, self.width, r));
+        self.depth = self.depth.as_ref().map(|d| crop(d, self.width, r));
+        self.width = r[2];
+        self.height = r[3];
+    }
 
-            map
+    /// Shrinks the image by `factor` across and down, which has to divide
+    /// its size, with `filter_down`. The alpha is filtered the same way, the
+    /// noise is the filtered variance shrunk by the pixels that went into
+    /// each, and each depth is the nearest in its block. 1 does nothing.
+    fn downsample(&mut self, factor: u32)
+    {
+        if factor == 1
+        {
+            return;
         }
-        else
+
+        let (width, height) = (self.width, self.height);
+
+        let rgb = self.pixels.iter().flat_map(|px| [px.r, px.g, px.b]).collect::<Vec<_>>();
+        self.pixels = filter_down(&rgb, 3, width, height, factor)
+            .chunks(3)
+            .map(|c| Colour
+            {
+                // the filter's negative lobes can ring below black
+                r: c[0].max(0.0),
+                g: c[1].max(0.0),
+                b: c[2].max(0.0),
+            })
+            .collect();
+
+        self.alpha = self.alpha.as_ref().map(|a| filter_down(a, 1, width, height, factor)
+            .into_iter()
+            .map(|a| a.clamp(0.0, 1.0))
+            .collect());
+
+        self.noise = self.noise.as_ref().map(|n|
         {
-            return Err("Scene didn't contain \"materials\" object".to_owned());
-        };
+            let variance = n.iter().map(|n| n * n).collect::<Vec<_>>();
+
+            filter_down(&variance, 1, width, height, factor)
+                .into_iter()
+                .map(|v| v.max(0.0).sqrt() / factor as f32)
+                .collect()
+        });
 
-        if !top.has_key("surfaces")
+        self.depth = self.depth.as_ref().map(|d|
         {
-            return Err("Scene didn't contain \"surfaces\" array".to_owned());
-        }
+            (0..height / factor)
+                .flat_map(|y| (0..width / factor).map(move |x| (x, y)))
+                .map(|(x, y)| (0..factor * factor)
+                    .map(|i| d[((y * factor + i / factor) * width + x * factor + i % factor) as usize])
+                    .fold(f32::INFINITY, f32::min))
+                .collect()
+        });
+
+        self.width = width / factor;
+        self.height = height / factor;
+    }
+
+    pub fn to_rgb_image(&self) -> image::RgbImage
+    {
+        self.image_with(|px, _| px)
+    }
 
-        let surfaces = &top["surfaces"];
+    /// The image with `colour` done to each pixel and its index before it's
+    /// quantised, and then any debug information and annotation.
+    fn image_with(&self, colour: impl Fn(Colour, usize) -> Colour) -> image::RgbImage
+    {
+        let mut file = image::RgbImage::from_fn(self.width, self.height, |x, y|
+        {
+            let i = (y * self.width + x) as usize;
+            let px = colour(self.pixels[i], i);
+
+            image::Rgb([
+                quantise(px.r, x, y),
+                quantise(px.g, x, y),
+                quantise(px.b, x, y),
+            ])
+        });
 
-        if !surfaces.is_array()
+        if let Some((triangles, time, mode)) = self.debug
         {
-            return Err("\"surfaces\" entry in Scene wasn't an array".to_owned());
+            add_debug_info(&mut file, triangles, self.samples, time, mode, &self.overlay);
         }
 
-        for obj in surfaces.members()
+        // above or below the debug information, out of its way
+        if let Some(text) = &self.annotation
         {
-            if !obj.is_object()
+            draw_overlay(&mut file, text, &Overlay
             {
-                return Err("surface wasn't an array".to_owned());
-            }
+                corner: self.overlay.corner.flip_vertical(),
+                .. self.overlay
+            });
+        }
 
-            let mat = if obj.has_key("mat")
-            {
-                if let Some(mat) = obj["mat"].as_u32()
-                {
-                    mat
-                }
-                else if let Some(mat) = obj["mat"].as_str()
-                {
-                    if let Some(mat) = materials.get(mat)
-                    {
-                        *mat
-                    }
-                    else
-                    {
-                        return Err(format!("Unknown material \"{}\"", mat));
-                    }
-                }
-                else
-                {
-                    return Err("\"mat\" entry in a surface wasn't a string or u32"
-                        .to_owned());
-                }
-            }
-            else
-            {
-                return Err("Surfaces didn't contain a \"mat\" index".to_owned());
-            };
+        file
+    }
 
-            if obj.has_key("tri")
+    /// The image with `alpha`, or opaque if there isn't one. The colours are
+    /// divided by the alpha before they're quantised, as PNGs expect.
+    pub fn to_rgba_image(&self) -> image::RgbaImage
+    {
+        let alpha = match &self.alpha
+        {
+            Some(alpha) => alpha,
+            None =>
             {
-                if obj.has_key("quad")
+                let rgb = self.to_rgb_image();
+                return image::RgbaImage::from_fn(self.width, self.height, |x, y|
                 {
-                    return Err("A surface cannot be a triangle and a quad".to_owned());
-                }
-
-                let tri = &obj["tri"];
+                    let [r, g, b] = rgb.get_pixel(x, y).0;
+                    image::Rgba([r, g, b, 255])
+                });
+            },
+        };
 
-                if !tri.is_array()
-                {
-                    return Err("A triangle was not an array of points".to_owned());
-                }
+        let rgb = self.image_with(|px, i| match alpha[i]
+        {
+            a if a > 0.0 => Colour { r: px.r / a, g: px.g / a, b: px.b / a },
+            _ => Colour { r: 0.0, g: 0.0, b: 0.0 },
+        });
 
-                if tri.len() != 3
-                {
-                    return Err("A triangle list did not have length 3".to_owned());
-                }
+        image::RgbaImage::from_fn(self.width, self.height, |x, y|
+        {
+            let [r, g, b] = rgb.get_pixel(x, y).0;
+            let a = alpha[(y * self.width + x) as usize];
 
-                let a = parse_vec3(&tri[0], "tri", "0")?;
-                let b = parse_vec3(&tri[1], "tri", "1")?;
-                let c = parse_vec3(&tri[2], "tri", "2")?;
+            image::Rgba([r, g, b, (a * 255.0).round() as u8])
+        })
+    }
 
-                scene.add_triangle(a, b, c, mat);
-            }
-            else if obj.has_key("quad")
-            {
-                if obj.has_key("tri")
-                {
-                    return Err("A surface cannot be a triangle and a quad".to_owned());
-                }
+    /// `to_rgba_image` as bytes, four to a pixel, for drawing without saving
+    /// a file first, like to a canvas on a web page.
+    pub fn to_rgba8(&self) -> Vec<u8>
+    {
+        self.to_rgba_image().into_raw()
+    }
 
-                let quad = &obj["quad"];
+    /// `noise` in false colour, from dark purple for the least noisy pixels
+    /// to yellow for the most, with a legend along the bottom.
+    pub fn to_heatmap(&self) -> Option<image::RgbImage>
+    {
+        let noise = self.noise.as_ref()?;
 
-                if !quad.is_array()
-                {
-                    return Err("A quad was not an array of points".to_owned());
-                }
+        let min = noise.iter().cloned().fold(f32::INFINITY, f32::min);
+        let max = noise.iter().cloned().fold(0.0, f32::max);
+        let range = max - min;
 
-                if quad.len() != 4
-                {
-                    return Err("A quad list did not have length 4".to_owned());
-                }
+        let heat = image::RgbImage::from_fn(self.width, self.height, |x, y|
+        {
+            let n = noise[(y * self.width + x) as usize];
 
-                let a = parse_vec3(&quad[0], "quad", "0")?;
-                let b = parse_vec3(&quad[1], "quad", "1")?;
-                let c = parse_vec3(&quad[2], "quad", "2")?;
-                let d = parse_vec3(&quad[3], "quad", "3")?;
+            image::Rgb(viridis(if range > 0.0 { (n - min) / range } else { 0.0 }))
+        });
 
-                scene.add_quad(a, b, c, d, mat);
-            }
-            else
-            {
-                return Err("A surfaces wasn't a triangle or quad".to_owned());
-            }
-        }
+        Some(add_legend(&heat, min.min(max), max))
+    }
 
-        return Ok(scene);
+    /// Writes `depth` to `path`, as distances in an OpenEXR or PFM file if
+    /// the extension is `.exr` or `.pfm`, otherwise as a 16-bit greyscale
+    /// image from black at `range[0]` to white at `range[1]`. Misses are
+    /// infinite in the float formats and white in the others.
+    pub fn save_depth(&self, path: &str, range: [f32; 2]) -> Result<(), String>
+    {
+        let depth = self.depth.as_ref()
+            .ok_or("The render didn't record depth".to_owned())?;
 
-        fn parse_vec3(val: &JsonValue, outer: &str, name: &str)
-            -> Result<[f32; 3], String>
+        match extension(path).as_deref()
         {
-            if !val.is_array()
+            Some("exr") => crate::float::write_exr(path, self.width, self.height, &[("Z", depth)]),
+            Some("pfm") => crate::float::write_pfm(path, self.width, self.height, &[depth]),
+            _ =>
             {
-                return Err(format!("\"{}\" in \"{}\" wasn't an array",
-                    name, outer))
-            }
+                let [near, far] = range;
+                let scale = if far > near { 1.0 / (far - near) } else { 0.0 };
 
-            if val.len() != 3
-            {
-                return Err(format!("\"{}\" in \"{}\" didn't have a length of 3",
-                    name, outer));
-            }
+                let image = image::ImageBuffer::<image::Luma<u16>, _>::from_fn(
+                    self.width, self.height, |x, y|
+                    {
+                        let d = depth[(y * self.width + x) as usize].min(far);
+                        let t = ((d - near) * scale).clamp(0.0, 1.0);
 
-            let a = val[0].as_f32().ok_or(format!(
-                "first value in \"{}\" wasn't an f32", name))?;
-            let b = val[1].as_f32().ok_or(format!(
-                "second value in \"{}\" wasn't an f32", name))?;
-            let c = val[2].as_f32().ok_or(format!(
-                "third value in \"{}\" wasn't an f32", name))?;
+                        image::Luma([(t * 65535.0).round() as u16])
+                    });
 
-            Ok([a, b, c])
+                image.save(path)
+                    .map_err(|e| format!("Could not save \"{}\": {}", path, e))
+            },
         }
     }
-}
-
-fn fmt_time(d: std::time::Duration) -> String
-{
-    let s = d.as_secs();
-
-    format!("{}:{:02}:{:02}",
-        s / 3600,
-        (s % 3600) / 60,
-        s % 60)
-}
-
-fn add_debug_info(
-    image: &mut image::RgbImage,
-    triangles: usize,
-    samples: u32,
-    time: std::time::Duration)
-    -> bool
-{
-    let samples = format!("{} ", samples);
-    let triangles = format!("{} ", triangles);
-    let time = format!("{} ", fmt_time(time));
 
-    let height = (3 * 8) + 1;
-    let width = *[
-        samples.len() + SAMPLES_TEXT[0].len(),
-        triangles.len() + TRIANGLES_TEXT[0].len(),
-        time.len() + TIME_TEXT[0].len()].iter().max().unwrap();
+    /// Whether `save` writes the `radiance` to `path`, rather than 8-bit
+    /// colours.
+    pub fn saves_radiance(path: &str) -> bool
+    {
+        matches!(extension(path).as_deref(), Some("exr" | "pfm" | "hdr"))
+    }
 
-    if image.height() < height || image.width() < width as u32
+    /// RGBA if there's an `alpha`, otherwise RGB.
+    pub fn to_image(&self) -> image::DynamicImage
     {
-        return false;
+        match self.alpha
+        {
+            Some(_) => image::DynamicImage::ImageRgba8(self.to_rgba_image()),
+            None => image::DynamicImage::ImageRgb8(self.to_rgb_image()),
+        }
     }
 
-    let mut y_init = image.height() as usize - 3 * 8;
+    /// The red, green and blue of each pixel as the light that reached the
+    /// camera, before the exposure and white balance.
+    pub fn radiance(&self) -> [Vec<f32>; 3]
+    {
+        let scale = self.exposure.exp2();
+
+        [0, 1, 2].map(|i| self.pixels.iter()
+            .map(|px| [px.r, px.g, px.b][i] / (scale * self.gains[i]))
+            .collect())
+    }
 
-    for (val, text) in [
-            (samples, SAMPLES_TEXT),
-            (triangles, TRIANGLES_TEXT),
-            (time, TIME_TEXT)].iter()
+    /// Writes the image to `path`, in the format its extension names. The
+    /// float formats, `.exr`, `.pfm` and `.hdr`, get the `radiance`, with
+    /// the alpha too in OpenEXR. The others get `to_image`, with `metadata`
+    /// if they can keep it.
+    pub fn save(&self, path: &str, metadata: &[(String, String)]) -> Result<(), String>
     {
-        let mut x_init = 1;
+        let (width, height) = (self.width, self.height);
 
-        for c in val.chars()
+        match extension(path).as_deref()
         {
-            let pat = NUMBERS_TEXT[match c
+            Some("exr") =>
             {
-                '0' => 0,
-                '1' => 1,
-                '2' => 2,
-                '3' => 3,
-                '4' => 4,
-                '5' => 5,
-                '6' => 6,
-                '7' => 7,
-                '8' => 8,
-                '9' => 9,
-                ':' => 10,
-                ' ' => 11,
-                _ => unreachable!(),
-            }];
+                let [r, g, b] = self.radiance();
+                let mut channels = vec![("R", &r[..]), ("G", &g[..]), ("B", &b[..])];
 
-            for (y, line) in pat.iter().enumerate()
-            {
-                for (x, c) in line.chars().enumerate()
+                if let Some(alpha) = &self.alpha
                 {
-                    image.put_pixel(
-                        (x_init + x) as u32,
-                        (y_init + y) as u32,
-                        image::Rgb(if c == '#' { [255; 3] } else { [0; 3] }));
+                    channels.push(("A", alpha));
                 }
-            }
 
-            x_init += pat[0].len();
-        }
-
-        for (y, line) in text.iter().enumerate()
-        {
-            for (x, c) in line.chars().enumerate()
+                crate::float::write_exr(path, width, height, &channels)
+            },
+            Some("pfm") =>
             {
-                image.put_pixel(
-                    (x_init + x) as u32,
-                    (y_init + y) as u32,
-                    image::Rgb(if c == '#' { [255; 3] } else { [0; 3] }));
+                let [r, g, b] = self.radiance();
+
+                crate::float::write_pfm(path, width, height, &[&r, &g, &b])
+            },
+            Some("hdr") =>
+            {
+                let [r, g, b] = self.radiance();
+                let pixels = (0..r.len())
+                    .map(|i| image::Rgb([r[i], g[i], b[i]]))
+                    .collect::<Vec<_>>();
+
+                std::fs::File::create(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|file|
+                    {
+                        image::codecs::hdr::HdrEncoder::new(std::io::BufWriter::new(file))
+                            .encode(&pixels, width as usize, height as usize)
+                            .map_err(|e| e.to_string())
+                    })
+                    .map_err(|e| format!("Could not save \"{}\": {}", path, e))
+            },
+            _ => crate::metadata::save_image(&self.to_image(), path, metadata),
+        }
+    }
+}
+
+impl Scene
+{
+    pub fn new(pos: [f32; 3], front: [f32; 3], up: [f32; 3], fov: f32) -> Scene
+    {
+        Scene
+        {
+            camera: Camera
+            {
+                pos: pos,
+                front: front,
+                up: up,
+                fov: fov,
+            },
+            triangles: Vec::new(),
+            materials: Vec::new(),
+            animation: None,
+            cameras: Vec::new(),
+            groups: Vec::new(),
+            nodes: Vec::new(),
+            auto_camera: false,
+            exposure: 0.0,
+            velocities: Vec::new(),
+            shutter: 0.0,
+            colours: Vec::new(),
+            uvs: Vec::new(),
+            textures: Vec::new(),
+            normals: Vec::new(),
+            noises: Vec::new(),
+            transformed: Transform::IDENTITY,
+        }
+    }
+
+    /// Renders on the adapter chosen in `settings`. Use `render_with` to
+    /// avoid opening the GPU again for every render.
+    pub fn render(
+        &self,
+        settings: &RenderSettings,
+        condition: &dyn StopCondition,
+        resume: Option<Checkpoint>)
+        -> Result<Framebuffer, GpuError>
+    {
+        let ctx = match settings.cpu
+        {
+            true => GpuContext::cpu(),
+            false => GpuContext::new(settings.adapter.as_deref(), settings.require_discrete)?,
+        };
+
+        if let Some(shader) = &settings.shader
+        {
+            ctx.load_shader(shader)?;
+        }
+
+        self.render_with(&ctx, settings, condition, resume)
+    }
+
+    /// Renders on a background thread until the stop condition in `settings`
+    /// or `RenderHandle::cancel`.
+    pub fn render_async(&self, settings: RenderSettings) -> RenderHandle
+    {
+        RenderHandle::spawn(self.clone(), settings, None, None)
+    }
+
+    /// Like `render_async`, also calling `on_progress` every `every` samples.
+    pub fn render_async_with_progress(
+        &self,
+        settings: RenderSettings,
+        every: u32,
+        on_progress: impl Fn(ProgressInfo) + Send + 'static)
+        -> RenderHandle
+    {
+        RenderHandle::spawn(self.clone(), settings, None, Some((every, Box::new(on_progress))))
+    }
+
+    /// Like `render_async_with_progress`, on `ctx` instead of a context
+    /// opened for the render, so renders one after another can share it.
+    /// `settings.adapter`, `settings.cpu` and `settings.shader` are ignored,
+    /// as they're `ctx`'s.
+    pub fn render_async_on(
+        &self,
+        ctx: std::sync::Arc<GpuContext>,
+        settings: RenderSettings,
+        every: u32,
+        on_progress: impl Fn(ProgressInfo) + Send + 'static)
+        -> RenderHandle
+    {
+        RenderHandle::spawn(self.clone(), settings, Some(ctx), Some((every, Box::new(on_progress))))
+    }
+
+    pub fn render_with(
+        &self,
+        ctx: &GpuContext,
+        settings: &RenderSettings,
+        condition: &dyn StopCondition,
+        resume: Option<Checkpoint>)
+        -> Result<Framebuffer, GpuError>
+    {
+        let mut timings = Timings::default();
+        let mut result = None;
+
+        self.render_cameras(
+            ctx,
+            settings,
+            Shot
+            {
+                cameras: &[self.camera],
+                condition: condition,
+                resume: resume,
+            },
+            &mut timings,
+            &mut |_, image| result = Some(image))?;
+
+        Ok(Framebuffer
+        {
+            profile: timings.profile,
+            .. result.unwrap()
+        })
+    }
+
+    /// Renders a left and right eye with the camera moved `ipd` apart across
+    /// the view, side by side in an image twice as wide as `settings.res`.
+    /// With `converge` both eyes turn in to look at the point that far in
+    /// front of the camera, otherwise they look straight ahead. The left eye
+    /// is rendered until `condition` stops it and the right eye gets the
+    /// same samples, on the same buffers.
+    pub fn render_stereo(
+        &self,
+        ctx: &GpuContext,
+        ipd: f32,
+        converge: Option<f32>,
+        settings: &RenderSettings,
+        condition: &dyn StopCondition)
+        -> Result<Framebuffer, GpuError>
+    {
+        use crate::vec3::{add, cross, normalize, scale, sub};
+
+        let camera = self.camera;
+        let front = normalize(camera.front);
+        // the same right as the shader
+        let right = normalize(cross(front, camera.up));
+
+        let eye = |side: f32|
+        {
+            let pos = add(camera.pos, scale(right, side * ipd / 2.0));
+            let front = match converge
+            {
+                Some(dist) => normalize(sub(add(camera.pos, scale(front, dist)), pos)),
+                None => front,
+            };
+
+            Camera { pos: pos, front: front, .. camera }
+        };
+
+        let mut eyes = Vec::new();
+
+        self.render_cameras(
+            ctx,
+            settings,
+            Shot
+            {
+                cameras: &[eye(-1.0), eye(1.0)],
+                condition: condition,
+                resume: None,
+            },
+            &mut Timings::default(),
+            &mut |_, image| eyes.push(image))?;
+
+        let right = eyes.pop().unwrap();
+        let left = eyes.pop().unwrap();
+
+        Ok(Framebuffer::side_by_side(left, right))
+    }
+
+    /// Renders a range of animation frames (starting at 1), handing each one
+    /// to `on_frame` as soon as it's finished. The first frame is rendered
+    /// until `condition` stops it, and the rest get the same sample count.
+    pub fn render_frames(
+        &self,
+        ctx: &GpuContext,
+        frames: std::ops::RangeInclusive<u32>,
+        settings: &RenderSettings,
+        condition: &dyn StopCondition,
+        on_frame: &mut dyn FnMut(u32, Framebuffer))
+        -> Result<(), GpuError>
+    {
+        let anim = self.animation.as_ref().ok_or_else(|| GpuError::Scene(
+            "Scene doesn't contain an \"animation\" object".to_owned()))?;
+
+        if *frames.start() < 1 || *frames.end() > anim.frames || frames.is_empty()
+        {
+            return Err(GpuError::Scene(format!(
+                "Frames {}..{} aren't within the animation's frames 1..{}",
+                frames.start(), frames.end(), anim.frames)));
+        }
+
+        if anim.node_names().next().is_some()
+        {
+            // each frame has its own triangles, so they're rendered one at a
+            // time, with the seeds `run_shader` would give them together
+            let total = std::cell::Cell::new(None);
+
+            for (i, f) in frames.enumerate()
+            {
+                let fixed;
+                let condition: &dyn StopCondition = match total.get()
+                {
+                    Some(total) =>
+                    {
+                        fixed = move |samples: u32| samples < total;
+                        &fixed
+                    },
+                    None => condition,
+                };
+                let settings = RenderSettings
+                {
+                    seed: settings.seed.map(|seed| seed ^ ((i as u64) << 48)),
+                    .. settings.clone()
+                };
+
+                self.at_frame(f).render_cameras(
+                    ctx,
+                    &settings,
+                    Shot
+                    {
+                        cameras: &[anim.camera_at_frame(f)],
+                        condition: condition,
+                        resume: None,
+                    },
+                    &mut Timings::default(),
+                    &mut |_, image|
+                    {
+                        total.set(Some(image.samples));
+                        on_frame(f, image);
+                    })?;
+            }
+
+            return Ok(());
+        }
+
+        let cameras = frames.clone()
+            .map(|f| anim.camera_at_frame(f))
+            .collect::<Vec<_>>();
+
+        self.render_cameras(
+            ctx,
+            settings,
+            Shot
+            {
+                cameras: &cameras,
+                condition: condition,
+                resume: None,
+            },
+            &mut Timings::default(),
+            &mut |i, image| on_frame(frames.start() + i as u32, image))
+    }
+
+    /// The scene with its nodes where the animation puts them in a frame,
+    /// starting at 1. Borrows the scene when the animation moves none.
+    pub fn at_frame(&self, frame: u32) -> std::borrow::Cow<'_, Scene>
+    {
+        use std::borrow::Cow;
+
+        let anim = match &self.animation
+        {
+            Some(anim) if anim.node_names().next().is_some() => anim,
+            _ => return Cow::Borrowed(self),
+        };
+
+        // where each node puts things in the scene file and in the frame,
+        // parents first so they're always there for their children
+        let mut still: Vec<Transform> = Vec::with_capacity(self.nodes.len());
+        let mut moved: Vec<Transform> = Vec::with_capacity(self.nodes.len());
+
+        for (node, _) in &self.nodes
+        {
+            let outer = |worlds: &[Transform]| match node.parent
+            {
+                Some(parent) => worlds[parent],
+                None => Transform::scaling(node.units),
+            };
+
+            let placement = match &node.name
+            {
+                Some(name) => anim.place_node(name, frame, node.placement),
+                None => node.placement,
+            };
+
+            still.push(node.placement.transform().then(&outer(&still)));
+            moved.push(placement.transform().then(&outer(&moved)));
+        }
+
+        let mut scene = self.clone();
+
+        for (i, (_, tris)) in self.nodes.iter().enumerate()
+        {
+            if moved[i] == still[i]
+            {
+                continue;
+            }
+
+            // the triangles are already where `still` and then `transformed`
+            // put them
+            let change = self.transformed.inverse()
+                .then(&still[i].inverse())
+                .then(&moved[i])
+                .then(&self.transformed);
+
+            for &t in tris
+            {
+                let tri = &mut scene.triangles[t];
+                tri.a = change.point(tri.a);
+                tri.b = change.point(tri.b);
+                tri.c = change.point(tri.c);
+
+                if let Some(n) = scene.normals.get_mut(t)
+                {
+                    *n = n.map(|n| crate::vec3::normalize(change.vector(n)));
+                }
+
+                if let Some(v) = scene.velocities.get_mut(t)
+                {
+                    *v = change.vector(*v);
+                }
+            }
+        }
+
+        Cow::Owned(scene)
+    }
+
+    /// Renders every frame of `orbit` with one context, like `render_frames`.
+    pub fn render_turntable(
+        &self,
+        ctx: &GpuContext,
+        orbit: &Orbit,
+        settings: &RenderSettings,
+        condition: &dyn StopCondition,
+        on_frame: &mut dyn FnMut(u32, Framebuffer))
+        -> Result<(), GpuError>
+    {
+        let cameras = (1..=orbit.frames)
+            .map(|f| orbit.camera_at_frame(f))
+            .collect::<Vec<_>>();
+
+        self.render_cameras(
+            ctx,
+            settings,
+            Shot
+            {
+                cameras: &cameras,
+                condition: condition,
+                resume: None,
+            },
+            &mut Timings::default(),
+            &mut |i, image| on_frame(1 + i as u32, image))
+    }
+
+    /// Renders for `warmup` and then `measure`, counted from the first
+    /// sample so opening buffers isn't included, and times the samples after
+    /// the warm-up. Tiles aren't used, so every sample is the whole image or
+    /// region.
+    pub fn benchmark(
+        &self,
+        ctx: &GpuContext,
+        settings: &RenderSettings,
+        warmup: std::time::Duration,
+        measure: std::time::Duration)
+        -> Result<(Framebuffer, Benchmark), String>
+    {
+        use std::cell::Cell;
+
+        let settings = RenderSettings
+        {
+            tile: None,
+            .. settings.clone()
+        };
+
+        let start = Cell::new(None);
+        let condition = |_: u32|
+        {
+            let first = start.get().unwrap_or_else(Instant::now);
+            start.set(Some(first));
+
+            first.elapsed() < warmup + measure
+        };
+
+        // each sample's own time, for the spread
+        let mut timings = Timings
+        {
+            wait: true,
+            .. Timings::default()
+        };
+        let mut result = None;
+
+        self.render_cameras(
+            ctx,
+            &settings,
+            Shot
+            {
+                cameras: &[self.camera],
+                condition: &condition,
+                resume: None,
+            },
+            &mut timings,
+            &mut |_, image| result = Some(image))
+            .map_err(|e| e.to_string())?;
+
+        // rays are per rendered pixel
+        let pixels = match settings.render_region()
+        {
+            Some(r) => r[2] as u64 * r[3] as u64,
+            None => settings.render_res()[0] as u64 * settings.render_res()[1] as u64,
+        };
+
+        let rays_per_path = match settings.mode
+        {
+            RenderMode::PathTrace => settings.depth,
+            RenderMode::AmbientOcclusion { rays, .. } => rays + 1,
+            _ => 1,
+        };
+
+        let benchmark = Benchmark::new(
+            &ctx.info().name,
+            settings.res,
+            rays_per_path,
+            pixels,
+            &timings,
+            warmup)?;
+
+        Ok((result.unwrap(), benchmark))
+    }
+
+    /// Renders a probe at half the width and height for up to 64 samples or
+    /// 10 seconds and scales its rate up to `settings.res`, to see how long
+    /// the real render would take. Nothing is saved or checkpointed.
+    pub fn estimate(&self, ctx: &GpuContext, settings: &RenderSettings) -> Result<Estimate, String>
+    {
+        const PROBE_SAMPLES: u32 = 64;
+        const PROBE_TIME: std::time::Duration = std::time::Duration::from_secs(10);
+
+        let half = |n: u32| (n / 2).max(1);
+
+        let probe = RenderSettings
+        {
+            res: settings.res.map(half),
+            region: settings.region.map(|r| [r[0] / 2, r[1] / 2, half(r[2]), half(r[3])]),
+            tile: settings.tile.map(|t| t.map(half)),
+            samples: PROBE_SAMPLES,
+            time_limit: Some(PROBE_TIME),
+            checkpoint: None,
+            snapshot: None,
+            control: None,
+            .. settings.clone()
+        };
+
+        let pixels = |s: &RenderSettings| match s.region
+        {
+            Some(r) => r[2] as u64 * r[3] as u64,
+            None => s.res[0] as u64 * s.res[1] as u64,
+        };
+
+        // otherwise the last few samples' time is in the final read back
+        let mut timings = Timings
+        {
+            wait: true,
+            .. Timings::default()
+        };
+
+        self.render_cameras(
+            ctx,
+            &probe,
+            Shot
+            {
+                cameras: &[self.camera],
+                condition: &probe.condition(),
+                resume: None,
+            },
+            &mut timings,
+            &mut |_, _| ())
+            .map_err(|e| e.to_string())?;
+
+        Estimate::new(settings.res, probe.res, pixels(settings), pixels(&probe), &timings)
+    }
+
+    /// The buffers rendering with `settings` allocates on the GPU, to see
+    /// whether it fits before opening one. `RenderBudget::check` is what
+    /// rendering fails with when it doesn't.
+    pub fn budget(&self, settings: &RenderSettings) -> Result<RenderBudget, String>
+    {
+        let visible = self.filter_groups(&settings.only, &settings.hide)?;
+
+        Ok(render_budget(&visible.buffers(), &settings.shader_settings(0, 0)))
+    }
+
+    /// The scene as `run_shader` takes it.
+    fn buffers(&self) -> SceneBuffers<'_>
+    {
+        SceneBuffers
+        {
+            triangles: &self.triangles,
+            materials: &self.materials,
+            velocities: &self.velocities,
+            shutter: self.shutter,
+            colours: &self.colours,
+            uvs: &self.uvs,
+            textures: &self.textures,
+            normals: &self.normals,
+            noises: &self.noises,
+        }
+    }
+
+    fn render_cameras(
+        &self,
+        ctx: &GpuContext,
+        settings: &RenderSettings,
+        shot: Shot,
+        timings: &mut Timings,
+        on_frame: &mut dyn FnMut(usize, Framebuffer))
+        -> Result<(), GpuError>
+    {
+        let visible = self.filter_groups(&settings.only, &settings.hide)
+            .map_err(GpuError::Scene)?;
+
+        if visible.triangles.len() < self.triangles.len()
+        {
+            pt_info!("Rendering {} of {} triangles, {} are in hidden groups",
+                visible.triangles.len(),
+                self.triangles.len(),
+                self.triangles.len() - visible.triangles.len());
+        }
+
+        visible.render_visible(ctx, settings, shot, timings, on_frame)
+    }
+
+    /// `render_cameras` after the groups are filtered.
+    fn render_visible(
+        &self,
+        ctx: &GpuContext,
+        settings: &RenderSettings,
+        shot: Shot,
+        timings: &mut Timings,
+        on_frame: &mut dyn FnMut(usize, Framebuffer))
+        -> Result<(), GpuError>
+    {
+        use std::cell::Cell;
+
+        let Shot { cameras, condition, resume } = shot;
+
+        self.check_materials()?;
+
+        let res = settings.render_res();
+
+        let start = Instant::now();
+        let frame_start = Cell::new(start);
+
+        let (mut image, start_samples, mut seeds) = match resume
+        {
+            Some(resume) => (resume.pixels, resume.samples, resume.seeds),
+            None => (Vec::with_capacity((res[0] * res[1]) as usize), 0, Vec::new()),
+        };
+
+        let exposure = settings.exposure.unwrap_or(self.exposure);
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        pt_debug!("Seed {}", seed);
+
+        // so `checkpoint::merge` can tell renders with the same samples apart
+        if !seeds.contains(&seed)
+        {
+            seeds.push(seed);
+        }
+
+        let hash = self.hash();
+        let save_checkpoint = |samples: u32, image: &[Colour]|
+        {
+            if let Some((path, _)) = &settings.checkpoint
+            {
+                if let Err(e) = checkpoint::save(
+                    path, res[0], res[1], samples, hash, &seeds, image)
+                {
+                    pt_error!("{}", e);
+                }
+            }
+        };
+
+        let last_snapshot = Cell::new(start);
+        let checkpoint_due = |samples: u32| match settings.checkpoint
+        {
+            Some((_, every)) => samples.is_multiple_of(every),
+            None => false,
+        };
+        let snapshot_due = |samples: u32| match settings.snapshot
+        {
+            Some((_, every)) => every.due(samples, last_snapshot.get()),
+            None => false,
+        };
+
+        let save_due = || match &settings.control
+        {
+            Some(control) => control.save_due(),
+            None => false,
+        };
+
+        if settings.profile_gpu
+        {
+            timings.profile = Some(GpuProfile::default());
+        }
+
+        let condition = Controlled
+        {
+            condition: condition,
+            control: settings.control.as_deref(),
+        };
+
+        let result = run_shader(
+            ctx,
+            &mut image,
+            cameras,
+            &self.buffers(),
+            &settings.shader_settings(seed, start_samples),
+            timings,
+            Hooks
+            {
+                condition: &condition,
+                want_image: &|samples| checkpoint_due(samples) || snapshot_due(samples) || save_due(),
+                on_image: &mut |samples, image|
+                {
+                    if checkpoint_due(samples)
+                    {
+                        save_checkpoint(samples, image);
+                    }
+
+                    let saves = match &settings.control
+                    {
+                        Some(control) => control.take_saves(),
+                        None => Vec::new(),
+                    };
+                    let snapshot = match &settings.snapshot
+                    {
+                        Some((path, _)) if snapshot_due(samples) => Some(path),
+                        _ => None,
+                    };
+
+                    if snapshot.is_none() && saves.is_empty()
+                    {
+                        return;
+                    }
+
+                    if snapshot.is_some()
+                    {
+                        last_snapshot.set(Instant::now());
+                    }
+
+                    // the final image warns if the white balance fails
+                    let mut file = Framebuffer::new(image, res, samples, exposure);
+                    let _ = file.white_balance(settings.white_balance);
+                    file.downsample(settings.supersample);
+                    let file = file.to_rgb_image();
+
+                    if let Some(path) = snapshot
+                    {
+                        if let Err(e) = save_snapshot(path, &file)
+                        {
+                            pt_error!("{}", e);
+                        }
+                    }
+
+                    for path in saves
+                    {
+                        match save_snapshot(&path, &file)
+                        {
+                            Ok(()) => pt_info!("Saved {} samples to {}", samples, path),
+                            Err(e) => pt_error!("{}", e),
+                        }
+                    }
+                },
+                on_frame: &mut |frame, samples, image, aovs|
+                {
+                    // checkpoints don't keep the matte or squares
+                    let new_samples = if frame == 0 { samples - start_samples } else { samples };
+
+                    let now = Instant::now();
+                    let time = now - frame_start.get();
+                    frame_start.set(now);
+
+                    on_frame(frame, self.finish_frame(
+                        settings, image, samples, new_samples, aovs, time));
+                },
+            });
+
+        let samples = match result
+        {
+            Ok(samples) => samples,
+            Err(GpuError::DeviceLost { samples, image }) =>
+            {
+                // keep what was rendered so the render can be resumed
+                save_checkpoint(samples, &image);
+                return Err(GpuError::DeviceLost { samples: samples, image: image });
+            },
+            Err(e) => return Err(e),
+        };
+
+        save_checkpoint(samples, &image);
+
+        if let Some(profile) = &timings.profile
+        {
+            for line in profile.summary()
+            {
+                pt_info!("{}", line);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails if a triangle's material isn't in the scene, since the shader
+    /// would read past the end of the materials.
+    fn check_materials(&self) -> Result<(), GpuError>
+    {
+        match self.triangles.iter()
+            .enumerate()
+            .find(|(_, t)| t.mat as usize >= self.materials.len())
+        {
+            Some((i, tri)) => Err(GpuError::Scene(format!(
+                "Triangle {} uses material {}, but the scene has {}",
+                i, tri.mat, self.materials.len()))),
+            None => Ok(()),
+        }
+    }
+
+    /// Renders the scene's camera on every GPU in `ctxs` at once, adding
+    /// their samples together. Each GPU asks for its next sample when it's
+    /// ready, so faster ones render more of them, and `condition` counts
+    /// the samples from all of them. Checkpoints and snapshots aren't
+    /// supported. With a single GPU this is `render_with`.
+    pub fn render_multi(
+        &self,
+        ctxs: &[GpuContext],
+        settings: &RenderSettings,
+        condition: &dyn StopCondition)
+        -> Result<Framebuffer, GpuError>
+    {
+        if ctxs.len() == 1
+        {
+            return self.render_with(&ctxs[0], settings, condition, None);
+        }
+
+        let visible = self.filter_groups(&settings.only, &settings.hide)
+            .map_err(GpuError::Scene)?;
+
+        visible.check_materials()?;
+
+        let start = Instant::now();
+        let res = settings.render_res();
+        let seed = settings.seed.unwrap_or_else(rand::random);
+        pt_debug!("Seed {}", seed);
+
+        // GPUs send their index when they want another sample, and get back
+        // whether to render it
+        let (ask, asks) = std::sync::mpsc::channel::<usize>();
+        let (answers, replies): (Vec<_>, Vec<_>) = ctxs.iter()
+            .map(|_| std::sync::mpsc::channel::<bool>())
+            .unzip();
+
+        struct Part
+        {
+            image: Vec<Colour>,
+            samples: u32,
+            matte: Option<Vec<[f32; 4]>>,
+            squares: Option<Vec<f32>>,
+            depth: Option<Vec<f32>>,
+        }
+
+        let parts = std::thread::scope(|scope|
+        {
+            let threads = ctxs.iter()
+                .zip(replies)
+                .enumerate()
+                .map(|(i, (ctx, reply))|
+                {
+                    let ask = ask.clone();
+                    let visible = &visible;
+
+                    scope.spawn(move ||
+                    {
+                        let mut image = Vec::with_capacity((res[0] * res[1]) as usize);
+                        let mut part = None;
+
+                        // every GPU needs its own noise
+                        let seed = seed ^ (i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+                        let result = run_shader(
+                            ctx,
+                            &mut image,
+                            &[visible.camera],
+                            &visible.buffers(),
+                            &settings.shader_settings(seed, 0),
+                            &mut Timings::default(),
+                            Hooks
+                            {
+                                condition: &|_| ask.send(i).is_ok() && reply.recv().unwrap_or(false),
+                                want_image: &|_| false,
+                                on_image: &mut |_, _| (),
+                                on_frame: &mut |_, samples, image, aovs| part = Some(Part
+                                {
+                                    image: image.to_vec(),
+                                    samples: samples,
+                                    matte: aovs.matte.map(|m| m.to_vec()),
+                                    squares: aovs.squares.map(|s| s.to_vec()),
+                                    depth: aovs.depth.map(|d| d.to_vec()),
+                                }),
+                            });
+
+                        result.map(|_| part.unwrap())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            // the GPUs hang up when they finish
+            drop(ask);
+
+            let condition = Controlled
+            {
+                condition: condition,
+                control: settings.control.as_deref(),
+            };
+
+            let mut total = 0;
+            for i in asks
+            {
+                let go = condition.go_on(total);
+                if go
+                {
+                    total += 1;
+                }
+
+                let _ = answers[i].send(go);
+            }
+
+            threads.into_iter()
+                .map(|t| t.join().unwrap())
+                .collect::<Result<Vec<_>, GpuError>>()
+        })?;
+
+        for (ctx, part) in ctxs.iter().zip(&parts)
+        {
+            pt_info!("{} rendered {} samples", ctx.info().name, part.samples);
+        }
+
+        fn sum<T: Copy>(parts: &[Part], get: impl Fn(&Part) -> Option<&Vec<T>>, add: impl Fn(T, T) -> T)
+            -> Option<Vec<T>>
+        {
+            let mut all = parts.iter().filter_map(get);
+            let first = all.next()?.clone();
+
+            Some(all.fold(first, |acc, v| acc.iter().zip(v).map(|(&a, &b)| add(a, b)).collect()))
+        }
+
+        let image = sum(&parts, |p| Some(&p.image), |a, b| Colour
+        {
+            r: a.r + b.r,
+            g: a.g + b.g,
+            b: a.b + b.b,
+        }).unwrap();
+        let matte = sum(&parts, |p| p.matte.as_ref(), |a, b|
+            [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]]);
+        let squares = sum(&parts, |p| p.squares.as_ref(), |a, b| a + b);
+        let samples = parts.iter().map(|p| p.samples).sum();
+
+        // any GPU's distances will do, they're from a single sample
+        let depth = parts[0].depth.as_deref();
+
+        Ok(visible.finish_frame(
+            settings, &image, samples, samples,
+            Aovs
+            {
+                matte: matte.as_deref(),
+                squares: squares.as_deref(),
+                depth: depth,
+            },
+            start.elapsed()))
+    }
+
+    /// The image from the shader's sums, with everything in `settings` that
+    /// happens after rendering applied. `new_samples` are the samples since
+    /// resuming, which the matte and noise are measured from.
+    fn finish_frame(
+        &self,
+        settings: &RenderSettings,
+        image: &[Colour],
+        samples: u32,
+        new_samples: u32,
+        aovs: Aovs,
+        time: std::time::Duration)
+        -> Framebuffer
+    {
+        let res = settings.render_res();
+        let exposure = settings.exposure.unwrap_or(self.exposure);
+
+        let mut file = match settings.denoise
+        {
+            Some(strength) => Framebuffer::new(
+                &crate::denoise::denoise(
+                    image, res[0], res[1], samples, strength),
+                res,
+                samples,
+                exposure),
+            None => Framebuffer::new(image, res, samples, exposure),
+        };
+
+        if let Err(e) = file.white_balance(settings.white_balance)
+        {
+            pt_warn!("{}, leaving the white balance as it is", e);
+        }
+
+        match aovs.matte
+        {
+            Some(matte) if new_samples > 0 => file.set_matte(matte, new_samples),
+            Some(_) => pt_warn!(
+                "No new samples since resuming, so the image has no alpha \
+                 for its shadow catchers"),
+            None => (),
+        }
+
+        match aovs.squares
+        {
+            Some(squares) if new_samples == samples && samples > 1 =>
+                file.set_noise(image, squares, samples, exposure),
+            Some(_) => pt_warn!(
+                "Measuring noise needs at least 2 samples and can't be \
+                 resumed, so there's no noise estimate"),
+            None => (),
+        }
+
+        if let Some(depth) = aovs.depth
+        {
+            file.set_depth(depth);
+        }
+
+        if let (Some(r), true) = (settings.render_region(), settings.crop)
+        {
+            file.crop(r);
+        }
+
+        file.downsample(settings.supersample);
+        file.time = time;
+
+        if new_samples > 0
+        {
+            pt_info!(
+                "Finished {}x{} render with {} samples in {} ({:0.02}s/sample average)",
+                res[0], res[1],
+                samples,
+                fmt_time(time),
+                time.as_secs_f32() / new_samples as f32);
+        }
+        else
+        {
+            pt_info!("Finished {}x{} render with {} samples, none new",
+                res[0], res[1], samples);
+        }
+
+        if settings.debug
+        {
+            file.debug = Some((self.triangles.len(), time, settings.mode));
+        }
+
+        file.annotation = settings.annotate.clone();
+        file.overlay = settings.overlay;
+
+        file
+    }
+
+    /// The scene with only the triangles in the `only` groups, or every
+    /// triangle if `only` is empty, and none of those in the `hide` groups.
+    /// Borrows the scene when nothing is filtered out.
+    pub fn filter_groups(&self, only: &[String], hide: &[String])
+        -> Result<std::borrow::Cow<'_, Scene>, String>
+    {
+        use std::borrow::Cow;
+
+        if only.is_empty() && hide.is_empty()
+        {
+            return Ok(Cow::Borrowed(self));
+        }
+
+        let group = |name: &String|
+        {
+            self.groups.iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, tris)| tris)
+                .ok_or_else(|| match self.groups.len()
+                {
+                    0 => format!("Unknown group \"{}\", the scene has no groups", name),
+                    _ => format!("Unknown group \"{}\", the scene has: {}",
+                        name,
+                        self.groups.iter()
+                            .map(|(n, _)| format!("\"{}\"", n))
+                            .collect::<Vec<_>>()
+                            .join(", ")),
+                })
+        };
+
+        let mut keep = vec![only.is_empty(); self.triangles.len()];
+
+        for name in only
+        {
+            for &i in group(name)?
+            {
+                keep[i] = true;
+            }
+        }
+
+        for name in hide
+        {
+            for &i in group(name)?
+            {
+                keep[i] = false;
+            }
+        }
+
+        // where each kept triangle ends up
+        let mut index = vec![None; self.triangles.len()];
+        let mut triangles = Vec::new();
+        let mut velocities = Vec::new();
+        let mut colours = Vec::new();
+        let mut uvs = Vec::new();
+        let mut normals = Vec::new();
+
+        for (i, tri) in self.triangles.iter().enumerate()
+        {
+            if keep[i]
+            {
+                index[i] = Some(triangles.len());
+                triangles.push(*tri);
+
+                if let Some(v) = self.velocities.get(i)
+                {
+                    velocities.push(*v);
+                }
+
+                if let Some(c) = self.colours.get(i)
+                {
+                    colours.push(*c);
+                }
+
+                if let Some(uv) = self.uvs.get(i)
+                {
+                    uvs.push(*uv);
+                }
+
+                if let Some(n) = self.normals.get(i)
+                {
+                    normals.push(*n);
+                }
+            }
+        }
+
+        let groups = self.groups.iter()
+            .map(|(name, tris)| (
+                name.clone(),
+                tris.iter().filter_map(|&i| index[i]).collect()))
+            .collect();
+        let nodes = self.nodes.iter()
+            .map(|(node, tris)| (
+                node.clone(),
+                tris.iter().filter_map(|&i| index[i]).collect()))
+            .collect();
+
+        Ok(Cow::Owned(Scene
+        {
+            triangles: triangles,
+            groups: groups,
+            nodes: nodes,
+            velocities: velocities,
+            colours: colours,
+            uvs: uvs,
+            normals: normals,
+            .. self.clone()
+        }))
+    }
+
+    /// Moves every triangle, and so every light, by `t`, and the cameras too
+    /// if `cameras` is set. Animated nodes go on moving within it, but the
+    /// animation's camera isn't moved.
+    pub fn transform(&mut self, t: &Transform, cameras: bool)
+    {
+        use crate::vec3::normalize;
+
+        for tri in &mut self.triangles
+        {
+            tri.a = t.point(tri.a);
+            tri.b = t.point(tri.b);
+            tri.c = t.point(tri.c);
+        }
+
+        // zeroes use the face's normal, so they stay zeroes
+        for n in self.normals.iter_mut().flatten().filter(|n| **n != [0.0; 3])
+        {
+            *n = normalize(t.vector(*n));
+        }
+
+        for v in &mut self.velocities
+        {
+            *v = t.vector(*v);
+        }
+
+        if cameras
+        {
+            let moved = |c: &mut Camera|
+            {
+                c.pos = t.point(c.pos);
+                c.front = normalize(t.vector(c.front));
+                c.up = normalize(t.vector(c.up));
+            };
+
+            moved(&mut self.camera);
+            self.cameras.iter_mut().for_each(|(_, c)| moved(c));
+        }
+
+        self.transformed = self.transformed.then(t);
+    }
+
+    /// Switches to one of the named cameras from the scene file. Without a
+    /// name, uses "default" or the only camera.
+    pub fn select_camera(&mut self, name: Option<&str>) -> Result<(), String>
+    {
+        let names = || self.cameras.iter()
+            .map(|(n, _)| format!("\"{}\"", n))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let camera = match name
+        {
+            Some(name) => self.cameras.iter()
+                .find(|(n, _)| n == name)
+                .ok_or(format!(
+                    "Unknown camera \"{}\", the scene has: {}", name, names()))?,
+            None if self.cameras.len() <= 1 => return Ok(()),
+            None => self.cameras.iter()
+                .find(|(n, _)| n == "default")
+                .ok_or(format!(
+                    "Scene has several cameras but no \"default\", choose one of: {}",
+                    names()))?,
+        }.1;
+
+        self.camera = camera;
+
+        if let Some(anim) = &mut self.animation
+        {
+            anim.set_base(camera);
+        }
+
+        Ok(())
+    }
+
+    /// Looks for problems that would make the render wrong without stopping
+    /// it, returning every one found.
+    pub fn validate(&self) -> Vec<String>
+    {
+        use crate::vec3::{cross, length, sub};
+
+        let mut problems = Vec::new();
+
+        for (name, camera) in &self.cameras
+        {
+            for problem in camera_problems(camera)
+            {
+                problems.push(format!("Camera \"{}\": {}", name, problem));
+            }
+        }
+
+        for (i, tri) in self.triangles.iter().enumerate()
+        {
+            if tri.mat as usize >= self.materials.len()
+            {
+                problems.push(format!(
+                    "Triangle {}: material {} doesn't exist, there are {}",
+                    i, tri.mat, self.materials.len()));
+            }
+
+            if length(cross(sub(tri.b, tri.a), sub(tri.c, tri.a))) < 1e-12
+            {
+                problems.push(format!("Triangle {}: has no area", i));
+            }
+        }
+
+        problems
+    }
+
+    /// The smallest and largest corners of a box around every triangle.
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])>
+    {
+        let mut points = self.triangles.iter().flat_map(|t| vec![t.a, t.b, t.c]);
+        let first = points.next()?;
+
+        Some(points.fold((first, first), |(min, max), p|
+        {
+            (
+                [min[0].min(p[0]), min[1].min(p[1]), min[2].min(p[2])],
+                [max[0].max(p[0]), max[1].max(p[1]), max[2].max(p[2])],
+            )
+        }))
+    }
+
+    /// The nearest and furthest the bounds are from `camera`, for the range
+    /// of a depth map. The nearest is 0 if the camera is inside them.
+    pub fn depth_range(&self, camera: &Camera) -> Option<[f32; 2]>
+    {
+        use crate::vec3::length;
+
+        let (min, max) = self.bounds()?;
+        let p = camera.pos;
+
+        let nearest = [
+            p[0].clamp(min[0], max[0]),
+            p[1].clamp(min[1], max[1]),
+            p[2].clamp(min[2], max[2])];
+        let furthest = [
+            if p[0] - min[0] > max[0] - p[0] { min[0] } else { max[0] },
+            if p[1] - min[1] > max[1] - p[1] { min[1] } else { max[1] },
+            if p[2] - min[2] > max[2] - p[2] { min[2] } else { max[2] }];
+
+        Some([
+            length(crate::vec3::sub(nearest, p)),
+            length(crate::vec3::sub(furthest, p))])
+    }
+
+    /// A camera looking at the whole scene from in front, to the right and
+    /// above, with z up, far enough back that the bounds fit in view with a
+    /// 10% margin. `fov` is in radians, across the image like
+    /// `Camera::fov`, and `aspect` is the image's width over its height.
+    pub fn auto_camera(&self, fov: f32, aspect: f32) -> Result<Camera, String>
+    {
+        use crate::vec3::{add, length, normalize, scale, sub};
+
+        let (min, max) = self.bounds()
+            .ok_or("Can't point the camera at a scene with no triangles")?;
+
+        let center = scale(add(min, max), 0.5);
+        // a single point still gets a little room around it
+        let radius = (length(sub(max, min)) * 0.5).max(1e-3);
+        let dist = framing_distance(radius, fov, aspect);
+
+        let dir = normalize([1.0, -1.0, 0.6]);
+
+        Ok(Camera
+        {
+            pos: add(center, scale(dir, dist)),
+            front: scale(dir, -1.0),
+            up: [0.0, 0.0, 1.0],
+            fov: fov,
+        })
+    }
+
+    /// An orbit for a turntable of `frames` around the camera's up, starting
+    /// from where the camera is and with its fov. The center defaults to
+    /// the middle of the bounds, and the camera to 20 degrees above it, far
+    /// enough away that the whole scene stays in view with a 10% margin.
+    pub fn orbit(
+        &self,
+        frames: u32,
+        center: Option<[f32; 3]>,
+        radius: Option<f32>,
+        height: Option<f32>,
+        aspect: f32)
+        -> Result<Orbit, String>
+    {
+        use crate::vec3::{add, cross, dot, length, normalize, scale, sub};
+
+        const ELEVATION: f32 = 20.0 * std::f32::consts::PI / 180.0;
+
+        if frames == 0
+        {
+            return Err("A turntable needs at least one frame".to_owned());
+        }
+
+        let bounds = self.bounds();
+        let center = match (center, bounds)
+        {
+            (Some(center), _) => center,
+            (None, Some((min, max))) => scale(add(min, max), 0.5),
+            (None, None) => return Err(
+                "Can't circle a scene with no triangles without a center".to_owned()),
+        };
+
+        let up = normalize(self.camera.up);
+        let across = |v: [f32; 3]| sub(v, scale(up, dot(v, up)));
+
+        // from the camera if it isn't right above the center, or the way
+        // it faces, or anywhere
+        let start = [
+            across(sub(self.camera.pos, center)),
+            across(scale(self.camera.front, -1.0)),
+            cross(up, [1.0, 0.0, 0.0]),
+            cross(up, [0.0, 1.0, 0.0])]
+            .iter()
+            .copied()
+            .find(|v| length(*v) > 1e-4)
+            .map(normalize)
+            .unwrap();
+
+        // far enough for the furthest corner from the center
+        let dist = match bounds
+        {
+            Some((min, max)) =>
+            {
+                let corners = (0..8).map(|i| [
+                    if i & 1 == 0 { min[0] } else { max[0] },
+                    if i & 2 == 0 { min[1] } else { max[1] },
+                    if i & 4 == 0 { min[2] } else { max[2] }]);
+                let furthest = corners.map(|c| length(sub(c, center))).fold(1e-3, f32::max);
+
+                framing_distance(furthest, self.camera.fov, aspect)
+            },
+            None => 1.0,
+        };
+
+        let (radius, height) = match (radius, height)
+        {
+            (Some(radius), Some(height)) => (radius, height),
+            (Some(radius), None) => (radius, radius * ELEVATION.tan()),
+            (None, Some(height)) => (dist * ELEVATION.cos(), height),
+            (None, None) => (dist * ELEVATION.cos(), dist * ELEVATION.sin()),
+        };
+
+        if radius.is_nan() || radius <= 0.0
+        {
+            return Err("The orbit's radius must be more than 0".to_owned());
+        }
+
+        Ok(Orbit
+        {
+            center: center,
+            radius: radius,
+            height: height,
+            up: up,
+            start: start,
+            fov: self.camera.fov,
+            frames: frames,
+        })
+    }
+
+    /// Replaces the camera, and the "default" camera, with `auto_camera`
+    /// using the camera's fov.
+    pub fn frame_camera(&mut self, aspect: f32) -> Result<Camera, String>
+    {
+        let camera = self.auto_camera(self.camera.fov, aspect)?;
+
+        self.set_camera(camera)?;
+
+        Ok(camera)
+    }
+
+    /// Replaces the camera, the "default" camera and the one the animation
+    /// starts from, failing if it has the problems `validate` looks for.
+    pub fn set_camera(&mut self, camera: Camera) -> Result<(), String>
+    {
+        let problems = camera_problems(&camera);
+        if !problems.is_empty()
+        {
+            return Err(format!("Camera: {}", problems.join(", ")));
+        }
+
+        self.camera = camera;
+
+        match self.cameras.iter_mut().find(|(n, _)| n == "default")
+        {
+            Some((_, c)) => *c = camera,
+            None => self.cameras.insert(0, ("default".to_owned(), camera)),
+        }
+
+        if let Some(anim) = &mut self.animation
+        {
+            anim.set_base(camera);
+        }
+
+        Ok(())
+    }
+
+    /// A hash of everything that affects the rendered image, used to check
+    /// that a checkpoint belongs to this scene.
+    pub fn hash(&self) -> u64
+    {
+        use bytemuck::cast_slice;
+
+        // FNV-1a, so the hash is stable between builds
+        let mut hash: u64 = 0xcbf29ce484222325;
+
+        let mut parts = vec![
+            cast_slice::<Camera, u8>(&[self.camera]).to_vec(),
+            cast_slice::<Triangle, u8>(&self.triangles).to_vec(),
+            cast_slice::<Material, u8>(&self.materials).to_vec()];
+
+        // only when there's motion, so static scenes keep their old hashes
+        if !self.velocities.is_empty()
+        {
+            parts.push(cast_slice::<[f32; 3], u8>(&self.velocities).to_vec());
+            parts.push(self.shutter.to_le_bytes().to_vec());
+        }
+
+        if !self.colours.is_empty()
+        {
+            parts.push(cast_slice::<[[f32; 3]; 3], u8>(&self.colours).to_vec());
+        }
+
+        if !self.uvs.is_empty()
+        {
+            parts.push(cast_slice::<[[f32; 2]; 3], u8>(&self.uvs).to_vec());
+        }
+
+        if !self.normals.is_empty()
+        {
+            parts.push(cast_slice::<[[f32; 3]; 3], u8>(&self.normals).to_vec());
+        }
+
+        if !self.noises.is_empty()
+        {
+            parts.push(cast_slice::<Noise, u8>(&self.noises).to_vec());
+        }
+
+        for texture in &self.textures
+        {
+            parts.push(cast_slice::<[f32; 4], u8>(&texture.texels).to_vec());
+        }
+
+        for bytes in parts.iter()
+        {
+            for byte in bytes.iter()
+            {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+
+        hash
+    }
+
+    pub fn add_triangle(
+        &mut self, a: [f32; 3], b: [f32; 3], c: [f32; 3], mat: u32)
+        -> &mut Self
+    {
+        self.triangles.push(Triangle
+        {
+            a: a,
+            b: b,
+            c: c,
+            mat: mat,
+        });
+
+        if !self.velocities.is_empty()
+        {
+            self.velocities.push([0.0, 0.0, 0.0]);
+        }
+
+        if !self.colours.is_empty()
+        {
+            self.colours.push([[1.0; 3]; 3]);
+        }
+
+        if !self.uvs.is_empty()
+        {
+            self.uvs.push(DEFAULT_UVS);
+        }
+
+        if !self.normals.is_empty()
+        {
+            self.normals.push([[0.0; 3]; 3]);
+        }
+
+        self
+    }
+
+    /// Sets the colours at triangle `tri`'s points, in the order of its
+    /// points. Its material's colour is multiplied by them, blended across
+    /// the face. Other triangles are left white. There's no PLY importer
+    /// yet to fill these in from a scan's vertex colours, that's left for
+    /// later.
+    pub fn set_colours(&mut self, tri: usize, colours: [[f32; 3]; 3]) -> &mut Self
+    {
+        self.colours.resize(self.triangles.len(), [[1.0; 3]; 3]);
+        self.colours[tri] = colours;
+
+        self
+    }
+
+    /// Sets where triangle `tri`'s points are on its material's textures, in
+    /// the order of its points. Other triangles get `DEFAULT_UVS`.
+    pub fn set_uvs(&mut self, tri: usize, uvs: [[f32; 2]; 3]) -> &mut Self
+    {
+        self.uvs.resize(self.triangles.len(), DEFAULT_UVS);
+        self.uvs[tri] = uvs;
+
+        self
+    }
+
+    /// Sets the normals at triangle `tri`'s points, in the order of its
+    /// points. They're blended across the face for shading, so meshes can
+    /// look smooth. Other triangles stay flat.
+    pub fn set_normals(&mut self, tri: usize, normals: [[f32; 3]; 3]) -> &mut Self
+    {
+        self.normals.resize(self.triangles.len(), [[0.0; 3]; 3]);
+        self.normals[tri] = normals;
+
+        self
+    }
+
+    /// Adds a texture for materials to use, or finds the same file already
+    /// loaded the same way, returning what `Material::glow_texture` should
+    /// be to use it.
+    pub fn add_texture(&mut self, texture: Texture) -> u32
+    {
+        let same = self.textures.iter()
+            .position(|t| t.path == texture.path && t.srgb == texture.srgb);
+
+        match same
+        {
+            Some(i) => i as u32 + 1,
+            None =>
+            {
+                self.textures.push(texture);
+
+                self.textures.len() as u32
+            },
+        }
+    }
+
+    /// Sets how far the triangles in `tris` move in a frame. They're blurred
+    /// along it while the `shutter` is open.
+    pub fn set_velocity(&mut self, tris: std::ops::Range<usize>, velocity: [f32; 3])
+        -> &mut Self
+    {
+        self.velocities.resize(self.triangles.len(), [0.0, 0.0, 0.0]);
+
+        for v in &mut self.velocities[tris]
+        {
+            *v = velocity;
+        }
+
+        self
+    }
+
+    pub fn add_quad(
+        &mut self, a: [f32; 3], b: [f32; 3], c: [f32; 3], d: [f32; 3], mat: u32)
+        -> &mut Self
+    {
+        self
+            .add_triangle(a, b, c, mat)
+            .add_triangle(a, c, d, mat)
+    }
+
+    /// Adds a flat polygon as triangles, which can be concave but not
+    /// self-intersecting. The points go around the edge in order, and the
+    /// triangles are wound the same way.
+    pub fn add_polygon(&mut self, points: &[[f32; 3]], mat: u32)
+        -> Result<&mut Self, String>
+    {
+        for [a, b, c] in triangulate(points)?
+        {
+            self.add_triangle(points[a], points[b], points[c], mat);
+        }
+
+        Ok(self)
+    }
+
+    /// An icosphere: an icosahedron with each triangle split in four
+    /// `subdivisions` times, so 20 * 4^subdivisions triangles. Every vertex
+    /// is exactly `radius` from `center`, with a normal pointing straight
+    /// out from it, so the sphere shades smoothly.
+    ///
+    /// Panics if `subdivisions` is more than `MAX_SPHERE_SUBDIVISIONS`.
+    pub fn add_sphere_mesh(
+        &mut self, center: [f32; 3], radius: f32, subdivisions: u32, mat: u32)
+        -> &mut Self
+    {
+        use crate::vec3::{add, scale};
+
+        assert!(subdivisions <= MAX_SPHERE_SUBDIVISIONS,
+            "at most {} sphere subdivisions", MAX_SPHERE_SUBDIVISIONS);
+
+        let at = |v: [f32; 3]| add(center, scale(v, radius));
+
+        // the icosphere's points are on the unit sphere, so they're normals
+        for [a, b, c] in icosphere(subdivisions)
+        {
+            self.add_triangle(at(a), at(b), at(c), mat);
+            self.set_normals(self.triangles.len() - 1, [a, b, c]);
+        }
+
+        self
+    }
+
+    /// `map`'s grid of vertices with smooth normals, see `mesh::height_grid`,
+    /// which is in front seen from above: the side `down` then `across` go
+    /// anticlockwise on.
+    ///
+    /// Panics if `heights` isn't one for each vertex, or either size is
+    /// less than 2.
+    pub fn add_heightmap(&mut self, map: &Heightmap, mat: u32) -> &mut Self
+    {
+        let size = map.size;
+        assert!(size[0] >= 2 && size[1] >= 2, "a heightmap needs 2 vertices each way");
+        assert_eq!(map.heights.len(), (size[0] * size[1]) as usize, "a height for every vertex");
+
+        let (triangles, normals) = height_grid(map);
+
+        for ([a, b, c], normals) in triangles.into_iter().zip(normals)
+        {
+            self.add_triangle(a, b, c, mat);
+            self.set_normals(self.triangles.len() - 1, normals);
+        }
+
+        self
+    }
+
+    /// Makes the winding of neighbouring triangles agree and turns closed
+    /// pieces outwards, see `mesh::fix_winding`.
+    pub fn fix_winding(&mut self) -> WindingReport
+    {
+        let before = self.triangles.iter().map(|t| t.b).collect::<Vec<_>>();
+        let report = crate::mesh::fix_winding(&mut self.triangles);
+
+        // flipped triangles had their last two points swapped
+        for (i, (t, b)) in self.triangles.iter().zip(before).enumerate()
+        {
+            if t.b != b
+            {
+                if let Some(c) = self.colours.get_mut(i)
+                {
+                    c.swap(1, 2);
+                }
+
+                if let Some(uv) = self.uvs.get_mut(i)
+                {
+                    uv.swap(1, 2);
+                }
+
+                if let Some(n) = self.normals.get_mut(i)
+                {
+                    n.swap(1, 2);
+                }
+            }
+        }
+
+        report
+    }
+
+    pub fn add_material(&mut self, mat: Material) -> u32
+    {
+        self.materials.push(mat);
+
+        if !self.noises.is_empty()
+        {
+            self.noises.push(Noise::NONE);
+        }
+
+        (self.materials.len() - 1) as u32
+    }
+
+    /// Gives material `mat` a colour that varies across space, replacing
+    /// its `colour`. Other materials keep theirs.
+    pub fn set_noise(&mut self, mat: u32, noise: Noise) -> &mut Self
+    {
+        self.noises.resize(self.materials.len(), Noise::NONE);
+        self.noises[mat as usize] = noise;
+
+        self
+    }
+
+    /// Parses a scene file, logging anything suspicious as a warning.
+    pub fn parse(s: &str) -> Result<Scene, String>
+    {
+        let (scene, warnings) = Scene::parse_with_warnings(s)?;
+
+        for w in warnings
+        {
+            pt_warn!("{}", w);
+        }
+
+        Ok(scene)
+    }
+
+    /// Reads a scene file in the format its extension says, also returning
+    /// problems that don't stop it from rendering.
+    pub fn load(path: &str) -> Result<(Scene, Vec<String>), String>
+    {
+        Scene::load_as(path, Format::from_path(path))
+    }
+
+    /// Like `Scene::load`, but reading the file itself as `format`.
+    pub fn load_as(path: &str, format: Format) -> Result<(Scene, Vec<String>), String>
+    {
+        let (def, mut warnings) = SceneDef::load_as(path, format)?;
+        let (scene, more) = Scene::from_def(&def)?;
+
+        warnings.extend(more);
+
+        Ok((scene, warnings))
+    }
+
+    /// Parses a JSON scene, which can have comments, also returning problems
+    /// that don't stop it from rendering.
+    pub fn parse_with_warnings(s: &str) -> Result<(Scene, Vec<String>), String>
+    {
+        Scene::parse_format(s, Format::Json)
+    }
+
+    /// Includes are relative to the working directory.
+    pub fn parse_format(s: &str, format: Format) -> Result<(Scene, Vec<String>), String>
+    {
+        let (mut def, mut warnings) = SceneDef::parse(s, format)?;
+        warnings.extend(def.resolve_includes(std::path::Path::new("."))?);
+
+        let (scene, more) = Scene::from_def(&def)?;
+
+        warnings.extend(more);
+
+        Ok((scene, warnings))
+    }
+
+    /// Writes the scene in a form `Scene::parse` reads back as the same
+    /// scene. Materials are named by their index, and quads come out as
+    /// the triangles they were split into. A scene that was never given
+    /// named cameras gets its camera back as "default".
+    pub fn to_json(&self) -> String
+    {
+        let camera = |c: &Camera| json::object!
+        {
+            "pos": vec3_json(c.pos),
+            "front": vec3_json(c.front),
+            "up": vec3_json(c.up),
+            // not every f32 of radians is an f32 of degrees converted, so
+            // both sides of this go through f64
+            "fov": (c.fov as f64).to_degrees(),
+        };
+
+        let mut top = json::JsonValue::new_object();
+
+        let only_default = match self.cameras.as_slice()
+        {
+            [] => true,
+            [(name, _)] => name == "default",
+            _ => false,
+        };
+
+        if only_default
+        {
+            top["camera"] = camera(&self.camera);
+        }
+        else
+        {
+            let mut cameras = json::JsonValue::new_object();
+
+            for (name, c) in &self.cameras
+            {
+                cameras[name.as_str()] = camera(c);
+            }
+
+            top["cameras"] = cameras;
+        }
+
+        let first = match self.cameras.iter().find(|(n, _)| n == "default")
+            .or(self.cameras.first())
+        {
+            Some((name, _)) if !only_default => &mut top["cameras"][name.as_str()],
+            _ => &mut top["camera"],
+        };
+
+        if self.exposure != 0.0
+        {
+            first["exposure"] = f32_json(self.exposure);
+        }
+
+        if self.shutter != 0.0
+        {
+            first["shutter"] = f32_json(self.shutter);
+        }
+
+        let mut materials = json::JsonValue::new_object();
+
+        for (i, mat) in self.materials.iter().enumerate()
+        {
+            materials[i.to_string().as_str()] = json::object!
+            {
+                "colour": vec3_json(mat.colour),
+                "glow": vec3_json(mat.glow),
+                "gloss": f32_json(mat.gloss),
+                "reflect_c": vec3_json(mat.reflect_c),
+            };
+
+            if mat.flags & Material::ONE_SIDED != 0
+            {
+                materials[i.to_string().as_str()]["one_sided"] = true.into();
+            }
+
+            if mat.flags & Material::SHADOW_CATCHER != 0
+            {
+                materials[i.to_string().as_str()]["shadow_catcher"] = true.into();
+            }
+
+            if let Some(t) = self.textures.get((mat.glow_texture as usize).wrapping_sub(1))
+            {
+                materials[i.to_string().as_str()]["glow_texture"] = json::object!
+                {
+                    "file": t.path.as_str(),
+                    "srgb": t.srgb,
+                };
+            }
+
+            if let Some(t) = self.textures.get((mat.normal_texture as usize).wrapping_sub(1))
+            {
+                materials[i.to_string().as_str()]["normal_map"] = json::object!
+                {
+                    "file": t.path.as_str(),
+                    "srgb": t.srgb,
+                };
+                materials[i.to_string().as_str()]["normal_strength"] = f32_json(mat.normal_strength);
+            }
+
+            if let Some(t) = self.textures.get((mat.alpha_texture as usize).wrapping_sub(1))
+            {
+                materials[i.to_string().as_str()]["alpha_texture"] = json::object!
+                {
+                    "file": t.path.as_str(),
+                    "srgb": t.srgb,
+                };
+                materials[i.to_string().as_str()]["alpha_cutoff"] = f32_json(mat.alpha_cutoff);
+            }
+
+            if let Some(n) = self.noises.get(i).filter(|n| n.octaves != 0)
+            {
+                materials[i.to_string().as_str()]["noise"] = json::object!
+                {
+                    "colours": vec![vec3_json(n.a), vec3_json(n.b)],
+                    "scale": f32_json(n.scale),
+                    "octaves": n.octaves,
+                    "lacunarity": f32_json(n.lacunarity),
+                    "gain": f32_json(n.gain),
+                };
+            }
+        }
+
+        let mut groups = vec![None; self.triangles.len()];
+
+        // nodes' groups come after the surfaces' own, which are kept
+        for (name, tris) in &self.groups
+        {
+            for &i in tris
+            {
+                groups[i].get_or_insert(name.as_str());
+            }
+        }
+
+        top["materials"] = materials;
+        top["surfaces"] = self.triangles.iter()
+            .zip(groups)
+            .enumerate()
+            .map(|(i, (t, group))|
+            {
+                let mut surface = json::object!
+                {
+                    "tri": json::array![vec3_json(t.a), vec3_json(t.b), vec3_json(t.c)],
+                    "mat": t.mat,
+                };
+
+                if let Some(group) = group
+                {
+                    surface["group"] = group.into();
+                }
+
+                if let Some(&v) = self.velocities.get(i)
+                {
+                    if v != [0.0, 0.0, 0.0]
+                    {
+                        surface["velocity"] = vec3_json(v);
+                    }
+                }
+
+                if let Some(&[a, b, c]) = self.colours.get(i)
+                {
+                    if [a, b, c] != [[1.0; 3]; 3]
+                    {
+                        surface["colours"] = json::array![
+                            vec3_json(a), vec3_json(b), vec3_json(c)];
+                    }
+                }
+
+                if let Some(&uvs) = self.uvs.get(i)
+                {
+                    if uvs != DEFAULT_UVS
+                    {
+                        surface["uvs"] = uvs.iter()
+                            .map(|uv| json::array![f32_json(uv[0]), f32_json(uv[1])])
+                            .collect::<Vec<_>>()
+                            .into();
+                    }
+                }
+
+                if let Some(&[a, b, c]) = self.normals.get(i)
+                {
+                    if [a, b, c] != [[0.0; 3]; 3]
+                    {
+                        surface["normals"] = json::array![
+                            vec3_json(a), vec3_json(b), vec3_json(c)];
+                    }
+                }
+
+                surface
+            })
+            .collect::<Vec<_>>()
+            .into();
+
+        if let Some(anim) = &self.animation
+        {
+            top["animation"] = anim.to_json();
+
+            // the nodes are written out as surfaces, so there are none to move
+            top["animation"].remove("nodes");
+        }
+
+        json::stringify_pretty(top, 4)
+    }
+
+    /// Builds the scene a definition describes, also returning warnings.
+    pub fn from_def(def: &SceneDef) -> Result<(Scene, Vec<String>), String>
+    {
+        use std::collections::HashMap;
+
+        let mut warnings = Vec::new();
+
+        let to_camera = |c: &CameraDef| Camera
+        {
+            pos: c.pos,
+            front: c.front,
+            up: c.up,
+            fov: c.fov.to_radians() as f32,
+        };
+
+        let mut cameras = Vec::new();
+
+        if let Some(camera) = &def.camera
+        {
+            cameras.push(("default".to_owned(), to_camera(camera)));
+        }
+        else if def.auto_camera
+        {
+            // framed once the triangles are in
+            cameras.push(("default".to_owned(), Camera
+            {
+                pos: [0.0, 0.0, 0.0],
+                front: [0.0, 1.0, 0.0],
+                up: [0.0, 0.0, 1.0],
+                fov: 60.0f32.to_radians(),
+            }));
+        }
+
+        for (name, camera) in &def.cameras
+        {
+            if cameras.iter().any(|(n, _)| n == name)
+            {
+                return Err(if name == "default" && (def.camera.is_some() || def.auto_camera)
+                {
+                    "\"cameras\" can't contain \"default\" when \"camera\" is used"
+                        .to_owned()
+                }
+                else
+                {
+                    format!("Duplicate camera \"{}\"", name)
+                });
+            }
+
+            cameras.push((name.clone(), to_camera(camera)));
+        }
+
+        let mut scene = match cameras.iter().find(|(n, _)| n == "default")
+            .or(cameras.first())
+        {
+            Some((_, c)) => Scene::new(c.pos, c.front, c.up, c.fov),
+            None => return Err(
+                "Scene didn't contain \"camera\" or \"cameras\" object".to_owned()),
+        };
+        scene.cameras = cameras;
+
+        // the exposure applies to the whole render, so it comes from the
+        // camera the scene starts with
+        let first = def.camera.as_ref()
+            .or(def.cameras.iter().find(|(n, _)| n == "default").map(|(_, c)| c))
+            .or(def.cameras.first().map(|(_, c)| c));
+        if let Some(c) = first
+        {
+            scene.exposure = c.exposure;
+            scene.shutter = c.shutter;
+        }
+
+        for (name, c) in &def.cameras
+        {
+            for (key, own, used) in [
+                ("exposure", c.exposure, scene.exposure),
+                ("shutter", c.shutter, scene.shutter)]
+            {
+                if own != used
+                {
+                    warnings.push(format!(
+                        "Camera \"{}\" has its own \"{}\", only the default camera's is used",
+                        name, key));
+                }
+            }
+        }
+
+        let texture = |scene: &mut Scene, t: &Option<TextureDef>| match t
+        {
+            Some(t) => Texture::load(&t.file, t.srgb).map(|t| scene.add_texture(t)),
+            None => Ok(0),
+        };
+
+        let to_material = |scene: &mut Scene, m: &MaterialDef|
+        {
+            let mut mat = Material::new(m.colour, m.glow, m.gloss, m.reflect_c);
+            mat.flags = if m.one_sided { Material::ONE_SIDED } else { 0 }
+                | if m.shadow_catcher { Material::SHADOW_CATCHER } else { 0 };
+            mat.glow_texture = texture(scene, &m.glow_texture)?;
+            mat.normal_texture = texture(scene, &m.normal_map)?;
+            mat.normal_strength = m.normal_strength;
+            mat.alpha_texture = texture(scene, &m.alpha_texture)?;
+            mat.alpha_cutoff = m.alpha_cutoff;
+
+            Ok::<_, String>(mat)
+        };
+
+        let add_material = |scene: &mut Scene, m: &MaterialDef|
+        {
+            let mat = to_material(scene, m)?;
+            let index = scene.add_material(mat);
+
+            if let Some(n) = &m.noise
+            {
+                scene.set_noise(index, Noise
+                {
+                    a: n.colours[0],
+                    b: n.colours[1],
+                    scale: n.scale,
+                    lacunarity: n.lacunarity,
+                    gain: n.gain,
+                    octaves: n.octaves,
+                });
+            }
+
+            Ok::<_, String>(index)
+        };
+
+        let mut materials = HashMap::new();
+
+        for (name, mat) in &def.materials
+        {
+            let index = add_material(&mut scene, mat)
+                .map_err(|e| format!("Material \"{}\": {}", name, e))?;
+
+            materials.insert(name.as_str(), index);
+        }
+
+        scene.nodes = def.nodes.iter().map(|node| (node.clone(), Vec::new())).collect();
+
+        // added the first time a surface needs it, so it has no name to clash
+        let mut default = None;
+        let mut defaulted = 0;
+
+        for surface in &def.surfaces
+        {
+            let at = &surface.at;
+
+            let mat = match &surface.mat
+            {
+                MatRef::Index(i) if (*i as usize) < def.materials.len() => *i,
+                MatRef::Index(i) => return Err(at.message(&format!(
+                    "material {} doesn't exist, the scene has {}",
+                    i, def.materials.len()))),
+                MatRef::Name(name) => *materials.get(name.as_str())
+                    .ok_or_else(|| at.message(&format!("unknown material \"{}\"", name)))?,
+                MatRef::Inline(mat) => add_material(&mut scene, mat).map_err(|e| at.message(&e))?,
+                MatRef::Default =>
+                {
+                    defaulted += 1;
+
+                    match default
+                    {
+                        Some(index) => index,
+                        None =>
+                        {
+                            let index = match &def.default_material
+                            {
+                                Some(mat) => add_material(&mut scene, mat)
+                                    .map_err(|e| format!("\"default_material\": {}", e))?,
+                                None => scene.add_material(Material::new(
+                                    [0.5, 0.5, 0.5], [0.0, 0.0, 0.0], 0.0, [1.0, 1.0, 1.0])),
+                            };
+
+                            *default.insert(index)
+                        },
+                    }
+                },
+            };
+
+            let first = scene.triangles.len();
+
+            match surface.shape
+            {
+                ShapeDef::Tri([a, b, c]) =>
+                {
+                    scene.add_triangle(a, b, c, mat);
+                },
+                ShapeDef::Quad([a, b, c, d]) =>
+                {
+                    if let Some(w) = check_quad(a, b, c, d).map_err(|e| at.message(&e))?
+                    {
+                        warnings.push(at.message(&w));
+                    }
+
+                    scene.add_quad(a, b, c, d, mat);
+                },
+                ShapeDef::Polygon(ref points) =>
+                {
+                    if let Some(w) = check_polygon(points)
+                    {
+                        warnings.push(at.message(&w));
+                    }
+
+                    scene.add_polygon(points, mat).map_err(|e| at.message(&e))?;
+                },
+                ShapeDef::SphereMesh { center, radius, subdivisions } =>
+                {
+                    scene.add_sphere_mesh(center, radius, subdivisions, mat);
+                },
+                ShapeDef::Heightmap { ref file, corner, across, down, height, resolution } =>
+                {
+                    let (heights, size) = crate::texture::load_heights(file, resolution)
+                        .map_err(|e| at.message(&e))?;
+
+                    if size.iter().any(|&n| !(2..=MAX_HEIGHTMAP_RESOLUTION).contains(&n))
+                    {
+                        return Err(at.message(&format!(
+                            "heightmap \"{}\" is {}x{}, but it needs from 2 to {} pixels \
+                             each way without a \"resolution\"",
+                            file, size[0], size[1], MAX_HEIGHTMAP_RESOLUTION)));
+                    }
+
+                    scene.add_heightmap(&Heightmap
+                    {
+                        heights: &heights,
+                        size: size,
+                        corner: corner,
+                        across: across,
+                        down: down,
+                        height: height,
+                    }, mat);
+                },
+            }
+
+            if surface.flip
+            {
+                for tri in &mut scene.triangles[first..]
+                {
+                    std::mem::swap(&mut tri.b, &mut tri.c);
+                }
+
+                for n in scene.normals.iter_mut().skip(first)
+                {
+                    n.swap(1, 2);
+                }
+            }
+
+            if let Some([a, b, c]) = surface.normals
+            {
+                scene.set_normals(first, if surface.flip { [a, c, b] } else { [a, b, c] });
+            }
+
+            if let Some([a, b, c]) = surface.colours
+            {
+                scene.set_colours(first, if surface.flip { [a, c, b] } else { [a, b, c] });
+            }
+
+            // textured quads without their own uvs show the whole texture
+            let uvs = match (&surface.uvs, &surface.shape)
+            {
+                (Some(uvs), _) => Some(uvs.clone()),
+                (None, ShapeDef::Quad(_)) if scene.materials[mat as usize].textured() =>
+                    Some(vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]),
+                (None, _) => None,
+            };
+
+            // split the same way as `add_quad`
+            let tris = match uvs.as_deref()
+            {
+                Some(&[a, b, c]) => vec![[a, b, c]],
+                Some(&[a, b, c, d]) => vec![[a, b, c], [a, c, d]],
+                _ => Vec::new(),
+            };
+
+            for (i, [a, b, c]) in tris.into_iter().enumerate()
+            {
+                scene.set_uvs(first + i, if surface.flip { [a, c, b] } else { [a, b, c] });
+            }
+
+            if surface.velocity != [0.0, 0.0, 0.0]
+            {
+                let tris = first..scene.triangles.len();
+                scene.set_velocity(tris, surface.velocity);
+            }
+
+            if let Some(name) = &surface.group
+            {
+                let tris = first..scene.triangles.len();
+
+                match scene.groups.iter_mut().find(|(n, _)| n == name)
+                {
+                    Some((_, group)) => group.extend(tris),
+                    None => scene.groups.push((name.to_owned(), tris.collect())),
+                }
+            }
+
+            if let Some(node) = surface.node
+            {
+                scene.nodes[node].1.extend(first..scene.triangles.len());
+            }
+        }
+
+        // named nodes are groups of everything in them, and children come
+        // after their parents, so each is done before it's added to its parent
+        let mut inside = scene.nodes.iter().map(|(_, tris)| tris.clone()).collect::<Vec<_>>();
+        for (i, (node, _)) in scene.nodes.iter().enumerate().rev()
+        {
+            let tris = std::mem::take(&mut inside[i]);
+
+            if let Some(parent) = node.parent
+            {
+                inside[parent].extend(&tris);
+            }
+
+            if let Some(name) = &node.name
+            {
+                match scene.groups.iter_mut().find(|(n, _)| n == name)
+                {
+                    Some((_, group)) => group.extend(tris),
+                    None => scene.groups.push((name.to_owned(), tris)),
+                }
+            }
+        }
+
+        if !scene.velocities.is_empty() && scene.shutter == 0.0
+        {
+            warnings.push(
+                "Some surfaces have a \"velocity\" but the camera has no \"shutter\", \
+                 so they won't be blurred".to_owned());
+        }
+
+        if defaulted > 0
+        {
+            warnings.push(match defaulted
+            {
+                1 => "1 surface has no \"mat\" and uses the default material".to_owned(),
+                n => format!("{} surfaces have no \"mat\" and use the default material", n),
+            });
+        }
+
+        if def.auto_camera
+        {
+            scene.auto_camera = true;
+            scene.frame_camera(1.0)?;
+        }
+
+        if let Some((anim, at)) = &def.animation
+        {
+            let anim = Animation::parse(anim, scene.camera, def.scale)
+                .map_err(|e| at.message(&e))?;
+
+            for name in anim.node_names()
+            {
+                if !def.nodes.iter().any(|n| n.name.as_deref() == Some(name))
+                {
+                    return Err(at.message(&format!(
+                        "moves node \"{}\", which isn't in \"nodes\"", name)));
+                }
+            }
+
+            scene.animation = Some(anim);
+        }
+
+        Ok((scene, warnings))
+    }
+}
+
+/// How often something should happen during a render.
+#[derive(Copy, Clone, Debug)]
+pub enum Every
+{
+    Samples(u32),
+    Time(std::time::Duration),
+}
+
+impl Every
+{
+    /// Parses either a sample count (`"100"`) or a number of seconds (`"30s"`).
+    pub fn parse(s: &str) -> Result<Every, String>
+    {
+        let s = s.trim();
+
+        if let Some(secs) = s.strip_suffix("s")
+        {
+            match secs.trim().parse::<f32>()
+            {
+                Ok(secs) if secs > 0.0 => Ok(Every::Time(
+                    std::time::Duration::from_secs_f32(secs))),
+                _ => Err(format!("Could not parse \"{}\" as seconds", s)),
+            }
+        }
+        else
+        {
+            match s.parse::<u32>()
+            {
+                Ok(n) if n > 0 => Ok(Every::Samples(n)),
+                _ => Err(format!("Could not parse \"{}\" as samples", s)),
+            }
+        }
+    }
+
+    fn due(&self, samples: u32, last: Instant) -> bool
+    {
+        match *self
+        {
+            Every::Samples(n) => samples.is_multiple_of(n),
+            Every::Time(t) => Instant::now() - last >= t,
+        }
+    }
+}
+
+/// The shortest form of `x` if it reads back the same, otherwise all of it.
+fn f32_json(x: f32) -> json::JsonValue
+{
+    match x.to_string().parse::<f64>()
+    {
+        Ok(short) if short as f32 == x => short.into(),
+        _ => (x as f64).into(),
+    }
+}
+
+fn vec3_json(v: [f32; 3]) -> json::JsonValue
+{
+    json::array![f32_json(v[0]), f32_json(v[1]), f32_json(v[2])]
+}
+
+/// How to take the colour of the light out of an image, as a gain on each
+/// channel of the linear image. Green is left as it is.
+#[derive(Copy, Clone, Debug)]
+pub enum WhiteBalance
+{
+    /// the colour temperature of the light, in kelvin
+    Kelvin(f32),
+    /// red, green and blue gains
+    Gains([f32; 3]),
+    /// make the pixel at x, y from the top left grey
+    Pixel([u32; 2]),
+}
+
+impl WhiteBalance
+{
+    /// The gains for `image`, which is only looked at for `Pixel`.
+    pub fn gains(&self, image: &Framebuffer) -> Result<[f32; 3], String>
+    {
+        match *self
+        {
+            WhiteBalance::Kelvin(k) =>
+            {
+                let light = kelvin_rgb(k);
+                Ok([light[1] / light[0], 1.0, light[1] / light[2]])
+            },
+            WhiteBalance::Gains(gains) => Ok(gains),
+            WhiteBalance::Pixel([x, y]) =>
+            {
+                if x >= image.width || y >= image.height
+                {
+                    return Err(format!(
+                        "White balance pixel {},{} is outside the {}x{} image",
+                        x, y, image.width, image.height));
+                }
+
+                let px = image.pixels[(y * image.width + x) as usize];
+
+                if px.r <= 0.0 || px.g <= 0.0 || px.b <= 0.0
+                {
+                    return Err(format!(
+                        "White balance pixel {},{} has no {}, so it can't be made grey",
+                        x, y,
+                        if px.r <= 0.0 { "red" } else if px.g <= 0.0 { "green" } else { "blue" }));
+                }
+
+                Ok([px.g / px.r, 1.0, px.g / px.b])
+            },
+        }
+    }
+}
+
+/// `c` from 0 to 1 as a byte, with anything brighter than 1 as 255 and
+/// anything below 0 as 0. It's dithered by up to half a step with an 8x8 Bayer matrix, so smooth
+/// gradients don't band, which makes this the last thing done to a pixel.
+fn quantise(c: f32, x: u32, y: u32) -> u8
+{
+    // interleaving the bits of x ^ y and y in reverse gives the matrix
+    let mut bayer = 0;
+    for bit in 0..3
+    {
+        bayer = (bayer << 2) | (((x ^ y) >> bit) & 1) << 1 | ((y >> bit) & 1);
+    }
+
+    let threshold = (bayer as f32 + 0.5) / 64.0;
+
+    (c * 255.0 + threshold).floor().clamp(0.0, 255.0) as u8
+}
+
+/// The linear colour of a black body at `kelvin`, from 1000K to 40000K, by
+/// Tanner Helland's fit to the sRGB values.
+fn kelvin_rgb(kelvin: f32) -> [f32; 3]
+{
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+    let r = if t <= 66.0
+    {
+        255.0
+    }
+    else
+    {
+        329.69873 * (t - 60.0).powf(-0.13320476)
+    };
+    let g = if t <= 66.0
+    {
+        99.4708 * t.ln() - 161.11957
+    }
+    else
+    {
+        288.12216 * (t - 60.0).powf(-0.07551485)
+    };
+    let b = if t >= 66.0
+    {
+        255.0
+    }
+    else if t <= 19.0
+    {
+        0.0
+    }
+    else
+    {
+        138.51773 * (t - 10.0).ln() - 305.0448
+    };
+
+    // sRGB to linear, with a floor so no channel gets an infinite gain
+    let linear = |c: f32|
+    {
+        let c = (c / 255.0).clamp(0.0, 1.0);
+
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    }.max(1e-3);
+
+    [linear(r), linear(g), linear(b)]
+}
+
+/// Quads are split along a to c, so they must be flat and have their
+/// vertices in order around the edge. Slightly bent quads only get a warning,
+/// since they still render as two triangles.
+fn check_quad(a: [f32; 3], b: [f32; 3], c: [f32; 3], d: [f32; 3])
+    -> Result<Option<String>, String>
+{
+    use crate::vec3::{cross, dot, length, normalize, sub};
+
+    let n1 = cross(sub(b, a), sub(c, a));
+    let n2 = cross(sub(c, a), sub(d, a));
+
+    if dot(n1, n2) <= 0.0
+    {
+        return Err("quad is self-intersecting or concave, its vertices \
+                    must go around the edge in order".to_owned());
+    }
+
+    let size = [sub(b, a), sub(c, b), sub(d, c), sub(a, d)].iter()
+        .map(|e| length(*e))
+        .fold(0.0, f32::max);
+    let off = dot(sub(d, a), normalize(n1)).abs();
+
+    if off > size * 1e-3
+    {
+        return Ok(Some(format!(
+            "quad isn't flat, the fourth vertex is {} off the plane of the others", off)));
+    }
+
+    Ok(None)
+}
+
+/// Warns if the polygon is bent, by the same measure as `check_quad`.
+fn check_polygon(points: &[[f32; 3]]) -> Option<String>
+{
+    use crate::vec3::{dot, length, normalize, sub};
+
+    let n = normalize(polygon_normal(points));
+    let size = points.iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(&a, &b)| length(sub(b, a)))
+        .fold(0.0, f32::max);
+    let off = points.iter()
+        .map(|&p| dot(sub(p, points[0]), n))
+        .fold((0.0f32, 0.0f32), |(lo, hi), d| (lo.min(d), hi.max(d)));
+
+    if off.1 - off.0 > size * 1e-3
+    {
+        return Some(format!(
+            "polygon isn't flat, its vertices are spread {} across its plane", off.1 - off.0));
+    }
+
+    None
+}
+
+/// What's wrong with a camera, for `Scene::validate`.
+fn camera_problems(camera: &Camera) -> Vec<String>
+{
+    use crate::vec3::{cross, length, normalize};
+
+    let mut problems = Vec::new();
+
+    if length(camera.front) == 0.0 || length(camera.up) == 0.0
+    {
+        problems.push("\"front\" and \"up\" can't be 0,0,0".to_owned());
+    }
+    else if length(cross(normalize(camera.front), normalize(camera.up))) < 1e-4
+    {
+        problems.push("\"front\" and \"up\" are parallel".to_owned());
+    }
+
+    if camera.fov <= 0.0 || camera.fov >= std::f32::consts::PI
+    {
+        problems.push(format!(
+            "\"fov\" of {} degrees isn't between 0 and 180", camera.fov.to_degrees()));
+    }
+
+    problems
+}
+
+/// How far from the center of a sphere of `radius` a camera has to be to fit
+/// it in view with a 10% margin, with `fov` across the image.
+fn framing_distance(radius: f32, fov: f32, aspect: f32) -> f32
+{
+    // the narrower of the horizontal and vertical fields of view
+    let half = (fov / 2.0).min(((fov / 2.0).tan() / aspect).atan());
+
+    radius * 1.1 / half.sin()
+}
+
+/// The extension of `path`, in lower case.
+fn extension(path: &str) -> Option<String>
+{
+    std::path::Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+}
+
+/// Writes an image through a temporary file so viewers watching `path`
+/// never see a half-written file.
+fn save_snapshot(path: &str, image: &image::RgbImage) -> Result<(), String>
+{
+    let format = image::ImageFormat::from_path(path)
+        .map_err(|e| format!("Could not write snapshot \"{}\": {}", path, e))?;
+
+    let temp = format!("{}.tmp", path);
+
+    image.save_with_format(&temp, format)
+        .map_err(|e| format!("Could not write snapshot \"{}\": {}", temp, e))?;
+
+    std::fs::rename(&temp, path)
+        .map_err(|e| format!("Could not move snapshot to \"{}\": {}", path, e))
+}
+
+/// The Mitchell-Netravali filter with B and C of 1/3, which is 0 from 2 out.
+fn mitchell(x: f32) -> f32
+{
+    let x = x.abs();
+
+    match x
+    {
+        x if x < 1.0 => (7.0 * x * x * x - 12.0 * x * x + 16.0 / 3.0) / 6.0,
+        x if x < 2.0 => (-7.0 / 3.0 * x * x * x + 12.0 * x * x - 20.0 * x + 32.0 / 3.0) / 6.0,
+        _ => 0.0,
+    }
+}
+
+/// Shrinks a `width` by `height` image with `channels` floats to a pixel by
+/// `factor` across and down with `mitchell`, two of the new pixels wide,
+/// across and then down. Past the edges the filter's cut off and the rest of
+/// it weighted up to make up for it.
+fn filter_down(image: &[f32], channels: usize, width: u32, height: u32, factor: u32) -> Vec<f32>
+{
+    // the pixels and weights for each new pixel along a side
+    let taps = |size: u32| (0..size / factor)
+        .map(|out|
+        {
+            let centre = (out as f32 + 0.5) * factor as f32;
+            let reach = 2 * factor as i64;
+            let first = (centre as i64 - reach).max(0);
+            let last = (centre as i64 + reach).min(size as i64 - 1);
+
+            let taps = (first..=last)
+                .map(|i| (i as usize, mitchell((i as f32 + 0.5 - centre) / factor as f32)))
+                .collect::<Vec<_>>();
+            let total = taps.iter().map(|(_, w)| w).sum::<f32>();
+
+            taps.into_iter().map(|(i, w)| (i, w / total)).collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let (across, down) = (taps(width), taps(height));
+    let (w, h) = (across.len(), down.len());
+    let row = w * channels;
+
+    let mut narrow = vec![0.0; row * height as usize];
+    for y in 0..height as usize
+    {
+        for (x, taps) in across.iter().enumerate()
+        {
+            for &(i, weight) in taps
+            {
+                let from = (y * width as usize + i) * channels;
+                let to = y * row + x * channels;
+
+                for c in 0..channels
+                {
+                    narrow[to + c] += weight * image[from + c];
+                }
+            }
+        }
+    }
+
+    let mut small = vec![0.0; row * h];
+    for (y, taps) in down.iter().enumerate()
+    {
+        for &(i, weight) in taps
+        {
+            for (to, from) in small[y * row..(y + 1) * row].iter_mut().zip(&narrow[i * row..(i + 1) * row])
+            {
+                *to += weight * from;
             }
         }
+    }
+
+    small
+}
+
+fn fmt_time(d: std::time::Duration) -> String
+{
+    let s = d.as_secs();
+
+    format!("{}:{:02}:{:02}",
+        s / 3600,
+        (s % 3600) / 60,
+        s % 60)
+}
+
+fn add_debug_info(
+    image: &mut image::RgbImage,
+    triangles: usize,
+    samples: u32,
+    time: std::time::Duration,
+    mode: RenderMode,
+    style: &Overlay)
+{
+    // the mode goes on top, so images of the scene that aren't path traced
+    // say what they are
+    let mode = match mode
+    {
+        RenderMode::PathTrace => None,
+        RenderMode::AmbientOcclusion { .. } => Some("AO"),
+        RenderMode::Normals => Some("NORMALS"),
+        RenderMode::Depth => Some("DEPTH"),
+        RenderMode::MaterialId => Some("MATID"),
+        RenderMode::Wireframe => Some("WIREFRAME"),
+    };
+
+    let text = format!("{}{} SAMPLES\n{} TRIANGLES\n{} TIME",
+        mode.map(|m| format!("{}\n", m)).unwrap_or_default(),
+        samples,
+        triangles,
+        fmt_time(time));
+
+    draw_overlay(image, &text, style);
+}
+
+/// Adds a strip below `heat` going from `min` to `max` through the colours
+/// of `viridis`, labelled at each end. The labels are left out if the image
+/// is too narrow for them.
+fn add_legend(heat: &image::RgbImage, min: f32, max: f32) -> image::RgbImage
+{
+    use image::GenericImage;
+
+    let (width, height) = heat.dimensions();
+    let mut image = image::RgbImage::new(width, height + 17);
+
+    image.copy_from(heat, 0, 0).unwrap();
+
+    for x in 0..width
+    {
+        let colour = image::Rgb(viridis(x as f32 / (width - 1).max(1) as f32));
+
+        for y in height..height + 8
+        {
+            image.put_pixel(x, y, colour);
+        }
+    }
 
-        y_init += text.len() + 1;
+    let min = format!("{:.4}", min);
+    let max = format!("{:.4}", max);
+    let max_width = text_size(&max)[0];
+
+    if text_size(&min)[0] + max_width + CHAR_WIDTH + 2 <= width
+    {
+        let y = height + 9;
+
+        draw_text(&mut image, 1, y, &min, [255; 3], [0; 3]);
+        draw_text(&mut image, width - 1 - max_width, y, &max, [255; 3], [0; 3]);
+    }
+
+    image
+}
+
+/// Maps 0 to 1 onto dark purple, blue, green and yellow, close to
+/// matplotlib's viridis.
+fn viridis(t: f32) -> [u8; 3]
+{
+    const STOPS: [[f32; 3]; 5] = [
+        [0.267, 0.005, 0.329],
+        [0.230, 0.322, 0.546],
+        [0.128, 0.567, 0.551],
+        [0.369, 0.789, 0.383],
+        [0.993, 0.906, 0.144],
+    ];
+
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let i = (x as usize).min(STOPS.len() - 2);
+    let f = x - i as f32;
+
+    let mut c = [0; 3];
+    for k in 0..3
+    {
+        let v = STOPS[i][k] * (1.0 - f) + STOPS[i + 1][k] * f;
+        c[k] = (v * 255.0).round() as u8;
     }
 
-    true
+    c
 }
 
-const SAMPLES_TEXT: [&'static str; 7] = [
-    " ###   ###  #   # ####  #     #####  ### ",
-    "#   # #   # ## ## #   # #     #     #   #",
-    "#     #   # ## ## #   # #     #     #    ",
-    " ###  ##### # # # ####  #     ###    ### ",
-    "    # #   # #   # #     #     #         #",
-    "#   # #   # #   # #     #     #     #   #",
-    " ###  #   # #   # #     ##### #####  ### ",
-//   ----- ----- ----- ----- ----- ----- -----
-];
-
-const TRIANGLES_TEXT: [&'static str; 7] = [
-    "##### ####   ###   ###  #   #  ###  #     #####  ###",
-    "  #   #   #   #   #   # ##  # #   # #     #     #   #",
-    "  #   #   #   #   #   # ##  # #     #     #     #    ",
-    "  #   ####    #   ##### # # # #  ## #     ####   ### ",
-    "  #   #  #    #   #   # #  ## #   # #     #         #",
-    "  #   #   #   #   #   # #  ## #   # #     #     #   #",
-    "  #   #   #  ###  #   # #   #  ###  ##### #####  ### ",
-//   ----- ----- ----- ----- ----- ----- ----- ----- -----
-];
-
-const TIME_TEXT: [&'static str; 7] = [
-    "#####  ###  #   # #####",
-    "  #     #   ## ## #    ",
-    "  #     #   ## ## #    ",
-    "  #     #   # # # #### ",
-    "  #     #   #   # #    ",
-    "  #     #   #   # #    ",
-    "  #    ###  #   # #####",
-];
-
-const NUMBERS_TEXT: [[&'static str; 7]; 12] = [
-    [
-        " ###  ",
-        "#   # ",
-        "#  ## ",
-        "# # # ",
-        "##  # ",
-        "#   # ",
-        " ###  ",
-    ],
-    [
-        "  #   ",
-        " ##   ",
-        "  #   ",
-        "  #   ",
-        "  #   ",
-        "  #   ",
-        " ###  ",
-    ],
-    [
-        " ###  ",
-        "#   # ",
-        "    # ",
-        "    # ",
-        "   #  ",
-        " ##   ",
-        "##### ",
-    ],
-    [
-        " ###  ",
-        "#   # ",
-        "    # ",
-        "  ##  ",
-        "    # ",
-        "#   # ",
-        " ###  ",
-    ],
-    [
-        "   #  ",
-        "  ##  ",
-        " # #  ",
-        "#  #  ",
-        "##### ",
-        "   #  ",
-        "  ### ",
-    ],
-    [
-        "##### ",
-        "#     ",
-        "###   ",
-        "   #  ",
-        "    # ",
-        "#   # ",
-        " ###  ",
-    ],
-    [
-        " ###  ",
-        "#   # ",
-        "#     ",
-        "####  ",
-        "#   # ",
-        "#   # ",
-        " ###  ",
-    ],
-    [
-        " ###  ",
-        "#   # ",
-        "    # ",
-        "   #  ",
-        "   #  ",
-        "  #   ",
-        "  #   ",
-    ],
-    [
-        " ###  ",
-        "#   # ",
-        "#   # ",
-        " ###  ",
-        "#   # ",
-        "#   # ",
-        " ###  ",
-    ],
-    [
-        " ###  ",
-        "#   # ",
-        "#   # ",
-        " #### ",
-        "    # ",
-        "#   # ",
-        " ###  ",
-    ],
-    [
-        "      ",
-        "  #   ",
-        "      ",
-        "      ",
-        "      ",
-        "  #   ",
-        "      ",
-    ],
-    [
-        "      ",
-        "      ",
-        "      ",
-        "      ",
-        "      ",
-        "      ",
-        "      ",
-    ],
-];
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn v3(rng: &mut StdRng, lo: f32, hi: f32) -> [f32; 3]
+    {
+        [0; 3].map(|_| rng.gen_range(lo..hi))
+    }
+
+    /// A scene of everything `to_json` writes, made at random.
+    fn random_scene(rng: &mut StdRng) -> Scene
+    {
+        let mut scene = Scene::new(
+            v3(rng, -10.0, 10.0), v3(rng, -1.0, 1.0), v3(rng, -1.0, 1.0), rng.gen_range(0.2..2.5));
+
+        if rng.gen_bool(0.5)
+        {
+            scene.exposure = rng.gen_range(-4.0..4.0);
+            scene.shutter = rng.gen_range(0.0..1.0);
+        }
+
+        for _ in 0..rng.gen_range(1..5)
+        {
+            let mut mat = Material::new(
+                v3(rng, 0.0, 1.0),
+                if rng.gen_bool(0.3) { v3(rng, 0.0, 10.0) } else { [0.0; 3] },
+                rng.gen_range(0.0..1.0),
+                v3(rng, 0.0, 1.0));
+            mat.flags = rng.gen_range(0..4);
+            let mat = scene.add_material(mat);
+
+            if rng.gen_bool(0.3)
+            {
+                scene.set_noise(mat, Noise
+                {
+                    a: v3(rng, 0.0, 1.0),
+                    b: v3(rng, 0.0, 1.0),
+                    scale: rng.gen_range(0.1..10.0),
+                    lacunarity: rng.gen_range(1.0..4.0),
+                    gain: rng.gen_range(0.1..0.9),
+                    octaves: rng.gen_range(1..8),
+                });
+            }
+        }
+
+        for i in 0..rng.gen_range(1..20)
+        {
+            let mat = rng.gen_range(0..scene.materials.len() as u32);
+            scene.add_triangle(v3(rng, -5.0, 5.0), v3(rng, -5.0, 5.0), v3(rng, -5.0, 5.0), mat);
+
+            if rng.gen_bool(0.2)
+            {
+                scene.set_colours(i, [(); 3].map(|_| v3(rng, 0.0, 1.0)));
+            }
+            if rng.gen_bool(0.2)
+            {
+                scene.set_uvs(i, [(); 3].map(|_| [rng.gen_range(0.0..1.0), rng.gen_range(0.0..1.0)]));
+            }
+            if rng.gen_bool(0.2)
+            {
+                scene.set_normals(i, [(); 3].map(|_| crate::vec3::normalize(v3(rng, -1.0, 1.0))));
+            }
+            if rng.gen_bool(0.2)
+            {
+                scene.set_velocity(i..i + 1, v3(rng, -1.0, 1.0));
+            }
+        }
+
+        scene
+    }
+
+    #[test]
+    fn to_json_round_trips()
+    {
+        let mut rng = StdRng::seed_from_u64(826);
+
+        for _ in 0..200
+        {
+            let scene = random_scene(&mut rng);
+            let json = scene.to_json();
+            let back = Scene::parse(&json).unwrap_or_else(|e| panic!("{}\n{}", e, json));
+
+            // Debug prints f32s exactly, so these are all bit for bit
+            let same = |a: &dyn std::fmt::Debug, b: &dyn std::fmt::Debug, what: &str|
+                assert_eq!(format!("{:?}", a), format!("{:?}", b), "{} in\n{}", what, json);
+
+            same(&scene.camera, &back.camera, "camera");
+            same(&scene.exposure, &back.exposure, "exposure");
+            same(&scene.shutter, &back.shutter, "shutter");
+            same(&scene.materials, &back.materials, "materials");
+            same(&scene.noises, &back.noises, "noises");
+            same(&scene.triangles, &back.triangles, "triangles");
+            same(&scene.velocities, &back.velocities, "velocities");
+            same(&scene.colours, &back.colours, "colours");
+            same(&scene.uvs, &back.uvs, "uvs");
+            same(&scene.normals, &back.normals, "normals");
+
+            // and it's written the same way again
+            assert_eq!(back.to_json(), json);
+        }
+    }
+
+    #[test]
+    fn sphere_mesh_normals_are_radial()
+    {
+        use crate::vec3::{dot, length, sub};
+
+        let center = [1.0, -2.0, 3.0];
+        let mut scene = Scene::new([0.0; 3], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 1.0);
+        scene.add_triangle([0.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], 0);
+        scene.add_sphere_mesh(center, 2.5, 2, 0);
+
+        assert_eq!(scene.normals.len(), 1 + 320);
+        assert_eq!(scene.normals[0], [[0.0; 3]; 3], "the triangle before stays flat");
+
+        for (tri, normals) in scene.triangles.iter().zip(&scene.normals).skip(1)
+        {
+            for (p, n) in [tri.a, tri.b, tri.c].iter().zip(normals)
+            {
+                let out = sub(*p, center);
+                assert!((length(*n) - 1.0).abs() < 1e-6, "{:?} isn't a unit vector", n);
+                assert!(dot(out, *n) / length(out) > 1.0 - 1e-6, "{:?} at {:?}", n, p);
+            }
+        }
+    }
+
+    #[test]
+    fn unpremultiplies_before_quantising()
+    {
+        let grey = Colour { r: 0.01, g: 0.01, b: 0.01 };
+        let mut frame = Framebuffer::new(&[grey; 64], [8, 8], 1, 0.0);
+        frame.alpha = Some(vec![0.02; 64]);
+
+        // 0.01 quantised first is 2 or 3, which comes out as 100 or 150
+        for px in frame.to_rgba_image().pixels()
+        {
+            assert!(px.0[..3].iter().all(|&c| c == 127 || c == 128), "{:?}", px);
+            assert_eq!(px.0[3], 5);
+        }
+
+        frame.alpha = Some(vec![0.0; 64]);
+        assert!(frame.to_rgba_image().pixels().all(|px| px.0 == [0; 4]));
+
+        frame.alpha = None;
+        assert!(frame.to_rgba_image().pixels().all(|px| px.0[3] == 255));
+    }
+
+    #[test]
+    fn quantise_clamps()
+    {
+        for (c, byte) in [(1.5, 255), (100.0, 255), (f32::INFINITY, 255), (-0.5, 0), (f32::NAN, 0)]
+        {
+            for (x, y) in [(0, 0), (3, 5), (7, 7)]
+            {
+                assert_eq!(quantise(c, x, y), byte, "{} at {}, {}", c, x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn quantise_dithers()
+    {
+        let longest_run = |row: &[u8]| row.windows(2)
+            .fold((1, 1), |(run, longest), w|
+            {
+                let run = if w[0] == w[1] { run + 1 } else { 1 };
+                (run, longest.max(run))
+            })
+            .1;
+
+        // a dim gradient crosses 13 steps in 256 pixels, so rounding makes
+        // runs of about 20
+        let ramp = |x: u32| 0.2 + 0.05 * x as f32 / 255.0;
+        let rounded = (0..256).map(|x| (ramp(x) * 255.0).round() as u8).collect::<Vec<_>>();
+        let banded = longest_run(&rounded);
+        assert!(banded >= 19, "{}", banded);
+
+        // a row only gets 8 of the matrix's thresholds, so it's the whole
+        // block that smooths the gradient, but every row's runs shorten
+        for y in 0..8
+        {
+            let row = (0..256).map(|x| quantise(ramp(x), x, y)).collect::<Vec<_>>();
+            assert!(longest_run(&row) * 4 < banded * 3, "row {}: {}", y, longest_run(&row));
+        }
+
+        // and every 8x8 block keeps the brightness it had
+        for c in [0.1, 0.37, 0.5, 0.999]
+        {
+            let total: u32 = (0..8).flat_map(|y| (0..8).map(move |x| quantise(c, x, y) as u32)).sum();
+            let mean = total as f32 / 64.0;
+            assert!((mean - c * 255.0).abs() <= 1.0 / 64.0, "{} became {}", c * 255.0, mean);
+        }
+    }
+}
@@ -0,0 +1,1012 @@
+//! The `serve` subcommand, which renders scenes sent over HTTP one after
+//! another on one GPU. Only a handful of requests are needed, so the HTTP is
+//! done by hand: one request per connection, with the body's length given.
+//! Scenes can only read files under `--scene-root`, and each connection has
+//! a deadline, so the server can listen somewhere it isn't fully trusted.
+
+use path_tracer_gpu::{Format, GpuContext, RenderControl, RenderSettings, Scene, SceneDef};
+use path_tracer_gpu::{pt_debug, pt_error, pt_info};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{parse_resolution, Failure, DEFAULT_MAX_TRIANGLES};
+
+/// Listed in `serve --help`.
+pub const SERVE_HELP: &str = "REQUESTS:
+    POST /jobs?resolution=800x600&samples=100&formats=png,exr
+        Queue the scene in the body, as JSON. Every parameter is optional, and
+        the response has the job's id. It's refused with 503 while
+        --max-queued jobs are waiting.
+    GET /jobs
+        Every job's status.
+    GET /jobs/ID
+        The job's status: queued, rendering, done, failed or cancelled, with
+        the samples so far.
+    GET /jobs/ID/image
+    GET /jobs/ID/image.EXT
+        The finished image, in its first format or the one given.
+    DELETE /jobs/ID
+        Cancel the job, or delete its images once it's finished.";
+
+/// The formats a job can ask for.
+const FORMATS: [(&str, &str); 7] = [
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("bmp", "image/bmp"),
+    ("tiff", "image/tiff"),
+    ("exr", "image/x-exr"),
+    ("hdr", "image/vnd.radiance"),
+    ("pfm", "application/octet-stream"),
+];
+
+/// Scenes bigger than this are refused before they're read.
+const MAX_BODY: u64 = 64 * 1024 * 1024;
+
+/// The longest the request line or a header can be.
+const MAX_LINE: u64 = 8 * 1024;
+
+const MAX_HEADERS: usize = 100;
+
+/// How long a connection has to send its whole request, and then to take
+/// each part of the answer.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connections past this many at once are turned away.
+const MAX_CONNECTIONS: usize = 32;
+
+/// What a job can ask for, from the command line.
+struct Limits
+{
+    /// in pixels, as width times height
+    pixels: u64,
+    samples: u32,
+    triangles: Option<u64>,
+    /// how many jobs can wait at once, since each keeps its scene
+    queued: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum State
+{
+    Queued,
+    Rendering,
+    Done,
+    Failed(String),
+    Cancelled,
+}
+
+struct Job
+{
+    /// taken when it starts rendering
+    scene: Option<Scene>,
+    res: [u32; 2],
+    samples: u32,
+    formats: Vec<String>,
+    state: State,
+    /// samples finished so far
+    done: u32,
+    control: Arc<RenderControl>,
+    /// the images, in the order of `formats`, once it's done
+    files: Vec<PathBuf>,
+}
+
+struct Jobs
+{
+    jobs: BTreeMap<u64, Job>,
+    queue: VecDeque<u64>,
+    next: u64,
+    /// how many finished jobs to remember
+    keep: usize,
+}
+
+impl Jobs
+{
+    /// Forgets the oldest finished jobs past the newest `keep`, and deletes
+    /// their images.
+    fn evict(&mut self)
+    {
+        let finished = self.jobs.iter()
+            .filter(|(_, job)| matches!(job.state, State::Done | State::Failed(_) | State::Cancelled))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+
+        for id in &finished[..finished.len().saturating_sub(self.keep)]
+        {
+            for file in &self.jobs[id].files
+            {
+                let _ = std::fs::remove_file(file);
+            }
+
+            self.jobs.remove(id);
+            pt_debug!("Forgot job {}", id);
+        }
+    }
+}
+
+/// The jobs, and a condition the worker waits on for more of them.
+type Shared = Arc<(Mutex<Jobs>, Condvar)>;
+
+/// Runs the `serve` subcommand until it's killed.
+pub fn serve(matches: &clap::ArgMatches) -> Result<(), Failure>
+{
+    let port = match matches.value_of("port").unwrap().trim().parse::<u16>()
+    {
+        Ok(port) => port,
+        Err(_) => return Err(Failure::Args("Could not parse the port".to_owned())),
+    };
+
+    let max_res = parse_resolution(matches.value_of("max-resolution").unwrap())
+        .map_err(Failure::Args)?;
+
+    let samples = match matches.value_of("max-samples").unwrap().trim().parse::<u32>()
+    {
+        Ok(s) if s > 0 => s,
+        _ => return Err(Failure::Args("Could not parse maximum samples, above 0".to_owned())),
+    };
+
+    let triangles = match matches.value_of("max-triangles").map(|n| n.trim().parse::<u64>())
+    {
+        _ if matches.is_present("no-triangle-limit") => None,
+        Some(Ok(n)) => Some(n),
+        Some(Err(_)) => return Err(Failure::Args("Could not parse the maximum number of triangles".to_owned())),
+        None => Some(DEFAULT_MAX_TRIANGLES),
+    };
+
+    let queued = match matches.value_of("max-queued").unwrap().trim().parse::<usize>()
+    {
+        Ok(n) if n > 0 => n,
+        _ => return Err(Failure::Args("Could not parse the maximum queued jobs, above 0".to_owned())),
+    };
+
+    let limits = Arc::new(Limits
+    {
+        pixels: max_res[0] as u64 * max_res[1] as u64,
+        samples: samples,
+        triangles: triangles,
+        queued: queued,
+    });
+
+    let dir = match matches.value_of("dir")
+    {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join("path-tracer-gpu"),
+    };
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| Failure::Io(format!("Could not create \"{}\": {}", dir.display(), e)))?;
+
+    let keep = match matches.value_of("keep-jobs").unwrap().trim().parse::<usize>()
+    {
+        Ok(keep) => keep,
+        Err(_) => return Err(Failure::Args("Could not parse the number of jobs to keep".to_owned())),
+    };
+
+    let root = PathBuf::from(matches.value_of("scene-root").unwrap_or("."));
+    let root = root.canonicalize()
+        .map_err(|e| Failure::Io(format!("Could not find \"{}\": {}", root.display(), e)))?;
+
+    let ctx = match matches.is_present("cpu")
+    {
+        true => GpuContext::cpu(),
+        false => GpuContext::new(matches.value_of("adapter"), false).map_err(Failure::from)?,
+    };
+    pt_info!("Rendering on {}", ctx.info().name);
+
+    let address = matches.value_of("address").unwrap();
+    let listener = TcpListener::bind((address, port))
+        .map_err(|e| Failure::Io(format!("Could not listen on {}:{}: {}", address, port, e)))?;
+
+    let shared = Arc::new((Mutex::new(Jobs
+    {
+        jobs: BTreeMap::new(),
+        queue: VecDeque::new(),
+        next: 0,
+        keep: keep,
+    }), Condvar::new()));
+    {
+        let shared = shared.clone();
+        std::thread::spawn(move || work(shared, Arc::new(ctx), dir));
+    }
+
+    pt_info!("Listening on http://{}:{}, results are capped at {} pixels and {} samples",
+        address, port, limits.pixels, limits.samples);
+
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming()
+    {
+        let stream = match stream
+        {
+            Ok(stream) => stream,
+            Err(e) =>
+            {
+                pt_debug!("Could not accept a connection: {}", e);
+                continue;
+            },
+        };
+
+        let open = Open::new(&connections);
+        if connections.load(Ordering::SeqCst) > MAX_CONNECTIONS
+        {
+            pt_debug!("Turning away a connection, {} are open", MAX_CONNECTIONS);
+
+            // the socket's buffer has room for this, so it won't hold up
+            // accepting the next one
+            let _ = stream.set_nonblocking(true);
+            send(stream, 503, "application/json", &error_body("The server is busy, try again later"));
+            continue;
+        }
+
+        let (shared, limits, root) = (shared.clone(), limits.clone(), root.clone());
+
+        std::thread::spawn(move ||
+        {
+            answer(stream, &shared, &limits, &root);
+            drop(open);
+        });
+    }
+
+    Ok(())
+}
+
+/// Renders the queued jobs one at a time, forever.
+fn work(shared: Shared, ctx: Arc<GpuContext>, dir: PathBuf)
+{
+    let (jobs, more) = &*shared;
+
+    loop
+    {
+        let (id, scene, settings, formats) =
+        {
+            let mut jobs = jobs.lock().unwrap();
+
+            let id = loop
+            {
+                match jobs.queue.pop_front()
+                {
+                    Some(id) => break id,
+                    None => jobs = more.wait(jobs).unwrap(),
+                }
+            };
+
+            // cancelled while it was queued
+            let job = match jobs.jobs.get_mut(&id)
+            {
+                Some(job) if job.state == State::Queued => job,
+                _ => continue,
+            };
+
+            job.state = State::Rendering;
+
+            let settings = RenderSettings
+            {
+                samples: job.samples,
+                control: Some(job.control.clone()),
+                .. RenderSettings::new(job.res)
+            };
+
+            (id, job.scene.take().unwrap(), settings, job.formats.clone())
+        };
+
+        pt_info!("Rendering job {} at {}x{}", id, settings.res[0], settings.res[1]);
+
+        let control = settings.control.clone().unwrap();
+        let handle =
+        {
+            let shared = shared.clone();
+
+            scene.render_async_on(ctx.clone(), settings, 1, move |progress|
+            {
+                if let Some(job) = shared.0.lock().unwrap().jobs.get_mut(&id)
+                {
+                    job.done = progress.samples;
+                }
+            })
+        };
+        drop(scene);
+
+        let result = handle.join().map_err(|e| e.to_string());
+
+        // a cancelled job's image isn't wanted
+        let result = match control.stopping()
+        {
+            true => None,
+            false => Some(result.and_then(|image|
+            {
+                let files = formats.iter()
+                    .map(|format| dir.join(format!("job_{}.{}", id, format)))
+                    .collect::<Vec<_>>();
+
+                for file in &files
+                {
+                    image.save(&file.to_string_lossy(), &[])?;
+                }
+
+                Ok((image.samples, files))
+            })),
+        };
+
+        let mut jobs = jobs.lock().unwrap();
+        let job = match jobs.jobs.get_mut(&id)
+        {
+            Some(job) => job,
+            None => continue,
+        };
+
+        job.state = match result
+        {
+            None =>
+            {
+                pt_info!("Job {} was cancelled", id);
+                State::Cancelled
+            },
+            Some(Ok((samples, files))) =>
+            {
+                pt_info!("Job {} finished with {} samples", id, samples);
+                job.done = samples;
+                job.files = files;
+                State::Done
+            },
+            Some(Err(e)) =>
+            {
+                pt_error!("Job {} failed: {}", id, e);
+                State::Failed(e)
+            },
+        };
+
+        jobs.evict();
+    }
+}
+
+/// Counts an open connection until it's dropped.
+struct Open(Arc<AtomicUsize>);
+
+impl Open
+{
+    fn new(count: &Arc<AtomicUsize>) -> Open
+    {
+        count.fetch_add(1, Ordering::SeqCst);
+        Open(count.clone())
+    }
+}
+
+impl Drop for Open
+{
+    fn drop(&mut self)
+    {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Reads one request from `stream` and answers it.
+fn answer(stream: TcpStream, shared: &Shared, limits: &Limits, root: &Path)
+{
+    if let Err(e) = stream.set_write_timeout(Some(TIMEOUT))
+    {
+        pt_debug!("Could not set a timeout: {}", e);
+        return;
+    }
+
+    let reader = Deadline
+    {
+        stream: &stream,
+        deadline: Instant::now() + TIMEOUT,
+    };
+
+    let (status, content_type, body) = match read_request(reader)
+    {
+        Ok(request) =>
+        {
+            pt_debug!("{} {}", request.method, request.path);
+            route(&request, shared, limits, root)
+        },
+        Err((status, e)) => (status, "application/json", error_body(&e)),
+    };
+
+    send(stream, status, content_type, &body);
+}
+
+fn send(mut stream: TcpStream, status: u16, content_type: &str, body: &[u8])
+{
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, reason(status), content_type, body.len());
+
+    let sent = stream.write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(body))
+        .and_then(|_| stream.flush());
+
+    if let Err(e) = sent
+    {
+        pt_debug!("Could not answer a request: {}", e);
+    }
+}
+
+/// Reads from a connection until `deadline`, however slowly the bytes come.
+struct Deadline<'a>
+{
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_>
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize>
+    {
+        let left = self.deadline.saturating_duration_since(Instant::now());
+        if left == Duration::from_secs(0)
+        {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+
+        self.stream.set_read_timeout(Some(left))?;
+        (&mut &*self.stream).read(buf)
+    }
+}
+
+struct Request
+{
+    method: String,
+    /// without the query
+    path: String,
+    query: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// The request on `stream`, or the status and message to answer with if
+/// it's broken.
+fn read_request(stream: impl Read) -> Result<Request, (u16, String)>
+{
+    let mut reader = BufReader::new(stream);
+    let bad = |e: String| (400, e);
+
+    let line = read_line(&mut reader)?;
+
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next())
+    {
+        (Some(method), Some(target)) => (method.to_owned(), target.to_owned()),
+        _ => return Err(bad("The request line is malformed".to_owned())),
+    };
+
+    let mut length = 0;
+    for count in 0..
+    {
+        if count > MAX_HEADERS
+        {
+            return Err((431, format!("There are more than {} headers", MAX_HEADERS)));
+        }
+
+        let header = read_line(&mut reader)?;
+
+        let header = header.trim_end();
+        if header.is_empty()
+        {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':')
+        {
+            if name.trim().eq_ignore_ascii_case("content-length")
+            {
+                length = value.trim().parse::<u64>()
+                    .map_err(|_| bad("Could not parse the Content-Length".to_owned()))?;
+            }
+        }
+    }
+
+    if length > MAX_BODY
+    {
+        return Err((413, format!("The body is more than {} bytes", MAX_BODY)));
+    }
+
+    // grown as it comes rather than trusting the length
+    let mut body = Vec::new();
+    reader.take(length).read_to_end(&mut body).map_err(failed)?;
+
+    if (body.len() as u64) < length
+    {
+        return Err(bad("The body is shorter than its Content-Length".to_owned()));
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair|
+        {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(key), decode(value))
+        })
+        .collect();
+
+    Ok(Request
+    {
+        method: method,
+        path: decode(path),
+        query: query,
+        body: body,
+    })
+}
+
+/// One line of the request's head.
+fn read_line(reader: &mut impl BufRead) -> Result<String, (u16, String)>
+{
+    let mut line = String::new();
+    reader.take(MAX_LINE).read_line(&mut line).map_err(failed)?;
+
+    if !line.ends_with('\n') && line.len() as u64 == MAX_LINE
+    {
+        return Err((431, format!("A line of the request is more than {} bytes", MAX_LINE)));
+    }
+
+    Ok(line)
+}
+
+/// The status and message for an error reading a request.
+fn failed(e: std::io::Error) -> (u16, String)
+{
+    match e.kind()
+    {
+        std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock =>
+            (408, format!("The request took more than {} seconds", TIMEOUT.as_secs())),
+        _ => (400, e.to_string()),
+    }
+}
+
+/// Undoes the %XX escapes in a URL, and `+` for spaces.
+fn decode(text: &str) -> String
+{
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len()
+    {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match (bytes[i], hex)
+        {
+            (b'%', Some(byte)) =>
+            {
+                out.push(byte);
+                i += 3;
+            },
+            (b'+', _) =>
+            {
+                out.push(b' ');
+                i += 1;
+            },
+            (byte, _) =>
+            {
+                out.push(byte);
+                i += 1;
+            },
+        }
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The status, content type and body to answer `request` with.
+fn route(request: &Request, shared: &Shared, limits: &Limits, root: &Path)
+    -> (u16, &'static str, Vec<u8>)
+{
+    let json = |status: u16, value: json::JsonValue| (status, "application/json", value.dump().into_bytes());
+    let fail = |status: u16, e: &str| (status, "application/json", error_body(e));
+
+    let parts = request.path.split('/').filter(|p| !p.is_empty()).collect::<Vec<_>>();
+
+    let id = match parts.get(1).map(|id| id.parse::<u64>())
+    {
+        Some(Ok(id)) => Some(id),
+        Some(Err(_)) => return fail(404, &format!("There's no job \"{}\"", parts[1])),
+        None => None,
+    };
+
+    let (jobs, more) = &**shared;
+
+    let full = |jobs: &Jobs| jobs.queue.len() >= limits.queued;
+    let busy = || fail(503, &format!("{} jobs are already queued, try again later", limits.queued));
+
+    match (request.method.as_str(), parts.as_slice(), id)
+    {
+        ("POST", ["jobs"], _) =>
+        {
+            // checked before reading the scene, and again once it's read
+            if full(&jobs.lock().unwrap())
+            {
+                return busy();
+            }
+
+            let job = match new_job(request, limits, root)
+            {
+                Ok(job) => job,
+                Err(e) => return fail(400, &e),
+            };
+
+            let mut jobs = jobs.lock().unwrap();
+            if full(&jobs)
+            {
+                return busy();
+            }
+
+            jobs.next += 1;
+            let id = jobs.next;
+
+            pt_info!("Queued job {}, {}x{} with {} samples",
+                id, job.res[0], job.res[1], job.samples);
+
+            jobs.jobs.insert(id, job);
+            jobs.queue.push_back(id);
+            more.notify_one();
+
+            json(201, status(id, &jobs))
+        },
+        ("GET", ["jobs"], _) =>
+        {
+            let jobs = jobs.lock().unwrap();
+
+            json(200, jobs.jobs.keys().map(|&id| status(id, &jobs)).collect::<Vec<_>>().into())
+        },
+        ("GET", ["jobs", _], Some(id)) =>
+        {
+            let jobs = jobs.lock().unwrap();
+
+            match jobs.jobs.contains_key(&id)
+            {
+                true => json(200, status(id, &jobs)),
+                false => fail(404, &format!("There's no job {}", id)),
+            }
+        },
+        ("GET", ["jobs", _, name], Some(id)) =>
+        {
+            let jobs = jobs.lock().unwrap();
+
+            let job = match jobs.jobs.get(&id)
+            {
+                Some(job) => job,
+                None => return fail(404, &format!("There's no job {}", id)),
+            };
+
+            let format = match (*name, name.strip_prefix("image."))
+            {
+                ("image", _) => job.formats[0].as_str(),
+                (_, Some(format)) => format,
+                _ => return fail(404, &format!("Job {} has no \"{}\"", id, name)),
+            };
+
+            if job.state != State::Done
+            {
+                return fail(409, &format!("Job {} isn't done", id));
+            }
+
+            let file = match job.formats.iter().position(|f| f == format)
+            {
+                Some(i) => &job.files[i],
+                None => return fail(404, &format!("Job {} wasn't saved as {}", id, format)),
+            };
+
+            match std::fs::read(file)
+            {
+                Ok(bytes) => (200, content_type(format), bytes),
+                Err(e) => fail(500, &format!("Could not read \"{}\": {}", file.display(), e)),
+            }
+        },
+        ("DELETE", ["jobs", _], Some(id)) =>
+        {
+            let mut jobs = jobs.lock().unwrap();
+
+            let job = match jobs.jobs.get_mut(&id)
+            {
+                Some(job) => job,
+                None => return fail(404, &format!("There's no job {}", id)),
+            };
+
+            match job.state
+            {
+                State::Queued =>
+                {
+                    job.state = State::Cancelled;
+                    job.scene = None;
+                    jobs.queue.retain(|&i| i != id);
+                    pt_info!("Job {} was cancelled", id);
+
+                    let answer = status(id, &jobs);
+                    jobs.evict();
+                    return json(200, answer);
+                },
+                // it's marked cancelled once the sample it's on finishes
+                State::Rendering => job.control.stop(),
+                _ =>
+                {
+                    for file in &job.files
+                    {
+                        let _ = std::fs::remove_file(file);
+                    }
+
+                    jobs.jobs.remove(&id);
+                    return json(200, json::object! { "id": id, "status": "deleted" });
+                },
+            }
+
+            json(200, status(id, &jobs))
+        },
+        (_, ["jobs"], _) | (_, ["jobs", _], _) | (_, ["jobs", _, _], _) =>
+            fail(405, &format!("{} isn't allowed on {}", request.method, request.path)),
+        _ => fail(404, &format!("There's nothing at {}", request.path)),
+    }
+}
+
+/// A job from a `POST /jobs` request, or why it can't be rendered.
+fn new_job(request: &Request, limits: &Limits, root: &Path) -> Result<Job, String>
+{
+    let param = |name: &str| request.query.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str());
+
+    for (key, _) in &request.query
+    {
+        if !["resolution", "samples", "formats"].contains(&key.as_str())
+        {
+            return Err(format!("Unknown parameter \"{}\"", key));
+        }
+    }
+
+    let res = parse_resolution(param("resolution").unwrap_or("800x600"))?;
+    if res[0] as u64 * res[1] as u64 > limits.pixels
+    {
+        return Err(format!("{}x{} is more than the limit of {} pixels", res[0], res[1], limits.pixels));
+    }
+
+    let samples = match param("samples").unwrap_or("100").trim().parse::<u32>()
+    {
+        Ok(s) if s > 0 && s <= limits.samples => s,
+        Ok(s) if s > 0 => return Err(format!("{} samples is more than the limit of {}", s, limits.samples)),
+        _ => return Err("Could not parse samples, above 0".to_owned()),
+    };
+
+    let formats = param("formats").unwrap_or("png")
+        .split(',')
+        .map(|f| f.trim().trim_start_matches('.').to_lowercase())
+        .collect::<Vec<_>>();
+    if let Some(f) = formats.iter().find(|f| !FORMATS.iter().any(|(name, _)| name == f))
+    {
+        return Err(format!("Unknown format \"{}\", use {}",
+            f, FORMATS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")));
+    }
+
+    let source = std::str::from_utf8(&request.body)
+        .map_err(|_| "The scene isn't UTF-8".to_owned())?;
+    let (def, _) = SceneDef::load_confined(source, Format::Json, root)?;
+
+    let triangles = def.triangle_count();
+    if let Some(max) = limits.triangles.filter(|&max| triangles > max)
+    {
+        return Err(format!("The scene makes {} triangles, more than the limit of {}", triangles, max));
+    }
+
+    let (mut scene, _) = Scene::from_def(&def)?;
+
+    if scene.auto_camera
+    {
+        scene.frame_camera(res[0] as f32 / res[1] as f32)?;
+    }
+
+    Ok(Job
+    {
+        scene: Some(scene),
+        res: res,
+        samples: samples,
+        formats: formats,
+        state: State::Queued,
+        done: 0,
+        control: Arc::new(RenderControl::new()),
+        files: Vec::new(),
+    })
+}
+
+/// A job's status, as JSON.
+fn status(id: u64, jobs: &Jobs) -> json::JsonValue
+{
+    let job = &jobs.jobs[&id];
+
+    let mut status = json::object!
+    {
+        "id": id,
+        "status": match job.state
+        {
+            State::Queued => "queued",
+            State::Rendering => "rendering",
+            State::Done => "done",
+            State::Failed(_) => "failed",
+            State::Cancelled => "cancelled",
+        },
+        "samples": job.done,
+        "max_samples": job.samples,
+        "resolution": &job.res[..],
+        "formats": job.formats.clone(),
+    };
+
+    match &job.state
+    {
+        State::Queued =>
+            status["queue_position"] = jobs.queue.iter().position(|&i| i == id).into(),
+        State::Failed(e) => status["error"] = e.as_str().into(),
+        State::Done => status["images"] = job.formats.iter()
+            .map(|f| format!("/jobs/{}/image.{}", id, f))
+            .collect::<Vec<_>>()
+            .into(),
+        _ => (),
+    }
+
+    status
+}
+
+fn error_body(e: &str) -> Vec<u8>
+{
+    json::object! { "error": e }.dump().into_bytes()
+}
+
+fn content_type(format: &str) -> &'static str
+{
+    FORMATS.iter()
+        .find(|(name, _)| *name == format)
+        .map_or("application/octet-stream", |(_, content_type)| content_type)
+}
+
+fn reason(status: u16) -> &'static str
+{
+    match status
+    {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn job(state: State, files: Vec<PathBuf>) -> Job
+    {
+        Job
+        {
+            scene: None,
+            res: [1, 1],
+            samples: 1,
+            formats: vec!["png".to_owned()],
+            state: state,
+            done: 0,
+            control: Arc::new(RenderControl::new()),
+            files: files,
+        }
+    }
+
+    #[test]
+    fn reads_a_request()
+    {
+        let request = read_request(&b"POST /jobs?samples=4&formats=png%2Cexr HTTP/1.1\r\n\
+            Content-Length: 5\r\n\r\nhello"[..]).unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/jobs");
+        assert_eq!(request.query, vec![
+            ("samples".to_owned(), "4".to_owned()),
+            ("formats".to_owned(), "png,exr".to_owned())]);
+        assert_eq!(request.body, b"hello");
+    }
+
+    #[test]
+    fn refuses_broken_requests()
+    {
+        let status = |request: &[u8]| read_request(request).err().map(|(status, _)| status);
+
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_LINE as usize));
+        let headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X: y\r\n".repeat(MAX_HEADERS + 1));
+        let huge = format!("POST /jobs HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+
+        assert_eq!(status(long_line.as_bytes()), Some(431));
+        assert_eq!(status(headers.as_bytes()), Some(431));
+        assert_eq!(status(huge.as_bytes()), Some(413));
+        assert_eq!(status(b"POST /jobs HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"), Some(400));
+        assert_eq!(status(b"nonsense\r\n\r\n"), Some(400));
+    }
+
+    #[test]
+    fn turns_jobs_away_when_the_queue_is_full()
+    {
+        let shared: Shared = Arc::new((Mutex::new(Jobs
+        {
+            jobs: BTreeMap::new(),
+            queue: VecDeque::new(),
+            next: 0,
+            keep: 10,
+        }), Condvar::new()));
+        let limits = Limits
+        {
+            pixels: 64,
+            samples: 4,
+            triangles: None,
+            queued: 2,
+        };
+
+        let request = |method: &str, path: &str| Request
+        {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            query: vec![("resolution".to_owned(), "8x8".to_owned()), ("samples".to_owned(), "4".to_owned())],
+            body: br#"{
+                "camera": { "pos": [0, 0, 5], "front": [0, 0, -1], "up": [0, 1, 0], "fov": 60 },
+                "materials": { "white": { "colour": [1, 1, 1] } },
+                "surfaces": [{ "tri": [[-1, -1, 0], [1, -1, 0], [0, 1, 0]], "mat": "white" }]
+            }"#.to_vec(),
+        };
+        let send = |method: &str, path: &str|
+        {
+            let (status, _, body) = route(&request(method, path), &shared, &limits, Path::new("."));
+            (status, json::parse(&String::from_utf8(body).unwrap()).unwrap())
+        };
+
+        assert_eq!(send("POST", "/jobs").0, 201);
+        assert_eq!(send("POST", "/jobs").0, 201);
+        let (status, body) = send("POST", "/jobs");
+        assert_eq!(status, 503);
+        assert!(body["error"].as_str().unwrap().contains("2 jobs are already queued"));
+        assert_eq!(shared.0.lock().unwrap().jobs.len(), 2);
+
+        // a cancelled job leaves the queue, making room for another
+        assert_eq!(send("DELETE", "/jobs/1").0, 200);
+        assert_eq!(shared.0.lock().unwrap().queue, [2]);
+        assert_eq!(send("GET", "/jobs/2").1["queue_position"], 0);
+
+        let (status, body) = send("POST", "/jobs");
+        assert_eq!(status, 201);
+        assert_eq!(body["queue_position"], 1);
+    }
+
+    #[test]
+    fn evicts_the_oldest_finished_jobs()
+    {
+        let dir = std::env::temp_dir().join(format!("path-tracer-gpu-evict-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("job_1.png");
+        std::fs::write(&file, b"").unwrap();
+
+        let mut jobs = Jobs
+        {
+            jobs: BTreeMap::new(),
+            queue: VecDeque::new(),
+            next: 5,
+            keep: 2,
+        };
+        jobs.jobs.insert(1, job(State::Done, vec![file.clone()]));
+        jobs.jobs.insert(2, job(State::Queued, Vec::new()));
+        jobs.jobs.insert(3, job(State::Failed("no".to_owned()), Vec::new()));
+        jobs.jobs.insert(4, job(State::Rendering, Vec::new()));
+        jobs.jobs.insert(5, job(State::Cancelled, Vec::new()));
+
+        jobs.evict();
+
+        assert_eq!(jobs.jobs.keys().copied().collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+        assert!(!file.exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -13,12 +13,57 @@ struct Triangle
     mat: u32;
 };
 
+// where a moving triangle's vertices are at the end of the frame
+struct Motion
+{
+    a: array<f32, 3>;
+    b: array<f32, 3>;
+    c: array<f32, 3>;
+};
+
+// colours or normals at a triangle's points
+struct Corners
+{
+    a: array<f32, 3>;
+    b: array<f32, 3>;
+    c: array<f32, 3>;
+};
+
+// texture coordinates at a triangle's points
+struct CornerUvs
+{
+    a: array<f32, 2>;
+    b: array<f32, 2>;
+    c: array<f32, 2>;
+};
+
+// textures count from 1, 0 is none
 struct Material
 {
-    colour   : array<f32, 3>;
-    glow     : array<f32, 3>;
-    gloss    : f32;
-    reflect_c: array<f32, 3>;
+    colour         : array<f32, 3>;
+    glow           : array<f32, 3>;
+    gloss          : f32;
+    reflect_c      : array<f32, 3>;
+    flags          : u32;
+    glow_texture   : u32;
+    // tangent space normals, with u along x and v along y
+    normal_texture : u32;
+    normal_strength: f32;
+    // rays go through where its alpha is below alpha_cutoff
+    alpha_texture  : u32;
+    alpha_cutoff   : f32;
+};
+
+// a material's colour as fractal noise between a and b, or none when
+// octaves is 0
+struct Noise
+{
+    a         : array<f32, 3>;
+    b         : array<f32, 3>;
+    scale     : f32;
+    lacunarity: f32;
+    gain      : f32;
+    octaves   : u32;
 };
 
 [[block]]
@@ -30,6 +75,26 @@ struct Info
     height   : u32;
     samples  : u32;
     depth    : u32;
+    tile_x   : u32;
+    tile_y   : u32;
+    tile_w   : u32;
+    slice_x  : u32;
+    slice_y  : u32;
+    slice_w  : u32;
+    slice_h  : u32;
+    sample   : u32;
+    seed     : u32;
+    shutter  : f32;
+    moving   : u32;
+    matte    : u32;
+    squares  : u32;
+    depths   : u32;
+    mode     : u32;
+    ao_rays  : u32;
+    ao_dist  : f32;
+    far      : f32;
+    // see offset
+    ray_eps  : f32;
 };
 
 [[block]]
@@ -41,10 +106,17 @@ struct Camera
     fov  : f32;
 };
 
+// a pixel's sum of samples, and what rounding lost from it, see `accumulate`
+struct Sum
+{
+    sum : array<f32, 3>;
+    lost: array<f32, 3>;
+};
+
 [[block]]
 struct Image
 {
-    pixels: [[stride(12)]] array<Colour>;
+    pixels: [[stride(24)]] array<Sum>;
 };
 
 [[block]]
@@ -56,20 +128,91 @@ struct Triangles
 [[block]]
 struct Materials
 {
-    data: [[stride(40)]] array<Material>;
+    data: [[stride(64)]] array<Material>;
+};
+
+[[block]]
+struct Motions
+{
+    data: [[stride(36)]] array<Motion>;
+};
+
+[[block]]
+struct Colours
+{
+    data: [[stride(36)]] array<Corners>;
+};
+
+[[block]]
+struct Normals
+{
+    data: [[stride(36)]] array<Corners>;
+};
+
+// one for each material
+[[block]]
+struct Noises
+{
+    data: [[stride(40)]] array<Noise>;
+};
+
+[[block]]
+struct Uvs
+{
+    data: [[stride(24)]] array<CornerUvs>;
+};
+
+// a header for each texture, with where its texels start, its width and
+// its height, then every texture's texels, top row first
+[[block]]
+struct Texels
+{
+    data: [[stride(16)]] array<vec4<f32>>;
+};
+
+// sums of Path.matte for each pixel, kept like `Sum`
+struct MatteSum
+{
+    sum : vec4<f32>;
+    lost: vec4<f32>;
 };
 
 [[block]]
-struct Seeds
+struct Matte
 {
-    data: [[stride(4)]] array<u32>;
+    data: [[stride(32)]] array<MatteSum>;
 };
 
+// sums of each pixel's squared luminance, for how noisy it is, kept like
+// `Sum`
+struct SquareSum
+{
+    sum : f32;
+    lost: f32;
+};
+
+[[block]]
+struct Squares
+{
+    data: [[stride(8)]] array<SquareSum>;
+};
 
+// each pixel's distance to the first surface, from whichever sample
+// reaches it first, or -1 for misses
+[[block]]
+struct Depths
+{
+    data: [[stride(4)]] array<f32>;
+};
+
+// Random numbers are a hash of a key, from the pixel, sample and seed, and
+// a counter, so no state carries between pixels or samples. Each bounce
+// starts the counter at its own block of numbers.
 struct Random
 {
-    state: u32;
-    latest: f32;
+    key    : u32;
+    counter: u32;
+    latest : f32;
 };
 
 [[group(0), binding(0)]]
@@ -82,8 +225,49 @@ var<storage, read_write> image: Image;
 var<storage, read> triangles: Triangles;
 [[group(0), binding(4)]]
 var<storage, read> materials: Materials;
+// a single unused entry unless MOVING is set
 [[group(0), binding(5)]]
-var<storage, read> seeds: Seeds;
+var<storage, read> motions: Motions;
+// a single unused entry unless MATTE is set
+[[group(0), binding(6)]]
+var<storage, read_write> matte: Matte;
+// a single unused entry unless SQUARES is set
+[[group(0), binding(7)]]
+var<storage, read_write> squares: Squares;
+// a single unused entry unless DEPTHS is set
+[[group(0), binding(8)]]
+var<storage, read_write> depths: Depths;
+// a single unused entry unless COLOURED is set
+[[group(0), binding(9)]]
+var<storage, read> colours: Colours;
+// a single unused entry each unless TEXTURED is set
+[[group(0), binding(10)]]
+var<storage, read> uvs: Uvs;
+[[group(0), binding(11)]]
+var<storage, read> texels: Texels;
+// a single unused entry unless SMOOTH is set
+[[group(0), binding(12)]]
+var<storage, read> normals: Normals;
+// a single unused entry unless NOISY is set
+[[group(0), binding(13)]]
+var<storage, read> noises: Noises;
+
+// These are replaced with each render's settings before compiling, so
+// the branches on them are on constants rather than uniforms. The same
+// values are in info.
+let DEPTH  : u32 = 5u;
+let MODE   : u32 = 0u;
+let MOVING : bool = false;
+let COLOURED: bool = false;
+let TEXTURED: bool = false;
+let SMOOTH : bool = false;
+let NOISY  : bool = false;
+let MATTE  : bool = false;
+let SQUARES: bool = false;
+let DEPTHS : bool = false;
+
+// misses are at least this far away, see gpu::MISS
+let MISS: f32 = 1.0e18;
 
 struct Ray
 {
@@ -96,61 +280,139 @@ fn _vec3(v: array<f32, 3>) -> vec3<f32>
     return vec3<f32>(v[0], v[1], v[2]);
 }
 
-fn xorshift(state: Random) -> Random
+// PCG-RXS-M-XS, as a hash
+fn pcg(v: u32) -> u32
 {
-    var r: Random;
-    var x: u32 = state.state;
+    var state: u32 = v * 747796405u + 2891336453u;
+    var word: u32 = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
 
-    x = x ^ (x << u32(13));
-    x = x ^ (x >> u32(17));
-    x = x ^ (x << u32(5));
+    return (word >> 22u) ^ word;
+}
 
-    r.state = x;
-    r.latest = f32(x) / 4294967295.0; // x / max u32
+fn next_random(rand: Random) -> Random
+{
+    var r: Random = rand;
+
+    // the top 24 bits, so it's below 1
+    r.latest = f32(pcg(rand.key ^ pcg(rand.counter)) >> 8u) / 16777216.0;
+    r.counter = rand.counter + 1u;
 
     return r;
 }
 
-fn ray_vs_triangle(ray: Ray, triangle: Triangle) -> vec3<f32>
+fn lerp3(a: array<f32, 3>, b: array<f32, 3>, t: f32) -> array<f32, 3>
+{
+    var v: vec3<f32> = _vec3(a) + (_vec3(b) - _vec3(a)) * t;
+
+    return array<f32, 3>(v.x, v.y, v.z);
+}
+
+// the triangle part way through the frame, at time t in frames
+fn at_time(triangle: Triangle, end: Motion, t: f32) -> Triangle
 {
-    var eps: f32 = 0.0001;
-    var invalid: vec3<f32> = vec3<f32>(99999.0, 99999.0, 99999.0);
+    var moved: Triangle = triangle;
 
-    var va: vec3<f32> = _vec3(triangle.a);
-    var vb: vec3<f32> = _vec3(triangle.b);
-    var vc: vec3<f32> = _vec3(triangle.c);
+    moved.a = lerp3(triangle.a, end.a, t);
+    moved.b = lerp3(triangle.b, end.b, t);
+    moved.c = lerp3(triangle.c, end.c, t);
 
-    var edge_1: vec3<f32> = vb - va;
-    var edge_2: vec3<f32> = vc - va;
+    return moved;
+}
 
-    var h: vec3<f32> = cross(ray.vec, edge_2);
-    var a: f32 = dot(edge_1, h);
+// v with its axes reordered so z is the ray's largest axis, kz, and x and
+// y swapped when flip is set
+fn permute(v: vec3<f32>, kz: u32, flip: bool) -> vec3<f32>
+{
+    var p: vec3<f32> = v;
 
-    if (a > -eps && a < eps)
+    if (kz == 0u)
     {
-        return invalid;
+        p = v.yzx;
     }
+    if (kz == 1u)
+    {
+        p = v.zxy;
+    }
+    if (flip)
+    {
+        p = p.yxz;
+    }
+
+    return p;
+}
+
+// Whether the ray is inside the edge from p to q, whose edge function is f,
+// where det has the sign of the inside. A ray exactly on the edge goes to
+// whichever triangle has its inside to the edge's top-left, so of two
+// triangles sharing the edge exactly one is hit.
+fn inside_edge(f: f32, p: vec2<f32>, q: vec2<f32>, det: f32) -> bool
+{
+    if (f != 0.0)
+    {
+        return (f > 0.0) == (det > 0.0);
+    }
+
+    var e: vec2<f32> = q - p;
+    var top_left: bool = e.y > 0.0 || (e.y == 0.0 && e.x > 0.0);
+
+    return top_left == (det > 0.0);
+}
+
+// Where the ray hits the triangle, or invalid if it misses. This is Woop,
+// Benthin and Wald's watertight test: the triangle is sheared into a space
+// where the ray runs along z from the origin, so the edge functions of two
+// triangles sharing an edge are computed from the same numbers and a ray
+// can't slip between them.
+fn ray_vs_triangle(ray: Ray, triangle: Triangle) -> vec3<f32>
+{
+    var invalid: vec3<f32> = vec3<f32>(MISS, MISS, MISS);
+
+    var m: vec3<f32> = abs(ray.vec);
+    var kz: u32 = select(select(0u, 1u, m.y >= m.x), 2u, m.z >= max(m.x, m.y));
+    var flip: bool = permute(ray.vec, kz, false).z < 0.0;
+
+    var d: vec3<f32> = permute(ray.vec, kz, flip);
+    var shear: vec3<f32> = vec3<f32>(-d.x / d.z, -d.y / d.z, 1.0 / d.z);
+
+    var a: vec3<f32> = permute(_vec3(triangle.a) - ray.start, kz, flip);
+    var b: vec3<f32> = permute(_vec3(triangle.b) - ray.start, kz, flip);
+    var c: vec3<f32> = permute(_vec3(triangle.c) - ray.start, kz, flip);
+
+    var ax: f32 = a.x + shear.x * a.z;
+    var ay: f32 = a.y + shear.y * a.z;
+    var bx: f32 = b.x + shear.x * b.z;
+    var by: f32 = b.y + shear.y * b.z;
+    var cx: f32 = c.x + shear.x * c.z;
+    var cy: f32 = c.y + shear.y * c.z;
+
+    // the edge functions, all the same sign inside
+    var u: f32 = cx * by - cy * bx;
+    var v: f32 = ax * cy - ay * cx;
+    var w: f32 = bx * ay - by * ax;
 
-    var f: f32 = 1.0 / a;
-    var s: vec3<f32> = ray.start - va;
-    var u: f32 = f * dot(s, h);
+    var det: f32 = u + v + w;
 
-    if (u < 0.0 || u > 1.0)
+    if (det == 0.0)
     {
         return invalid;
     }
 
-    var q: vec3<f32> = cross(s, edge_1);
-    var v: f32 = f * dot(ray.vec, q);
+    var sa: vec2<f32> = vec2<f32>(ax, ay);
+    var sb: vec2<f32> = vec2<f32>(bx, by);
+    var sc: vec2<f32> = vec2<f32>(cx, cy);
 
-    if (v < 0.0 || (u + v) > 1.0)
+    if (!inside_edge(u, sb, sc, det) || !inside_edge(v, sc, sa, det)
+        || !inside_edge(w, sa, sb, det))
     {
         return invalid;
     }
 
-    let t: f32 = f * dot(edge_2, q);
+    // anything in front of the start counts, since rays leaving a surface
+    // start off it (see offset), and a minimum distance would let rays
+    // starting next to a wall through it
+    var t: f32 = (u * a.z + v * b.z + w * c.z) * shear.z / det;
 
-    if (t > eps)
+    if (t > 0.0)
     {
         return ray.start + ray.vec * t;
     }
@@ -178,6 +440,25 @@ fn pos_normal(ray: Ray, triangle: Triangle) -> vec3<f32>
     }
 }
 
+fn front_face(ray: Ray, triangle: Triangle) -> bool
+{
+    var a: vec3<f32> = _vec3(triangle.a);
+    var b: vec3<f32> = _vec3(triangle.b);
+    var c: vec3<f32> = _vec3(triangle.c);
+
+    return dot(ray.vec, cross(b - a, c - a)) < 0.0;
+}
+
+// where a ray leaving point starts, off the surface by info.ray_eps times
+// the point's largest coordinate, since floats lose precision in
+// proportion to their size
+fn offset(point: vec3<f32>, norm: vec3<f32>) -> vec3<f32>
+{
+    var size: f32 = max(1.0, max(abs(point.x), max(abs(point.y), abs(point.z))));
+
+    return point + norm * (info.ray_eps * size);
+}
+
 fn reflect_vec(incoming: vec3<f32>, normal: vec3<f32>) -> vec3<f32>
 {
     var v: vec3<f32> = normalize(incoming);
@@ -186,99 +467,609 @@ fn reflect_vec(incoming: vec3<f32>, normal: vec3<f32>) -> vec3<f32>
     return normalize(v - n * 2.0 * dot(v, n));
 }
 
-fn cast_ray(ray: Ray, rand: Random) -> vec3<f32>
+struct Hit
+{
+    dist : f32;
+    point: vec3<f32>;
+    norm : vec3<f32>;
+    front: bool;
+    mat  : Material;
+    // the triangle that was hit, moved to the ray's time
+    tri  : Triangle;
+    index: u32;
+};
+
+// the colour along a path, and for the matte: whether the camera saw a
+// surface, whether it saw a shadow catcher, and the light reaching the
+// catcher with and without things in the way
+struct Path
+{
+    colour: vec3<f32>;
+    matte : vec4<f32>;
+};
+
+// the brightness of the glow a ray hit, or 0 if it missed
+fn glow_seen(hit: Hit) -> f32
+{
+    // one sided (flag 1) materials don't glow from the back
+    if (hit.dist >= MISS || (!hit.front && (hit.mat.flags & u32(1)) != u32(0)))
+    {
+        return 0.0;
+    }
+
+    return dot(_vec3(hit.mat.glow), vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// how close p is to each of the triangle's points, adding up to 1
+fn weights(tri: Triangle, p: vec3<f32>) -> vec3<f32>
+{
+    var a: vec3<f32> = _vec3(tri.a);
+    var b: vec3<f32> = _vec3(tri.b);
+    var c: vec3<f32> = _vec3(tri.c);
+
+    var area: f32 = length(cross(b - a, c - a));
+
+    return vec3<f32>(
+        length(cross(b - p, c - p)),
+        length(cross(c - p, a - p)),
+        length(cross(a - p, b - p))) / area;
+}
+
+// how close the hit is to each of the triangle's points
+fn barycentric(hit: Hit) -> vec3<f32>
+{
+    return weights(hit.tri, hit.point);
+}
+
+// a random number below 1 for each point of each octave's grid
+fn lattice(p: vec3<i32>, octave: u32) -> f32
+{
+    var h: u32 = pcg(u32(p.x) ^ pcg(u32(p.y) ^ pcg(u32(p.z) ^ pcg(octave))));
+
+    return f32(h >> 8u) / 16777216.0;
+}
+
+// the lattice's numbers blended smoothly between the grid points around p
+fn value_noise(p: vec3<f32>, octave: u32) -> f32
+{
+    var cell: vec3<f32> = floor(p);
+    var i: vec3<i32> = vec3<i32>(cell);
+    var f: vec3<f32> = p - cell;
+    var w: vec3<f32> = f * f * (3.0 - 2.0 * f);
+
+    var x00: f32 = mix(lattice(i, octave), lattice(i + vec3<i32>(1, 0, 0), octave), w.x);
+    var x10: f32 = mix(lattice(i + vec3<i32>(0, 1, 0), octave), lattice(i + vec3<i32>(1, 1, 0), octave), w.x);
+    var x01: f32 = mix(lattice(i + vec3<i32>(0, 0, 1), octave), lattice(i + vec3<i32>(1, 0, 1), octave), w.x);
+    var x11: f32 = mix(lattice(i + vec3<i32>(0, 1, 1), octave), lattice(i + vec3<i32>(1, 1, 1), octave), w.x);
+
+    return mix(mix(x00, x10, w.y), mix(x01, x11, w.y), w.z);
+}
+
+// octaves of value noise, each finer and weaker than the last, averaged so
+// it stays between 0 and 1
+fn fbm(p: vec3<f32>, noise: Noise) -> f32
+{
+    var sum: f32 = 0.0;
+    var total: f32 = 0.0;
+    var amplitude: f32 = 1.0;
+    var frequency: f32 = noise.scale;
+
+    for (var o: u32 = 0u; o < noise.octaves; o = o + 1u)
+    {
+        sum = sum + amplitude * value_noise(p * frequency, o);
+        total = total + amplitude;
+        amplitude = amplitude * noise.gain;
+        frequency = frequency * noise.lacunarity;
+    }
+
+    return sum / total;
+}
+
+// the material's colour, or its noise at the hit, times the colours at the
+// triangle's points blended by how close the hit is to each
+fn albedo(hit: Hit) -> vec3<f32>
+{
+    var colour: vec3<f32> = _vec3(hit.mat.colour);
+
+    if (NOISY)
+    {
+        var noise: Noise = noises.data[hit.tri.mat];
+
+        if (noise.octaves != 0u)
+        {
+            colour = mix(_vec3(noise.a), _vec3(noise.b), fbm(hit.point, noise));
+        }
+    }
+
+    if (!COLOURED)
+    {
+        return colour;
+    }
+
+    var w: vec3<f32> = barycentric(hit);
+    var corners: Corners = colours.data[hit.index];
+
+    return colour * (_vec3(corners.a) * w.x + _vec3(corners.b) * w.y + _vec3(corners.c) * w.z);
+}
+
+// where the point with weights w is on triangle index's textures
+fn uv_at(index: u32, w: vec3<f32>) -> vec2<f32>
+{
+    var corners: CornerUvs = uvs.data[index];
+
+    return vec2<f32>(corners.a[0], corners.a[1]) * w.x
+        + vec2<f32>(corners.b[0], corners.b[1]) * w.y
+        + vec2<f32>(corners.c[0], corners.c[1]) * w.z;
+}
+
+// where the hit is on the triangle's textures
+fn hit_uv(hit: Hit) -> vec2<f32>
+{
+    return uv_at(hit.index, barycentric(hit));
+}
+
+// texel x, y of the texture starting at start, repeating past its edges
+fn texel(start: u32, width: u32, height: u32, x: i32, y: i32) -> vec4<f32>
+{
+    var col: u32 = u32(((x % i32(width)) + i32(width)) % i32(width));
+    var row: u32 = u32(((y % i32(height)) + i32(height)) % i32(height));
+
+    return texels.data[start + row * width + col];
+}
+
+// texture t (counting from 1) at uv, blended between the nearest four
+// texels. U goes right and v goes up.
+fn sample_texture(t: u32, uv: vec2<f32>) -> vec4<f32>
+{
+    var header: vec4<f32> = texels.data[t - 1u];
+    var start: u32 = u32(header.x);
+    var width: u32 = u32(header.y);
+    var height: u32 = u32(header.z);
+
+    var x: f32 = uv.x * f32(width) - 0.5;
+    var y: f32 = (1.0 - uv.y) * f32(height) - 0.5;
+    var x0: f32 = floor(x);
+    var y0: f32 = floor(y);
+    var fx: f32 = x - x0;
+    var fy: f32 = y - y0;
+    var xi: i32 = i32(x0);
+    var yi: i32 = i32(y0);
+
+    var top: vec4<f32> = texel(start, width, height, xi, yi)
+        + (texel(start, width, height, xi + 1, yi)
+            - texel(start, width, height, xi, yi)) * fx;
+    var bottom: vec4<f32> = texel(start, width, height, xi, yi + 1)
+        + (texel(start, width, height, xi + 1, yi + 1)
+            - texel(start, width, height, xi, yi + 1)) * fx;
+
+    return top + (bottom - top) * fy;
+}
+
+// the nearest triangle along the ray, at time t in the frame, only looking
+// at glowing triangles when lights_only is set
+fn trace(ray: Ray, time: f32, lights_only: bool) -> Hit
+{
+    var hit: Hit;
+    hit.dist = MISS;
+    hit.front = true;
+
+    for (var i: u32 = u32(0); i < info.triangles; i = i + u32(1))
+    {
+        var tri: Triangle = triangles.data[i];
+
+        if (lights_only)
+        {
+            var glow: vec3<f32> = _vec3(materials.data[tri.mat].glow);
+
+            if (glow.x + glow.y + glow.z <= 0.0)
+            {
+                continue;
+            }
+        }
+
+        if (MOVING)
+        {
+            tri = at_time(tri, motions.data[i], time);
+        }
+
+        var p: vec3<f32> = ray_vs_triangle(ray, tri);
+
+        var dist: f32 = length(p - ray.start);
+
+        if (dist < hit.dist)
+        {
+            var mat: Material = materials.data[tri.mat];
+
+            // cut out parts are passed over, so the ray goes on to whatever
+            // is behind them
+            if (TEXTURED && mat.alpha_texture != 0u)
+            {
+                var uv: vec2<f32> = uv_at(i, weights(tri, p));
+
+                if (sample_texture(mat.alpha_texture, uv).w < mat.alpha_cutoff)
+                {
+                    continue;
+                }
+            }
+
+            hit.dist = dist;
+            hit.point = p;
+            hit.norm = pos_normal(ray, tri);
+            hit.front = front_face(ray, tri);
+            hit.mat = mat;
+            hit.tri = tri;
+            hit.index = i;
+        }
+    }
+
+    return hit;
+}
+
+// the material's glow, times its glow texture if it has one
+fn emission(hit: Hit) -> vec3<f32>
+{
+    var glow: vec3<f32> = _vec3(hit.mat.glow);
+
+    if (!TEXTURED || hit.mat.glow_texture == 0u)
+    {
+        return glow;
+    }
+
+    return glow * sample_texture(hit.mat.glow_texture, hit_uv(hit)).xyz;
+}
+
+// n tilted by the material's normal map, in a tangent frame along the
+// triangle's u and v, or n if it has none or its uvs are degenerate
+fn normal_mapped(hit: Hit, n: vec3<f32>) -> vec3<f32>
+{
+    if (!TEXTURED || hit.mat.normal_texture == 0u)
+    {
+        return n;
+    }
+
+    var corners: CornerUvs = uvs.data[hit.index];
+    var e1: vec3<f32> = _vec3(hit.tri.b) - _vec3(hit.tri.a);
+    var e2: vec3<f32> = _vec3(hit.tri.c) - _vec3(hit.tri.a);
+    var d1: vec2<f32> = vec2<f32>(corners.b[0] - corners.a[0], corners.b[1] - corners.a[1]);
+    var d2: vec2<f32> = vec2<f32>(corners.c[0] - corners.a[0], corners.c[1] - corners.a[1]);
+
+    var det: f32 = d1.x * d2.y - d2.x * d1.y;
+
+    if (det == 0.0)
+    {
+        return n;
+    }
+
+    var t: vec3<f32> = (e1 * d2.y - e2 * d1.y) / det;
+    var b: vec3<f32> = (e2 * d1.x - e1 * d2.x) / det;
+
+    // the tangent square to n, and the bitangent the way v goes
+    t = t - n * dot(n, t);
+
+    if (dot(t, t) == 0.0)
+    {
+        return n;
+    }
+
+    t = normalize(t);
+    b = cross(n, t) * select(-1.0, 1.0, dot(cross(n, t), b) >= 0.0);
+
+    var m: vec3<f32> = sample_texture(hit.mat.normal_texture, hit_uv(hit)).xyz * 2.0 - 1.0;
+    var tilted: vec3<f32> = t * (m.x * hit.mat.normal_strength)
+        + b * (m.y * hit.mat.normal_strength)
+        + n * m.z;
+
+    if (dot(tilted, tilted) == 0.0)
+    {
+        return n;
+    }
+
+    return normalize(tilted);
+}
+
+// the normals at the triangle's points blended by how close the hit is to
+// each, on the same side as hit.norm, then tilted by the material's normal
+// map. Triangles without any, with zeroes, use hit.norm.
+fn shading_normal(hit: Hit) -> vec3<f32>
+{
+    if (!SMOOTH)
+    {
+        return normal_mapped(hit, hit.norm);
+    }
+
+    var w: vec3<f32> = barycentric(hit);
+    var corners: Corners = normals.data[hit.index];
+    var n: vec3<f32> = _vec3(corners.a) * w.x + _vec3(corners.b) * w.y + _vec3(corners.c) * w.z;
+
+    if (dot(n, n) == 0.0)
+    {
+        return normal_mapped(hit, hit.norm);
+    }
+
+    return normal_mapped(hit, normalize(n) * select(-1.0, 1.0, dot(n, hit.norm) >= 0.0));
+}
+
+// a direction around n, more likely the closer it is to n (a pdf of
+// cos / pi), from two random numbers
+fn cosine_sample(n: vec3<f32>, u1: f32, u2: f32) -> vec3<f32>
+{
+    // the branchless basis from Duff et al., "Building an Orthonormal
+    // Basis, Revisited"
+    var sign: f32 = select(-1.0, 1.0, n.z >= 0.0);
+    var a: f32 = -1.0 / (sign + n.z);
+    var c: f32 = n.x * n.y * a;
+
+    var t: vec3<f32> = vec3<f32>(1.0 + sign * n.x * n.x * a, sign * c, -sign * n.x);
+    var b: vec3<f32> = vec3<f32>(c, sign + n.y * n.y * a, -n.y);
+
+    var r: f32 = sqrt(u1);
+    var phi: f32 = 6.2831853 * u2;
+
+    return normalize(t * (r * cos(phi)) + b * (r * sin(phi)) + n * sqrt(max(0.0, 1.0 - u1)));
+}
+
+fn cast_ray(ray: Ray, rand: Random, time: f32) -> Path
 {
     var ray = ray;
     var rand = rand;
 
-    var push: f32 = 0.001;
+    var path: Path;
+    path.colour = vec3<f32>(0.0, 0.0, 0.0);
+    path.matte = vec4<f32>(0.0, 0.0, 0.0, 0.0);
 
     var colour: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
     var throughput: vec3<f32> = vec3<f32>(1.0, 1.0, 1.0);
-    var weight: f32 = 1.0;
 
-    for (var d: u32 = u32(0); d < info.depth; d = d + u32(1))
+    for (var d: u32 = u32(0); d < DEPTH; d = d + u32(1))
     {
-        var min_dist: f32 = 99999.0;
-        var point: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
-        var norm: vec3<f32> = vec3<f32>(0.0, 0.0, 0.0);
-        var mat: Material;
+        // the camera uses the first block
+        rand.counter = (d + 1u) * 8u;
+
+        var hit: Hit = trace(ray, time, false);
 
-        for (var i: u32 = u32(0); i < info.triangles; i = i + u32(1))
+        if (hit.dist >= MISS)
         {
-            var p: vec3<f32> = ray_vs_triangle(ray, triangles.data[i]);
+            break;
+        }
 
-            var dist: f32 = length(p - ray.start);
+        var point: vec3<f32> = hit.point;
+        var norm: vec3<f32> = hit.norm;
+        var shade: vec3<f32> = shading_normal(hit);
+        var front: bool = hit.front;
+        var mat: Material = hit.mat;
 
-            if (dist < min_dist)
-            {
-                min_dist = dist;
-                point = p;
-                norm = pos_normal(ray, triangles.data[i]);
-                mat = materials.data[triangles.data[i].mat];
-            }
+        // shadow catchers (flag 2) are see-through to the camera, and only
+        // measure how much light things around them block. Other rays
+        // treat them as plain surfaces.
+        if (d == u32(0) && (mat.flags & u32(2)) != u32(0))
+        {
+            rand = next_random(rand);
+            var u1: f32 = rand.latest;
+            rand = next_random(rand);
+            var u2: f32 = rand.latest;
+
+            var light: Ray;
+            light.start = offset(point, norm);
+            light.vec = cosine_sample(shade, u1, u2);
+
+            path.matte = vec4<f32>(
+                0.0,
+                1.0,
+                glow_seen(trace(light, time, false)),
+                glow_seen(trace(light, time, true)));
+
+            return path;
         }
 
-        if (min_dist > 1000.0)
+        if (d == u32(0))
         {
-            break;
+            path.matte = vec4<f32>(1.0, 0.0, 0.0, 0.0);
         }
 
-        rand = xorshift(rand);
+        rand = next_random(rand);
         if (rand.latest >= mat.gloss)
         {
-            colour = colour + (throughput * (_vec3(mat.glow) * weight));
-            throughput = throughput * (_vec3(mat.colour) * weight);
-
-            ray.start = point + norm * push;
+            // one sided (flag 1) materials don't glow from the back
+            if (front || (mat.flags & u32(1)) == u32(0))
+            {
+                colour = colour + throughput * emission(hit);
+            }
 
-            rand = xorshift(rand);
-            var x: f32 = rand.latest * 2.0 - 1.0;
-            rand = xorshift(rand);
-            var y: f32 = rand.latest * 2.0 - 1.0;
-            rand = xorshift(rand);
-            var z: f32 = rand.latest * 2.0 - 1.0;
+            // bounces are as likely as the cosine makes them count, so the
+            // cosine and the pdf cancel and only the albedo is left
+            throughput = throughput * albedo(hit);
 
-            ray.vec = normalize(norm + normalize(vec3<f32>(x, y, z)));
+            rand = next_random(rand);
+            var u1: f32 = rand.latest;
+            rand = next_random(rand);
+            var u2: f32 = rand.latest;
 
-            weight = dot(norm, ray.vec);
+            ray.start = offset(point, norm);
+            ray.vec = cosine_sample(shade, u1, u2);
         }
         else
         {
             throughput = throughput * _vec3(mat.reflect_c);
 
-            ray.start = point + norm * push;
-            ray.vec = normalize(reflect_vec(ray.vec, -norm));
+            ray.start = offset(point, norm);
+            ray.vec = normalize(reflect_vec(ray.vec, -shade));
+        }
 
-            weight = 1.0;
+        // bounces off smooth normals can point into the surface, so they're
+        // mirrored back out of it
+        var under: f32 = dot(ray.vec, norm);
+        if (under < 0.0)
+        {
+            ray.vec = ray.vec - norm * (2.0 * under);
         }
     }
 
-    return colour;
+    path.colour = colour;
+
+    return path;
 }
 
-[[stage(compute), workgroup_size(1)]]
-fn main([[builtin(workgroup_id)]] coords: vec3<u32>)
+// mode 1: how much of the sky the first surface sees, within info.ao_dist,
+// ignoring materials
+fn ambient_occlusion(ray: Ray, rand: Random, time: f32) -> vec3<f32>
 {
-    var rand: Random;
+    var rand = rand;
+
+    var hit: Hit = trace(ray, time, false);
 
-    rand.state = seeds.data[coords.z];
+    if (hit.dist >= MISS)
+    {
+        return vec3<f32>(1.0, 1.0, 1.0);
+    }
 
-    rand.state = rand.state ^ (coords.x | u32(1)) << u32(6);
-    rand.state = rand.state ^ (coords.y | u32(1)) << u32(18);
+    rand.counter = 8u;
 
-    var mask: u32 = u32(31);
-    var count: u32 = coords.x & mask;
-    rand.state = (rand.state << count) | (rand.state >> ((~count + u32(1)) & mask));
+    var open: f32 = 0.0;
 
-    var count: u32 = coords.y & mask;
-    rand.state = (rand.state >> count) | (rand.state << ((~count + u32(1)) & mask));
+    for (var i: u32 = u32(0); i < info.ao_rays; i = i + u32(1))
+    {
+        rand = next_random(rand);
+        var u1: f32 = rand.latest;
+        rand = next_random(rand);
+        var u2: f32 = rand.latest;
 
-    rand.state = rand.state | u32(1);
+        var probe: Ray;
+        probe.start = offset(hit.point, hit.norm);
+        probe.vec = cosine_sample(hit.norm, u1, u2);
 
-    rand.latest = 0.0;
+        if (trace(probe, time, false).dist > info.ao_dist)
+        {
+            open = open + 1.0;
+        }
+    }
+
+    var grey: f32 = open / f32(max(info.ao_rays, u32(1)));
+
+    return vec3<f32>(grey, grey, grey);
+}
+
+// modes 2 to 5: the first surface's normal, distance, material or edges
+fn debug_view(ray: Ray, time: f32) -> vec3<f32>
+{
+    var hit: Hit = trace(ray, time, false);
+
+    if (hit.dist >= MISS)
+    {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+
+    // normals as they face, not as they're flipped towards the ray
+    if (MODE == u32(2))
+    {
+        var n: vec3<f32> = shading_normal(hit);
+        if (!hit.front)
+        {
+            n = -n;
+        }
+
+        return n * 0.5 + vec3<f32>(0.5, 0.5, 0.5);
+    }
+
+    // white up close, fading to black at the furthest vertex
+    if (MODE == u32(3))
+    {
+        var d: f32 = clamp(1.0 - hit.dist / info.far, 0.0, 1.0);
+
+        return vec3<f32>(d, d, d);
+    }
+
+    // a colour from a hash of the material's index
+    if (MODE == u32(4))
+    {
+        var h: u32 = pcg(hit.tri.mat + 1u);
+
+        return vec3<f32>(
+            f32(h & 255u),
+            f32((h >> 8u) & 255u),
+            f32((h >> 16u) & 255u)) / 255.0;
+    }
+
+    // each vertex's barycentric weight times its height over the opposite
+    // edge is the distance to that edge
+    var a: vec3<f32> = _vec3(hit.tri.a);
+    var b: vec3<f32> = _vec3(hit.tri.b);
+    var c: vec3<f32> = _vec3(hit.tri.c);
+    var p: vec3<f32> = hit.point;
+
+    var area: f32 = length(cross(b - a, c - a));
+    var wa: f32 = length(cross(b - p, c - p)) / area;
+    var wb: f32 = length(cross(c - p, a - p)) / area;
+    var wc: f32 = length(cross(a - p, b - p)) / area;
+
+    var edge: f32 = min(
+        wa * area / length(c - b),
+        min(wb * area / length(a - c), wc * area / length(b - a)));
 
-    rand = xorshift(rand);
+    // how wide a pixel is this far from the camera
+    var pixel: f32 = hit.dist / (f32(info.width) * 0.5 / tan(camera.fov / 2.0));
+    var grey: f32 = 0.2 + 0.7 * clamp(edge / pixel - 0.5, 0.0, 1.0);
+
+    return vec3<f32>(grey, grey, grey);
+}
+
+// Adds x to a sum, keeping what rounding loses so the sum keeps improving
+// after hundreds of thousands of samples, and returns the new sum and
+// losses. This is `Sum::add` in gpu.rs, which has to match.
+fn add_exact(sum: f32, lost: f32, x: f32) -> vec2<f32>
+{
+    var t: f32 = sum + x;
+    var l: f32 = lost;
+
+    if (abs(sum) >= abs(x))
+    {
+        l = l + ((sum - t) + x);
+    }
+    else
+    {
+        l = l + ((x - t) + sum);
+    }
+
+    // what fits of the losses goes into the sum, so they stay small
+    var s: f32 = t + l;
+    return vec2<f32>(s, l - (s - t));
+}
+
+// Adds c to the pixel's sum.
+fn accumulate(px: u32, c: vec3<f32>)
+{
+    for (var i: i32 = 0; i < 3; i = i + 1)
+    {
+        var next: vec2<f32> = add_exact(image.pixels[px].sum[i], image.pixels[px].lost[i], c[i]);
+        image.pixels[px].sum[i] = next.x;
+        image.pixels[px].lost[i] = next.y;
+    }
+}
+
+// WORKGROUP_SIZE in gpu.rs must match
+[[stage(compute), workgroup_size(8, 8)]]
+fn main([[builtin(global_invocation_id)]] id: vec3<u32>)
+{
+    // the last groups of a slice hang off its edges unless it's a multiple
+    // of the workgroup size
+    if (id.x >= info.slice_w || id.y >= info.slice_h)
+    {
+        return;
+    }
+
+    // the dispatch covers a slice of a tile, coords are in the whole image
+    var local: vec2<u32> = vec2<u32>(
+        id.x + info.slice_x,
+        id.y + info.slice_y);
+    var coords: vec3<u32> = vec3<u32>(
+        local.x + info.tile_x,
+        local.y + info.tile_y,
+        id.z);
+
+    var rand: Random;
+
+    rand.key = pcg(coords.x ^ pcg(coords.y ^ pcg(info.sample ^ pcg(info.seed))));
+    rand.counter = 0u;
+    rand.latest = 0.0;
 
     var x: f32 = f32(coords.x);
     var y: f32 = f32(coords.y);
@@ -297,11 +1088,15 @@ fn main([[builtin(workgroup_id)]] coords: vec3<u32>)
     var x_offset: f32 = -0.5 + x_step * (x + 0.5);
     var y_offset: f32 = (-0.5 + y_step * (y + 0.5)) / ratio;
 
-    rand = xorshift(rand);
+    rand = next_random(rand);
     var rx: f32 = rand.latest - 0.5;
-    rand = xorshift(rand);
+    rand = next_random(rand);
     var ry: f32 = rand.latest - 0.5;
 
+    // when in the frame this path is, for motion blur
+    rand = next_random(rand);
+    var time: f32 = rand.latest * info.shutter;
+
     var pos: vec3<f32> = _vec3(camera.pos);
     var pix: vec3<f32> = pos
         + (front * dist)
@@ -312,13 +1107,64 @@ fn main([[builtin(workgroup_id)]] coords: vec3<u32>)
     ray.start = pos;
     ray.vec = normalize(pix - pos);
 
-    var px: u32 = coords.y * info.width + coords.x;
+    var px: u32 = local.y * info.tile_w + local.x;
 
-    var c: vec3<f32> = cast_ray(ray, rand);
+    var path: Path;
+    if (MODE == u32(0))
+    {
+        path = cast_ray(ray, rand, time);
+    }
+    else
+    {
+        if (MODE == u32(1))
+        {
+            path.colour = ambient_occlusion(ray, rand, time);
+        }
+        else
+        {
+            path.colour = debug_view(ray, time);
+        }
+    }
+    var c: vec3<f32> = path.colour;
+
+    accumulate(px, c / f32(info.samples));
+
+    if (MATTE)
+    {
+        var m: MatteSum = matte.data[px];
+
+        for (var i: i32 = 0; i < 4; i = i + 1)
+        {
+            var next: vec2<f32> = add_exact(m.sum[i], m.lost[i], path.matte[i]);
+            m.sum[i] = next.x;
+            m.lost[i] = next.y;
+        }
+
+        matte.data[px] = m;
+    }
+
+    if (SQUARES)
+    {
+        var l: f32 = dot(c, vec3<f32>(0.2126, 0.7152, 0.0722));
+        var next: vec2<f32> = add_exact(squares.data[px].sum, squares.data[px].lost, l * l);
+        squares.data[px].sum = next.x;
+        squares.data[px].lost = next.y;
+    }
 
-    image.pixels[px][0] = image.pixels[px][0] + c.x / f32(info.samples);
-    image.pixels[px][1] = image.pixels[px][1] + c.y / f32(info.samples);
-    image.pixels[px][2] = image.pixels[px][2] + c.z / f32(info.samples);
+    // the buffer starts at 0 for every tile, so this is only traced once
+    if (DEPTHS && depths.data[px] == 0.0)
+    {
+        var hit: Hit = trace(ray, time, false);
+
+        if (hit.dist >= MISS)
+        {
+            depths.data[px] = -1.0;
+        }
+        else
+        {
+            depths.data[px] = hit.dist;
+        }
+    }
 
-    rand = xorshift(rand);
+    rand = next_random(rand);
 }
@@ -0,0 +1,138 @@
+//! `--stats`, a JSON file about each render for scripts, so they don't have
+//! to read the log. It's written however the render ends.
+
+use path_tracer_gpu::{GpuProfile, RenderBudget};
+
+use std::cell::RefCell;
+use std::time::{Duration, Instant};
+
+use crate::Failure;
+
+/// Goes up when a field changes or goes, but not when one is added.
+pub const STATS_VERSION: u32 = 1;
+
+/// What's known about a render so far, filled in as it gets further.
+pub struct Stats
+{
+    scene: String,
+    start: Instant,
+    pub hash: Option<u64>,
+    pub res: Option<[u32; 2]>,
+    pub requested_samples: Option<u32>,
+    /// the samples of the last image or frame, or that were finished when
+    /// the GPU was lost
+    pub samples: Option<u32>,
+    pub render_time: Option<Duration>,
+    pub depth: Option<u32>,
+    pub seed: Option<u64>,
+    /// name and backend
+    pub adapter: Option<(String, String)>,
+    pub budget: Option<RenderBudget>,
+    pub profile: Option<GpuProfile>,
+    /// the target, and the last measurement as samples and noise
+    pub noise: Option<(f32, Option<(u32, f32)>)>,
+    /// the files saved, in order
+    outputs: RefCell<Vec<String>>,
+}
+
+impl Stats
+{
+    pub fn new(scene: &str) -> Stats
+    {
+        Stats
+        {
+            scene: scene.to_owned(),
+            start: Instant::now(),
+            hash: None,
+            res: None,
+            requested_samples: None,
+            samples: None,
+            render_time: None,
+            depth: None,
+            seed: None,
+            adapter: None,
+            budget: None,
+            profile: None,
+            noise: None,
+            outputs: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Records a file the render saved.
+    pub fn saved(&self, path: &str)
+    {
+        self.outputs.borrow_mut().push(path.to_owned());
+    }
+
+    /// Writes the stats to `path`, with how the render ended.
+    pub fn write(&self, path: &str, result: &Result<Option<u32>, Failure>, interrupted: bool)
+        -> Result<(), String>
+    {
+        std::fs::write(path, self.to_json(result, interrupted).pretty(4) + "\n")
+            .map_err(|e| format!("Could not write stats to \"{}\": {}", path, e))
+    }
+
+    fn to_json(&self, result: &Result<Option<u32>, Failure>, interrupted: bool) -> json::JsonValue
+    {
+        let (status, code, error) = match result
+        {
+            Ok(_) if interrupted => ("interrupted", 0, None),
+            Ok(_) => ("done", 0, None),
+            Err(e) => ("failed", e.code(), Some(e.to_string())),
+        };
+
+        let rate = match (self.samples, self.render_time)
+        {
+            (Some(samples), Some(time)) if time > Duration::from_secs(0) =>
+                Some(samples as f64 / time.as_secs_f64()),
+            _ => None,
+        };
+
+        let buffers = self.budget.as_ref().map(|budget|
+        {
+            let mut sizes = json::JsonValue::new_object();
+            for &(name, size, _) in &budget.buffers
+            {
+                sizes[name] = size.into();
+            }
+
+            json::object!
+            {
+                "total_bytes": budget.total(),
+                "bytes": sizes,
+            }
+        });
+
+        let noise = self.noise.map(|(target, measured)| json::object!
+        {
+            "target": target,
+            "measured": measured.map(|(_, noise)| noise),
+            "measured_at_samples": measured.map(|(samples, _)| samples),
+            "reached": matches!(measured, Some((_, noise)) if noise <= target),
+        });
+
+        json::object!
+        {
+            "stats_version": STATS_VERSION,
+            "scene": self.scene.as_str(),
+            "scene_hash": self.hash.map(|hash| format!("{:016x}", hash)),
+            "resolution": self.res.map(|res| res.to_vec()),
+            "requested_samples": self.requested_samples,
+            "samples": self.samples,
+            "depth": self.depth,
+            "seed": self.seed,
+            "wall_time_s": self.start.elapsed().as_secs_f64(),
+            "render_time_s": self.render_time.map(|t| t.as_secs_f64()),
+            "samples_per_sec": rate,
+            "adapter": self.adapter.as_ref().map(|(name, _)| name.as_str()),
+            "backend": self.adapter.as_ref().map(|(_, backend)| backend.as_str()),
+            "buffers": buffers,
+            "outputs": self.outputs.borrow().clone(),
+            "status": status,
+            "exit_code": code,
+            "error": error,
+            "gpu_profile": self.profile.as_ref().map(GpuProfile::to_json),
+            "noise": noise,
+        }
+    }
+}
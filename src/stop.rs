@@ -0,0 +1,168 @@
+use crate::gpu::Colour;
+use crate::handle::RenderControl;
+
+use std::cell::Cell;
+
+/// Decides when a render stops, from the samples finished so far and, for
+/// conditions that ask for it, how noisy the image is. Any `Fn(u32) -> bool`
+/// saying whether to go on is one.
+pub trait StopCondition
+{
+    /// Whether to render another sample after `samples`.
+    fn go_on(&self, samples: u32) -> bool;
+
+    /// How many samples apart the noise should be measured, or `None` if
+    /// this doesn't use it. Measuring it needs a readback, so it's slower
+    /// the more often it's done. `Some(0)` never measures it.
+    fn noise_every(&self) -> Option<u32>
+    {
+        None
+    }
+
+    /// The image's noise after `samples`, see `relative_noise`, given every
+    /// `noise_every` samples before `go_on` is asked about them.
+    fn noise(&self, _samples: u32, _noise: f32)
+    {
+    }
+}
+
+impl<F: Fn(u32) -> bool> StopCondition for F
+{
+    fn go_on(&self, samples: u32) -> bool
+    {
+        self(samples)
+    }
+}
+
+/// A condition that also stops when its render is told to, through
+/// `RenderControl::stop`.
+pub(crate) struct Controlled<'a>
+{
+    pub condition: &'a dyn StopCondition,
+    pub control: Option<&'a RenderControl>,
+}
+
+impl StopCondition for Controlled<'_>
+{
+    fn go_on(&self, samples: u32) -> bool
+    {
+        !self.control.is_some_and(RenderControl::stopping) && self.condition.go_on(samples)
+    }
+
+    fn noise_every(&self) -> Option<u32>
+    {
+        self.condition.noise_every()
+    }
+
+    fn noise(&self, samples: u32, noise: f32)
+    {
+        self.condition.noise(samples, noise)
+    }
+}
+
+/// Stops once the noise drops to a target, or when another condition stops
+/// first, like the sample and time limits.
+pub struct NoiseTarget<'a>
+{
+    target: f32,
+    limit: &'a dyn StopCondition,
+    /// the last measurement, as samples and noise
+    measured: Cell<Option<(u32, f32)>>,
+}
+
+impl<'a> NoiseTarget<'a>
+{
+    /// How many samples apart the noise is measured.
+    pub const EVERY: u32 = 32;
+
+    /// `target` is in the units of `relative_noise`.
+    pub fn new(target: f32, limit: &'a dyn StopCondition) -> NoiseTarget<'a>
+    {
+        NoiseTarget
+        {
+            target: target,
+            limit: limit,
+            measured: Cell::new(None),
+        }
+    }
+
+    pub fn target(&self) -> f32
+    {
+        self.target
+    }
+
+    /// The last measurement, as samples and noise.
+    pub fn measured(&self) -> Option<(u32, f32)>
+    {
+        self.measured.get()
+    }
+
+    /// Whether the last measurement was at or below the target.
+    pub fn reached(&self) -> bool
+    {
+        matches!(self.measured.get(), Some((_, noise)) if noise <= self.target)
+    }
+
+    /// Starts again, for another render with the same target.
+    pub fn reset(&self)
+    {
+        self.measured.set(None);
+    }
+}
+
+impl StopCondition for NoiseTarget<'_>
+{
+    fn go_on(&self, samples: u32) -> bool
+    {
+        !self.reached() && self.limit.go_on(samples)
+    }
+
+    fn noise_every(&self) -> Option<u32>
+    {
+        Some(NoiseTarget::EVERY)
+    }
+
+    fn noise(&self, samples: u32, noise: f32)
+    {
+        self.measured.set(Some((samples, noise)));
+    }
+}
+
+/// Pixels with a mean luminance below this are left out of
+/// `relative_noise`, as a little noise is a lot next to black.
+pub const NOISE_FLOOR: f32 = 0.01;
+
+/// How noisy an image is, as the standard error of each pixel's mean
+/// luminance over the mean, averaged over the pixels brighter than
+/// `NOISE_FLOOR`. `image` and `squares` are sums of colours and squared
+/// luminances over `samples` samples, like the shader's. `None` before
+/// there are two samples, or when nothing is bright enough.
+pub fn relative_noise(image: &[Colour], squares: &[f32], samples: u32) -> Option<f32>
+{
+    if samples < 2
+    {
+        return None;
+    }
+
+    let n = samples as f64;
+    let (mut sum, mut count) = (0.0, 0u64);
+
+    for (px, &square) in image.iter().zip(squares)
+    {
+        let mean = (0.2126 * px.r + 0.7152 * px.g + 0.0722 * px.b) as f64 / n;
+
+        if mean > NOISE_FLOOR as f64
+        {
+            let variance = (square as f64 / n - mean * mean).max(0.0) * n / (n - 1.0);
+
+            sum += (variance / n).sqrt() / mean;
+            count += 1;
+        }
+    }
+
+    match count
+    {
+        0 => None,
+        _ => Some((sum / count as f64) as f32),
+    }
+}
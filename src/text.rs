@@ -0,0 +1,750 @@
+//! A small bitmap font for writing on images.
+
+use image::RgbImage;
+
+/// How far apart characters are, 5 pixels of glyph and a space.
+pub const CHAR_WIDTH: u32 = 6;
+/// How far apart lines are, 7 pixels of glyph and a space.
+pub const LINE_HEIGHT: u32 = 8;
+
+/// A corner of the image.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Corner
+{
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner
+{
+    /// The corner above or below this one.
+    pub fn flip_vertical(self) -> Corner
+    {
+        match self
+        {
+            Corner::TopLeft => Corner::BottomLeft,
+            Corner::TopRight => Corner::BottomRight,
+            Corner::BottomLeft => Corner::TopLeft,
+            Corner::BottomRight => Corner::TopRight,
+        }
+    }
+}
+
+/// How `draw_overlay` writes text.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Overlay
+{
+    pub corner: Corner,
+    pub colour: [u8; 3],
+    /// how much the black behind the text covers the image, from 0 to 1
+    pub opacity: f32,
+    /// how many pixels wide each pixel of the font is
+    pub scale: u32,
+}
+
+impl Default for Overlay
+{
+    fn default() -> Overlay
+    {
+        Overlay
+        {
+            corner: Corner::BottomLeft,
+            colour: [255; 3],
+            opacity: 1.0,
+            scale: 1,
+        }
+    }
+}
+
+/// Writes `text` with its top left at `x`, `y`, in `fg` with `bg` around
+/// it. Letters are drawn as capitals, characters without a glyph as a box,
+/// and anything off the image is left out. Returns the width and height
+/// written, without the space below the last line.
+pub fn draw_text(
+    image: &mut RgbImage,
+    x: u32,
+    y: u32,
+    text: &str,
+    fg: [u8; 3],
+    bg: [u8; 3])
+    -> [u32; 2]
+{
+    draw(image, [x, y], text, fg, bg, 1.0, 1);
+
+    text_size(text)
+}
+
+/// Writes `text` in a corner of the image with a margin of one font pixel,
+/// blending the background over what's there by `style.opacity`. Text that
+/// doesn't fit is made smaller, down to a scale of 1, and then cut off with
+/// a warning.
+pub fn draw_overlay(image: &mut RgbImage, text: &str, style: &Overlay)
+{
+    let [width, height] = text_size(text);
+
+    // the largest scale up to style.scale that fits with its margins
+    let fits = |s: u32| (width + 2) * s <= image.width() && (height + 2) * s <= image.height();
+    let scale = (1..=style.scale.max(1)).rev().find(|&s| fits(s)).unwrap_or(1);
+
+    if !fits(1)
+    {
+        pt_warn!("The {}x{} image is too small for all of the overlay text",
+            image.width(), image.height());
+    }
+
+    let [w, h] = [width * scale, height * scale];
+    let x = match style.corner
+    {
+        Corner::TopLeft | Corner::BottomLeft => scale,
+        Corner::TopRight | Corner::BottomRight => image.width().saturating_sub(w + scale),
+    };
+    let y = match style.corner
+    {
+        Corner::TopLeft | Corner::TopRight => scale,
+        Corner::BottomLeft | Corner::BottomRight => image.height().saturating_sub(h + scale),
+    };
+
+    draw(image, [x, y], text, style.colour, [0; 3], style.opacity.clamp(0.0, 1.0), scale);
+}
+
+/// Writes each glyph pixel as a `scale` by `scale` square, with `bg` mixed
+/// into the image by `opacity`.
+fn draw(
+    image: &mut RgbImage,
+    pos: [u32; 2],
+    text: &str,
+    fg: [u8; 3],
+    bg: [u8; 3],
+    opacity: f32,
+    scale: u32)
+{
+    let blend = |under: u8, over: u8|
+        (under as f32 + (over as f32 - under as f32) * opacity).round() as u8;
+
+    for (row, line) in text.lines().enumerate()
+    {
+        let top = pos[1] + row as u32 * LINE_HEIGHT * scale;
+
+        for (col, c) in line.chars().enumerate()
+        {
+            let left = pos[0] + col as u32 * CHAR_WIDTH * scale;
+            let glyph = glyph(c);
+
+            for dy in 0..(LINE_HEIGHT - 1) * scale
+            {
+                for dx in 0..CHAR_WIDTH * scale
+                {
+                    let (x, y) = (left + dx, top + dy);
+
+                    if x >= image.width() || y >= image.height()
+                    {
+                        continue;
+                    }
+
+                    let on = glyph[(dy / scale) as usize]
+                        .as_bytes()
+                        .get((dx / scale) as usize) == Some(&b'#');
+
+                    let px = image.get_pixel_mut(x, y);
+                    *px = image::Rgb(match on
+                    {
+                        true => fg,
+                        false => [blend(px[0], bg[0]), blend(px[1], bg[1]), blend(px[2], bg[2])],
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// How much room `draw_text` needs for `text`.
+pub fn text_size(text: &str) -> [u32; 2]
+{
+    let lines = text.lines().count() as u32;
+    let longest = text.lines().map(|l| l.chars().count()).max().unwrap_or(0) as u32;
+
+    [longest * CHAR_WIDTH, (lines * LINE_HEIGHT).saturating_sub(1)]
+}
+
+fn glyph(c: char) -> &'static [&'static str; 7]
+{
+    let c = c.to_ascii_uppercase();
+
+    GLYPHS.iter()
+        .find(|(g, _)| *g == c)
+        .map(|(_, glyph)| glyph)
+        .unwrap_or(&BOX)
+}
+
+const BOX: [&str; 7] = [
+    "#####",
+    "#   #",
+    "#   #",
+    "#   #",
+    "#   #",
+    "#   #",
+    "#####",
+];
+
+const GLYPHS: [(char, [&str; 7]); 62] = [
+    ('0', [
+        " ### ",
+        "#   #",
+        "#  ##",
+        "# # #",
+        "##  #",
+        "#   #",
+        " ### ",
+    ]),
+    ('1', [
+        "  #  ",
+        " ##  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        " ### ",
+    ]),
+    ('2', [
+        " ### ",
+        "#   #",
+        "    #",
+        "    #",
+        "   # ",
+        " ##  ",
+        "#####",
+    ]),
+    ('3', [
+        " ### ",
+        "#   #",
+        "    #",
+        "  ## ",
+        "    #",
+        "#   #",
+        " ### ",
+    ]),
+    ('4', [
+        "   # ",
+        "  ## ",
+        " # # ",
+        "#  # ",
+        "#####",
+        "   # ",
+        "  ###",
+    ]),
+    ('5', [
+        "#####",
+        "#    ",
+        "###  ",
+        "   # ",
+        "    #",
+        "#   #",
+        " ### ",
+    ]),
+    ('6', [
+        " ### ",
+        "#   #",
+        "#    ",
+        "#### ",
+        "#   #",
+        "#   #",
+        " ### ",
+    ]),
+    ('7', [
+        " ### ",
+        "#   #",
+        "    #",
+        "   # ",
+        "   # ",
+        "  #  ",
+        "  #  ",
+    ]),
+    ('8', [
+        " ### ",
+        "#   #",
+        "#   #",
+        " ### ",
+        "#   #",
+        "#   #",
+        " ### ",
+    ]),
+    ('9', [
+        " ### ",
+        "#   #",
+        "#   #",
+        " ####",
+        "    #",
+        "#   #",
+        " ### ",
+    ]),
+    ('A', [
+        " ### ",
+        "#   #",
+        "#   #",
+        "#####",
+        "#   #",
+        "#   #",
+        "#   #",
+    ]),
+    ('B', [
+        "#### ",
+        "#   #",
+        "#   #",
+        "#### ",
+        "#   #",
+        "#   #",
+        "#### ",
+    ]),
+    ('C', [
+        " ### ",
+        "#   #",
+        "#    ",
+        "#    ",
+        "#    ",
+        "#   #",
+        " ### ",
+    ]),
+    ('D', [
+        "#### ",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#### ",
+    ]),
+    ('E', [
+        "#####",
+        "#    ",
+        "#    ",
+        "#### ",
+        "#    ",
+        "#    ",
+        "#####",
+    ]),
+    ('F', [
+        "#####",
+        "#    ",
+        "#    ",
+        "#### ",
+        "#    ",
+        "#    ",
+        "#    ",
+    ]),
+    ('G', [
+        " ### ",
+        "#   #",
+        "#    ",
+        "#  ##",
+        "#   #",
+        "#   #",
+        " ### ",
+    ]),
+    ('H', [
+        "#   #",
+        "#   #",
+        "#   #",
+        "#####",
+        "#   #",
+        "#   #",
+        "#   #",
+    ]),
+    ('I', [
+        " ### ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        " ### ",
+    ]),
+    ('J', [
+        "  ###",
+        "   # ",
+        "   # ",
+        "   # ",
+        "   # ",
+        "#  # ",
+        " ##  ",
+    ]),
+    ('K', [
+        "#   #",
+        "#  # ",
+        "# #  ",
+        "##   ",
+        "# #  ",
+        "#  # ",
+        "#   #",
+    ]),
+    ('L', [
+        "#    ",
+        "#    ",
+        "#    ",
+        "#    ",
+        "#    ",
+        "#    ",
+        "#####",
+    ]),
+    ('M', [
+        "#   #",
+        "## ##",
+        "## ##",
+        "# # #",
+        "#   #",
+        "#   #",
+        "#   #",
+    ]),
+    ('N', [
+        "#   #",
+        "##  #",
+        "##  #",
+        "# # #",
+        "#  ##",
+        "#  ##",
+        "#   #",
+    ]),
+    ('O', [
+        " ### ",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        " ### ",
+    ]),
+    ('P', [
+        "#### ",
+        "#   #",
+        "#   #",
+        "#### ",
+        "#    ",
+        "#    ",
+        "#    ",
+    ]),
+    ('Q', [
+        " ### ",
+        "#   #",
+        "#   #",
+        "#   #",
+        "# # #",
+        "#  # ",
+        " ## #",
+    ]),
+    ('R', [
+        "#### ",
+        "#   #",
+        "#   #",
+        "#### ",
+        "#  # ",
+        "#   #",
+        "#   #",
+    ]),
+    ('S', [
+        " ### ",
+        "#   #",
+        "#    ",
+        " ### ",
+        "    #",
+        "#   #",
+        " ### ",
+    ]),
+    ('T', [
+        "#####",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+    ]),
+    ('U', [
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        " ### ",
+    ]),
+    ('V', [
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        "#   #",
+        " # # ",
+        "  #  ",
+    ]),
+    ('W', [
+        "#   #",
+        "#   #",
+        "#   #",
+        "# # #",
+        "# # #",
+        "## ##",
+        "#   #",
+    ]),
+    ('X', [
+        "#   #",
+        "#   #",
+        " # # ",
+        "  #  ",
+        " # # ",
+        "#   #",
+        "#   #",
+    ]),
+    ('Y', [
+        "#   #",
+        "#   #",
+        " # # ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+    ]),
+    ('Z', [
+        "#####",
+        "    #",
+        "   # ",
+        "  #  ",
+        " #   ",
+        "#    ",
+        "#####",
+    ]),
+    (' ', [
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+    ]),
+    ('.', [
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        " ##  ",
+        " ##  ",
+    ]),
+    (',', [
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        " ##  ",
+        " ##  ",
+        " #   ",
+    ]),
+    (':', [
+        "     ",
+        "  #  ",
+        "     ",
+        "     ",
+        "     ",
+        "  #  ",
+        "     ",
+    ]),
+    (';', [
+        "     ",
+        "  #  ",
+        "     ",
+        "     ",
+        "     ",
+        "  #  ",
+        " #   ",
+    ]),
+    ('!', [
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "  #  ",
+        "     ",
+        "  #  ",
+    ]),
+    ('?', [
+        " ### ",
+        "#   #",
+        "    #",
+        "   # ",
+        "  #  ",
+        "     ",
+        "  #  ",
+    ]),
+    ('\'', [
+        "  #  ",
+        "  #  ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+    ]),
+    ('"', [
+        " # # ",
+        " # # ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+    ]),
+    ('-', [
+        "     ",
+        "     ",
+        "     ",
+        " ### ",
+        "     ",
+        "     ",
+        "     ",
+    ]),
+    ('+', [
+        "     ",
+        "  #  ",
+        "  #  ",
+        "#####",
+        "  #  ",
+        "  #  ",
+        "     ",
+    ]),
+    ('=', [
+        "     ",
+        "     ",
+        "#####",
+        "     ",
+        "#####",
+        "     ",
+        "     ",
+    ]),
+    ('_', [
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "     ",
+        "#####",
+    ]),
+    ('/', [
+        "    #",
+        "    #",
+        "   # ",
+        "  #  ",
+        " #   ",
+        "#    ",
+        "#    ",
+    ]),
+    ('\\', [
+        "#    ",
+        "#    ",
+        " #   ",
+        "  #  ",
+        "   # ",
+        "    #",
+        "    #",
+    ]),
+    ('(', [
+        "   # ",
+        "  #  ",
+        " #   ",
+        " #   ",
+        " #   ",
+        "  #  ",
+        "   # ",
+    ]),
+    (')', [
+        " #   ",
+        "  #  ",
+        "   # ",
+        "   # ",
+        "   # ",
+        "  #  ",
+        " #   ",
+    ]),
+    ('[', [
+        " ### ",
+        " #   ",
+        " #   ",
+        " #   ",
+        " #   ",
+        " #   ",
+        " ### ",
+    ]),
+    (']', [
+        " ### ",
+        "   # ",
+        "   # ",
+        "   # ",
+        "   # ",
+        "   # ",
+        " ### ",
+    ]),
+    ('#', [
+        " # # ",
+        " # # ",
+        "#####",
+        " # # ",
+        "#####",
+        " # # ",
+        " # # ",
+    ]),
+    ('%', [
+        "##   ",
+        "##  #",
+        "   # ",
+        "  #  ",
+        " #   ",
+        "#  ##",
+        "   ##",
+    ]),
+    ('*', [
+        "     ",
+        "#   #",
+        " # # ",
+        "#####",
+        " # # ",
+        "#   #",
+        "     ",
+    ]),
+    ('<', [
+        "   # ",
+        "  #  ",
+        " #   ",
+        "#    ",
+        " #   ",
+        "  #  ",
+        "   # ",
+    ]),
+    ('>', [
+        " #   ",
+        "  #  ",
+        "   # ",
+        "    #",
+        "   # ",
+        "  #  ",
+        " #   ",
+    ]),
+    ('@', [
+        " ### ",
+        "#   #",
+        "# ###",
+        "# # #",
+        "# ###",
+        "#    ",
+        " ### ",
+    ]),
+    ('&', [
+        " ##  ",
+        "#  # ",
+        "#  # ",
+        " ##  ",
+        "# # #",
+        "#  # ",
+        " ## #",
+    ]),
+];
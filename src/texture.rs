@@ -0,0 +1,172 @@
+//! Images that materials read across a triangle's face, by where its
+//! `Scene::uvs` put each point on the image, and images of heights.
+
+/// The texture coordinates of triangles that weren't given any.
+pub const DEFAULT_UVS: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+
+#[derive(Clone, Debug)]
+pub struct Texture
+{
+    /// the file it was loaded from, for writing the scene back out
+    pub path: String,
+    /// the file's colours were sRGB, rather than linear
+    pub srgb: bool,
+    pub width: u32,
+    pub height: u32,
+    /// linear RGBA, top row first
+    pub texels: Vec<[f32; 4]>,
+}
+
+impl Texture
+{
+    /// Loads an image, converting it to linear colours if it's `srgb`.
+    /// Alpha is always linear, and images without it take it from their
+    /// brightness, so greyscale masks can cut out surfaces.
+    pub fn load(path: &str, srgb: bool) -> Result<Texture, String>
+    {
+        let image = image::open(path)
+            .map_err(|e| format!("Could not load texture \"{}\": {}", path, e))?;
+
+        let has_alpha = image.color().has_alpha();
+        let image = image.to_rgba8();
+
+        let linear = |c: u8|
+        {
+            let c = c as f32 / 255.0;
+
+            match srgb
+            {
+                true if c <= 0.04045 => c / 12.92,
+                true => ((c + 0.055) / 1.055).powf(2.4),
+                false => c,
+            }
+        };
+
+        Ok(Texture
+        {
+            path: path.to_owned(),
+            srgb: srgb,
+            width: image.width(),
+            height: image.height(),
+            texels: image.pixels()
+                .map(|p|
+                {
+                    let alpha = match has_alpha
+                    {
+                        true => p[3] as f32,
+                        false => 0.2126 * p[0] as f32 + 0.7152 * p[1] as f32 + 0.0722 * p[2] as f32,
+                    };
+
+                    [linear(p[0]), linear(p[1]), linear(p[2]), alpha / 255.0]
+                })
+                .collect(),
+        })
+    }
+
+    /// The colour at `uv`, blended between the nearest four texels. U goes
+    /// right and v goes up, and the image repeats outside 0 to 1, like the
+    /// shader's `sample_texture`.
+    pub fn sample(&self, uv: [f32; 2]) -> [f32; 4]
+    {
+        let x = uv[0] * self.width as f32 - 0.5;
+        let y = (1.0 - uv[1]) * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let texel = |x: i64, y: i64|
+        {
+            let x = x.rem_euclid(self.width as i64) as usize;
+            let y = y.rem_euclid(self.height as i64) as usize;
+
+            self.texels[y * self.width as usize + x]
+        };
+        let lerp = |a: [f32; 4], b: [f32; 4], t: f32|
+            [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t);
+
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        lerp(
+            lerp(texel(x0, y0), texel(x0 + 1, y0), fx),
+            lerp(texel(x0, y0 + 1), texel(x0 + 1, y0 + 1), fx),
+            fy)
+    }
+}
+
+/// The brightness of each pixel of a greyscale image from 0 to 1, rows
+/// first, resampled to `resolution` if it's given. Vertices of the resampled
+/// grid are spread evenly from the centre of the first pixel to the centre
+/// of the last, and blended between the nearest four.
+pub fn load_heights(path: &str, resolution: Option<[u32; 2]>)
+    -> Result<(Vec<f32>, [u32; 2]), String>
+{
+    let image = image::open(path)
+        .map_err(|e| format!("Could not load heightmap \"{}\": {}", path, e))?
+        .to_luma16();
+
+    let (width, height) = image.dimensions();
+    let pixel = |x: u32, y: u32| image.get_pixel(x, y)[0] as f32 / 65535.0;
+
+    let [cols, rows] = match resolution
+    {
+        Some(resolution) => resolution,
+        None => return Ok((
+            image.pixels().map(|p| p[0] as f32 / 65535.0).collect(),
+            [width, height])),
+    };
+
+    // where vertex i of n falls between the first and last pixel of size
+    let place = |i: u32, n: u32, size: u32|
+    {
+        let p = i as f32 * (size - 1) as f32 / (n - 1) as f32;
+        let p0 = (p.floor() as u32).min(size - 1);
+
+        (p0, (p0 + 1).min(size - 1), p - p0 as f32)
+    };
+
+    let mut heights = Vec::with_capacity((cols * rows) as usize);
+
+    for y in 0..rows
+    {
+        let (y0, y1, fy) = place(y, rows, height);
+
+        for x in 0..cols
+        {
+            let (x0, x1, fx) = place(x, cols, width);
+
+            let top = pixel(x0, y0) + (pixel(x1, y0) - pixel(x0, y0)) * fx;
+            let bottom = pixel(x0, y1) + (pixel(x1, y1) - pixel(x0, y1)) * fx;
+
+            heights.push(top + (bottom - top) * fy);
+        }
+    }
+
+    Ok((heights, [cols, rows]))
+}
+
+/// Every texture in one buffer for the shader: a header for each, with
+/// where its texels start, its width and its height, then all the texels.
+/// It's never empty, so it can always be bound.
+pub fn pack(textures: &[Texture]) -> Vec<[f32; 4]>
+{
+    let mut packed = Vec::with_capacity(
+        textures.len() + textures.iter().map(|t| t.texels.len()).sum::<usize>());
+
+    let mut start = textures.len();
+    for t in textures
+    {
+        packed.push([start as f32, t.width as f32, t.height as f32, 0.0]);
+        start += t.texels.len();
+    }
+
+    for t in textures
+    {
+        packed.extend_from_slice(&t.texels);
+    }
+
+    if packed.is_empty()
+    {
+        packed.push([0.0; 4]);
+    }
+
+    packed
+}
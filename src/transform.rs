@@ -0,0 +1,114 @@
+//! Moving, turning and scaling things, always by the same amount along
+//! every axis so spheres stay spheres.
+
+use crate::vec3::{add, dot, rotate, scale};
+
+/// Scaling by `scale`, then turning `angle` radians about `axis`, then
+/// moving by `offset`, kept apart so an animation can change one of them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Placement
+{
+    pub scale: f32,
+    /// normalized
+    pub axis: [f32; 3],
+    /// in radians
+    pub angle: f32,
+    pub offset: [f32; 3],
+}
+
+impl Placement
+{
+    pub const STILL: Placement = Placement
+    {
+        scale: 1.0,
+        axis: [0.0, 0.0, 1.0],
+        angle: 0.0,
+        offset: [0.0; 3],
+    };
+
+    pub fn transform(&self) -> Transform
+    {
+        // the columns are where each axis ends up
+        let [x, y, z] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+            .map(|v| rotate(v, self.axis, self.angle));
+
+        Transform
+        {
+            scale: self.scale,
+            rotation: [[x[0], y[0], z[0]], [x[1], y[1], z[1]], [x[2], y[2], z[2]]],
+            offset: self.offset,
+        }
+    }
+}
+
+/// A `Placement` as a matrix, so they can be combined.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform
+{
+    pub scale: f32,
+    /// rows of a rotation matrix
+    pub rotation: [[f32; 3]; 3],
+    pub offset: [f32; 3],
+}
+
+impl Transform
+{
+    pub const IDENTITY: Transform = Transform
+    {
+        scale: 1.0,
+        rotation: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        offset: [0.0; 3],
+    };
+
+    /// Scaling about the origin.
+    pub fn scaling(s: f32) -> Transform
+    {
+        Transform
+        {
+            scale: s,
+            .. Transform::IDENTITY
+        }
+    }
+
+    pub fn point(&self, p: [f32; 3]) -> [f32; 3]
+    {
+        add(self.offset, self.vector(p))
+    }
+
+    /// Directions and velocities aren't moved.
+    pub fn vector(&self, v: [f32; 3]) -> [f32; 3]
+    {
+        scale(self.rotation.map(|row| dot(row, v)), self.scale)
+    }
+
+    /// This and then `outer`, which is the parent's transform for a child's.
+    pub fn then(&self, outer: &Transform) -> Transform
+    {
+        let r = &outer.rotation;
+        let columns = [0, 1, 2]
+            .map(|j| [self.rotation[0][j], self.rotation[1][j], self.rotation[2][j]]);
+
+        Transform
+        {
+            scale: self.scale * outer.scale,
+            rotation: [0, 1, 2].map(|i| columns.map(|c| dot(r[i], c))),
+            offset: outer.point(self.offset),
+        }
+    }
+
+    /// Undoes this transform.
+    pub fn inverse(&self) -> Transform
+    {
+        let r = &self.rotation;
+        // a rotation's inverse is its transpose
+        let rotation = [0, 1, 2].map(|i| [r[0][i], r[1][i], r[2][i]]);
+        let scale = 1.0 / self.scale;
+
+        Transform
+        {
+            scale: scale,
+            rotation: rotation,
+            offset: rotation.map(|row| -dot(row, self.offset) * scale),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Small vector helpers for working with scene geometry on the CPU.
+
+pub fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3]
+{
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3]
+{
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale(a: [f32; 3], s: f32) -> [f32; 3]
+{
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn dot(a: [f32; 3], b: [f32; 3]) -> f32
+{
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3]
+{
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn length(a: [f32; 3]) -> f32
+{
+    dot(a, a).sqrt()
+}
+
+/// Returns `a` unchanged if it has no length.
+pub fn normalize(a: [f32; 3]) -> [f32; 3]
+{
+    let len = length(a);
+
+    if len > 0.0 { scale(a, 1.0 / len) } else { a }
+}
+
+/// `v` turned `angle` radians anticlockwise about `axis`, looking down the
+/// axis towards the origin. `axis` must be normalized.
+pub fn rotate(v: [f32; 3], axis: [f32; 3], angle: f32) -> [f32; 3]
+{
+    let (sin, cos) = angle.sin_cos();
+
+    add(add(
+        scale(v, cos),
+        scale(cross(axis, v), sin)),
+        scale(axis, dot(axis, v) * (1.0 - cos)))
+}
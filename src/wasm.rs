@@ -0,0 +1,67 @@
+//! The renderer for web pages, built for `wasm32-unknown-unknown` and
+//! called through `wasm-bindgen`, see `web/index.html`.
+//!
+//! It renders on the CPU on the page's thread: the GPU renderer waits for
+//! each image to be read back, which on the web only happens once control
+//! goes back to the page.
+
+use crate::gpu::GpuContext;
+use crate::scene::{RenderSettings, Scene};
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C"
+{
+    #[wasm_bindgen(js_namespace = console, js_name = error)]
+    fn console_error(message: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = warn)]
+    fn console_warn(message: &str);
+    #[wasm_bindgen(js_namespace = console, js_name = log)]
+    fn console_log(message: &str);
+}
+
+/// Sends the renderer's messages to the browser's console, since there's
+/// no stdout or stderr on the web.
+fn log_to_console()
+{
+    use crate::log::{self, Level};
+
+    static ONCE: std::sync::Once = std::sync::Once::new();
+
+    ONCE.call_once(|| log::set_sink(Some(Box::new(|level, message| match level
+    {
+        Level::Error => console_error(message),
+        Level::Warn => console_warn(message),
+        _ => console_log(message),
+    }))));
+}
+
+/// Renders `scene`, the text of a JSON scene, at `width` by `height` with
+/// `samples` samples per pixel. Returns the pixels as RGBA bytes from the
+/// top left, ready for an `ImageData`, or the error as a string. Files the
+/// scene refers to, like textures, can't be read.
+#[wasm_bindgen]
+pub fn render(scene: &str, width: u32, height: u32, samples: u32) -> Result<Vec<u8>, JsValue>
+{
+    log_to_console();
+
+    if width == 0 || height == 0 || samples == 0
+    {
+        return Err(JsValue::from_str("The width, height and samples must be above 0"));
+    }
+
+    let scene = Scene::parse(scene).map_err(|e| JsValue::from_str(&e))?;
+
+    let settings = RenderSettings
+    {
+        samples: samples,
+        cpu: true,
+        .. RenderSettings::new([width, height])
+    };
+
+    let image = scene.render_with(&GpuContext::cpu(), &settings, &settings.condition(), None)
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+    Ok(image.to_rgba8())
+}
@@ -0,0 +1,368 @@
+//! The CPU renderer is the shader's reference, so the two have to agree.
+
+use path_tracer_gpu::{
+    builtin_scene, GpuContext, Material, RenderMode, RenderSettings, Scene, StopCondition};
+
+const GLOW: [f32; 3] = [0.3, 0.7, 0.1];
+
+/// A dark wall glowing `GLOW` that fills the view, so every sample of
+/// every pixel is exactly `GLOW`.
+fn glowing_wall() -> Scene
+{
+    let mut scene = Scene::new([0.0, 0.0, -1.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 0.5);
+    let wall = scene.add_material(Material::new([0.0, 0.0, 0.0], GLOW, 0.0, [0.0, 0.0, 0.0]));
+    scene.add_quad(
+        [-10.0, -10.0, 1.0], [10.0, -10.0, 1.0], [10.0, 10.0, 1.0], [-10.0, 10.0, 1.0], wall);
+
+    scene
+}
+
+/// Root mean square error over every channel, relative to the mean.
+fn relative_rmse(a: &[path_tracer_gpu::Colour], b: &[path_tracer_gpu::Colour]) -> f64
+{
+    let channels = |c: &path_tracer_gpu::Colour| [c.r as f64, c.g as f64, c.b as f64];
+
+    let (mut squares, mut total) = (0.0, 0.0);
+    for (a, b) in a.iter().zip(b)
+    {
+        for (a, b) in channels(a).iter().zip(channels(b))
+        {
+            squares += (a - b) * (a - b);
+            total += a;
+        }
+    }
+
+    let n = (a.len() * 3) as f64;
+    (squares / n).sqrt() / (total / n)
+}
+
+#[test]
+fn cpu_matches_gpu()
+{
+    if let Err(e) = GpuContext::new(None, false)
+    {
+        eprintln!("Skipping, there's no GPU to compare with: {}", e);
+        return;
+    }
+
+    let scene = builtin_scene("cornell").unwrap();
+    let settings = RenderSettings
+    {
+        samples: 64,
+        seed: Some(7),
+        .. RenderSettings::new([24, 24])
+    };
+
+    let gpu = path_tracer_gpu::render(&scene, &settings).unwrap();
+    let cpu = path_tracer_gpu::render(&scene, &RenderSettings { cpu: true, .. settings }).unwrap();
+
+    assert_eq!(gpu.samples, cpu.samples);
+
+    // the same random numbers go into both, so only rounding differs
+    let rmse = relative_rmse(&gpu.pixels, &cpu.pixels);
+    assert!(rmse < 0.02, "the CPU and GPU images differ by {:.4}", rmse);
+}
+
+#[test]
+fn odd_resolutions_cover_every_pixel()
+{
+    let scene = glowing_wall();
+    let settings = RenderSettings
+    {
+        samples: 1,
+        depth: 1,
+        .. RenderSettings::new([1023, 517])
+    };
+
+    let mut backends = vec![("CPU", RenderSettings { cpu: true, .. settings.clone() })];
+    match GpuContext::new(None, false)
+    {
+        Ok(_) => backends.push(("GPU", settings)),
+        Err(e) => eprintln!("Only checking the CPU, there's no GPU: {}", e),
+    }
+
+    for (name, settings) in backends
+    {
+        let frame = path_tracer_gpu::render(&scene, &settings).unwrap();
+        assert_eq!(frame.pixels.len(), 1023 * 517);
+
+        // a pixel that was missed is black, and one rendered twice is too
+        // bright
+        for (i, px) in frame.pixels.iter().enumerate()
+        {
+            assert!([px.r, px.g, px.b] == GLOW,
+                "{}: pixel {},{} is {:?}", name, i % 1023, i / 1023, px);
+        }
+    }
+}
+
+#[test]
+fn furnace_conserves_energy()
+{
+    // a sphere inside a bigger one glowing 0.5 everywhere, which reflects all of
+    // it when it's white and half of it when it's grey
+    let furnace = |grey: f32| Scene::parse(&format!(
+        r#"{{
+            "camera": {{ "pos": [0, 0, -3], "front": [0, 0, 1], "up": [0, 1, 0], "fov": 60 }},
+            "materials": {{
+                "walls": {{ "colour": [0, 0, 0], "glow": [0.5, 0.5, 0.5] }},
+                "ball": {{ "colour": [{0}, {0}, {0}] }}
+            }},
+            "surfaces": [
+                {{ "sphere_mesh": {{ "center": [0, 0, 0], "radius": 5, "subdivisions": 2 }}, "mat": "walls" }},
+                {{ "sphere_mesh": {{ "center": [0, 0, 0], "radius": 1, "subdivisions": 3 }}, "mat": "ball" }}
+            ]
+        }}"#,
+        grey)).unwrap();
+
+    let settings = RenderSettings
+    {
+        samples: 16,
+        depth: 4,
+        seed: Some(3),
+        cpu: true,
+        .. RenderSettings::new([16, 16])
+    };
+
+    // with the bounces sampled in proportion to the cosine, every sample
+    // carries exactly the albedo, so there's no noise to allow for
+    for (grey, ball) in [(1.0, 0.5), (0.5, 0.25)]
+    {
+        let frame = path_tracer_gpu::render(&furnace(grey), &settings).unwrap();
+
+        let centre = frame.pixels[8 * 16 + 8];
+        let corner = frame.pixels[0];
+        for (px, want) in [(centre, ball), (corner, 0.5)]
+        {
+            for c in [px.r, px.g, px.b]
+            {
+                assert!((c - want).abs() < 1e-4, "{} grey: {:?} isn't {}", grey, px, want);
+            }
+        }
+
+        if grey == 1.0
+        {
+            assert!(frame.pixels.iter().all(|px| (px.g - 0.5).abs() < 1e-4),
+                "the sphere shows in the furnace");
+        }
+    }
+}
+
+#[test]
+fn closed_box_lets_no_light_in()
+{
+    // a white box with glowing walls all round it, so any light inside came
+    // through a crack along one of its edges
+    let cube = |size: f32, mat: &str|
+    {
+        // bit k of a corner's number says which side it is along axis k
+        let corner = |i: u32| [0, 1, 2].map(|k| if i >> k & 1 == 1 { size } else { -size });
+        let faces = [
+            [0, 1, 3, 2], [4, 6, 7, 5], [0, 4, 5, 1], [2, 3, 7, 6], [0, 2, 6, 4], [1, 5, 7, 3]];
+
+        faces.iter()
+            .map(|face| format!(r#"{{ "quad": {:?}, "mat": "{}" }}"#, face.map(corner), mat))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let scene = Scene::parse(&format!(
+        r#"{{
+            "camera": {{ "pos": [0.2, 0.1, -0.5], "front": [0.1, 0.2, 1], "up": [0, 1, 0], "fov": 90 }},
+            "materials": {{
+                "white": {{ "colour": [1, 1, 1] }},
+                "light": {{ "colour": [0, 0, 0], "glow": [100, 100, 100] }}
+            }},
+            "surfaces": [{}, {}]
+        }}"#,
+        cube(1.0, "white"), cube(3.0, "light"))).unwrap();
+
+    let settings = RenderSettings
+    {
+        samples: 10_000,
+        depth: 3,
+        seed: Some(11),
+        cpu: true,
+        .. RenderSettings::new([4, 4])
+    };
+
+    let mut backends = vec![("CPU", settings.clone())];
+    match GpuContext::new(None, false)
+    {
+        Ok(_) => backends.push(("GPU", RenderSettings { cpu: false, .. settings })),
+        Err(e) => eprintln!("Only checking the CPU, there's no GPU: {}", e),
+    }
+
+    for (backend, settings) in backends
+    {
+        let frame = path_tracer_gpu::render(&scene, &settings).unwrap();
+
+        assert!(frame.pixels.iter().all(|px| px.r == 0.0 && px.g == 0.0 && px.b == 0.0),
+            "{}: light got into the box, {:?}", backend, frame.pixels);
+    }
+}
+
+#[test]
+fn shadow_catcher_keeps_only_the_shadow()
+{
+    // looking straight down on a sphere over a catcher, with a light off to
+    // the right that throws the shadow to the left, out from under it
+    let scene = Scene::parse(r#"{
+        "camera": { "pos": [0, 8, 0], "front": [0, -1, 0], "up": [0, 0, -1], "fov": 60 },
+        "materials": {
+            "white": { "colour": [0.8, 0.8, 0.8] },
+            "ground": { "colour": [0.5, 0.5, 0.5], "shadow_catcher": true },
+            "sun": { "colour": [0, 0, 0], "glow": [20, 20, 20] }
+        },
+        "surfaces": [
+            { "quad": [[-20, 0, -20], [-20, 0, 20], [20, 0, 20], [20, 0, -20]], "mat": "ground" },
+            { "sphere_mesh": { "center": [0, 1, 0], "radius": 0.75, "subdivisions": 1 }, "mat": "white" },
+            { "quad": [[5, 2, -2], [5, 6, -2], [5, 6, 2], [5, 2, 2]], "mat": "sun" }
+        ]
+    }"#).unwrap();
+
+    const SIZE: u32 = 16;
+    let settings = RenderSettings
+    {
+        samples: 512,
+        depth: 3,
+        seed: Some(5),
+        cpu: true,
+        .. RenderSettings::new([SIZE, SIZE])
+    };
+
+    let frame = path_tracer_gpu::render(&scene, &settings).unwrap();
+    let alpha = frame.alpha.as_ref().expect("a shadow catcher gives an alpha channel");
+    let rgba = frame.to_rgba8();
+
+    // where each pixel's centre lands on the ground, with +x to the right
+    let half = 8.0 * 30f32.to_radians().tan();
+    let ground = |i: u32| (i as f32 + 0.5) / SIZE as f32 * 2.0 * half - half;
+
+    let (mut umbra, mut penumbra) = (Vec::new(), 0);
+    for y in 0..SIZE
+    {
+        for x in 0..SIZE
+        {
+            let i = (y * SIZE + x) as usize;
+            let (gx, gz) = (ground(x), ground(y));
+            let a = alpha[i];
+
+            if gx * gx + gz * gz < 0.5 * 0.5
+            {
+                assert_eq!(a, 1.0, "the sphere is see-through at {}, {}", x, y);
+                assert_eq!(rgba[i * 4 + 3], 255);
+            }
+            else if gx > 1.0
+            {
+                let px = frame.pixels[i];
+                assert_eq!(a, 0.0, "the lit ground isn't clear at {}, {}", x, y);
+                assert_eq!([px.r, px.g, px.b, rgba[i * 4 + 3] as f32], [0.0; 4]);
+            }
+            else if (gx + 1.7).abs() < 0.6 && gz.abs() < 0.6
+            {
+                umbra.push(a);
+            }
+            else if gx < -0.9 && a > 0.1 && a < 0.9
+            {
+                penumbra += 1;
+            }
+        }
+    }
+
+    // only a few percent of the ground's rays reach the light, so single
+    // pixels are noisy
+    let umbra = umbra.iter().sum::<f32>() / umbra.len() as f32;
+    assert!(umbra > 0.7, "the middle of the shadow is only {} opaque", umbra);
+    assert!(penumbra >= 4, "the shadow has a hard edge, {} pixels are part shaded", penumbra);
+}
+
+#[test]
+fn noise_every_0_samples_is_never_measured()
+{
+    /// Four samples, asking for the noise every 0 of them.
+    struct Zero(std::cell::Cell<bool>);
+
+    impl StopCondition for Zero
+    {
+        fn go_on(&self, samples: u32) -> bool
+        {
+            samples < 4
+        }
+
+        fn noise_every(&self) -> Option<u32>
+        {
+            Some(0)
+        }
+
+        fn noise(&self, _samples: u32, _noise: f32)
+        {
+            self.0.set(true);
+        }
+    }
+
+    let settings = RenderSettings
+    {
+        seed: Some(1),
+        cpu: true,
+        .. RenderSettings::new([4, 4])
+    };
+
+    let mut backends = vec![("CPU", settings.clone())];
+    match GpuContext::new(None, false)
+    {
+        Ok(_) => backends.push(("GPU", RenderSettings { cpu: false, .. settings })),
+        Err(e) => eprintln!("Only checking the CPU, there's no GPU: {}", e),
+    }
+
+    for (backend, settings) in backends
+    {
+        let zero = Zero(std::cell::Cell::new(false));
+        let frame = glowing_wall().render(&settings, &zero, None).unwrap();
+
+        assert_eq!(frame.samples, 4, "{}", backend);
+        assert!(!zero.0.get(), "{}: the noise was measured", backend);
+    }
+}
+
+#[test]
+fn depth_is_from_each_frames_camera()
+{
+    // the camera backs away from a wall, so the furthest corner is further
+    // in the second frame
+    let scene = Scene::parse(r#"{
+        "camera": { "pos": [0, 0, 2], "front": [0, 0, -1], "up": [0, 1, 0], "fov": 10 },
+        "materials": { "white": { "colour": [1, 1, 1] } },
+        "surfaces": [
+            { "quad": [[-10, -10, 0], [10, -10, 0], [10, 10, 0], [-10, 10, 0]], "mat": "white" }
+        ],
+        "animation": {
+            "frames": 2,
+            "fps": 1,
+            "keyframes": [
+                { "time": 0, "pos": [0, 0, 2] },
+                { "time": 1, "pos": [0, 0, 20] }
+            ]
+        }
+    }"#).unwrap();
+
+    let settings = RenderSettings
+    {
+        mode: RenderMode::Depth,
+        samples: 1,
+        seed: Some(1),
+        cpu: true,
+        .. RenderSettings::new([3, 3])
+    };
+
+    let mut centres = Vec::new();
+    scene.render_frames(&GpuContext::cpu(), 1..=2, &settings, &settings.condition(),
+        &mut |_, frame| centres.push(frame.pixels[4].r)).unwrap();
+
+    // white up close to black at the furthest corner
+    for (got, d) in centres.into_iter().zip([2.0f32, 20.0])
+    {
+        let want = 1.0 - d / (d * d + 200.0).sqrt();
+        assert!((got - want).abs() < 1e-2, "{} away the depth is {}, not {}", d, got, want);
+    }
+}
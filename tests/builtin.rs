@@ -0,0 +1,38 @@
+//! Every built-in scene has to survive being written out and parsed back, and
+//! render to something other than black.
+
+use path_tracer_gpu::{builtin_scene, RenderSettings, Scene, BUILTIN_SCENES};
+
+#[test]
+fn builtin_scenes_parse_and_render()
+{
+    // the debug CPU renderer is slow, so just enough to see light arrive
+    let settings = RenderSettings
+    {
+        samples: 4,
+        depth: 4,
+        seed: Some(1),
+        cpu: true,
+        .. RenderSettings::new([8, 8])
+    };
+
+    for (name, _) in BUILTIN_SCENES
+    {
+        let built = builtin_scene(name).unwrap();
+        let scene = Scene::parse(&built.to_json())
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+
+        assert_eq!(scene.triangles.len(), built.triangles.len(), "{}", name);
+        assert_eq!(scene.materials.len(), built.materials.len(), "{}", name);
+
+        let frame = path_tracer_gpu::render(&scene, &settings)
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+
+        assert_eq!(frame.pixels.len(), 64, "{}", name);
+        assert!(frame.pixels.iter().all(|px| [px.r, px.g, px.b].iter().all(|c| c.is_finite())),
+            "{}: not every pixel is a number", name);
+        assert!(frame.pixels.iter().any(|px| px.r + px.g + px.b > 0.0), "{}: it's black", name);
+    }
+
+    assert!(builtin_scene("caustic-glass").is_none());
+}
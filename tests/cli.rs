@@ -0,0 +1,188 @@
+//! Runs the binary and checks what it says, its exit code and what it writes.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const ARGS: i32 = 2;
+const SCENE: i32 = 3;
+const IO: i32 = 5;
+
+fn scene(path: &str) -> String
+{
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenes").join(path)
+        .to_string_lossy().into_owned()
+}
+
+/// An empty directory of its own for each test, deleted when it's dropped.
+struct Scratch(PathBuf);
+
+impl Scratch
+{
+    fn join(&self, path: &str) -> PathBuf
+    {
+        self.0.join(path)
+    }
+}
+
+impl Drop for Scratch
+{
+    fn drop(&mut self)
+    {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn scratch(name: &str) -> Scratch
+{
+    let dir = std::env::temp_dir()
+        .join(format!("path-tracer-gpu-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    Scratch(dir)
+}
+
+/// The exit code and what was written to stderr.
+fn render(args: &[&str]) -> (i32, String)
+{
+    let output = Command::new(env!("CARGO_BIN_EXE_path-tracer-gpu"))
+        .arg("render")
+        .args(["--cpu", "-m", "1"])
+        .args(args)
+        .output()
+        .unwrap();
+
+    (output.status.code().unwrap(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+#[test]
+fn bad_scene()
+{
+    let dir = scratch("bad-scene");
+    let out = dir.join("out.png");
+
+    let (code, stderr) = render(&[
+        "-s", &scene("bad/unknown-material.json"), "-r", "8x8", "-o", out.to_str().unwrap()]);
+
+    assert_eq!(code, SCENE, "{}", stderr);
+    assert!(stderr.contains("Error: surfaces[0]: unknown material \"whit\" at line 6"), "{}", stderr);
+    assert!(!out.exists());
+}
+
+#[test]
+fn missing_scene()
+{
+    let dir = scratch("missing-scene");
+    let missing = dir.join("missing.json");
+
+    let (code, stderr) = render(&[
+        "-s", missing.to_str().unwrap(), "-r", "8x8", "-o", dir.join("out.png").to_str().unwrap()]);
+
+    assert_eq!(code, SCENE, "{}", stderr);
+    assert!(stderr.contains("Could not read scene"), "{}", stderr);
+}
+
+#[test]
+fn bad_resolution()
+{
+    let dir = scratch("bad-resolution");
+
+    let (code, stderr) = render(&[
+        "-s", &scene("good/minimal.json"), "-r", "8x", "-o", dir.join("out.png").to_str().unwrap()]);
+
+    assert_eq!(code, ARGS, "{}", stderr);
+    assert!(stderr.contains("Could not parse resolution \"8x\""), "{}", stderr);
+}
+
+#[test]
+fn bad_output_path()
+{
+    let dir = scratch("bad-output");
+    let out = dir.join("missing").join("out.png");
+
+    let (code, stderr) = render(&[
+        "-s", &scene("good/minimal.json"), "-r", "8x8", "-o", out.to_str().unwrap()]);
+
+    assert_eq!(code, IO, "{}", stderr);
+    assert!(stderr.contains("doesn't exist"), "{}", stderr);
+}
+
+#[test]
+fn existing_output()
+{
+    let dir = scratch("existing-output");
+    let out = dir.join("out.png");
+    std::fs::write(&out, b"").unwrap();
+
+    let (code, stderr) = render(&[
+        "-s", &scene("good/minimal.json"), "-r", "8x8", "-o", out.to_str().unwrap()]);
+
+    assert_eq!(code, IO, "{}", stderr);
+    assert!(stderr.contains("already exists, use --force"), "{}", stderr);
+    assert_eq!(std::fs::read(&out).unwrap(), b"");
+}
+
+#[test]
+fn renders()
+{
+    let dir = scratch("renders");
+    let out = dir.join("out.png");
+
+    let (code, stderr) = render(&[
+        "-s", &scene("good/minimal.json"), "-r", "8x8", "-o", out.to_str().unwrap()]);
+
+    assert_eq!(code, 0, "{}", stderr);
+    assert!(out.exists());
+}
+
+/// Renders the built-in Cornell box at 8x8 with one sample, checking it
+/// works, and returns what was written to stdout.
+fn render_cornell(out: &Path, seed: Option<&str>) -> String
+{
+    let mut args = vec!["--builtin", "cornell", "-r", "8x8", "--force", "-o", out.to_str().unwrap()];
+    if let Some(seed) = seed
+    {
+        args.extend(["--seed", seed]);
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_path-tracer-gpu"))
+        .arg("render")
+        .args(["--cpu", "-m", "1"])
+        .args(&args)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn same_seed_same_image()
+{
+    let dir = scratch("same-seed");
+
+    // PNGs hold the render time, so use formats without metadata
+    for name in ["out.pfm", "out.bmp"]
+    {
+        let out = dir.join(name);
+
+        render_cornell(&out, Some("1234"));
+        let first = std::fs::read(&out).unwrap();
+        render_cornell(&out, Some("1234"));
+        assert!(std::fs::read(&out).unwrap() == first, "{} changed with the same seed", name);
+
+        render_cornell(&out, Some("1235"));
+        assert!(std::fs::read(&out).unwrap() != first, "{} didn't change with the seed", name);
+    }
+
+    // without --seed, the seed it prints gives the same image again
+    let out = dir.join("out.pfm");
+    let stdout = render_cornell(&out, None);
+    let seed = stdout.lines()
+        .find_map(|line| line.strip_prefix("Seed: "))
+        .unwrap_or_else(|| panic!("no seed in {:?}", stdout))
+        .to_owned();
+    let first = std::fs::read(&out).unwrap();
+
+    render_cornell(&out, Some(&seed));
+    assert!(std::fs::read(&out).unwrap() == first, "seed {} didn't repeat the render", seed);
+}
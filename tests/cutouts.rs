@@ -0,0 +1,73 @@
+//! Holes cut by an alpha texture have to let light through, both to the
+//! camera and to whatever's in the surface's shadow.
+
+use path_tracer_gpu::{GpuContext, RenderSettings, Scene};
+
+const SIZE: u32 = 32;
+
+#[test]
+fn fence_lets_light_through_its_holes()
+{
+    // a white fence of upright bars a quarter wide, every other one cut out
+    let mask = std::env::temp_dir()
+        .join(format!("path-tracer-gpu-fence-{}.png", std::process::id()));
+    image::RgbaImage::from_fn(2, 1, |x, _| image::Rgba([255, 255, 255, if x == 0 { 255 } else { 0 }]))
+        .save(&mask)
+        .unwrap();
+
+    // the light's behind the camera, so the red wall behind the fence is
+    // only lit through the holes, and seen through them
+    let scene = Scene::parse(&format!(
+        r#"{{
+            "camera": {{ "pos": [0, 0, -1], "front": [0, 0, 1], "up": [0, 1, 0], "fov": 30 }},
+            "materials": {{
+                "fence": {{ "colour": [0.8, 0.8, 0.8], "alpha_texture": {:?} }},
+                "wall": {{ "colour": [0.8, 0, 0] }},
+                "light": {{ "colour": [0, 0, 0], "glow": [1, 1, 1] }}
+            }},
+            "surfaces": [
+                {{ "quad": [[-20, -20, 1], [20, -20, 1], [20, 20, 1], [-20, 20, 1]], "mat": "fence",
+                   "uvs": [[-80, 0], [80, 0], [80, 1], [-80, 1]] }},
+                {{ "quad": [[-20, -20, 1.5], [20, -20, 1.5], [20, 20, 1.5], [-20, 20, 1.5]], "mat": "wall" }},
+                {{ "quad": [[-20, -20, -2], [20, -20, -2], [20, 20, -2], [-20, 20, -2]], "mat": "light" }}
+            ]
+        }}"#,
+        mask.to_str().unwrap())).unwrap();
+    std::fs::remove_file(&mask).unwrap();
+
+    let settings = RenderSettings
+    {
+        samples: 64,
+        depth: 3,
+        seed: Some(3),
+        cpu: true,
+        .. RenderSettings::new([SIZE, SIZE])
+    };
+
+    let mut backends = vec![("CPU", settings.clone())];
+    match GpuContext::new(None, false)
+    {
+        Ok(_) => backends.push(("GPU", RenderSettings { cpu: false, .. settings })),
+        Err(e) => eprintln!("Only checking the CPU, there's no GPU: {}", e),
+    }
+
+    for (backend, settings) in backends
+    {
+        let frame = path_tracer_gpu::render(&scene, &settings).unwrap();
+
+        // nothing green gets off the wall, so the holes are the pixels
+        // without any, and the bars are the ones with plenty
+        let holes = frame.pixels.iter().filter(|px| px.g == 0.0).collect::<Vec<_>>();
+        let bars = frame.pixels.iter().filter(|px| px.g > 0.1).count();
+
+        let quarter = frame.pixels.len() / 4;
+        assert!(holes.len() > quarter, "{}: the wall shows through {} pixels", backend, holes.len());
+        assert!(bars > quarter, "{}: the fence covers {} pixels", backend, bars);
+
+        // in a solid shadow the wall would be black
+        for px in holes
+        {
+            assert!(px.r > 0.1, "{}: the fence's shadow has no holes, {:?}", backend, px);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//! The same scene written as JSON, YAML and TOML has to load into the same
+//! scene and render the same image.
+
+#![cfg(all(feature = "yaml", feature = "toml"))]
+
+use path_tracer_gpu::{Format, RenderSettings, Scene};
+
+const JSON: &str = r#"{
+    "camera": { "pos": [0, 1, 5], "front": [0, 0, -1], "up": [0, 1, 0], "fov": 60 },
+    "materials": {
+        "white": { "colour": [0.8, 0.8, 0.8] },
+        "red": { "colour": [0.8, 0.2, 0.2], "gloss": 0.5 },
+        "light": { "colour": [0, 0, 0], "glow": [4, 4, 4] }
+    },
+    "surfaces": [
+        { "quad": [[-2, 0, 2], [2, 0, 2], [2, 0, -2], [-2, 0, -2]], "mat": "white" },
+        { "tri": [[-1, 3, -1], [1, 3, -1], [0, 3, 1]], "mat": "light" },
+        { "sphere_mesh": { "center": [0, 0.5, 0], "radius": 0.5, "subdivisions": 1 }, "mat": "red" }
+    ]
+}"#;
+
+const YAML: &str = "
+# the same as JSON
+camera: { pos: [0, 1, 5], front: [0, 0, -1], up: [0, 1, 0], fov: 60 }
+materials:
+  white: { colour: [0.8, 0.8, 0.8] }
+  red:
+    colour: [0.8, 0.2, 0.2]
+    gloss: 0.5
+  light: { colour: [0, 0, 0], glow: [4, 4, 4] }
+surfaces:
+  - quad: [[-2, 0, 2], [2, 0, 2], [2, 0, -2], [-2, 0, -2]]
+    mat: white
+  - tri: [[-1, 3, -1], [1, 3, -1], [0, 3, 1]]
+    mat: light
+  - sphere_mesh: { center: [0, 0.5, 0], radius: 0.5, subdivisions: 1 }
+    mat: red
+";
+
+const TOML: &str = r#"
+# the same as JSON
+camera = { pos = [0.0, 1.0, 5.0], front = [0.0, 0.0, -1.0], up = [0.0, 1.0, 0.0], fov = 60.0 }
+
+[materials.white]
+colour = [0.8, 0.8, 0.8]
+
+[materials.red]
+colour = [0.8, 0.2, 0.2]
+gloss = 0.5
+
+[materials.light]
+colour = [0.0, 0.0, 0.0]
+glow = [4.0, 4.0, 4.0]
+
+[[surfaces]]
+quad = [[-2.0, 0.0, 2.0], [2.0, 0.0, 2.0], [2.0, 0.0, -2.0], [-2.0, 0.0, -2.0]]
+mat = "white"
+
+[[surfaces]]
+tri = [[-1.0, 3.0, -1.0], [1.0, 3.0, -1.0], [0.0, 3.0, 1.0]]
+mat = "light"
+
+[[surfaces]]
+sphere_mesh = { center = [0.0, 0.5, 0.0], radius = 0.5, subdivisions = 1 }
+mat = "red"
+"#;
+
+fn parse(source: &str, format: Format) -> Scene
+{
+    match Scene::parse_format(source, format)
+    {
+        Ok((scene, warnings)) =>
+        {
+            assert!(warnings.is_empty(), "{:?}: {:?}", format, warnings);
+            scene
+        },
+        Err(e) => panic!("{:?}: {}", format, e),
+    }
+}
+
+#[test]
+fn every_format_gives_the_same_scene()
+{
+    let settings = RenderSettings
+    {
+        samples: 2,
+        depth: 2,
+        seed: Some(3),
+        cpu: true,
+        .. RenderSettings::new([8, 8])
+    };
+
+    let json = parse(JSON, Format::Json);
+    let image = path_tracer_gpu::render(&json, &settings).unwrap().pixels;
+    assert!(!json.triangles.is_empty());
+
+    for (source, format) in [(YAML, Format::Yaml), (TOML, Format::Toml)]
+    {
+        let scene = parse(source, format);
+
+        // none of them have PartialEq, since the GPU reads them as bytes
+        let same = |a: String, b: String| assert_eq!(a, b, "{:?}", format);
+        same(format!("{:?}", scene.triangles), format!("{:?}", json.triangles));
+        same(format!("{:?}", scene.materials), format!("{:?}", json.materials));
+        same(format!("{:?}", scene.camera), format!("{:?}", json.camera));
+
+        let pixels = path_tracer_gpu::render(&scene, &settings).unwrap().pixels;
+        same(format!("{:?}", pixels), format!("{:?}", image));
+    }
+}
+
+#[test]
+fn syntax_errors_say_where()
+{
+    for (source, format) in [
+        ("camera: [0, 1\nmaterials: {}\n", Format::Yaml),
+        ("[camera]\npos = [0, 1, 5\n", Format::Toml),
+    ]
+    {
+        match Scene::parse_format(source, format)
+        {
+            Ok(_) => panic!("{:?} loaded", format),
+            Err(e) => assert!(e.contains("line"), "{:?}: {}", format, e),
+        }
+    }
+}
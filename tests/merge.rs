@@ -0,0 +1,85 @@
+//! Merging checkpoints of renders with different seeds has to give the same
+//! image as one render with all their samples.
+
+use path_tracer_gpu::{builtin_scene, Checkpoint, Colour, Framebuffer, RenderSettings, Scene};
+
+const RES: [u32; 2] = [8, 8];
+
+fn settings(samples: u32, seed: u64) -> RenderSettings
+{
+    RenderSettings
+    {
+        samples: samples,
+        depth: 3,
+        seed: Some(seed),
+        cpu: true,
+        .. RenderSettings::new(RES)
+    }
+}
+
+/// Renders `samples` samples and keeps the checkpoint it finishes with.
+fn checkpoint(scene: &Scene, samples: u32, seed: u64) -> Checkpoint
+{
+    let path = std::env::temp_dir()
+        .join(format!("path-tracer-gpu-merge-{}-{}.ckpt", std::process::id(), seed));
+    let path = path.to_str().unwrap();
+
+    let settings = RenderSettings
+    {
+        checkpoint: Some((path.to_owned(), samples)),
+        .. settings(samples, seed)
+    };
+    path_tracer_gpu::render(scene, &settings).unwrap();
+
+    let checkpoint = Checkpoint::load(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    checkpoint
+}
+
+/// Root mean square error over every channel, relative to the mean.
+fn relative_rmse(a: &[Colour], b: &[Colour]) -> f64
+{
+    let channels = |c: &Colour| [c.r as f64, c.g as f64, c.b as f64];
+
+    let (mut squares, mut total) = (0.0, 0.0);
+    for (a, b) in a.iter().zip(b)
+    {
+        for (a, b) in channels(a).iter().zip(channels(b))
+        {
+            squares += (a - b) * (a - b);
+            total += b;
+        }
+    }
+
+    let n = (a.len() * 3) as f64;
+    (squares / n).sqrt() / (total / n)
+}
+
+#[test]
+fn two_halves_make_a_whole()
+{
+    let scene = builtin_scene("cornell").unwrap();
+
+    let reference = path_tracer_gpu::render(&scene, &settings(8000, 1)).unwrap();
+    let single = path_tracer_gpu::render(&scene, &settings(1000, 2)).unwrap();
+    let half = path_tracer_gpu::render(&scene, &settings(500, 3)).unwrap();
+
+    let merged = Checkpoint::merge(&[
+        ("a", checkpoint(&scene, 500, 3)),
+        ("b", checkpoint(&scene, 500, 4)),
+    ]).unwrap();
+    assert_eq!(merged.samples, 1000);
+    assert_eq!(merged.seeds, [3, 4]);
+    let merged = Framebuffer::from_checkpoint(&merged, 0.0);
+
+    let single = relative_rmse(&single.pixels, &reference.pixels);
+    let half = relative_rmse(&half.pixels, &reference.pixels);
+    let merged = relative_rmse(&merged.pixels, &reference.pixels);
+
+    // noise falls with the square root of the samples, so a merge that lost
+    // either half would be about as far off as `half`
+    assert!((merged / single - 1.0).abs() < 0.25,
+        "merged is {:.4} from the reference, one render {:.4}", merged, single);
+    assert!(merged < half * 0.85,
+        "merged is {:.4} from the reference, half the samples {:.4}", merged, half);
+}
@@ -0,0 +1,96 @@
+//! Checks where nodes put their surfaces: a node scales, then rotates, then
+//! translates, and a child's placement happens inside its parent's.
+
+use path_tracer_gpu::Scene;
+
+/// Parses a scene holding just `nodes`, and returns the first corner of its
+/// only triangle.
+fn corner(nodes: &str) -> [f32; 3]
+{
+    let scene = Scene::parse(&format!(
+        r#"{{
+            "camera": {{ "pos": [0, 0, 5], "front": [0, 0, -1], "up": [0, 1, 0], "fov": 50 }},
+            "materials": {{ "white": {{ "colour": [1, 1, 1] }} }},
+            "nodes": {}
+        }}"#,
+        nodes)).unwrap();
+
+    assert_eq!(scene.triangles.len(), 1);
+    scene.triangles[0].a
+}
+
+fn tri(at: [f32; 3]) -> String
+{
+    format!(
+        r#"{{ "tri": [[{0}, {1}, {2}], [{0}, {1}, {3}], [{4}, {1}, {2}]], "mat": "white" }}"#,
+        at[0], at[1], at[2], at[2] + 1.0, at[0] + 1.0)
+}
+
+fn assert_near(got: [f32; 3], want: [f32; 3])
+{
+    for k in 0..3
+    {
+        assert!((got[k] - want[k]).abs() < 1e-4, "got {:?}, wanted {:?}", got, want);
+    }
+}
+
+#[test]
+fn scale_then_rotate_then_translate()
+{
+    let got = corner(&format!(
+        r#"[{{
+            "scale": 2,
+            "rotate": {{ "axis": [0, 0, 1], "angle": 90 }},
+            "translate": [0, 0, 5],
+            "surfaces": [{}]
+        }}]"#,
+        tri([1.0, 0.0, 0.0])));
+
+    // (1, 0, 0) scales to (2, 0, 0), turns to (0, 2, 0) and moves to (0, 2, 5)
+    assert_near(got, [0.0, 2.0, 5.0]);
+}
+
+#[test]
+fn children_are_placed_inside_their_parent()
+{
+    let got = corner(&format!(
+        r#"[{{
+            "rotate": {{ "axis": [0, 0, 1], "angle": 90 }},
+            "children": [{{ "translate": [1, 0, 0], "surfaces": [{}] }}]
+        }}]"#,
+        tri([0.0, 0.0, 0.0])));
+
+    // the child's step along x is turned by its parent onto y
+    assert_near(got, [0.0, 1.0, 0.0]);
+}
+
+#[test]
+fn sixteen_levels_deep()
+{
+    const DEPTH: usize = 16;
+
+    let mut nodes = tri([1.0, 0.0, 0.0]);
+    for level in 0..DEPTH
+    {
+        let key = if level == 0 { "surfaces" } else { "children" };
+        nodes = format!(
+            r#"{{
+                "name": "level {}",
+                "scale": 0.5,
+                "rotate": {{ "axis": [0, 0, 1], "angle": 90 }},
+                "translate": [1, 0, 0.25],
+                "{}": [{}]
+            }}"#,
+            DEPTH - level, key, nodes);
+    }
+    let got = corner(&format!("[{}]", nodes));
+
+    // every level halves, turns a quarter about z and then moves, innermost
+    // first
+    let mut want = [1.0f64, 0.0, 0.0];
+    for _ in 0..DEPTH
+    {
+        want = [-want[1] * 0.5 + 1.0, want[0] * 0.5, want[2] * 0.5 + 0.25];
+    }
+    assert_near(got, [want[0] as f32, want[1] as f32, want[2] as f32]);
+}
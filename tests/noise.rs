@@ -0,0 +1,152 @@
+//! The random numbers have to be independent between pixels, samples and
+//! bounces, or the noise shows up as streaks and patterns.
+
+use path_tracer_gpu::{GpuContext, Material, RenderSettings, Scene};
+
+const SIZE: u32 = 32;
+const SAMPLES: u32 = 4;
+const DEPTH: u32 = 4;
+const RENDERS: u64 = 24;
+
+/// The camera between two huge white walls that glow 1 and are half glossy.
+/// Each hit goes diffuse, and adds the glow, or mirrors off with even odds,
+/// and every bounce lands on the other wall, so each sample of every pixel
+/// is a count of heads out of `DEPTH` coin flips.
+fn walls() -> Scene
+{
+    let mut scene = Scene::new([0.0; 3], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0], 0.5);
+    let white = scene.add_material(Material::new([1.0; 3], [1.0; 3], 0.5, [1.0; 3]));
+
+    for z in [1.0, -1.0]
+    {
+        let w = 1000.0;
+        scene.add_quad([-w, -w, z], [w, -w, z], [w, w, z], [-w, w, z], white);
+    }
+
+    scene
+}
+
+/// `RENDERS` images with different seeds, as each pixel's red.
+fn renders(cpu: bool) -> Vec<Vec<f64>>
+{
+    let scene = walls();
+
+    (0..RENDERS)
+        .map(|seed|
+        {
+            let settings = RenderSettings
+            {
+                samples: SAMPLES,
+                depth: DEPTH,
+                seed: Some(seed),
+                cpu: cpu,
+                .. RenderSettings::new([SIZE, SIZE])
+            };
+
+            path_tracer_gpu::render(&scene, &settings).unwrap()
+                .pixels.iter().map(|px| px.r as f64).collect()
+        })
+        .collect()
+}
+
+/// The average of `of` over the pixels `px`.
+fn average(px: &[usize], of: &[f64]) -> f64
+{
+    px.iter().map(|&px| of[px]).sum::<f64>() / px.len() as f64
+}
+
+fn at(x: u32, y: u32) -> usize
+{
+    (y * SIZE + x) as usize
+}
+
+/// Checks that every pixel varies as much as the coin flips should, and
+/// independently of its neighbours.
+fn check(renders: &[Vec<f64>], backend: &str)
+{
+    let n = renders.len() as f64;
+    let all = (0..(SIZE * SIZE) as usize).collect::<Vec<_>>();
+    let mean = all.iter()
+        .map(|&px| renders.iter().map(|r| r[px]).sum::<f64>() / n)
+        .collect::<Vec<_>>();
+    let variance = all.iter()
+        .map(|&px| renders.iter().map(|r| (r[px] - mean[px]).powi(2)).sum::<f64>() / (n - 1.0))
+        .collect::<Vec<_>>();
+
+    // a sample is DEPTH flips, with a variance of 1/4 each, averaged over
+    // SAMPLES samples
+    let want_mean = DEPTH as f64 / 2.0;
+    let want_variance = DEPTH as f64 / 4.0 / SAMPLES as f64;
+
+    let got = average(&all, &mean);
+    assert!((got / want_mean - 1.0).abs() < 0.02, "{}: mean {}", backend, got);
+    let got = average(&all, &variance);
+    assert!((got / want_variance - 1.0).abs() < 0.08, "{}: variance {}", backend, got);
+
+    // the same in each 8x8 block, row and column, so nothing is streaky
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    for b in 0..16
+    {
+        let block = (0..64).map(|p| at(b % 4 * 8 + p % 8, b / 4 * 8 + p / 8)).collect();
+        groups.push((format!("block {}", b), block));
+    }
+    for i in 0..SIZE
+    {
+        groups.push((format!("row {}", i), (0..SIZE).map(|x| at(x, i)).collect()));
+        groups.push((format!("column {}", i), (0..SIZE).map(|y| at(i, y)).collect()));
+    }
+
+    for (name, px) in &groups
+    {
+        let got = average(px, &variance);
+        assert!((got / want_variance - 1.0).abs() < 0.3,
+            "{}: {} has variance {}, not {}", backend, name, got, want_variance);
+    }
+
+    // neighbours, and the same pixel a seed apart, don't move together. The
+    // means measured from so few renders would pull pairs of them apart.
+    let correlation = |pairs: &[(usize, usize, usize, usize)]|
+    {
+        let products = pairs.iter()
+            .map(|&(ra, a, rb, b)| (renders[ra][a] - want_mean) * (renders[rb][b] - want_mean))
+            .sum::<f64>();
+
+        products / pairs.len() as f64 / want_variance
+    };
+
+    for (name, dx, dy) in [("right", 1, 0), ("below", 0, 1), ("diagonal", 1, 1), ("across", 1, -1)]
+    {
+        let mut pairs = Vec::new();
+        for r in 0..renders.len()
+        {
+            for y in 1..SIZE - 1
+            {
+                for x in 0..SIZE - 1
+                {
+                    pairs.push((r, at(x, y), r, at(x + dx, (y as i32 + dy) as u32)));
+                }
+            }
+        }
+
+        let got = correlation(&pairs);
+        assert!(got.abs() < 0.05, "{}: pixels {} correlate by {}", backend, name, got);
+    }
+
+    let pairs = (1..renders.len())
+        .flat_map(|r| all.iter().map(move |&px| (r - 1, px, r, px)))
+        .collect::<Vec<_>>();
+    let got = correlation(&pairs);
+    assert!(got.abs() < 0.05, "{}: seeds a step apart correlate by {}", backend, got);
+}
+
+#[test]
+fn noise_is_even_and_uncorrelated()
+{
+    check(&renders(true), "CPU");
+
+    match GpuContext::new(None, false)
+    {
+        Ok(_) => check(&renders(false), "GPU"),
+        Err(e) => eprintln!("Skipping the GPU, there isn't one: {}", e),
+    }
+}
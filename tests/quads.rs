@@ -0,0 +1,88 @@
+//! A quad has to cover all of itself and face one way, whichever corner its
+//! vertices start from.
+
+use path_tracer_gpu::{RenderSettings, Scene};
+
+const SIZE: u32 = 16;
+
+/// A glowing square from (-1, -1) to (1, 1), seen head on from 5 away, with
+/// its corners given in order around the edge starting at `start`.
+fn square(start: usize) -> Scene
+{
+    let corners = ["[-1, -1, 0]", "[1, -1, 0]", "[1, 1, 0]", "[-1, 1, 0]"];
+    let quad = (0..4).map(|i| corners[(start + i) % 4]).collect::<Vec<_>>().join(", ");
+
+    Scene::parse(&format!(
+        r#"{{
+            "camera": {{ "pos": [0, 0, 5], "front": [0, 0, -1], "up": [0, 1, 0], "fov": 60 }},
+            "materials": {{ "light": {{ "colour": [0, 0, 0], "glow": [1, 1, 1] }} }},
+            "surfaces": [{{ "quad": [{}], "mat": "light" }}]
+        }}"#,
+        quad)).unwrap()
+}
+
+/// How bright each pixel of the scene is.
+fn render(scene: &Scene) -> Vec<f32>
+{
+    let settings = RenderSettings
+    {
+        samples: 4,
+        depth: 1,
+        seed: Some(1),
+        cpu: true,
+        .. RenderSettings::new([SIZE, SIZE])
+    };
+
+    path_tracer_gpu::render(scene, &settings).unwrap()
+        .pixels.iter().map(|px| px.r + px.g + px.b).collect()
+}
+
+#[test]
+fn quads_cover_themselves()
+{
+    // the square reaches 1 / tan(30°) / 5 of the way from the middle to the
+    // edge of the image, about 0.35
+    let reach = 1.0 / (5.0 * 30f32.to_radians().tan());
+
+    for start in 0..4
+    {
+        let pixels = render(&square(start));
+
+        for y in 0..SIZE
+        {
+            for x in 0..SIZE
+            {
+                // the middle of the pixel, from -1 to 1, and half a pixel
+                // for the samples spread across it
+                let at = |i: u32| ((i as f32 + 0.5) / SIZE as f32 * 2.0 - 1.0).abs();
+                let margin = 1.0 / SIZE as f32;
+                let px = pixels[(y * SIZE + x) as usize];
+
+                if at(x) < reach - margin && at(y) < reach - margin
+                {
+                    assert!(px > 0.0, "starting at corner {}, ({}, {}) is a hole", start, x, y);
+                }
+                else if at(x) > reach + margin || at(y) > reach + margin
+                {
+                    assert_eq!(px, 0.0, "starting at corner {}, ({}, {}) is lit", start, x, y);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn both_halves_face_the_camera()
+{
+    for start in 0..4
+    {
+        let scene = square(start);
+        assert_eq!(scene.triangles.len(), 2);
+
+        for t in &scene.triangles
+        {
+            let (u, v) = ([t.b[0] - t.a[0], t.b[1] - t.a[1]], [t.c[0] - t.a[0], t.c[1] - t.a[1]]);
+            assert!(u[0] * v[1] - u[1] * v[0] > 0.0, "starting at corner {}, {:?} faces away", start, t);
+        }
+    }
+}
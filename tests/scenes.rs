@@ -0,0 +1,116 @@
+//! Parses every scene under `tests/scenes`. The good ones have to load with
+//! no warnings, and each bad one starts with a `// error: ...` or
+//! `// warning: ...` comment holding the message it should give.
+
+use std::path::{Path, PathBuf};
+
+use path_tracer_gpu::{Format, Scene, SceneDef};
+
+fn scenes(dir: &str) -> Vec<PathBuf>
+{
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenes").join(dir);
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("json".as_ref()))
+        .collect();
+    paths.sort();
+
+    assert!(!paths.is_empty(), "no scenes in {}", dir.display());
+    paths
+}
+
+fn load(path: &Path) -> Result<(Scene, Vec<String>), String>
+{
+    Scene::load(path.to_str().unwrap())
+}
+
+#[test]
+fn good_scenes_load()
+{
+    let mut paths = scenes("good");
+    paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("scene.json"));
+    paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/c/cornell.json"));
+
+    for path in paths
+    {
+        match load(&path)
+        {
+            Ok((_, warnings)) => assert!(
+                warnings.is_empty(), "{}: unexpected warnings {:?}", path.display(), warnings),
+            Err(e) => panic!("{}: {}", path.display(), e),
+        }
+    }
+}
+
+#[test]
+fn bad_scenes_fail()
+{
+    for path in scenes("bad")
+    {
+        let source = std::fs::read_to_string(&path).unwrap();
+        let first = source.lines().next().unwrap_or("");
+
+        if let Some(expected) = first.strip_prefix("// error: ")
+        {
+            match load(&path)
+            {
+                Ok(_) => panic!("{}: loaded, expected {:?}", path.display(), expected),
+                Err(e) => assert!(
+                    e.contains(expected), "{}: got {:?}, expected {:?}", path.display(), e, expected),
+            }
+        }
+        else if let Some(expected) = first.strip_prefix("// warning: ")
+        {
+            match load(&path)
+            {
+                Ok((_, warnings)) => assert!(
+                    warnings.iter().any(|w| w.contains(expected)),
+                    "{}: got {:?}, expected {:?}", path.display(), warnings, expected),
+                Err(e) => panic!("{}: {}", path.display(), e),
+            }
+        }
+        else
+        {
+            panic!("{}: first line should say what error to expect", path.display());
+        }
+    }
+}
+
+#[test]
+fn confined_scenes_stay_inside_the_root()
+{
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenes/good");
+    let outside = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+
+    let scene = |extra: &str| format!(r#"{{
+        "camera": {{ "pos": [0, 0, 5], "front": [0, 0, -1], "up": [0, 1, 0], "fov": 60 }},
+        "materials": {{ "grey": {{ "colour": [0.5, 0.5, 0.5] }} }},
+        "surfaces": [],
+        {}
+    }}"#, extra);
+
+    let inside = scene(r#""include": ["minimal.json"]"#);
+    if let Err(e) = SceneDef::load_confined(&inside, Format::Json, &root)
+    {
+        panic!("{}", e);
+    }
+
+    let escapes = [
+        scene(r#""include": ["../bad/syntax.json"]"#),
+        scene(&format!(r#""include": [{:?}]"#, outside.to_str().unwrap())),
+        scene(&format!(r#""default_material": {{ "glow_texture": {{ "file": {:?} }} }}"#,
+            outside.to_str().unwrap())),
+        scene(r#""default_material": { "normal_map": { "file": "../../../Cargo.toml" } }"#),
+    ];
+
+    for source in &escapes
+    {
+        match SceneDef::load_confined(source, Format::Json, &root)
+        {
+            Ok(_) => panic!("loaded {}", source),
+            Err(e) => assert!(e.contains("inside the scene root"), "{}", e),
+        }
+    }
+}
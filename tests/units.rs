@@ -0,0 +1,127 @@
+//! A scene written in other units has to render just as it does in meters.
+
+use path_tracer_gpu::{Colour, RenderSettings, Scene};
+
+/// A box with a light in the ceiling and a ball on the floor, with every
+/// length divided by `per_meter`.
+fn room(units: &str, per_meter: f32) -> Scene
+{
+    let l = |meters: f32| meters * per_meter;
+
+    Scene::parse(&format!(
+        r#"{{
+            "units": "{units}",
+            "camera": {{ "pos": [0, {h}, {back}], "front": [0, 0, -1], "up": [0, 1, 0], "fov": 60 }},
+            "materials": {{
+                "white": {{ "colour": [0.8, 0.8, 0.8] }},
+                "red": {{ "colour": [0.8, 0.2, 0.2], "gloss": 0.3 }},
+                "light": {{ "colour": [0, 0, 0], "glow": [4, 4, 4] }}
+            }},
+            "surfaces": [
+                {{ "quad": [[-{w}, 0, {w}], [{w}, 0, {w}], [{w}, 0, -{w}], [-{w}, 0, -{w}]], "mat": "white" }},
+                {{ "quad": [[-{w}, 0, -{w}], [{w}, 0, -{w}], [{w}, {t}, -{w}], [-{w}, {t}, -{w}]], "mat": "white" }},
+                {{ "quad": [[-{w}, 0, {w}], [-{w}, 0, -{w}], [-{w}, {t}, -{w}], [-{w}, {t}, {w}]], "mat": "red" }},
+                {{ "quad": [[-{w}, {t}, {w}], [-{w}, {t}, -{w}], [{w}, {t}, -{w}], [{w}, {t}, {w}]], "mat": "white" }},
+                {{ "quad": [[-{s}, {ceiling}, {s}], [-{s}, {ceiling}, -{s}], [{s}, {ceiling}, -{s}], [{s}, {ceiling}, {s}]], "mat": "light" }},
+                {{ "sphere_mesh": {{ "center": [{s}, {r}, 0], "radius": {r}, "subdivisions": 2 }}, "mat": "red" }}
+            ]
+        }}"#,
+        units = units,
+        h = l(1.0), back = l(4.0), w = l(1.0), t = l(2.0),
+        s = l(0.3), ceiling = l(1.99), r = l(0.4))).unwrap()
+}
+
+fn render(scene: &Scene) -> Vec<Colour>
+{
+    let settings = RenderSettings
+    {
+        samples: 16,
+        depth: 3,
+        seed: Some(5),
+        cpu: true,
+        .. RenderSettings::new([16, 16])
+    };
+
+    path_tracer_gpu::render(scene, &settings).unwrap().pixels
+}
+
+/// Root mean square error over every channel, relative to the mean.
+fn relative_rmse(a: &[Colour], b: &[Colour]) -> f64
+{
+    let channels = |c: &Colour| [c.r as f64, c.g as f64, c.b as f64];
+
+    let (mut squares, mut total) = (0.0, 0.0);
+    for (a, b) in a.iter().zip(b)
+    {
+        for (a, b) in channels(a).iter().zip(channels(b))
+        {
+            squares += (a - b) * (a - b);
+            total += a;
+        }
+    }
+
+    let n = (a.len() * 3) as f64;
+    (squares / n).sqrt() / (total / n)
+}
+
+#[test]
+fn centimeters_render_like_meters()
+{
+    let meters = room("m", 1.0);
+
+    for (units, per_meter) in [("cm", 100.0), ("mm", 1000.0), ("km", 0.001)]
+    {
+        let other = room(units, per_meter);
+
+        assert_eq!(meters.triangles.len(), other.triangles.len());
+        for (a, b) in meters.triangles.iter().zip(&other.triangles)
+        {
+            for (a, b) in [a.a, a.b, a.c].iter().zip([b.a, b.b, b.c])
+            {
+                for k in 0..3
+                {
+                    assert!((a[k] - b[k]).abs() < 1e-5, "{}: {:?} isn't {:?}", units, b, a);
+                }
+            }
+        }
+
+        // rounding can send the odd path another way, but only the odd one
+        let (a, b) = (render(&meters), render(&other));
+        assert!(a.iter().any(|px| px.r > 0.1), "the room is dark");
+        let differ = a.iter().zip(&b)
+            .filter(|(a, b)| [a.r - b.r, a.g - b.g, a.b - b.b].iter().any(|d| d.abs() > 1e-3))
+            .count();
+        assert!(differ <= a.len() / 50, "{}: {} of {} pixels differ", units, differ, a.len());
+    }
+}
+
+#[test]
+fn huge_scenes_render_like_small_ones()
+{
+    // a floor and a ball under a glowing ceiling, so most of the light is
+    // from a bounce, which a ray stuck in the surface it left can't make
+    let lit = |size: f32| Scene::parse(&format!(
+        r#"{{
+            "camera": {{ "pos": [0, {h}, {back}], "front": [0, -0.3, -1], "up": [0, 1, 0], "fov": 60 }},
+            "materials": {{
+                "white": {{ "colour": [0.8, 0.8, 0.8] }},
+                "red": {{ "colour": [0.8, 0.2, 0.2] }},
+                "light": {{ "colour": [0, 0, 0], "glow": [1, 1, 1] }}
+            }},
+            "surfaces": [
+                {{ "quad": [[-{w}, 0, {w}], [{w}, 0, {w}], [{w}, 0, -{w}], [-{w}, 0, -{w}]], "mat": "white" }},
+                {{ "quad": [[-{w}, {t}, {w}], [-{w}, {t}, -{w}], [{w}, {t}, -{w}], [{w}, {t}, {w}]], "mat": "light" }},
+                {{ "sphere_mesh": {{ "center": [0, {r}, 0], "radius": {r}, "subdivisions": 2 }}, "mat": "red" }}
+            ]
+        }}"#,
+        h = size * 1.5, back = size * 3.0, w = size * 10.0, t = size * 2.0, r = size * 0.5)).unwrap();
+
+    // in meters both times, so nothing's scaled back down on loading and the
+    // rays start tens of thousands of units out, where a fixed offset from
+    // the surface would be lost to rounding
+    let (a, b) = (render(&lit(1.0)), render(&lit(10_000.0)));
+    assert!(a.iter().filter(|px| px.r > 0.3).count() > a.len() / 2, "the floor is dark");
+
+    let error = relative_rmse(&a, &b);
+    assert!(error < 0.05, "10,000 times bigger is {} off", error);
+}